@@ -1,13 +1,14 @@
 use crate::block::{Header, Serializable, Transaction};
-use crate::crypto::{HASH_SIZE, Hashable, double_sha256};
+use crate::crypto::{HASH_SIZE, Hashable, double_sha256, hex32};
 //use crate::consensus::validate_pow;
+use crate::consensus::{ConsensusParams, MAX_BLOCK_SIZE};
 use crate::error::block::BlockError;
 
 use bincode::{Decode, Encode};
 
 
 /// A block contains a header and a list of transactions.
-#[derive(Clone, Encode, Decode)]
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
 pub struct Block {
     pub header: Header,
     pub transactions: Vec<Transaction>,
@@ -20,12 +21,29 @@ impl Block {
         Self { header, transactions }
     }
 
-    /// Validate block (simplified)
-    /// - PoW is valid
-    /// - Merkle root matches tx list (stub for now)
-    // fn validate(&self) -> bool {
-    //     validate_pow(&self.header) && self.validate_merkle_root().is_ok()
-    // }
+    /// Check what the block proves on its own: its hash meets its target
+    /// and its merkle root matches its transactions
+    pub fn validate(&self) -> Result<(), BlockError> {
+        self.validate_with(&ConsensusParams::default())
+    }
+
+    /// As `validate`, refusing a block without transactions unless `params`
+    /// allow empty blocks
+    pub fn validate_with(&self, params: &ConsensusParams) -> Result<(), BlockError> {
+        if self.transactions.is_empty() && !params.allow_empty_blocks {
+            return Err(BlockError::EmptyTransactions);
+        }
+        self.header.validate_pow().map_err(|_| BlockError::InvalidPoW)?;
+        self.validate_merkle_root()
+    }
+
+    /// Check the block serializes to at most `MAX_BLOCK_SIZE` bytes
+    pub fn validate_size(&self) -> Result<(), BlockError> {
+        if self.byte_size() > MAX_BLOCK_SIZE {
+            return Err(BlockError::TooLarge);
+        }
+        Ok(())
+    }
 
     /// Validate merkle root against transactions
     pub fn validate_merkle_root(&self) -> Result<(), BlockError> {
@@ -38,7 +56,10 @@ impl Block {
         Ok(())
     }
 
-    
+    /// Txids of the block's transactions, in block order
+    pub fn iter_txids(&self) -> impl ExactSizeIterator<Item = [u8; HASH_SIZE]> + '_ {
+        self.transactions.iter().map(Hashable::double_sha256)
+    }
 
     #[cfg(test)]
     fn new_with_merkle(header: Header, txs: Vec<Transaction>) -> Self {
@@ -57,7 +78,7 @@ impl std::fmt::Display for Block {
         write!(
             f,
             "Block(hash={:x?}, txs={}, header={})",
-            hex::encode(self.double_sha256()),
+            hex32(&self.double_sha256()),
             self.transactions.len(),
             self.header,
         )
@@ -96,14 +117,11 @@ mod tests {
     use super::*;
     use crate::block::Header;
     use crate::consensus::fake_validate_pow;
+    use crate::testutil::{seeded_tx, ChainBuilder};
 
     #[test]
     fn test_block_roundtrip_serialization() {
-        // create two transactions
-        let tx1 = Transaction::new(vec![b"in1".to_vec()], vec![b"out1".to_vec()])
-            .expect("Failed to create tx1");
-        let tx2 = Transaction::new(vec![b"in2".to_vec()], vec![b"out2".to_vec()])
-            .expect("Failed to create tx2");
+        let (tx1, tx2) = (seeded_tx(1), seeded_tx(2));
 
         let header = Header::new(1, 1234567890, 0x1d00ffff, 42, [0u8; HASH_SIZE], [0u8; 32]);
 
@@ -128,9 +146,7 @@ mod tests {
 
     #[test]
     fn test_block_display() {
-        // create a transaction
-        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()])
-            .expect("Failed to create tx");
+        let tx = seeded_tx(1);
 
         // create a header with a placeholder merkle root
         let header = Header::new(1, 123, 0x207fffff, 42, [0u8; HASH_SIZE], [0u8; 32]);
@@ -144,13 +160,49 @@ mod tests {
         assert!(s.contains("hash="));
     }
 
+    #[test]
+    fn test_validate() {
+        let block = ChainBuilder::new(1).mine_next(2).clone();
+        assert!(block.validate().is_ok());
+
+        let mut bad_merkle = block.clone();
+        bad_merkle.header.merkle_root = [1u8; 32];
+        bad_merkle.header = crate::consensus::mine_block(&mut bad_merkle.header);
+        assert!(matches!(bad_merkle.validate(), Err(BlockError::InvalidMerkleRoot)));
+
+        // A zero target can't be met
+        let mut bad_pow = block;
+        bad_pow.header.difficulty_compact = 0x01000000;
+        assert!(matches!(bad_pow.validate(), Err(BlockError::InvalidPoW)));
+
+        let mut empty = Block::new_with_merkle(Header::new(1, 123, 0x207fffff, 0, [0u8; HASH_SIZE], [0u8; 32]), Vec::new());
+        empty.header = crate::consensus::mine_block(&mut empty.header);
+        assert!(empty.validate().is_ok());
+        let strict = ConsensusParams { allow_empty_blocks: false, ..Default::default() };
+        assert!(matches!(empty.validate_with(&strict), Err(BlockError::EmptyTransactions)));
+    }
+
+    #[test]
+    fn test_validate_size() {
+        let tx = |len| Transaction::new(vec![vec![7u8; len]], vec![b"out".to_vec()]).expect("Failed to create tx");
+        let header = Header::new(1, 123, 0x207fffff, u64::MAX, [0u8; HASH_SIZE], [0u8; 32]);
+
+        let block = Block::new_with_merkle(header.clone(), vec![tx(100), tx(5_000)]);
+        assert_eq!(block.byte_size(), block.serialize().unwrap().len());
+        assert_eq!(tx(100).byte_size(), tx(100).serialize().unwrap().len());
+        assert!(block.validate_size().is_ok());
+
+        // The header at its widest and the count add at most the overhead
+        let overhead = block.byte_size() - tx(100).byte_size() - tx(5_000).byte_size();
+        assert!(overhead <= crate::consensus::MAX_BLOCK_OVERHEAD);
+
+        let oversized = Block::new_with_merkle(header, vec![tx(MAX_BLOCK_SIZE)]);
+        assert!(matches!(oversized.validate_size(), Err(BlockError::TooLarge)));
+    }
+
     #[test]
     fn test_merkle_root_consistency() {
-        let tx1 = Transaction::new(vec![b"a".to_vec()], vec![b"b".to_vec()])
-            .expect("Failed to create tx1");
-        let tx2 = Transaction::new(vec![b"c".to_vec()], vec![b"d".to_vec()])
-            .expect("Failed to create tx2");
-        let txs = vec![tx1.clone(), tx2.clone()];
+        let txs = vec![seeded_tx(1), seeded_tx(2)];
 
         let root1 = compute_merkle_root(&txs);
         let root2 = compute_merkle_root(&txs);
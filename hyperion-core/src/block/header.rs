@@ -1,11 +1,11 @@
 use crate::block::Serializable;
-use crate::crypto::{HASH_SIZE, Hashable};
+use crate::crypto::{HASH_SIZE, Hashable, hex32};
 use crate::error::header::HeaderError;
 
 use bincode::{Decode, Encode};
 
 
-#[derive(Clone, Debug, Encode, Decode)]
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
 pub struct Header {
     pub version: u32,
     pub time: u32,
@@ -41,8 +41,6 @@ impl Header {
     pub fn compact_to_target(&self) -> [u8; HASH_SIZE] {
         crate::consensus::compact_to_target(self.difficulty_compact)
     }
-
-    
 }
 
 impl Serializable for Header {}
@@ -53,7 +51,7 @@ impl std::fmt::Display for Header {
         write!(
             f,
             "Header(hash={:x?}, time={}, nonce={})",
-            hex::encode(self.double_sha256()),
+            hex32(&self.double_sha256()),
             self.time,
             self.nonce,
         )
@@ -63,6 +61,7 @@ impl std::fmt::Display for Header {
 #[cfg(test)]
 mod tests {
     use crate::consensus;
+    use crate::testutil::ChainBuilder;
 
     use super::*;
 
@@ -90,6 +89,16 @@ mod tests {
         assert_eq!(h.double_sha256(), decoded.double_sha256());
     }
 
+    #[test]
+    fn test_mined_header_meets_target() {
+        let mut header = ChainBuilder::new(1).with_difficulty(0x2000ffff).mine_next(0).header.clone();
+        assert!(header.validate_pow().is_ok());
+
+        // Raising the difficulty far enough leaves the same hash short
+        header.difficulty_compact = 0x01000000;
+        assert!(header.validate_pow().is_err());
+    }
+
     #[test]
     fn test_pow_failure() {
         let h = Header::new(1, 0, 0x207fffff, 0, [0u8; HASH_SIZE], [0u8; HASH_SIZE]);
@@ -1,5 +1,6 @@
 pub mod header;
 pub mod transaction;
+#[allow(clippy::module_inception)]
 pub mod block;
 
 pub use header::Header;
@@ -7,7 +8,8 @@ pub use transaction::Transaction;
 pub use block::Block;
 
 use std::error::Error;
-use bincode::{Decode, Encode, decode_from_slice, encode_to_vec, config::standard};
+use bincode::{Decode, Encode, decode_from_slice, encode_into_writer, encode_to_vec, config::standard};
+use bincode::enc::write::SizeWriter;
 
 
 /// Trait for types that can be serialized/deserialize via bincode
@@ -16,7 +18,44 @@ pub trait Serializable: Sized + Encode + Decode<()> {
         encode_to_vec(self, standard()).map_err(|e| e.into())
     }
 
+    /// Decode `data`, which must hold the value and nothing after it
     fn from_bytes(data: &[u8]) -> Result<Self, Box<dyn Error>> {
-        decode_from_slice(data, standard()).map(|(decoded, _len)| decoded).map_err(|e| e.into())
+        let (decoded, len) = decode_bounded(data)?;
+        if len != data.len() {
+            return Err(format!("{} trailing bytes after the encoded value", data.len() - len).into());
+        }
+        Ok(decoded)
     }
+
+    /// Length of the serialized form in bytes, counted without building it
+    fn byte_size(&self) -> usize {
+        let mut counter = SizeWriter::default();
+        encode_into_writer(self, &mut counter, standard()).expect("Counting bytes never fails");
+        counter.bytes_written
+    }
+}
+
+/// Bytes a decoded value may claim per byte of its encoding. bincode claims
+/// the in-memory size of what it decodes, which for a one-byte varint or an
+/// empty `Vec` is many times the bytes it was read from.
+const DECODE_EXPANSION: usize = 64;
+
+/// Decode `data` under a claim limit that grows with its length, so a length
+/// prefix can't have bincode allocate far more than the input could fill.
+/// bincode limits are compile-time constants, hence the fixed steps.
+fn decode_bounded<T: Decode<()>>(data: &[u8]) -> Result<(T, usize), Box<dyn Error>> {
+    let budget = data.len().saturating_mul(DECODE_EXPANSION);
+
+    macro_rules! decode_within {
+        ($($limit:expr),+) => {
+            $(if budget <= $limit {
+                return Ok(decode_from_slice(data, standard().with_limit::<{ $limit }>())?);
+            })+
+        };
+    }
+    decode_within!(1 << 20, 1 << 24, 1 << 28);
+    #[cfg(target_pointer_width = "64")]
+    decode_within!(1 << 32, 1 << 36);
+
+    Err(format!("{} bytes is too large to decode", data.len()).into())
 }
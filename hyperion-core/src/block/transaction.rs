@@ -1,5 +1,5 @@
 use crate::block::Serializable;
-use crate::crypto::Hashable;
+use crate::crypto::{Hashable, hex32};
 use crate::error::transaction::TransactionError;
 
 use bincode::{Encode, Decode};
@@ -9,7 +9,7 @@ use serde::{Serialize, Deserialize};
 pub type InputData = Vec<u8>;
 pub type OutputData = Vec<u8>;
 
-#[derive(Debug, Encode, Decode, Clone, Serialize, Deserialize)]
+#[derive(Debug, Encode, Decode, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     pub inputs: Vec<InputData>,
     pub outputs: Vec<OutputData>,
@@ -39,7 +39,7 @@ impl std::fmt::Display for Transaction {
             "Tx(inputs={}, output={}, hash={:x?})",
             self.inputs.len(),
             self.outputs.len(),
-            hex::encode(self.double_sha256())
+            hex32(&self.double_sha256())
         )
     }
 }
@@ -63,4 +63,24 @@ mod tests {
         let decoded = Transaction::from_bytes(&bytes).expect("Failed to decode tx from bytes");
         assert_eq!(tx.double_sha256(), decoded.double_sha256());
     }
+
+    #[test]
+    fn test_transaction_rejects_trailing_bytes() {
+        let tx = Transaction::new(vec![b"a".to_vec()], vec![b"b".to_vec()]).expect("Failed to create tx");
+        let mut bytes = tx.serialize().expect("Failed to serialize tx bytes");
+        bytes.push(0);
+        assert!(Transaction::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_transaction_rejects_huge_claimed_length() {
+        // Inputs claiming 2^64 - 1 entries, in nine bytes
+        let mut bytes = vec![0xfd];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Transaction::from_bytes(&bytes).is_err());
+
+        let mut bytes = vec![0xfd];
+        bytes.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(Transaction::from_bytes(&bytes).is_err());
+    }
 }
@@ -2,15 +2,34 @@ use crate::block::{Block, Header, Serializable, Transaction};
 use crate::block::block::compute_merkle_root;
 use crate::crypto::{Hashable, HASH_SIZE};
 use crate::error::blockchain::BlockchainError;
-use crate::consensus::{adjust_difficulty, create_genesis_block};
+use crate::consensus::{adjust_difficulty, create_genesis_block, difficulty_at, ConsensusParams};
 
 use std::collections::VecDeque;
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
 use bincode::{Encode, Decode};
 
 
-#[derive(Encode, Decode)]
+/// Blocks from genesis to the tip. Blocks only join through `add_block`,
+/// which checks they extend the tip.
 pub struct Blockchain {
-    pub blocks: VecDeque<Block>,
+    blocks: VecDeque<Block>,
+    /// Not serialized: whoever loads the chain sets them
+    params: ConsensusParams,
+}
+
+/// Only the blocks are encoded, so chains saved before `params` load
+impl Encode for Blockchain {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.blocks.encode(encoder)
+    }
+}
+
+impl<Context> Decode<Context> for Blockchain {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self { blocks: VecDeque::decode(decoder)?, params: ConsensusParams::default() })
+    }
 }
 
 impl Blockchain {
@@ -18,7 +37,7 @@ impl Blockchain {
     pub fn new(genesis_block: Block) -> Self {
         let mut blocks = VecDeque::new();
         blocks.push_back(genesis_block);
-        Self { blocks }
+        Self { blocks, params: ConsensusParams::default() }
     }
 
     pub fn new_with_genesis() -> Self {
@@ -31,7 +50,23 @@ impl Blockchain {
         self.blocks.back().expect("Blockchain should have at least one block")
     }
 
-    /// Add a new block to the chain
+    /// Check blocks joining from now on against `params`
+    pub fn set_params(&mut self, params: ConsensusParams) {
+        self.params = params;
+    }
+
+    pub fn params(&self) -> &ConsensusParams {
+        &self.params
+    }
+
+    /// Bits the block at `height` must carry, up to the next block's;
+    /// none for the genesis block or past the next block
+    pub fn expected_difficulty(&self, height: usize) -> Option<u32> {
+        (1..=self.len()).contains(&height).then(|| difficulty_at(self, height))
+    }
+
+    /// Add a new block to the chain. With `skip_pow` neither its proof of
+    /// work nor the difficulty that is measured against is checked.
     pub fn add_block(&mut self, block: Block, skip_pow: bool) -> Result<(), BlockchainError> {
         let prev_hash = self.latest_block().double_sha256();
         if block.header.prev_hash != prev_hash {
@@ -39,23 +74,61 @@ impl Blockchain {
         }
 
         block.validate_merkle_root().map_err(|_| BlockchainError::InvalidMerkleRoot)?;
+        if block.transactions.is_empty() && !self.params.allow_empty_blocks {
+            return Err(BlockchainError::EmptyBlock);
+        }
 
         if !skip_pow {
             block.header.validate_pow().map_err(|_| BlockchainError::InvalidMerkleRoot)?;
+            self.check_difficulty(self.len(), &block.header)?;
         }
 
         self.blocks.push_back(block);
         Ok(())
     }
 
-    /// Simple validation: check PoW and merkle roots for all blocks
+    /// Check `header`'s bits are those retargeting dictates at `height`
+    fn check_difficulty(&self, height: usize, header: &Header) -> Result<(), BlockchainError> {
+        if self.params.no_retarget {
+            return Ok(());
+        }
+
+        let expected = difficulty_at(self, height);
+        if header.difficulty_compact != expected {
+            return Err(BlockchainError::IncorrectDifficulty { expected, got: header.difficulty_compact });
+        }
+        Ok(())
+    }
+
+    /// Remove and return the tip block. The genesis block is never removed.
+    pub fn pop_tip(&mut self) -> Option<Block> {
+        if self.blocks.len() <= 1 {
+            return None;
+        }
+        self.blocks.pop_back()
+    }
+
+    /// Chain without even a genesis block
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn empty_for_test() -> Self {
+        Self { blocks: VecDeque::new(), params: ConsensusParams::default() }
+    }
+
+    /// Append `block` without checking it extends the tip, to build chains
+    /// `validate` should refuse
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn push_unchecked(&mut self, block: Block) {
+        self.blocks.push_back(block);
+    }
+
+    /// Simple validation: check PoW, difficulty and merkle roots for all blocks
     pub fn validate(&self) -> bool {
         self.validate_with_options(false)
     }
 
-    /// Validate chain with option to skip PoW
+    /// Validate chain with option to skip PoW, and with it difficulty
     pub fn validate_with_options(&self, skip_pow: bool) -> bool {
-        for (i, block) in self.blocks.iter().enumerate() {
+        for (i, block) in self.iter_with_heights() {
             // Skip prev_hash check for genesis
             if i > 0 {
                 let prev_block = &self.blocks[i - 1];
@@ -68,9 +141,17 @@ impl Blockchain {
                 return false;
             }
 
+            if i > 0 && block.transactions.is_empty() && !self.params.allow_empty_blocks {
+                return false;
+            }
+
             if !skip_pow && block.header.validate_pow().is_err() {
                 return false;
             }
+
+            if !skip_pow && i > 0 && self.check_difficulty(i, &block.header).is_err() {
+                return false;
+            }
         }
 
         true
@@ -133,9 +214,21 @@ impl Blockchain {
         self.blocks.get(height)
     }
 
-    /// Find a block by hash
+    /// Find a block by hash. The search starts at the tip, where the blocks
+    /// asked after most are.
     pub fn find_block(&self, hash: [u8; HASH_SIZE]) -> Option<&Block> {
-        self.iter().find(|b| b.double_sha256() == hash)
+        self.iter_rev().find(|b| b.double_sha256() == hash)
+    }
+
+    /// Height of the block with `hash`, searching from the tip down
+    pub fn height_of(&self, hash: [u8; HASH_SIZE]) -> Option<usize> {
+        self.iter_with_heights().rev().find(|(_, b)| b.double_sha256() == hash).map(|(height, _)| height)
+    }
+
+    /// Headers of up to `count` consecutive blocks from `start`, fewer
+    /// where the tip comes first
+    pub fn get_headers_range(&self, start: usize, count: usize) -> impl Iterator<Item=&Header> {
+        self.blocks.range(start.min(self.blocks.len())..).take(count).map(|b| &b.header)
     }
 
     /// Convenience: return number of block
@@ -155,6 +248,19 @@ impl Blockchain {
     pub fn iter_rev(&self) -> impl DoubleEndedIterator<Item=&Block> {
         self.blocks.iter().rev()
     }
+
+    /// Blocks from genesis, each with its height
+    pub fn iter_with_heights(&self) -> impl DoubleEndedIterator<Item=(usize, &Block)> + ExactSizeIterator {
+        self.blocks.iter().enumerate()
+    }
+
+    /// Every transaction from genesis on, with the height of its block and
+    /// its position there
+    pub fn iter_transactions(&self) -> impl Iterator<Item=(usize, usize, &Transaction)> {
+        self.iter_with_heights().flat_map(|(height, block)| {
+            block.transactions.iter().enumerate().map(move |(index, tx)| (height, index, tx))
+        })
+    }
 }
 
 impl Serializable for Blockchain {}
@@ -1,27 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use crate::block::{block::compute_merkle_root, Block, Header, Transaction};
+    use crate::block::{block::compute_merkle_root, Block, Transaction};
     use crate::crypto::{HASH_SIZE, Hashable};
     use crate::chain::blockchain::Blockchain;
+    use crate::consensus::{mine_block, ConsensusParams, POW_LIMIT_BITS};
+    use crate::error::blockchain::BlockchainError;
+    use crate::testutil::{seeded_tx, unmined_block, ChainBuilder};
 
-    use std::collections::VecDeque;
-
-    /// Helper: create a simple transaction
-    fn make_tx() -> Transaction {
-        Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).expect("Failed to make new tx")
-    }
-
-    /// Helper: create a block with given previous hash
-    fn make_block(prev_hash: [u8; HASH_SIZE], txs: Vec<Transaction>) -> Block {
-        let merkle_root = compute_merkle_root(&txs);
-        let header = Header::new(1, 123, 0x207fffff, 0, prev_hash, merkle_root);
-        Block::new(header, txs)
-    }
-
-    /// Helper: create a default block with a single tx
+    /// Unmined block on `prev_hash` with a single transaction
     fn make_block_single(prev_hash: [u8; HASH_SIZE]) -> Block {
-        let tx = make_tx();
-        make_block(prev_hash, vec![tx])
+        unmined_block(prev_hash, vec![seeded_tx(0)])
     }
 
     #[test]
@@ -35,14 +23,14 @@ mod tests {
 
     #[test]
     fn test_add_block() {
-        let genesis = make_block_single([0u8; HASH_SIZE]);
-        let mut chain = Blockchain::new(genesis.clone());
-
-        let block1 = make_block_single(genesis.double_sha256());
-        chain.add_block(block1.clone(), true).expect("Failed to add block to chain");
+        let mut builder = ChainBuilder::new(1);
+        let block1 = builder.mine_next(1).clone();
+        let mut chain = Blockchain::new_with_genesis();
+        chain.add_block(block1.clone(), false).expect("Failed to add block to chain");
 
         assert_eq!(chain.len(), 2);
         assert_eq!(chain.latest_block().double_sha256(), block1.double_sha256());
+        assert!(chain.validate());
     }
 
     #[test]
@@ -57,10 +45,10 @@ mod tests {
 
     #[test]
     fn test_merkle_root_consistency() {
-        let tx1 = make_tx();
-        let tx2 = make_tx();
-        let block = make_block([0u8; HASH_SIZE], vec![tx1.clone(), tx2.clone()]);
-        let expected_root = compute_merkle_root(&vec![tx1, tx2]);
+        let tx1 = seeded_tx(1);
+        let tx2 = seeded_tx(2);
+        let block = unmined_block([0u8; HASH_SIZE], vec![tx1.clone(), tx2.clone()]);
+        let expected_root = compute_merkle_root(&[tx1, tx2]);
 
         assert_eq!(compute_merkle_root(&block.transactions), expected_root);
         assert!(block.validate_merkle_root().is_ok());
@@ -71,7 +59,7 @@ mod tests {
         let genesis = make_block_single([0u8; HASH_SIZE]);
         let chain = Blockchain::new(genesis.clone());
 
-        let txs = vec![make_tx(), make_tx()];
+        let txs = vec![seeded_tx(1), seeded_tx(2)];
         let block_template = chain.create_block_template(txs.clone(), 0x207fffff, 12345);
 
         // Check prev_hash
@@ -84,14 +72,8 @@ mod tests {
 
     #[test]
     fn test_chain_lookup_and_iterators() {
-        let genesis = make_block_single([0u8; HASH_SIZE]);
-        let mut chain = Blockchain::new(genesis.clone());
-
-        let block1 = make_block_single(genesis.double_sha256());
-        chain.add_block(block1.clone(), true).expect("Failed adding block1 to chain");
-
-        let block2 = make_block_single(block1.double_sha256());
-        chain.add_block(block2.clone(), true).expect("Failed adding block2 to chain");
+        let chain = ChainBuilder::new(1).mine_blocks(2, 1).build();
+        let [genesis, block1, block2] = [0, 1, 2].map(|height| chain.get_block_by_height(height).unwrap().clone());
 
         // get_block_by_height
         assert_eq!(chain.get_block_by_height(0).expect("Failed to get block1 by height").double_sha256(), genesis.double_sha256());
@@ -102,6 +84,16 @@ mod tests {
         assert_eq!(chain.find_block(block1.double_sha256()).expect("Failed to find block1").double_sha256(), block1.double_sha256());
         assert!(chain.find_block([1u8; HASH_SIZE]).is_none());
 
+        // height_of
+        assert_eq!(chain.height_of(block2.double_sha256()), Some(2));
+        assert_eq!(chain.height_of([1u8; HASH_SIZE]), None);
+
+        // get_headers_range stops at the tip
+        let headers: Vec<_> = chain.get_headers_range(1, 5).map(|h| h.double_sha256()).collect();
+        assert_eq!(headers, vec![block1.header.double_sha256(), block2.header.double_sha256()]);
+        assert_eq!(chain.get_headers_range(0, 1).count(), 1);
+        assert_eq!(chain.get_headers_range(3, 5).count(), 0);
+
         // iter and iter_rev
         let hashes: Vec<_> = chain.iter().map(|b| b.double_sha256()).collect();
         let rev_hashes: Vec<_> = chain.iter_rev().map(|b| b.double_sha256()).collect();
@@ -111,10 +103,21 @@ mod tests {
 
     #[test]
     fn test_empty_transaction_block() {
-        let block = make_block([0u8; HASH_SIZE], vec![]);
+        let block = unmined_block([0u8; HASH_SIZE], vec![]);
         // Merkle root should be zero
         assert_eq!(compute_merkle_root(&block.transactions), [0u8; HASH_SIZE]);
         assert!(block.validate_merkle_root().is_ok());
+
+        // Empty blocks connect only while the chain's params allow them
+        let mut chain = Blockchain::new(make_block_single([0u8; HASH_SIZE]));
+        let empty = unmined_block(chain.latest_block().double_sha256(), vec![]);
+        chain.set_params(ConsensusParams { allow_empty_blocks: false, ..Default::default() });
+        assert!(matches!(chain.add_block(empty.clone(), true), Err(BlockchainError::EmptyBlock)));
+        chain.set_params(ConsensusParams::default());
+        chain.add_block(empty, true).expect("Empty blocks are allowed by default");
+        assert!(chain.validate_with_options(true));
+        chain.set_params(ConsensusParams { allow_empty_blocks: false, ..Default::default() });
+        assert!(!chain.validate_with_options(true));
     }
 
     #[test]
@@ -131,9 +134,9 @@ mod tests {
 
     #[test]
     fn test_invalid_merkle_root_detection() {
-        let tx1 = make_tx();
-        let tx2 = make_tx();
-        let mut block = make_block([0u8; HASH_SIZE], vec![tx1.clone(), tx2.clone()]);
+        let tx1 = seeded_tx(1);
+        let tx2 = seeded_tx(2);
+        let mut block = unmined_block([0u8; HASH_SIZE], vec![tx1.clone(), tx2.clone()]);
         block.header.merkle_root = [1u8; HASH_SIZE];
 
         let chain = Blockchain::new(block.clone());
@@ -183,15 +186,28 @@ mod tests {
         assert_eq!(chain.len(), 1);
         assert!(!chain.is_empty());
 
-        // manually pop all blocks (simulate empty)
-        let blocks_only_chain = Blockchain { blocks: VecDeque::new() };
+        // A chain with no blocks at all
+        let blocks_only_chain = Blockchain::empty_for_test();
         assert_eq!(blocks_only_chain.len(), 0);
         assert!(blocks_only_chain.is_empty());
     }
 
+    #[test]
+    fn test_pop_tip_keeps_genesis() {
+        let mut builder = ChainBuilder::new(1);
+        let block1 = builder.mine_next(1).clone();
+        let mut chain = builder.build();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+
+        assert_eq!(chain.pop_tip().map(|b| b.double_sha256()), Some(block1.double_sha256()));
+        assert!(chain.pop_tip().is_none());
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain.latest_block().double_sha256(), genesis.double_sha256());
+    }
+
     #[test]
     fn test_iterators_on_empty_chain() {
-        let empty_chain = Blockchain { blocks: VecDeque::new() };
+        let empty_chain = Blockchain::empty_for_test();
         assert_eq!(empty_chain.iter().count(), 0);
         assert_eq!(empty_chain.iter_rev().count(), 0);
     }
@@ -206,7 +222,7 @@ mod tests {
             })
             .collect();
 
-        let block = make_block([0u8; HASH_SIZE], txs.clone());
+        let block = unmined_block([0u8; HASH_SIZE], txs.clone());
         let expected_root = compute_merkle_root(&txs);
         assert_eq!(block.header.merkle_root, expected_root);
         assert!(block.validate_merkle_root().is_ok());
@@ -217,7 +233,7 @@ mod tests {
         let genesis = make_block_single([0u8; HASH_SIZE]);
         let chain = Blockchain::new(genesis.clone());
 
-        let txs = vec![make_tx()];
+        let txs = vec![seeded_tx(1)];
         let difficulty = 0x1d00ffff;
         let block_template = chain.create_block_template(txs.clone(), difficulty, 1000);
 
@@ -234,21 +250,15 @@ mod tests {
 
         // Tamper with prev_hash after adding
         block1.header.prev_hash = [1u8; HASH_SIZE];
-        chain.blocks.push_back(block1);
+        chain.push_unchecked(block1);
 
         assert!(!chain.validate_with_options(true));
     }
 
     #[test]
     fn test_iterators_order_consistency() {
-        let genesis = make_block_single([0u8; HASH_SIZE]);
-        let mut chain = Blockchain::new(genesis.clone());
-
-        let block1 = make_block_single(genesis.double_sha256());
-        chain.add_block(block1.clone(), true).expect("Failed to add block1 to chain");
-
-        let block2 = make_block_single(block1.double_sha256());
-        chain.add_block(block2.clone(), true).expect("Failed to add block2 to chain");
+        let chain = ChainBuilder::new(2).mine_blocks(2, 3).build();
+        let [genesis, block1, block2] = [0, 1, 2].map(|height| chain.get_block_by_height(height).unwrap().clone());
 
         let iter_hashes: Vec<_> = chain.iter().map(|b| b.double_sha256()).collect();
         let rev_iter_hashes: Vec<_> = chain.iter_rev().map(|b| b.double_sha256()).collect();
@@ -256,4 +266,58 @@ mod tests {
         assert_eq!(iter_hashes, vec![genesis.double_sha256(), block1.double_sha256(), block2.double_sha256()]);
         assert_eq!(rev_iter_hashes, vec![block2.double_sha256(), block1.double_sha256(), genesis.double_sha256()]);
     }
+
+    #[test]
+    fn test_difficulty_enforced_across_retarget() {
+        // Blocks a minute apart make the retarget at height 3 harder
+        let mut builder = ChainBuilder::new(3).with_interval(60).mine_blocks(2, 1);
+        let expected = builder.chain().expected_difficulty(3).unwrap();
+        assert_ne!(expected, POW_LIMIT_BITS);
+        assert_eq!(builder.chain().expected_difficulty(2), Some(POW_LIMIT_BITS));
+        assert_eq!((builder.chain().expected_difficulty(0), builder.chain().expected_difficulty(4)), (None, None));
+
+        assert_eq!(builder.mine_next(1).header.difficulty_compact, expected);
+        let mut chain = builder.build();
+        assert!(chain.validate());
+        chain.pop_tip();
+
+        // A block keeping the old bits meets them, but they're stale
+        let mut stale = chain.create_block_template(vec![seeded_tx(9)], POW_LIMIT_BITS, chain.latest_block().header.time + 60);
+        mine_block(&mut stale.header);
+        assert!(matches!(
+            chain.add_block(stale.clone(), false),
+            Err(BlockchainError::IncorrectDifficulty { expected: e, got: POW_LIMIT_BITS }) if e == expected
+        ));
+        chain.add_block(stale.clone(), true).expect("skip_pow skips the difficulty check");
+        assert!(!chain.validate());
+        assert!(chain.validate_with_options(true));
+
+        // Regtest accepts any bits the proof of work meets
+        chain.pop_tip();
+        chain.set_params(ConsensusParams { no_retarget: true, ..Default::default() });
+        chain.add_block(stale, false).expect("No retarget accepts stale bits");
+        assert!(chain.validate());
+    }
+
+    #[test]
+    fn test_transaction_iterators_walk_chain_in_order() {
+        let chain = ChainBuilder::new(4).mine_blocks(3, 2).build();
+
+        let heights: Vec<_> = chain.iter_with_heights().map(|(height, block)| (height, block.transactions.len())).collect();
+        assert_eq!(heights, [(0, 1), (1, 2), (2, 2), (3, 2)]);
+        assert_eq!(chain.iter_with_heights().next_back().map(|(height, _)| height), Some(3));
+
+        let positions: Vec<_> = chain.iter_transactions().map(|(height, index, _)| (height, index)).collect();
+        assert_eq!(positions, [(0, 0), (1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (3, 1)]);
+
+        // Transactions are lent from the chain, not copied out of it
+        for (height, index, tx) in chain.iter_transactions() {
+            let block = chain.get_block_by_height(height).unwrap();
+            assert!(std::ptr::eq(tx, &block.transactions[index]));
+        }
+        let tip = chain.latest_block();
+        let txids: Vec<_> = tip.iter_txids().collect();
+        assert_eq!(txids, tip.transactions.iter().map(|tx| tx.double_sha256()).collect::<Vec<_>>());
+        assert_eq!(chain.height_of(tip.double_sha256()), Some(3));
+    }
 }
@@ -1,9 +1,14 @@
 use crate::block::block::compute_merkle_root;
 use crate::block::{Block, Header, Transaction};
 use crate::chain::Blockchain;
-use crate::crypto::{Hashable, HASH_SIZE};
+use crate::crypto::{hex32, Hashable, HASH_SIZE};
 
+use bincode::config::standard;
+use bincode::encode_into_slice;
 use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::ops::Add;
 
 
 /// Target block time in seconds
@@ -12,9 +17,71 @@ pub const TARGET_BLOCK_TIME: u32 = 600;
 /// Difficulty adjustment interval in block
 pub const ADJUSTMENT_INTERVAL: usize = 3;
 
+/// Difficulty of the genesis block, the easiest a private network may start at
+pub const POW_LIMIT_BITS: u32 = 0x207fffff;
+
+/// Largest serialized block, in bytes
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+
+/// Most a block's serialization adds to its transactions': the header at
+/// its widest and the transaction count's varint at its widest
+pub const MAX_BLOCK_OVERHEAD: usize = MAX_HEADER_SIZE + 9;
+
+/// How far a block's time may run ahead of the clock of the node that
+/// built its template, in seconds
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// Rules that differ between networks, set on a chain by whoever builds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusParams {
+    /// Accept blocks at any difficulty their proof of work meets, as
+    /// regtest does
+    pub no_retarget: bool,
+    /// Accept blocks without a single transaction. Blocks don't carry a
+    /// coinbase yet, so one mined on an empty mempool is empty and this
+    /// stays on by default until they do.
+    pub allow_empty_blocks: bool,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self { no_retarget: false, allow_empty_blocks: true }
+    }
+}
+
+/// What a genesis block is built from. The default gives the genesis block
+/// of the built-in networks; private networks pick their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisParams {
+    pub time: u32,
+    pub difficulty_compact: u32,
+    /// Sole input of the genesis transaction
+    pub message: Vec<u8>,
+    /// Outputs of the genesis transaction
+    pub outputs: Vec<Vec<u8>>,
+}
+
+impl Default for GenesisParams {
+    fn default() -> Self {
+        Self {
+            time: 0,
+            difficulty_compact: POW_LIMIT_BITS,
+            message: b"genesis".to_vec(),
+            outputs: vec![b"genesis_out".to_vec()],
+        }
+    }
+}
+
+/// Time a retarget expects its window to take
+const EPOCH_EXPECTED_TIME: u32 = TARGET_BLOCK_TIME * ADJUSTMENT_INTERVAL as u32;
+
 const EXPONENT_BIAS: u32 = 3;
 const MANTISSA_MASK: u32 = 0x007fffff;
 
+/// Longest header encoding: three u32s and a u64 at their widest varints,
+/// then the two hashes
+const MAX_HEADER_SIZE: usize = 3 * 5 + 9 + 2 * HASH_SIZE;
+
 /// Validate Proof-of-Work for a header
 pub fn validate_pow(header: &Header) -> bool {
     let hash = BigUint::from_bytes_be(&header.double_sha256());
@@ -23,21 +90,214 @@ pub fn validate_pow(header: &Header) -> bool {
     hash <= target
 }
 
+/// Hashes one header at many nonces without reserializing it. The header is
+/// encoded once; each nonce rewrites only its own bytes, moving the hashes
+/// after it when its varint length changes, so every hash matches
+/// `Header::double_sha256` for the same nonce.
+pub struct PowScratch {
+    buf: [u8; MAX_HEADER_SIZE],
+    /// Start of the nonce's encoding
+    nonce_offset: usize,
+    /// Length of the encoded header with the last nonce written
+    len: usize,
+    /// `prev_hash` then `merkle_root`, as they follow the nonce
+    hashes: [u8; 2 * HASH_SIZE],
+    target: [u8; HASH_SIZE],
+}
+
+impl PowScratch {
+    pub fn new(header: &Header) -> Self {
+        let mut buf = [0u8; MAX_HEADER_SIZE];
+        let len = encode_into_slice(header, &mut buf, standard()).expect("Header fits its largest encoding");
+        let prefix = (header.version, header.time, header.difficulty_compact);
+        let nonce_offset = encode_into_slice(prefix, &mut [0u8; MAX_HEADER_SIZE], standard())
+            .expect("Header fields fit their largest encoding");
+
+        let mut hashes = [0u8; 2 * HASH_SIZE];
+        hashes.copy_from_slice(&buf[len - 2 * HASH_SIZE..len]);
+
+        Self { buf, nonce_offset, len, hashes, target: header.compact_to_target() }
+    }
+
+    /// Double SHA-256 of the header with `nonce`
+    pub fn hash(&mut self, nonce: u64) -> [u8; HASH_SIZE] {
+        let nonce_len = encode_into_slice(nonce, &mut self.buf[self.nonce_offset..], standard())
+            .expect("Nonce fits its largest encoding");
+        let hashes_offset = self.nonce_offset + nonce_len;
+        if hashes_offset + 2 * HASH_SIZE != self.len {
+            self.len = hashes_offset + 2 * HASH_SIZE;
+            self.buf[hashes_offset..self.len].copy_from_slice(&self.hashes);
+        }
+
+        let first = Sha256::digest(&self.buf[..self.len]);
+        Sha256::digest(first).into()
+    }
+
+    /// Target the header's difficulty sets
+    pub fn target(&self) -> &[u8; HASH_SIZE] {
+        &self.target
+    }
+
+    /// Whether the header with `nonce` meets its target. Big-endian hashes
+    /// and targets compare like the numbers they encode.
+    pub fn meets_target(&mut self, nonce: u64) -> bool {
+        self.hash(nonce) <= self.target
+    }
+}
+
+/// When the next difficulty retarget happens, and what it would set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetargetProjection {
+    /// Height of the next block mined at a retargeted difficulty
+    pub next_retarget_height: u64,
+    /// Blocks still to be mined before that one
+    pub blocks_until_retarget: u64,
+    /// Bits the retarget would set if the rest of its window comes at the
+    /// pace of the blocks so far; the current bits until the window has
+    /// two blocks to take a pace from, and always on chains that don't
+    /// retarget
+    pub estimated_next_difficulty: u32,
+}
+
+/// Observed block spacing over the last blocks of a chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainTiming {
+    /// Blocks the window covers, fewer than asked for on a short chain
+    pub blocks: u64,
+    /// Seconds between each block of the window and its parent, oldest
+    /// first. Timestamps needn't increase, so intervals can be negative.
+    pub intervals: Vec<i64>,
+    pub mean: f64,
+    pub median: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    pub min: i64,
+    pub max: i64,
+    /// Retarget windows ending inside the window, oldest first
+    pub epochs: Vec<EpochTiming>,
+}
+
+/// The time a retarget measured over its window against the time it
+/// expected, as `adjust_difficulty` compares them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochTiming {
+    pub start_height: u64,
+    pub end_height: u64,
+    /// Height of the first block mined at the difficulty this window set
+    pub retarget_height: u64,
+    pub actual_time: u32,
+    pub expected_time: u32,
+}
+
+/// Hashes expected to have produced a block or a run of blocks, as a
+/// 256-bit big-endian number. Sums saturate rather than wrap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChainWork([u8; HASH_SIZE]);
+
+impl ChainWork {
+    /// Work of one block at `difficulty_compact`, 2^256 / (target + 1)
+    pub fn of_block(difficulty_compact: u32) -> Self {
+        let target = BigUint::from_bytes_be(&compact_to_target(difficulty_compact));
+        Self::from_biguint((BigUint::from(1u8) << 256u32) / (target + 1u8))
+    }
+
+    pub fn to_bytes(&self) -> [u8; HASH_SIZE] {
+        self.0
+    }
+
+    /// The work as a float, for rates and estimates
+    pub fn to_f64(&self) -> f64 {
+        self.0.iter().fold(0.0, |acc, &byte| acc * 256.0 + byte as f64)
+    }
+
+    fn from_biguint(work: BigUint) -> Self {
+        let bytes = work.to_bytes_be();
+        if bytes.len() > HASH_SIZE {
+            return Self([0xff; HASH_SIZE]);
+        }
+
+        let mut out = [0u8; HASH_SIZE];
+        out[HASH_SIZE - bytes.len()..].copy_from_slice(&bytes);
+        Self(out)
+    }
+}
+
+impl Add for ChainWork {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::from_biguint(BigUint::from_bytes_be(&self.0) + BigUint::from_bytes_be(&other.0))
+    }
+}
+
+/// Lowercase hex of all 32 bytes
+impl fmt::Display for ChainWork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex32(&self.0))
+    }
+}
+
+/// Total work of every block in `chain`, genesis included
+pub fn chain_work(chain: &Blockchain) -> ChainWork {
+    chain.iter().fold(ChainWork::default(), |work, block| {
+        work + ChainWork::of_block(block.header.difficulty_compact)
+    })
+}
+
+/// Bits the next block on `chain` should carry. Chains that don't
+/// retarget keep the tip's bits, which is what their templates hand out.
 pub fn adjust_difficulty(chain: &Blockchain) -> u32 {
-    let len = chain.len();
-    if len < ADJUSTMENT_INTERVAL || len % ADJUSTMENT_INTERVAL != 0 {
+    if chain.params().no_retarget {
         return chain.latest_block().header.difficulty_compact;
     }
+    difficulty_at(chain, chain.len())
+}
+
+/// Bits the block at `height` must carry, from the blocks below it. At most
+/// the chain's length, the height of the next block; the genesis block has
+/// no parent to take bits from.
+pub fn difficulty_at(chain: &Blockchain, height: usize) -> u32 {
+    assert!((1..=chain.len()).contains(&height), "no difficulty rule for height {}", height);
+    if height < ADJUSTMENT_INTERVAL || !height.is_multiple_of(ADJUSTMENT_INTERVAL) {
+        return chain.get_block_by_height(height - 1).unwrap().header.difficulty_compact;
+    }
+
+    retarget(chain, height - ADJUSTMENT_INTERVAL, height - 1)
+}
+
+/// Project the next retarget from the blocks of its window mined so far
+pub fn project_retarget(chain: &Blockchain) -> RetargetProjection {
+    let len = chain.len();
+    let next_retarget_height = len.next_multiple_of(ADJUSTMENT_INTERVAL).max(ADJUSTMENT_INTERVAL);
+    let window_start = next_retarget_height - ADJUSTMENT_INTERVAL;
+    let estimated_next_difficulty = if len > window_start + 1 && !chain.params().no_retarget {
+        retarget(chain, window_start, len - 1)
+    } else {
+        chain.latest_block().header.difficulty_compact
+    };
+
+    RetargetProjection {
+        next_retarget_height: next_retarget_height as u64,
+        blocks_until_retarget: (next_retarget_height - len) as u64,
+        estimated_next_difficulty,
+    }
+}
 
-    let first_block = chain.get_block_by_height(len - ADJUSTMENT_INTERVAL).unwrap();
-    let last_block = chain.latest_block();
+/// Bits retargeted over the window from the block at `window_start` to
+/// the one at `window_end`. A window cut short by the tip has its time
+/// scaled up to the `ADJUSTMENT_INTERVAL - 1` block intervals of a full one.
+fn retarget(chain: &Blockchain, window_start: usize, window_end: usize) -> u32 {
+    let first_block = chain.get_block_by_height(window_start).unwrap();
+    let last_block = chain.get_block_by_height(window_end).unwrap();
 
+    let intervals = (window_end - window_start) as u64;
+    let full_intervals = ADJUSTMENT_INTERVAL as u64 - 1;
     let actual_time = last_block.header.time.saturating_sub(first_block.header.time);
-    let expected_time = TARGET_BLOCK_TIME * ADJUSTMENT_INTERVAL as u32;
+    let expected_time = EPOCH_EXPECTED_TIME;
 
     let mut target = BigUint::from_bytes_be(&last_block.header.compact_to_target());
-    target *= BigUint::from(actual_time.max(1));
-    target /= BigUint::from(expected_time.max(1));
+    target *= BigUint::from(actual_time.max(1)) * full_intervals;
+    target /= BigUint::from(expected_time.max(1)) * intervals;
 
     if target.bits() > 256 {
         target = BigUint::from_bytes_be(&[0xFF; HASH_SIZE]);
@@ -46,6 +306,59 @@ pub fn adjust_difficulty(chain: &Blockchain) -> u32 {
     target_to_compact(target)
 }
 
+/// Spacing of the last `window` blocks of the chain whose headers
+/// `headers` yields from genesis up. The genesis block has no parent, so it
+/// adds no interval.
+pub fn chain_timing<'a>(headers: impl IntoIterator<Item = &'a Header>, window: usize) -> ChainTiming {
+    let times: Vec<u32> = headers.into_iter().map(|header| header.time).collect();
+    let first = times.len().saturating_sub(window);
+
+    let intervals: Vec<i64> = (first.max(1)..times.len())
+        .map(|height| times[height] as i64 - times[height - 1] as i64)
+        .collect();
+
+    let mut sorted = intervals.clone();
+    sorted.sort_unstable();
+    let (mean, median, stddev) = if sorted.is_empty() {
+        (0.0, 0.0, 0.0)
+    } else {
+        let count = sorted.len() as f64;
+        let mean = sorted.iter().sum::<i64>() as f64 / count;
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) as f64 / 2.0
+        } else {
+            sorted[middle] as f64
+        };
+        let variance = sorted.iter().map(|&interval| (interval as f64 - mean).powi(2)).sum::<f64>() / count;
+        (mean, median, variance.sqrt())
+    };
+
+    let epochs = (0..times.len())
+        .step_by(ADJUSTMENT_INTERVAL)
+        .map(|start| (start, start + ADJUSTMENT_INTERVAL - 1))
+        .filter(|&(_, end)| end >= first && end < times.len())
+        .map(|(start, end)| EpochTiming {
+            start_height: start as u64,
+            end_height: end as u64,
+            retarget_height: (end + 1) as u64,
+            actual_time: times[end].saturating_sub(times[start]),
+            expected_time: EPOCH_EXPECTED_TIME,
+        })
+        .collect();
+
+    ChainTiming {
+        blocks: (times.len() - first) as u64,
+        min: sorted.first().copied().unwrap_or(0),
+        max: sorted.last().copied().unwrap_or(0),
+        intervals,
+        mean,
+        median,
+        stddev,
+        epochs,
+    }
+}
+
 #[cfg(test)]
 pub fn fake_validate_pow(hash: [u8; HASH_SIZE], difficulty_compact: u32) -> bool {
     let h = BigUint::from_bytes_be(&hash);
@@ -63,29 +376,33 @@ pub fn fake_validate_pow(hash: [u8; HASH_SIZE], difficulty_compact: u32) -> bool
 //         header.nonce = nonce;
 //     }
 // }
-
 pub fn mine_block(header: &mut Header) -> Header {
+    let mut scratch = PowScratch::new(header);
     let mut nonce: u64 = 0;
-    loop {
-        header.nonce = nonce;
-        if validate_pow(&header) {
-            return header.clone();
-        }
+    while !scratch.meets_target(nonce) {
         nonce = nonce.wrapping_add(1);  // wrap around if overflow
     }
+    header.nonce = nonce;
+    header.clone()
 }
 
-/// Build and mine the genesis block
+/// Build and mine the genesis block every built-in network shares
 pub fn create_genesis_block() -> Block {
-    let tx = Transaction::new(vec![b"genesis".to_vec()], vec![b"genesis_out".to_vec()])
+    create_genesis_block_with(&GenesisParams::default())
+}
+
+/// Build and mine a genesis block from `params`, which must give the
+/// transaction a message and at least one output
+pub fn create_genesis_block_with(params: &GenesisParams) -> Block {
+    let tx = Transaction::new(vec![params.message.clone()], params.outputs.clone())
         .expect("Failed to build genesis tx");
 
-    let merkle_root = compute_merkle_root(&[tx.clone()]);
+    let merkle_root = compute_merkle_root(std::slice::from_ref(&tx));
 
     let mut header = Header::new(
         1,             // version
-        0,             // timestamp
-        0x207fffff,    // easy difficulty
+        params.time,
+        params.difficulty_compact,
         0,             // nonce will be mined
         [0u8; HASH_SIZE], // prev hash = 0
         merkle_root,
@@ -97,7 +414,7 @@ pub fn create_genesis_block() -> Block {
 
 /// Convert compact difficulty to 256-bit target
 pub fn compact_to_target(difficulty_compact: u32) -> [u8; HASH_SIZE] {
-    let exponent = (difficulty_compact >> 24) as u32;
+    let exponent = difficulty_compact >> 24;
     let mantissa = difficulty_compact & MANTISSA_MASK; // Bitcoin caps highest bit
 
     let mut target = BigUint::from(mantissa);
@@ -145,6 +462,7 @@ pub fn target_to_compact(target: BigUint) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*; 
+    use crate::testutil::ChainBuilder;
 
     #[test]
     fn test_pow_check_fake() {
@@ -154,6 +472,105 @@ mod tests {
         assert!(fake_validate_pow(fake_hash, difficulty));
     }
 
+    /// Chain of blocks mined at `times`, after a genesis at 0
+    fn chain_at(times: &[u32]) -> Blockchain {
+        let mut builder = ChainBuilder::new(0);
+        for &time in times {
+            builder.mine_at(time, 0);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_chain_work_sums_block_work() {
+        // Bitcoin's genesis difficulty
+        assert_eq!(ChainWork::of_block(0x1d00ffff).to_string(), format!("{:0>64}", "100010001"));
+
+        // The easiest target is about half of all hashes, so two expected per block
+        let chain = chain_at(&[600, 1200]);
+        assert_eq!(ChainWork::of_block(POW_LIMIT_BITS).to_string(), format!("{:0>64}", "2"));
+        assert_eq!(chain_work(&chain).to_string(), format!("{:0>64}", "6"));
+
+        let max = ChainWork([0xff; HASH_SIZE]);
+        assert_eq!(max + ChainWork::of_block(POW_LIMIT_BITS), max);
+
+        assert_eq!(ChainWork::of_block(0x1d00ffff).to_f64(), 4_295_032_833.0);
+        assert_eq!(ChainWork::of_block(POW_LIMIT_BITS).to_f64(), 2.0);
+        // A zero target can't be met, but still gives a finite number
+        assert_eq!(ChainWork::of_block(0x01000000).to_f64(), 2f64.powi(256));
+    }
+
+    #[test]
+    fn test_retarget_saturates_at_all_ones() {
+        // Genesis is stamped 0, so the first window looks decades long and
+        // the target tops out at all ones, whose bits existing chains carry
+        let chain = chain_at(&[1_700_000_000, 1_700_000_000]);
+        assert_eq!(adjust_difficulty(&chain), 0x2100ffff);
+    }
+
+    #[test]
+    fn test_retarget_projected_mid_window() {
+        // The window for height 6 holds the blocks at 3, 4 and 5, here
+        // half a target interval apart
+        let chain = chain_at(&[600, 1200, 1800, 2100]);
+        let projection = project_retarget(&chain);
+        assert_eq!((projection.next_retarget_height, projection.blocks_until_retarget), (6, 1));
+
+        // The same pace to the end of the window retargets to the projection
+        let finished = chain_at(&[600, 1200, 1800, 2100, 2400]);
+        assert_eq!(projection.estimated_next_difficulty, adjust_difficulty(&finished));
+        let target = |bits| BigUint::from_bytes_be(&compact_to_target(bits));
+        assert!(target(projection.estimated_next_difficulty) < target(0x207f_ffff));
+
+        // With the window complete the projection is the retarget itself
+        let projection = project_retarget(&finished);
+        assert_eq!((projection.next_retarget_height, projection.blocks_until_retarget), (6, 0));
+        assert_eq!(projection.estimated_next_difficulty, adjust_difficulty(&finished));
+
+        // A single block of the window gives no pace yet
+        let started = chain_at(&[600, 1200, 1800]);
+        let projection = project_retarget(&started);
+        assert_eq!((projection.next_retarget_height, projection.blocks_until_retarget), (6, 2));
+        assert_eq!(projection.estimated_next_difficulty, started.latest_block().header.difficulty_compact);
+    }
+
+    #[test]
+    fn test_no_retarget_keeps_tip_bits() {
+        // Blocks a second apart would make a retargeting chain harder
+        let mut chain = chain_at(&[1, 2, 3, 4]);
+        assert_ne!(adjust_difficulty(&chain), POW_LIMIT_BITS);
+
+        chain.set_params(ConsensusParams { no_retarget: true, ..Default::default() });
+        assert_eq!(adjust_difficulty(&chain), chain.latest_block().header.difficulty_compact);
+        assert_eq!(project_retarget(&chain).estimated_next_difficulty, chain.latest_block().header.difficulty_compact);
+    }
+
+    #[test]
+    fn test_chain_timing_over_window() {
+        let chain = chain_at(&[600, 1000, 1800, 2200, 2100, 3000, 3600]);
+        let headers = || chain.iter().map(|block| &block.header);
+
+        // The last four blocks are 400, -100, 900 and 600 seconds after their parents
+        let timing = chain_timing(headers(), 4);
+        assert_eq!(timing.blocks, 4);
+        assert_eq!(timing.intervals, [400, -100, 900, 600]);
+        assert_eq!((timing.mean, timing.median, timing.min, timing.max), (450.0, 500.0, -100, 900));
+        assert!((timing.stddev - 364.0055).abs() < 1e-3, "stddev {}", timing.stddev);
+
+        // Of the retarget windows only heights 3 to 5 end among heights 4 to 7
+        let epoch = EpochTiming { start_height: 3, end_height: 5, retarget_height: 6, actual_time: 300, expected_time: 1800 };
+        assert_eq!(timing.epochs, [epoch]);
+
+        // A window past genesis covers the whole chain, genesis adding no interval
+        let whole = chain_timing(headers(), 100);
+        assert_eq!((whole.blocks, whole.intervals.len(), whole.epochs.len()), (8, 7, 2));
+        assert_eq!(whole.epochs[0].actual_time, 1000);
+
+        let empty = chain_timing(headers(), 0);
+        assert_eq!((empty.blocks, empty.intervals.len(), empty.mean, empty.max), (0, 0, 0.0, 0));
+        assert!(empty.epochs.is_empty());
+    }
+
     #[test]
     fn test_pow_failure() {
         let h = Header::new(1, 0, 0x207fffff, 0, [0u8; HASH_SIZE], [0u8; HASH_SIZE]);
@@ -171,4 +588,68 @@ mod tests {
         // Big hash should be greater than target
         assert!(fake_hash_num > target_num);
     }
+
+    /// The nonce `mine_block` finds by reserializing the header each time
+    fn mine_slow(header: &Header) -> u64 {
+        let mut header = header.clone();
+        header.nonce = 0;
+        while !validate_pow(&header) {
+            header.nonce += 1;
+        }
+        header.nonce
+    }
+
+    #[test]
+    fn test_scratch_hash_matches_header_hash() {
+        let header = Header::new(7, 1_700_000_000, 0x207fffff, 0, [3u8; HASH_SIZE], [9u8; HASH_SIZE]);
+        let mut scratch = PowScratch::new(&header);
+
+        // Either side of each varint width, in an order that grows and shrinks the encoding
+        let nonces = [0, 250, 251, u16::MAX as u64, 65_536, u32::MAX as u64, 1 << 32, u64::MAX, 42, 1 << 40, 300];
+        for nonce in nonces {
+            let expected = Header { nonce, ..header.clone() }.double_sha256();
+            assert_eq!(scratch.hash(nonce), expected, "nonce {}", nonce);
+        }
+        assert_eq!(scratch.target(), &header.compact_to_target());
+    }
+
+    #[test]
+    fn test_mine_block_matches_slow_path() {
+        // Around 1 in 256 hashes meets this target
+        let difficulty = 0x2000ffff;
+        for time in 0..8 {
+            let mut header = Header::new(1, time, difficulty, 0, [0u8; HASH_SIZE], [time as u8; HASH_SIZE]);
+            let expected = mine_slow(&header);
+
+            let mined = mine_block(&mut header);
+            assert_eq!(mined.nonce, expected);
+            assert!(mined.validate_pow().is_ok());
+        }
+    }
+
+    /// `cargo test -p hyperion-core --release -- --ignored --nocapture bench_pow_scratch`
+    #[test]
+    #[ignore]
+    fn bench_pow_scratch() {
+        const HASHES: u64 = 1_000_000;
+        let header = Header::new(1, 0, 0x0300_0001, 0, [0u8; HASH_SIZE], [0u8; HASH_SIZE]);
+
+        let started = std::time::Instant::now();
+        let mut slow = header.clone();
+        for nonce in 0..HASHES {
+            slow.nonce = nonce;
+            std::hint::black_box(validate_pow(&slow));
+        }
+        let slow_rate = HASHES as f64 / started.elapsed().as_secs_f64();
+
+        let started = std::time::Instant::now();
+        let mut scratch = PowScratch::new(&header);
+        for nonce in 0..HASHES {
+            std::hint::black_box(scratch.meets_target(nonce));
+        }
+        let fast_rate = HASHES as f64 / started.elapsed().as_secs_f64();
+
+        println!("validate_pow: {:.0} H/s, PowScratch: {:.0} H/s ({:.1}x)", slow_rate, fast_rate, fast_rate / slow_rate);
+        assert!(fast_rate > slow_rate);
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use crate::block::Serializable;
+use crate::error::hex::HexError;
 use sha2::{Digest, Sha256};
 
 
@@ -17,8 +18,64 @@ pub trait Hashable: Serializable {
 /// Utility function for double SHA-256
 pub fn double_sha256(data: &[u8]) -> [u8; HASH_SIZE] {
     let first = Sha256::digest(data);
-    let second = Sha256::digest(&first);
+    let second = Sha256::digest(first);
     let mut out = [0u8; HASH_SIZE];
     out.copy_from_slice(&second);
     out
-}
\ No newline at end of file
+}
+
+/// `hash` as lowercase hex, the way hashes are shown over RPC and in logs
+pub fn hex32(hash: &[u8; HASH_SIZE]) -> String {
+    hex::encode(hash)
+}
+
+/// A hash from its hex, as `hex32` writes it
+pub fn parse_hex32(s: &str) -> Result<[u8; HASH_SIZE], HexError> {
+    parse_hex_array(s)
+}
+
+/// `bytes` as lowercase hex
+pub fn to_hex(bytes: impl AsRef<[u8]>) -> String {
+    hex::encode(bytes)
+}
+
+/// Bytes of any length from their hex
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, HexError> {
+    hex::decode(s).map_err(|e| match e {
+        hex::FromHexError::InvalidHexCharacter { c, index } => HexError::InvalidCharacter { character: c, index },
+        hex::FromHexError::OddLength | hex::FromHexError::InvalidStringLength => HexError::OddLength(s.len()),
+    })
+}
+
+/// Exactly `N` bytes from their hex
+pub fn parse_hex_array<const N: usize>(s: &str) -> Result<[u8; N], HexError> {
+    let bytes = parse_hex(s)?;
+    let got = bytes.len();
+    bytes.try_into().map_err(|_| HexError::WrongLength { expected: N, got })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex32_round_trips() {
+        let hash = double_sha256(b"hyperion");
+        assert_eq!(parse_hex32(&hex32(&hash)), Ok(hash));
+        assert_eq!(parse_hex32(&hex32(&hash).to_uppercase()), Ok(hash));
+        assert_eq!(parse_hex(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_errors_state_what_was_expected() {
+        let cases = [
+            ("00ff", "expected 32 bytes (64 hex digits), got 2"),
+            ("0g", "invalid hex character 'g' at position 1"),
+            ("abc", "odd number of hex digits (3)"),
+        ];
+        for (input, message) in cases {
+            assert_eq!(parse_hex32(input).unwrap_err().to_string(), message);
+        }
+        assert_eq!(parse_hex_array::<4>("00"), Err(HexError::WrongLength { expected: 4, got: 1 }));
+    }
+}
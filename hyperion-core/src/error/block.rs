@@ -2,6 +2,9 @@
 pub enum BlockError {
     InvalidMerkleRoot,
     EmptyTransactions,
+    InvalidPoW,
+    /// Serialized larger than `MAX_BLOCK_SIZE`
+    TooLarge,
 }
 
 impl std::fmt::Display for BlockError {
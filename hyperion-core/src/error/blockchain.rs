@@ -3,6 +3,10 @@ pub enum BlockchainError {
     InvalidPreviousHash,
     InvalidMerkleRoot,
     InvalidPoW,
+    /// The block's bits aren't those the chain's retargeting dictates
+    IncorrectDifficulty { expected: u32, got: u32 },
+    /// The block has no transactions and the chain's params don't allow that
+    EmptyBlock,
 }
 
 impl std::fmt::Display for BlockchainError {
@@ -0,0 +1,26 @@
+/// Why a string isn't the hex expected of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// A character that isn't a hex digit
+    InvalidCharacter { character: char, index: usize },
+    /// An odd number of digits, which can't make whole bytes
+    OddLength(usize),
+    /// Well-formed hex of the wrong number of bytes
+    WrongLength { expected: usize, got: usize },
+}
+
+impl std::fmt::Display for HexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HexError::InvalidCharacter { character, index } => {
+                write!(f, "invalid hex character {:?} at position {}", character, index)
+            }
+            HexError::OddLength(digits) => write!(f, "odd number of hex digits ({})", digits),
+            HexError::WrongLength { expected, got } => {
+                write!(f, "expected {} bytes ({} hex digits), got {}", expected, expected * 2, got)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
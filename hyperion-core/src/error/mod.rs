@@ -1,4 +1,5 @@
 pub mod transaction;
 pub mod blockchain;
 pub mod block;
-pub mod header;
\ No newline at end of file
+pub mod header;
+pub mod hex;
\ No newline at end of file
@@ -1,7 +1,10 @@
-mod error;
+pub mod error;
 pub mod consensus;
 pub mod crypto;
 mod utils;
 pub mod chain;
 pub mod block;
 pub mod miner;
+
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
@@ -0,0 +1,140 @@
+//! Deterministic fixtures for tests: seeded transactions, unmined blocks, and
+//! a `ChainBuilder` that mines real, PoW-valid chains at trivial difficulty.
+
+use crate::block::block::compute_merkle_root;
+use crate::block::{Block, Header, Transaction};
+use crate::chain::Blockchain;
+use crate::consensus::{adjust_difficulty, mine_block, ConsensusParams, POW_LIMIT_BITS, TARGET_BLOCK_TIME};
+use crate::crypto::HASH_SIZE;
+
+
+/// Transaction determined by `seed`, with one input and one output naming it
+pub fn seeded_tx(seed: u64) -> Transaction {
+    let seed = seed.to_le_bytes();
+    Transaction::new(vec![[b"in".as_slice(), &seed].concat()], vec![[b"out".as_slice(), &seed].concat()])
+        .expect("Seeded transactions have inputs and outputs")
+}
+
+/// Block on `prev_hash` with a matching merkle root but no proof of work,
+/// for chains built with `skip_pow`
+pub fn unmined_block(prev_hash: [u8; HASH_SIZE], txs: Vec<Transaction>) -> Block {
+    let header = Header::new(1, 123, POW_LIMIT_BITS, 0, prev_hash, compute_merkle_root(&txs));
+    Block::new(header, txs)
+}
+
+/// Builds a chain on the real genesis block by mining each block in turn.
+/// Builders given the same seed and calls produce the same blocks.
+pub struct ChainBuilder {
+    chain: Blockchain,
+    /// Next transaction's seed is drawn from this
+    state: u64,
+    /// Every block's difficulty, or the chain's retarget if unset
+    difficulty: Option<u32>,
+    /// Seconds between consecutive block times
+    interval: u32,
+}
+
+impl ChainBuilder {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            chain: Blockchain::new_with_genesis(),
+            state: seed,
+            difficulty: None,
+            interval: TARGET_BLOCK_TIME,
+        }
+    }
+
+    /// Mine every block at `bits` rather than the difficulty the chain asks for
+    pub fn with_difficulty(mut self, bits: u32) -> Self {
+        self.difficulty = Some(bits);
+        self.chain.set_params(ConsensusParams { no_retarget: true, ..*self.chain.params() });
+        self
+    }
+
+    /// Space block times `secs` apart, `TARGET_BLOCK_TIME` by default
+    pub fn with_interval(mut self, secs: u32) -> Self {
+        self.interval = secs;
+        self
+    }
+
+    /// The next seeded transaction
+    pub fn next_tx(&mut self) -> Transaction {
+        seeded_tx(self.next_seed())
+    }
+
+    /// Mine a block of `n_txs` fresh transactions onto the tip and add it
+    pub fn mine_next(&mut self, n_txs: usize) -> &Block {
+        let time = self.chain.latest_block().header.time + self.interval;
+        self.mine_at(time, n_txs)
+    }
+
+    /// Mine a block of `n_txs` fresh transactions stamped `time`, whatever
+    /// the interval, onto the tip and add it
+    pub fn mine_at(&mut self, time: u32, n_txs: usize) -> &Block {
+        let txs = (0..n_txs).map(|_| self.next_tx()).collect();
+        let difficulty = self.difficulty.unwrap_or_else(|| adjust_difficulty(&self.chain));
+
+        let mut block = self.chain.create_block_template(txs, difficulty, time);
+        mine_block(&mut block.header);
+        self.chain.add_block(block, false).expect("Mined block extends the tip");
+        self.chain.latest_block()
+    }
+
+    /// Mine `count` blocks of `n_txs` transactions each
+    pub fn mine_blocks(mut self, count: usize, n_txs: usize) -> Self {
+        for _ in 0..count {
+            self.mine_next(n_txs);
+        }
+        self
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    pub fn tip(&self) -> &Block {
+        self.chain.latest_block()
+    }
+
+    pub fn build(self) -> Blockchain {
+        self.chain
+    }
+
+    /// splitmix64, so seeds spread without pulling in an RNG
+    fn next_seed(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Hashable;
+
+    #[test]
+    fn test_same_seed_builds_same_chain() {
+        let hashes = |seed| {
+            let chain = ChainBuilder::new(seed).mine_blocks(5, 2).build();
+            chain.iter().map(|block| block.double_sha256()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(hashes(7), hashes(7));
+        assert_ne!(hashes(7), hashes(8));
+        assert_eq!(seeded_tx(3).double_sha256(), seeded_tx(3).double_sha256());
+    }
+
+    #[test]
+    fn test_built_chain_is_pow_valid() {
+        let mut builder = ChainBuilder::new(1).with_difficulty(0x2000_ffff).with_interval(60);
+        let block = builder.mine_next(3).clone();
+        assert_eq!((block.transactions.len(), block.header.difficulty_compact, block.header.time), (3, 0x2000_ffff, 60));
+
+        let chain = builder.mine_blocks(3, 1).build();
+        assert_eq!(chain.len(), 5);
+        assert!(chain.validate());
+    }
+}
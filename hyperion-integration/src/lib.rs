@@ -0,0 +1,75 @@
+//! Harness for tests that run a node and a miner together in-process: a
+//! regtest node on ephemeral ports with its data in a temp dir, a miner
+//! config pointed at it, and a client for the node's RPC.
+
+use anyhow::{anyhow, Context, Result};
+use hyperion_miner::config::MiningConfig;
+use hyperion_node::config::NodeConfig;
+use hyperion_node::config::node_config::Network;
+use hyperion_node::{Node, NodeHandle, RunOptions};
+use hyperion_rpc::RpcResponse;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Fresh data directory under the temp dir, unique to `name` and this process
+pub fn temp_datadir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hyperion-integration-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Regtest node keeping its data in `datadir`, with RPC and P2P on
+/// ephemeral localhost ports and no log file
+pub fn regtest_config(datadir: &Path) -> NodeConfig {
+    let mut config = NodeConfig { chain: Network::Regtest, ..Default::default() };
+    config.storage.datadir = datadir.to_path_buf();
+    config.network.rpc_port = 0;
+    config.network.p2p_listen = vec![SocketAddr::from(([127, 0, 0, 1], 0))];
+    config.logging.file_enabled = false;
+    config.logging.summary = false;
+    config
+}
+
+/// Start a regtest node in `datadir`, with `fill_mempool` generated
+/// transactions in its mempool
+pub async fn start_node(datadir: &Path, fill_mempool: Option<usize>) -> Result<NodeHandle> {
+    Node::new(regtest_config(datadir))
+        .with_options(RunOptions { fill_mempool, ..Default::default() })
+        .start()
+        .await
+}
+
+/// Solo miner with `threads` workers, mining against `node` until it has
+/// `max_blocks` accepted and logging its solutions to `solution_log_dir`
+pub fn miner_config(node: &NodeHandle, threads: usize, max_blocks: u64, solution_log_dir: &Path) -> MiningConfig {
+    MiningConfig {
+        node_url: format!("http://{}", node.rpc_addr()),
+        threads,
+        max_blocks: Some(max_blocks),
+        tip_poll_interval: 50,
+        solution_log_dir: Some(solution_log_dir.to_path_buf()),
+        ..Default::default()
+    }
+}
+
+/// Call `method` on the node at `addr`, failing on an RPC error
+pub async fn call<T: DeserializeOwned>(addr: SocketAddr, method: &str, params: Value) -> Result<T> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let response: RpcResponse<T> = reqwest::Client::new()
+        .post(format!("http://{}/rpc", addr))
+        .json(&request)
+        .send()
+        .await
+        .with_context(|| format!("Failed to call {}", method))?
+        .json()
+        .await
+        .with_context(|| format!("Bad response to {}", method))?;
+
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(anyhow!("{} failed: {} ({})", method, error.message, error.code)),
+        (Some(result), None) => Ok(result),
+        (None, None) => Err(anyhow!("{} returned neither a result nor an error", method)),
+    }
+}
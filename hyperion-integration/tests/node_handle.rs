@@ -0,0 +1,104 @@
+use hyperion_integration::{call, regtest_config, start_node, temp_datadir};
+use hyperion_node::Node;
+use hyperion_node::config::node_config::Network;
+use hyperion_rpc::{ChainInfo, MempoolInfo};
+use serde_json::Value;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_node_runs_and_stops_through_its_handle() {
+    let datadir = temp_datadir("handle");
+    let node = start_node(&datadir, Some(5)).await.unwrap();
+    assert_ne!(node.rpc_addr().port(), 0);
+    assert!(node.p2p_addrs().iter().all(|addr| addr.port() != 0));
+
+    // What RPC reports matches what the handle sees
+    let info: ChainInfo = call(node.rpc_addr(), "get_blockchain_info", Value::Null).await.unwrap();
+    let tip = node.tip();
+    assert_eq!((tip.height, info.best_blockhash), (0, hex::encode(tip.hash)));
+    let mempool: MempoolInfo = call(node.rpc_addr(), "get_mempool_info", Value::Null).await.unwrap();
+    assert_eq!(mempool.size as usize, node.mempool_size().await);
+
+    // A shutdown triggered elsewhere ends `wait`, which saves the node's data
+    let rpc_addr = node.rpc_addr();
+    let shutdown = node.shutdown_handle();
+    tokio::spawn(async move { shutdown.trigger() });
+    tokio::time::timeout(Duration::from_secs(10), node.wait()).await.expect("node didn't stop");
+    assert!(call::<u64>(rpc_addr, "get_block_count", Value::Null).await.is_err());
+
+    let node = start_node(&datadir, None).await.unwrap();
+    assert_eq!(node.mempool_size().await, 5);
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test]
+async fn test_prune_refuses_to_start() {
+    let datadir = temp_datadir("prune");
+    let mut config = regtest_config(&datadir);
+    config.storage.prune = Some(550);
+    let err = Node::new(config.clone()).start().await.err().expect("node started with --prune");
+    assert!(err.to_string().contains("Pruning is not supported"), "{}", err);
+
+    config.storage.txindex = true;
+    let err = Node::new(config).start().await.err().expect("node started with --prune and --txindex");
+    assert!(err.to_string().contains("incompatible"), "{}", err);
+    assert!(!datadir.exists());
+}
+
+#[tokio::test]
+async fn test_unreadable_chain_file_stops_startup() {
+    let datadir = temp_datadir("unreadable-chain");
+    let config = regtest_config(&datadir);
+    let chain_file = config.chain_dir().join("blockchain.dat");
+    std::fs::create_dir_all(config.chain_dir()).unwrap();
+
+    // A corrupt file, and one from a newer node, are both left as they are
+    let newer = [b"\xffHYC".as_slice(), &u32::MAX.to_le_bytes(), b"blocks"].concat();
+    for contents in [b"\xffHYC\x01\x00\x00\x00not a chain".to_vec(), newer] {
+        std::fs::write(&chain_file, &contents).unwrap();
+        let err = Node::new(config.clone()).start().await.err().expect("node started over an unreadable chain");
+        assert!(err.to_string().contains("Failed to load chain"), "{}", err);
+        assert_eq!(std::fs::read(&chain_file).unwrap(), contents);
+    }
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test]
+async fn test_interrupted_chain_write_keeps_old_chain() {
+    let datadir = temp_datadir("interrupted-write");
+    let mut config = regtest_config(&datadir);
+    config.network.p2p_enabled = false;
+    let chain_dir = config.chain_dir();
+    std::fs::create_dir_all(&chain_dir).unwrap();
+    std::fs::write(chain_dir.join("blockchain.dat"), include_bytes!("../../hyperion-node/testdata/legacy-blockchain.dat")).unwrap();
+    Node::new(config.clone()).start().await.unwrap().stop().await;
+
+    // A crash partway through the next write leaves its temporary file
+    let saved = std::fs::read(chain_dir.join("blockchain.dat")).unwrap();
+    std::fs::write(chain_dir.join("blockchain.dat.tmp"), &saved[..saved.len() / 2]).unwrap();
+
+    let node = Node::new(config).start().await.unwrap();
+    assert_eq!(node.tip().height, 11);
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test]
+async fn test_legacy_mainnet_chain_migrates_at_startup() {
+    let datadir = temp_datadir("legacy-mainnet");
+    let mut config = regtest_config(&datadir);
+    config.chain = Network::Mainnet;
+    config.network.p2p_enabled = false;
+    let chain_dir = config.chain_dir();
+    std::fs::create_dir_all(&chain_dir).unwrap();
+    // Saved by a node from before the versioned format, past three retargets
+    std::fs::write(chain_dir.join("blockchain.dat"), include_bytes!("../../hyperion-node/testdata/legacy-blockchain.dat")).unwrap();
+
+    let node = Node::new(config).start().await.unwrap();
+    let tip = node.tip();
+    assert_eq!((tip.height, hex::encode(tip.hash)), (11, "468b433d8f24314b3db4e9d5ffa79c06e0c12b5ac92293d6b41562613a7ad277".to_string()));
+    assert!(chain_dir.join("blockchain.dat.bak").exists());
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
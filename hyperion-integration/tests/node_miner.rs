@@ -0,0 +1,165 @@
+use hyperion_core::block::{Block, Header, Serializable};
+use hyperion_core::consensus::mine_block;
+use hyperion_core::crypto::Hashable;
+use hyperion_integration::{call, miner_config, regtest_config, start_node, temp_datadir};
+use hyperion_node::{Node, NodeHandle, RunOptions};
+use hyperion_miner::{exit, Miner};
+use hyperion_miner::mining::audit::{SolutionOutcome, SolutionRecord, SOLUTION_LOG_FILE};
+use hyperion_miner::network::node_client::{NodeClient, SubmitOutcome};
+use hyperion_miner::utils::MiningStats;
+use hyperion_rpc::{ChainInfo, MempoolInfo, RawBlock, RawTransaction};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+
+/// Run a miner against `node` until it has `max_blocks` accepted, returning
+/// the accepted solutions from its log
+async fn mine(node: &NodeHandle, max_blocks: u64, log_dir: &Path) -> Vec<SolutionRecord> {
+    let config = miner_config(node, 2, max_blocks, log_dir);
+    let mining = Miner::new(config).run(std::future::pending());
+    let (stats, code) = tokio::time::timeout(Duration::from_secs(60), mining)
+        .await
+        .expect("miner didn't reach its block target")
+        .unwrap();
+    assert_eq!(code, exit::SUCCESS);
+    assert_eq!(stats.shutdown_summary(code).blocks_accepted, max_blocks);
+
+    let log = std::fs::read_to_string(log_dir.join(SOLUTION_LOG_FILE)).unwrap();
+    log.lines()
+        .map(|line| serde_json::from_str::<SolutionRecord>(line).unwrap())
+        .filter(|record| record.outcome == SolutionOutcome::Accepted)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_mined_blocks_reach_the_node() {
+    let datadir = temp_datadir("mined");
+    let node = start_node(&datadir, Some(250)).await.unwrap();
+    let rpc = node.rpc_addr();
+    let mempool: MempoolInfo = call(rpc, "get_mempool_info", Value::Null).await.unwrap();
+    assert_eq!(mempool.size, 250);
+
+    // Templates carry at most 100 transactions, so this takes three blocks
+    let accepted = mine(&node, 4, &datadir.join("miner")).await;
+    assert_eq!(accepted.len(), 4);
+    assert_eq!(call::<u64>(rpc, "get_block_count", Value::Null).await.unwrap(), 4);
+
+    // Every accepted block is on the chain as the miner submitted it
+    let mut confirmed = 0;
+    for record in &accepted {
+        let block: RawBlock = call(rpc, "get_block", json!({ "hash": record.block_hash })).await.unwrap();
+        assert_eq!(block.hex, record.block_hex);
+        assert_eq!(Some(block.height), record.height);
+        confirmed += Block::from_bytes(&hex::decode(&block.hex).unwrap()).unwrap().transactions.len();
+    }
+
+    // And between them they took every pooled transaction
+    assert_eq!(confirmed, 250);
+    let mempool: MempoolInfo = call(rpc, "get_mempool_info", Value::Null).await.unwrap();
+    assert_eq!(mempool.size, 0);
+
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_restart_reloads_the_tip() {
+    let datadir = temp_datadir("restart");
+    let node = start_node(&datadir, None).await.unwrap();
+    mine(&node, 2, &datadir.join("miner")).await;
+    let before: ChainInfo = call(node.rpc_addr(), "get_blockchain_info", Value::Null).await.unwrap();
+    node.stop().await;
+
+    let node = start_node(&datadir, None).await.unwrap();
+    let after: ChainInfo = call(node.rpc_addr(), "get_blockchain_info", Value::Null).await.unwrap();
+    assert_eq!(after.best_blockhash, before.best_blockhash);
+    assert_eq!(call::<u64>(node.rpc_addr(), "get_block_count", Value::Null).await.unwrap(), 2);
+
+    // Mining carries on from the reloaded tip
+    mine(&node, 1, &datadir.join("miner-restarted")).await;
+    assert_eq!(call::<u64>(node.rpc_addr(), "get_block_count", Value::Null).await.unwrap(), 3);
+
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_node_decodes_miner_blocks_byte_for_byte() {
+    let datadir = temp_datadir("serialization");
+    let node = start_node(&datadir, Some(20)).await.unwrap();
+    let stats = MiningStats::new();
+    let client = NodeClient::new(&miner_config(&node, 1, 1, &datadir.join("miner"))).unwrap().with_stats(stats.clone());
+
+    // Built from a template the way the miner's workers build blocks
+    let template = client.get_block_template(None, None).await.unwrap();
+    let mut header = Header::new(
+        template.version,
+        template.timestamp,
+        template.difficulty_compact,
+        0,
+        hex::decode(&template.previous_block_hash).unwrap().try_into().unwrap(),
+        hex::decode(&template.merkle_root).unwrap().try_into().unwrap(),
+    );
+    let block = Block::new(mine_block(&mut header), template.transactions.to_vec());
+    assert_eq!(block.transactions.len(), 20);
+
+    assert_eq!(client.submit_block(block.clone()).await.unwrap(), SubmitOutcome::Accepted);
+    assert_eq!(stats.hash_mismatches.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+    // The node stored exactly the bytes the miner sent
+    let hash = hex::encode(block.double_sha256());
+    let stored: RawBlock = call(node.rpc_addr(), "get_block", json!({ "hash": hash })).await.unwrap();
+    assert_eq!(stored.hex, hex::encode(block.serialize().unwrap()));
+    assert_eq!(stored.height, 1);
+
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reindex_repairs_corrupt_txindex() {
+    let datadir = temp_datadir("reindex");
+    let mut config = regtest_config(&datadir);
+    config.storage.txindex = true;
+    let chain_dir = config.chain_dir();
+
+    // A stored chain with transactions, and a mangled index next to it
+    let node = Node::new(config.clone())
+        .with_options(RunOptions { fill_mempool: Some(3), ..Default::default() })
+        .start()
+        .await
+        .unwrap();
+    let accepted = mine(&node, 1, &datadir.join("miner")).await;
+    node.stop().await;
+    std::fs::write(chain_dir.join("txindex.dat"), b"not an index").unwrap();
+
+    let err = Node::new(config.clone()).start().await.err().expect("node started with a corrupt txindex");
+    assert!(err.to_string().contains("--reindex"), "{}", err);
+
+    let node = Node::new(config)
+        .with_options(RunOptions { reindex: true, ..Default::default() })
+        .start()
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let info: ChainInfo = call(node.rpc_addr(), "get_blockchain_info", Value::Null).await.unwrap();
+            if info.verification_progress == 1.0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("reindex didn't finish");
+
+    let block = Block::from_bytes(&hex::decode(&accepted[0].block_hex).unwrap()).unwrap();
+    assert_eq!(block.transactions.len(), 3);
+    for tx in &block.transactions {
+        let txid = hex::encode(tx.double_sha256());
+        let found: RawTransaction = call(node.rpc_addr(), "get_raw_transaction", json!({ "txid": txid })).await.unwrap();
+        assert_eq!((found.height, found.block_hash.as_str()), (1, accepted[0].block_hash.as_str()));
+        assert_eq!(found.hex, hex::encode(tx.serialize().unwrap()));
+    }
+
+    node.stop().await;
+    std::fs::remove_dir_all(datadir).unwrap();
+}
@@ -1,24 +1,180 @@
-use anyhow::Result;
+use crate::mining::worker::{MAX_BATCH_SIZE, MIN_BATCH_SIZE};
+use crate::utils::hardware;
+
+use anyhow::{bail, Context, Result};
 use serde::{Serialize, Deserialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Most mining threads a config may ask for
+pub const MAX_THREADS: usize = 1024;
+
+/// Longest `batch_time`, in ms
+pub const MAX_BATCH_TIME: u64 = 10_000;
+
+/// Prefix of environment variables overriding config fields, e.g.
+/// `HYPERION_MINER_THREADS`. `__` separates nested fields, as in
+/// `HYPERION_MINER_POOL__ADDRESS`.
+pub const ENV_PREFIX: &str = "HYPERION_MINER_";
+
+/// Where the miner gets its work
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MiningMode {
+    /// Block templates from a node, blocks submitted back to it
+    #[default]
+    Solo,
+    /// Jobs from a pool, shares submitted back to it
+    Pool,
+}
+
+/// How each work's nonce space is divided between workers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NonceStrategy {
+    /// Contiguous ranges from nonce 0, the same for every work
+    Split,
+    /// Every `workers`th nonce from nonce 0, offset by the worker's index
+    Interleave,
+    /// Contiguous ranges from a base that moves with every work, so
+    /// refreshed work starts on nonces not yet tried
+    #[default]
+    RandomOffset,
+}
 
+/// What workers hash with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// The worker threads themselves
+    #[default]
+    Cpu,
+    /// A program per worker, run as `backend_command`
+    External,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MiningConfig {
+    pub mode: MiningMode,
     pub node_url: String,
     pub threads: usize,
+    /// Thread counts SIGUSR2 steps through in turn, e.g. `[8, 4]`
+    pub thread_presets: Vec<usize>,
+    /// Pin each worker thread to its own core
+    pub pin_threads: bool,
+    /// Cores to pin workers to, worker `i` to the `i`th; by default one
+    /// hardware thread per physical core while there are enough cores
+    pub cpu_set: Vec<usize>,
+    /// Fraction of the time workers spend hashing, from 0.1 to 1.0
+    pub intensity: f64,
+    /// Run worker threads at the lowest scheduling priority
+    pub low_priority: bool,
+    /// Nonces a worker hashes between checks for new work; where batches
+    /// start when `batch_time` is set
+    pub batch_size: u64,
+    /// Resize each worker's batches to take about this long, in ms, which
+    /// bounds how long replaced work is hashed; 0 keeps `batch_size`
+    pub batch_time: u64,
     pub reconnect_delay: u64,
+    /// Limit on connecting to the node, in ms
+    pub connect_timeout: u64,
+    /// Limit on a whole request to the node, longpolls aside, in ms
+    pub request_timeout: u64,
+    /// Extra attempts for node calls that are safe to repeat, after a
+    /// timeout, refused connection or server error
+    pub request_retries: u32,
+    /// User for HTTP Basic auth to the node, set with `rpc_password`
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    /// The node's cookie file, holding `user:password`, as an alternative
+    /// to `rpc_user`/`rpc_password`
+    pub rpc_cookie_path: Option<PathBuf>,
+    /// Mine against a node on another chain or an incompatible protocol
+    /// version, with a warning instead of refusing to start
+    pub allow_incompatible_node: bool,
     pub work_update_interval: u64,
     pub stats_interval: u64,
+    /// How often to ask the node for its tip and check it's reachable, in ms
+    pub tip_poll_interval: u64,
+    /// Exit with an error after this many consecutive failed attempts to
+    /// reconnect to the node
+    pub fail_after: Option<u32>,
+    /// Stop once the node has accepted this many blocks; solo mode only
+    pub max_blocks: Option<u64>,
+    /// Stop after mining this long, in seconds; solo mode only
+    pub max_duration: Option<u64>,
+    /// How far workers may advance a template's timestamp, in seconds
+    pub max_time_roll: u32,
+    /// Warn when the node's clock is further than this from ours, in
+    /// seconds; 0 never warns
+    pub clock_skew_warning: u64,
+    /// Mine with the local time when a template's lags it, as far as the
+    /// template's `max_time` allows
+    pub use_local_time: bool,
+    pub nonce_strategy: NonceStrategy,
+    pub backend: BackendKind,
+    /// Program and arguments for the external backend
+    pub backend_command: Vec<String>,
+    /// How long to keep retrying a found block the node didn't answer for,
+    /// in seconds; 0 gives up after the first attempt
+    pub submit_retry_window: u64,
+    /// Warn when a block submission takes longer than this, in ms; 0
+    /// never warns
+    pub submit_latency_warning: u64,
+    /// Address to serve `GET /stats` on, e.g. `127.0.0.1:7010`
+    pub stats_bind: Option<String>,
+    /// Bearer token the `/control` routes require; without one they only
+    /// answer loopback clients
+    pub control_token: Option<String>,
+    /// File the stats JSON is written to every stats interval
+    pub stats_file: Option<PathBuf>,
+    /// Directory to keep `solutions.jsonl` in, a record of every block
+    /// found and its submissions; solo mode only
+    pub solution_log_dir: Option<PathBuf>,
+    /// File the session's totals are written to on a clean shutdown
+    pub session_file: Option<PathBuf>,
+    /// Carry on the totals in `session_file` rather than starting afresh
+    pub resume_session: bool,
+    /// Default level for the hyperion crates; `RUST_LOG` replaces it
     pub log_level: String,
+    /// Also write the log as JSON lines to `log_dir`, rotated by size
+    pub log_file: bool,
+    /// Log file directory, relative to the working directory unless absolute
+    pub log_dir: PathBuf,
+    /// Rotate the log file once it reaches this size
+    pub log_max_file_size_mb: u64,
+    /// Rotated log files kept
+    pub log_max_files: usize,
+    pub pool: PoolConfig,
+}
+
+/// Pool settings, used when `mode = "pool"`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Pool address as `host:port`
+    pub address: String,
+    /// Worker name the pool credits shares to
+    pub worker: String,
+    pub password: String,
 }
 
 impl MiningConfig {
+    /// Load the config at `path`, writing a default config there on first
+    /// run. Validate it once any overrides are applied.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        if path.as_ref().exists() {
-            let content = fs::read_to_string(path)?;
-            let config: Self = toml::from_str(&content)?;
+        let path = path.as_ref();
+
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let (config, unknown) = Self::parse(&content)
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+            if !unknown.is_empty() {
+                warn!("Ignoring unknown keys in {}: {}", path.display(), unknown.join(", "));
+            }
             Ok(config)
         } else {
             // Create default config file
@@ -28,17 +184,404 @@ impl MiningConfig {
             Ok(default)
         }
     }
+
+    /// Parse a config, also returning the dotted paths of any keys it ignored
+    pub fn parse(content: &str) -> Result<(Self, Vec<String>)> {
+        let mut unknown = Vec::new();
+        let deserializer = toml::Deserializer::parse(content)?;
+        let config = serde_ignored::deserialize(deserializer, |path| unknown.push(path.to_string()))?;
+        unknown.sort();
+        Ok((config, unknown))
+    }
+
+    /// Override fields from the `HYPERION_MINER_*` variables among `vars`,
+    /// returning the names of those applied
+    pub fn apply_env<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) -> Result<Vec<String>> {
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        vars.sort();
+
+        for (name, value) in &vars {
+            let field = name[ENV_PREFIX.len()..].to_lowercase();
+            let path: Vec<&str> = field.split("__").collect();
+            *self = self.with_field(&path, value).with_context(|| format!("Invalid {}", name))?;
+        }
+        Ok(vars.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Override top-level fields from command-line arguments named like
+    /// them, with dashes for underscores
+    pub fn apply_args(&mut self, args: &[(&str, String)]) -> Result<()> {
+        for (field, value) in args {
+            *self = self
+                .with_field(&[field], value)
+                .with_context(|| format!("Invalid --{}", field.replace('_', "-")))?;
+        }
+        Ok(())
+    }
+
+    /// This config with the field at `path` set to `value`, read as TOML
+    /// when that fits the field, otherwise as a string
+    fn with_field(&self, path: &[&str], value: &str) -> Result<Self> {
+        let (field, parents) = path.split_last().context("No field named")?;
+        let as_toml = format!("v = {}", value).parse::<toml::Table>().ok().and_then(|mut table| table.remove("v"));
+
+        let mut first_error = None;
+        for value in as_toml.into_iter().chain([toml::Value::String(value.to_string())]) {
+            let mut table = toml::Table::try_from(self)?;
+            let mut parent = &mut table;
+            for key in parents {
+                parent = parent
+                    .entry(key.to_string())
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                    .as_table_mut()
+                    .with_context(|| format!("{} has no fields", key))?;
+            }
+            parent.insert(field.to_string(), value);
+
+            let mut unknown = Vec::new();
+            match serde_ignored::deserialize(toml::Value::Table(table), |path| unknown.push(path.to_string())) {
+                Ok(_) if !unknown.is_empty() => bail!("no such field {}", unknown.join(", ")),
+                Ok(config) => return Ok(config),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap().into())
+    }
+
+    /// Check settings that would otherwise fail late or not at all
+    pub fn validate(&self) -> Result<()> {
+        if self.threads == 0 || self.threads > MAX_THREADS {
+            bail!("threads must be between 1 and {}, got {}", MAX_THREADS, self.threads);
+        }
+        if let Some(&preset) = self.thread_presets.iter().find(|&&preset| preset == 0 || preset > MAX_THREADS) {
+            bail!("thread_presets must be between 1 and {}, got {}", MAX_THREADS, preset);
+        }
+        if let Some(core) = self.cpu_set.iter().find(|&&core| core >= num_cpus::get()) {
+            bail!("cpu_set may only name cores 0 to {}, got {}", num_cpus::get() - 1, core);
+        }
+
+        if !(0.1..=1.0).contains(&self.intensity) {
+            bail!("intensity must be between 0.1 and 1.0, got {}", self.intensity);
+        }
+
+        if !(MIN_BATCH_SIZE..=MAX_BATCH_SIZE).contains(&self.batch_size) {
+            bail!("batch_size must be between {} and {}, got {}", MIN_BATCH_SIZE, MAX_BATCH_SIZE, self.batch_size);
+        }
+        if self.batch_time > MAX_BATCH_TIME {
+            bail!("batch_time must be at most {} ms, got {}", MAX_BATCH_TIME, self.batch_time);
+        }
+
+        let url = reqwest::Url::parse(&self.node_url)
+            .with_context(|| format!("node_url '{}' is not a valid URL", self.node_url))?;
+        if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+            bail!("node_url must be an http(s) URL with a host, got '{}'", self.node_url);
+        }
+
+        if self.backend == BackendKind::External && self.backend_command.is_empty() {
+            bail!("backend = \"external\" needs a backend_command");
+        }
+
+        if self.rpc_user.is_some() != self.rpc_password.is_some() {
+            bail!("rpc_user and rpc_password must be set together");
+        }
+        if self.rpc_user.is_some() && self.rpc_cookie_path.is_some() {
+            bail!("set either rpc_user/rpc_password or rpc_cookie_path, not both");
+        }
+        if self.control_token.as_deref().is_some_and(str::is_empty) {
+            bail!("control_token must not be empty");
+        }
+
+        for (field, value) in [
+            ("work_update_interval", self.work_update_interval),
+            ("stats_interval", self.stats_interval),
+            ("tip_poll_interval", self.tip_poll_interval),
+            ("connect_timeout", self.connect_timeout),
+            ("request_timeout", self.request_timeout),
+        ] {
+            if value == 0 {
+                bail!("{} must be non-zero, got {}", field, value);
+            }
+        }
+        for (field, value) in [
+            ("fail_after", self.fail_after.map(u64::from)),
+            ("max_blocks", self.max_blocks),
+            ("max_duration", self.max_duration),
+        ] {
+            if value == Some(0) {
+                bail!("{} must be non-zero when set, got 0", field);
+            }
+        }
+        if self.resume_session && self.session_file.is_none() {
+            bail!("resume_session needs a session_file");
+        }
+
+        Ok(())
+    }
+
+    /// Settings that are valid but likely mistakes, to be logged once per
+    /// load
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        let cores = num_cpus::get_physical();
+        if self.threads > cores {
+            warnings.push(format!("threads = {} is more than the {} physical cores", self.threads, cores));
+        }
+        if !self.cpu_set.is_empty() && !self.pin_threads {
+            warnings.push("cpu_set is ignored without pin_threads = true".to_string());
+        }
+        if self.mode == MiningMode::Pool && (self.max_blocks.is_some() || self.max_duration.is_some()) {
+            warnings.push("max_blocks and max_duration are ignored in pool mode".to_string());
+        }
+        if self.mode == MiningMode::Pool && self.solution_log_dir.is_some() {
+            warnings.push("solution_log_dir is ignored in pool mode".to_string());
+        }
+
+        warnings
+    }
 }
 
 impl Default for MiningConfig {
     fn default() -> Self {
         Self {
-            node_url: "http://127.0.0.1:45154".to_string(),
-            threads: num_cpus::get(),
+            mode: MiningMode::Solo,
+            node_url: "http://127.0.0.1:6001".to_string(),
+            threads: hardware::detect_optimal_threads(),
+            thread_presets: Vec::new(),
+            pin_threads: false,
+            cpu_set: Vec::new(),
+            intensity: 1.0,
+            low_priority: false,
+            batch_size: 10_000,
+            batch_time: 0,  // ms
             reconnect_delay: 5,
+            connect_timeout: 5000,  // ms
+            request_timeout: 15000,  // ms
+            request_retries: 2,
+            rpc_user: None,
+            rpc_password: None,
+            rpc_cookie_path: None,
+            allow_incompatible_node: false,
             work_update_interval: 1000,  // ms
             stats_interval: 30,  // seconds
+            tip_poll_interval: 2000,  // ms
+            fail_after: None,
+            max_blocks: None,
+            max_duration: None,
+            max_time_roll: 600,  // seconds
+            clock_skew_warning: 60,  // seconds
+            use_local_time: false,
+            nonce_strategy: NonceStrategy::RandomOffset,
+            backend: BackendKind::Cpu,
+            backend_command: Vec::new(),
+            submit_retry_window: 30,  // seconds
+            submit_latency_warning: 2000,  // ms
+            stats_bind: None,
+            control_token: None,
+            stats_file: None,
+            solution_log_dir: None,
+            session_file: None,
+            resume_session: false,
             log_level: "info".to_string(),
+            log_file: false,
+            log_dir: PathBuf::from("logs"),
+            log_max_file_size_mb: 10,
+            log_max_files: 9,
+            pool: PoolConfig::default(),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:3333".to_string(),
+            worker: "hyperion-miner".to_string(),
+            password: "x".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(config: MiningConfig) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        MiningConfig::default().validate().unwrap();
+        let (config, unknown) =
+            MiningConfig::parse("threads = 2\nnonce_strategy = \"interleave\"\n[pool]\nworker = \"rig\"").unwrap();
+        config.validate().unwrap();
+        assert!(unknown.is_empty());
+        assert_eq!((config.threads, config.pool.worker.as_str()), (2, "rig"));
+        assert_eq!(config.nonce_strategy, NonceStrategy::Interleave);
+    }
+
+    #[test]
+    fn test_likely_mistakes_are_warnings() {
+        let config = MiningConfig { mode: MiningMode::Pool, max_blocks: Some(1), cpu_set: vec![0], ..Default::default() };
+        config.validate().unwrap();
+        assert_eq!(config.warnings(), [
+            "cpu_set is ignored without pin_threads = true",
+            "max_blocks and max_duration are ignored in pool mode",
+        ]);
+    }
+
+    #[test]
+    fn test_thread_count_bounds() {
+        let error = invalid(MiningConfig { threads: 0, ..Default::default() });
+        assert!(error.contains("threads") && error.contains("got 0"), "{}", error);
+
+        let error = invalid(MiningConfig { threads: MAX_THREADS + 1, ..Default::default() });
+        assert!(error.contains("threads") && error.contains("got 1025"), "{}", error);
+
+        let error = invalid(MiningConfig { thread_presets: vec![4, 0], ..Default::default() });
+        assert!(error.contains("thread_presets") && error.contains("got 0"), "{}", error);
+
+        let error = invalid(MiningConfig { pin_threads: true, cpu_set: vec![0, MAX_THREADS], ..Default::default() });
+        assert!(error.contains("cpu_set") && error.contains("got 1024"), "{}", error);
+    }
+
+    #[test]
+    fn test_intensity_bounds() {
+        for intensity in [0.0, 0.05, 1.5, f64::NAN] {
+            let error = invalid(MiningConfig { intensity, ..Default::default() });
+            assert!(error.starts_with("intensity"), "{}", error);
+        }
+        MiningConfig { intensity: 0.1, ..Default::default() }.validate().unwrap();
+    }
+
+    #[test]
+    fn test_batch_bounds() {
+        for batch_size in [0, MIN_BATCH_SIZE - 1, MAX_BATCH_SIZE + 1] {
+            let error = invalid(MiningConfig { batch_size, ..Default::default() });
+            assert!(error.starts_with("batch_size"), "{}", error);
         }
+        let error = invalid(MiningConfig { batch_time: MAX_BATCH_TIME + 1, ..Default::default() });
+        assert!(error.starts_with("batch_time"), "{}", error);
+        MiningConfig { batch_size: MIN_BATCH_SIZE, batch_time: 50, ..Default::default() }.validate().unwrap();
+    }
+
+    #[test]
+    fn test_node_url_must_be_http() {
+        for url in ["127.0.0.1:6001", "ftp://127.0.0.1:6001", "http://", "not a url"] {
+            let error = invalid(MiningConfig { node_url: url.to_string(), ..Default::default() });
+            assert!(error.contains("node_url") && error.contains(url), "{}", error);
+        }
+        MiningConfig { node_url: "https://node.example:6001".to_string(), ..Default::default() }.validate().unwrap();
+    }
+
+    #[test]
+    fn test_intervals_must_be_non_zero() {
+        let configs = [
+            ("work_update_interval", MiningConfig { work_update_interval: 0, ..Default::default() }),
+            ("stats_interval", MiningConfig { stats_interval: 0, ..Default::default() }),
+            ("tip_poll_interval", MiningConfig { tip_poll_interval: 0, ..Default::default() }),
+        ];
+        for (field, config) in configs {
+            let error = invalid(config);
+            assert!(error.starts_with(field) && error.contains("got 0"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_rpc_credentials_are_consistent() {
+        let error = invalid(MiningConfig { rpc_user: Some("miner".to_string()), ..Default::default() });
+        assert!(error.contains("rpc_password"), "{}", error);
+
+        let error = invalid(MiningConfig {
+            rpc_user: Some("miner".to_string()),
+            rpc_password: Some("secret".to_string()),
+            rpc_cookie_path: Some(PathBuf::from(".cookie")),
+            ..Default::default()
+        });
+        assert!(error.contains("not both"), "{}", error);
+    }
+
+    #[test]
+    fn test_external_backend_needs_command() {
+        let error = invalid(MiningConfig { backend: BackendKind::External, ..Default::default() });
+        assert!(error.contains("backend_command"), "{}", error);
+
+        let (config, _) = MiningConfig::parse("backend = \"external\"\nbackend_command = [\"gpu-miner\", \"--json\"]").unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.backend_command, ["gpu-miner", "--json"]);
+    }
+
+    #[test]
+    fn test_resumed_session_needs_file() {
+        let error = invalid(MiningConfig { resume_session: true, ..Default::default() });
+        assert!(error.contains("session_file"), "{}", error);
+
+        let config = MiningConfig { resume_session: true, session_file: Some("session.json".into()), ..Default::default() };
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_unknown_keys_are_listed() {
+        let (config, unknown) = MiningConfig::parse(
+            "thread = 4\nstats_interval = 10\n[pool]\nadress = \"pool:3333\"\n[extra]\nkey = 1",
+        )
+        .unwrap();
+        assert_eq!(unknown, ["extra", "pool.adress", "thread"]);
+        assert_eq!(config.stats_interval, 10);
+    }
+
+    /// `vars` as the environment would hold them, each name prefixed
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (format!("{}{}", ENV_PREFIX, name), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_file_and_args_override_env() {
+        let (mut config, _) = MiningConfig::parse(
+            "threads = 2\nstats_interval = 10\nnode_url = \"http://file:6001\"\n[pool]\nworker = \"file-rig\"",
+        )
+        .unwrap();
+        let mut vars = env(&[
+            ("THREADS", "3"),
+            ("NODE_URL", "http://env:6001"),
+            ("POOL__WORKER", "env-rig"),
+            ("RPC_USER", "miner"),
+            ("RPC_PASSWORD", "1234"),
+            ("LOW_PRIORITY", "true"),
+        ]);
+        vars.push(("PATH".to_string(), "/usr/bin".to_string()));
+
+        let applied = config.apply_env(vars).unwrap();
+        assert_eq!(applied.len(), 6);
+        assert!(applied.iter().all(|name| name.starts_with(ENV_PREFIX)));
+        config.apply_args(&[("threads", "4".to_string())]).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.threads, 4);
+        assert_eq!(config.node_url, "http://env:6001");
+        assert_eq!(config.stats_interval, 10);
+        assert_eq!(config.pool.worker, "env-rig");
+        assert_eq!(config.rpc_password.as_deref(), Some("1234"));
+        assert!(config.low_priority);
+    }
+
+    #[test]
+    fn test_bad_overrides_name_their_source() {
+        let mut config = MiningConfig::default();
+        let error = format!("{:#}", config.apply_env(env(&[("THREADS", "many")])).unwrap_err());
+        assert!(error.contains("HYPERION_MINER_THREADS") && error.contains("many"), "{}", error);
+
+        let error = format!("{:#}", config.apply_env(env(&[("THREAD", "4")])).unwrap_err());
+        assert!(error.contains("HYPERION_MINER_THREAD") && error.contains("no such field"), "{}", error);
+
+        let error = format!("{:#}", config.apply_args(&[("max_blocks", "-1".to_string())]).unwrap_err());
+        assert!(error.contains("--max-blocks"), "{}", error);
+        assert_eq!(config.max_blocks, None);
     }
 }
@@ -1,2 +1,4 @@
 pub mod mining_config;
-pub use mining_config::MiningConfig;
\ No newline at end of file
+pub mod reload;
+pub use mining_config::{BackendKind, MiningConfig, MiningMode, NonceStrategy};
+pub use reload::ConfigReload;
\ No newline at end of file
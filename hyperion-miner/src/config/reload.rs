@@ -0,0 +1,103 @@
+//! Triggers for reloading the config while the miner runs: SIGHUP on unix,
+//! and a change to the config file's modification time on every platform.
+
+use super::MiningConfig;
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// How often the config file's modification time is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Where the config lives and how to load it, CLI overrides included
+pub struct ConfigReload {
+    pub path: PathBuf,
+    load: Box<dyn Fn() -> Result<MiningConfig> + Send + Sync>,
+}
+
+impl ConfigReload {
+    pub fn new(path: PathBuf, load: impl Fn() -> Result<MiningConfig> + Send + Sync + 'static) -> Self {
+        Self { path, load: Box::new(load) }
+    }
+
+    /// Load the config again. Only the first start writes a default config,
+    /// so a file that has gone missing since is an error.
+    pub fn load(&self) -> Result<MiningConfig> {
+        if !self.path.exists() {
+            bail!("{} is missing", self.path.display());
+        }
+        (self.load)()
+    }
+}
+
+/// Send on `reload_tx` whenever the config at `path` should be reloaded
+pub fn spawn_watcher(path: PathBuf, reload_tx: mpsc::Sender<()>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        tokio::join!(watch_mtime(&path, reload_tx.clone()), watch_hangup(reload_tx));
+    })
+}
+
+async fn watch_mtime(path: &Path, reload_tx: mpsc::Sender<()>) {
+    let mut modified = modified_time(path);
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let now = modified_time(path);
+        if now != modified {
+            modified = now;
+            info!("{} changed, reloading config", path.display());
+            if reload_tx.send(()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(unix)]
+async fn watch_hangup(reload_tx: mpsc::Sender<()>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading config");
+        if reload_tx.send(()).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn watch_hangup(_reload_tx: mpsc::Sender<()>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_is_not_recreated() {
+        let path = std::env::temp_dir().join(format!("hyperion-miner-reload-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let reload = ConfigReload::new(path.clone(), {
+            let path = path.clone();
+            move || MiningConfig::load(&path)
+        });
+
+        let err = reload.load().unwrap_err();
+        assert!(err.to_string().contains("is missing"), "{}", err);
+        assert!(!path.exists());
+    }
+}
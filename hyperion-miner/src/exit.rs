@@ -0,0 +1,71 @@
+//! Exit codes of the miner process, kept stable so scripts and supervisors
+//! can tell why it stopped
+
+use crate::mining::MiningOutcome;
+use crate::network::NodeError;
+
+/// Stopped, or reached the block target
+pub const SUCCESS: i32 = 0;
+/// Failed in a way without a code of its own
+pub const FAILURE: i32 = 1;
+/// The config file, environment overrides or arguments are invalid
+pub const CONFIG_ERROR: i32 = 2;
+/// The node couldn't be reached, or refused the miner, at startup
+pub const NODE_UNREACHABLE: i32 = 3;
+/// The node stayed unreachable through `fail_after` reconnect attempts
+pub const GAVE_UP: i32 = 4;
+/// `max_duration` passed without an accepted block
+pub const NO_BLOCKS: i32 = 5;
+
+/// Code for mining that ended with `outcome`
+pub fn outcome_code(outcome: MiningOutcome) -> i32 {
+    match outcome {
+        MiningOutcome::Stopped | MiningOutcome::BlockTarget(_) => SUCCESS,
+        MiningOutcome::DurationExpired(0) => NO_BLOCKS,
+        MiningOutcome::DurationExpired(_) => SUCCESS,
+        MiningOutcome::GaveUp(_) => GAVE_UP,
+    }
+}
+
+/// Code for a failure to start mining: `NODE_UNREACHABLE` when the node
+/// was behind it, otherwise `FAILURE`
+pub fn startup_error_code(error: &anyhow::Error) -> i32 {
+    if error.chain().any(|cause| cause.is::<NodeError>()) {
+        NODE_UNREACHABLE
+    } else {
+        FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_outcome_codes() {
+        assert_eq!(outcome_code(MiningOutcome::Stopped), SUCCESS);
+        assert_eq!(outcome_code(MiningOutcome::BlockTarget(1)), SUCCESS);
+        assert_eq!(outcome_code(MiningOutcome::DurationExpired(2)), SUCCESS);
+        assert_eq!(outcome_code(MiningOutcome::DurationExpired(0)), NO_BLOCKS);
+        assert_eq!(outcome_code(MiningOutcome::GaveUp(3)), GAVE_UP);
+
+        // Every failure has its own code
+        let codes = [SUCCESS, FAILURE, CONFIG_ERROR, NODE_UNREACHABLE, GAVE_UP, NO_BLOCKS];
+        assert!(codes.iter().enumerate().all(|(i, code)| !codes[..i].contains(code)));
+    }
+
+    #[test]
+    fn test_startup_errors_from_the_node_are_told_apart() {
+        let error = anyhow::Error::from(NodeError::Timeout);
+        assert_eq!(startup_error_code(&error), NODE_UNREACHABLE);
+
+        // However deep in the chain
+        let wrapped = Err::<(), _>(NodeError::Connect("refused".to_string()))
+            .context("Failed to get initial work")
+            .unwrap_err();
+        assert_eq!(startup_error_code(&wrapped), NODE_UNREACHABLE);
+
+        assert_eq!(startup_error_code(&anyhow::anyhow!("Failed to bind stats server")), FAILURE);
+    }
+}
@@ -0,0 +1,138 @@
+//! The Hyperion miner. The binary loads the config, sets up logging and
+//! prints the summary on exit; `Miner` does the mining, so tests
+//! can run a miner in-process.
+
+pub mod config;
+pub mod exit;
+pub mod logging;
+pub mod mining;
+pub mod network;
+#[cfg(test)]
+mod testsupport;
+pub mod utils;
+
+use anyhow::{Context, Result};
+use config::{ConfigReload, MiningConfig, MiningMode};
+use mining::{PoolMiner, SoloMiner};
+use std::future::Future;
+use utils::session::Session;
+use utils::{hardware, MiningStats};
+use std::path::Path;
+use tracing::{info, error, warn};
+
+/// A miner ready to run
+pub struct Miner {
+    config: MiningConfig,
+    reload: Option<ConfigReload>,
+}
+
+impl Miner {
+    pub fn new(config: MiningConfig) -> Self {
+        Self { config, reload: None }
+    }
+
+    /// Reload the config through `reload` while mining; solo mode only
+    pub fn with_reload(mut self, reload: ConfigReload) -> Self {
+        self.reload = Some(reload);
+        self
+    }
+
+    /// Mine until a stopping condition in the config is met, mining fails
+    /// or `shutdown` resolves. Returns the stats and the exit code from
+    /// `exit`, or an error when mining couldn't start or stop.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> Result<(MiningStats, i32)> {
+        let Miner { config, reload } = self;
+        info!("Starting Hyperion Miner...");
+        match config.mode {
+            MiningMode::Solo => info!("Node URL: {}", config.node_url),
+            MiningMode::Pool => info!("Pool: {} as {}", config.pool.address, config.pool.worker),
+        }
+        info!("System: {}", hardware::get_system_info().summary());
+        info!("Mining threads: {}", config.threads);
+
+        let session_file = config.session_file.clone();
+        let resumed = match &session_file {
+            Some(path) if config.resume_session => Session::load(path).context("Failed to resume the session")?,
+            _ => None,
+        };
+        match &resumed {
+            Some(session) => info!(
+                "Resuming session of {} runs: {} blocks accepted over {:.0} s",
+                session.runs, session.blocks_accepted, session.runtime_secs
+            ),
+            None if config.resume_session => info!("No session to resume, starting a new one"),
+            None => {}
+        }
+
+        // Failures from here on end in an exit code from `exit` rather than an error
+        let stopped = match config.mode {
+            MiningMode::Solo => {
+                let mut miner = SoloMiner::new(config).await.context("Failed to start mining")?;
+                if let Some(session) = resumed {
+                    miner.stats().resume(session);
+                }
+                if let Some(reload) = reload {
+                    miner.watch_config(reload);
+                }
+
+                let code = tokio::select! {
+                    result = miner.start_mining() => match result {
+                        Ok(outcome) => exit::outcome_code(outcome),
+                        Err(e) => {
+                            error!("Mining error: {:#}", e);
+                            exit::startup_error_code(&e)
+                        }
+                    },
+                    _ = shutdown => {
+                        info!("Received shutdown signal, stopping miner...");
+                        miner.stop().await?;
+                        exit::SUCCESS
+                    }
+                };
+                (miner.stats().clone(), code)
+            }
+            MiningMode::Pool => {
+                let mut miner = PoolMiner::new(config);
+                if let Some(session) = resumed {
+                    miner.stats().resume(session);
+                }
+
+                let code = tokio::select! {
+                    result = miner.start_mining() => match result {
+                        Ok(()) => exit::SUCCESS,
+                        Err(e) => {
+                            error!("Mining error: {:#}", e);
+                            miner.stop().await?;
+                            exit::FAILURE
+                        }
+                    },
+                    _ = shutdown => {
+                        info!("Received shutdown signal, stopping miner...");
+                        miner.stop().await?;
+                        exit::SUCCESS
+                    }
+                };
+                (miner.stats().clone(), code)
+            }
+        };
+
+        info!("Miner stopped.");
+        if let Some(path) = session_file {
+            save_session(&stopped.0, &path);
+        }
+        Ok(stopped)
+    }
+}
+
+/// Write the session's totals to `path`, logging rather than failing so
+/// the exit summary still prints
+fn save_session(stats: &MiningStats, path: &Path) {
+    let session = stats.session();
+    match session.save(path) {
+        Ok(()) => info!(
+            "Session saved to {}: {} blocks accepted over {} runs",
+            path.display(), session.blocks_accepted, session.runs
+        ),
+        Err(e) => warn!("Failed to save the session to {}: {:#}", path.display(), e),
+    }
+}
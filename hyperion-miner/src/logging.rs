@@ -0,0 +1,152 @@
+use crate::config::MiningConfig;
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_rolling_file::RollingFileAppender;
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
+use tracing_subscriber::layer::Layered;
+
+/// Name of the log file in `log_dir`
+pub const LOG_FILE_NAME: &str = "hyperion-miner.log";
+
+type BoxedLayer = Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>;
+
+/// Install the global subscriber described by `config`. Keep the returned
+/// guard alive until exit so buffered file output is flushed.
+pub fn init(config: &MiningConfig) -> Result<Option<WorkerGuard>> {
+    let (subscriber, guard) = build_subscriber(config)?;
+    tracing::subscriber::set_global_default(subscriber)
+        .context("Failed to install log subscriber")?;
+    Ok(guard)
+}
+
+/// Subscriber for `config` without installing it
+pub fn build_subscriber(config: &MiningConfig) -> Result<(Box<dyn Subscriber + Send + Sync>, Option<WorkerGuard>)> {
+    // RUST_LOG replaces the configured level entirely
+    let env_filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(filter_directives(config))
+            .with_context(|| format!("Invalid log_level '{}'", config.log_level))?,
+    };
+
+    let console_layer: BoxedLayer = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_thread_ids(false)
+        .with_level(false)
+        .with_ansi(true)
+        .compact()
+        .boxed();
+
+    let mut layers = vec![console_layer];
+
+    let guard = if config.log_file {
+        let dir = &config.log_dir;
+        check_writable(dir)?;
+
+        let file_appender = RollingFileAppender::builder()
+            .filename(dir.join(LOG_FILE_NAME).to_string_lossy().into_owned())
+            .max_filecount(config.log_max_files)
+            .condition_max_file_size(config.log_max_file_size_mb * 1024 * 1024)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to create log file in {}: {}", dir.display(), e))?;
+        let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+
+        layers.push(
+            tracing_subscriber::fmt::layer()
+                .with_writer(file_writer)
+                .json()
+                .with_target(true)
+                .with_thread_ids(true)
+                .with_current_span(false)
+                .boxed(),
+        );
+        Some(guard)
+    } else {
+        None
+    };
+
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(layers);
+
+    Ok((Box::new(subscriber), guard))
+}
+
+/// Filter directives for `config`: its level for the hyperion crates
+pub fn filter_directives(config: &MiningConfig) -> String {
+    format!("hyperion_miner={0},hyperion_core={0}", config.log_level)
+}
+
+/// Create `dir` if needed and make sure a file can be written in it
+fn check_writable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Log directory {} cannot be created", dir.display()))?;
+
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"")
+        .with_context(|| format!("Log directory {} is not writable", dir.display()))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_log_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hyperion-miner-logging-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_file_written_as_json() {
+        let log_dir = temp_log_dir("file");
+        let config = MiningConfig { log_file: true, log_dir: log_dir.clone(), log_level: "debug".to_string(), ..Default::default() };
+        assert_eq!(filter_directives(&config), "hyperion_miner=debug,hyperion_core=debug");
+
+        let (subscriber, guard) = build_subscriber(&config).expect("Failed to build subscriber");
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "hyperion_miner", "hello from the test");
+        });
+        drop(guard);
+
+        let log = fs::read_to_string(log_dir.join(LOG_FILE_NAME)).unwrap();
+        let line: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+        assert_eq!(line["fields"]["message"], "hello from the test");
+        fs::remove_dir_all(log_dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_disabled_has_no_writer() {
+        let log_dir = temp_log_dir("disabled");
+        let config = MiningConfig { log_dir: log_dir.clone(), ..Default::default() };
+
+        let (_subscriber, guard) = build_subscriber(&config).expect("Failed to build subscriber");
+        assert!(guard.is_none());
+        assert!(!log_dir.exists());
+    }
+
+    #[test]
+    fn test_invalid_level_rejected() {
+        let config = MiningConfig { log_level: "loud".to_string(), ..Default::default() };
+        let error = build_subscriber(&config).err().expect("Expected an error");
+        assert!(error.to_string().contains("loud"), "{}", error);
+    }
+
+    /// Output from the mining loop and workers must go through `tracing`,
+    /// or it never reaches the log file
+    #[test]
+    fn test_mining_path_has_no_println() {
+        let println = concat!("print", "ln!");
+        for (file, source) in [
+            ("mining/solo.rs", include_str!("mining/solo.rs")),
+            ("mining/worker.rs", include_str!("mining/worker.rs")),
+            ("mining/pool.rs", include_str!("mining/pool.rs")),
+        ] {
+            assert!(!source.contains(println), "{} prints to stdout", file);
+        }
+    }
+}
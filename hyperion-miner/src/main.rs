@@ -1,24 +1,12 @@
-mod config;
-mod mining;
-mod network;
-mod utils;
-
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Arg, Command};
-use config::MiningConfig;
-use mining::solo::SoloMiner;
-use tracing::{info, warn, error, debug};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
-use tracing_appender::non_blocking;
-use tracing_rolling_file::RollingFileAppender;
+use hyperion_miner::config::{ConfigReload, MiningConfig};
+use hyperion_miner::mining::audit;
+use hyperion_miner::{exit, logging, Miner};
+use tracing::{debug, error, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let _log_guard = init_logging().unwrap_or_else(|e| {
-        eprintln!("Failed to initialize logging: {}", e);
-        std::process::exit(1);
-    });
-    
     // Parse command line arguments
     let matches = Command::new("hyperion-miner")
         .version("0.1.0")
@@ -37,87 +25,165 @@ async fn main() -> Result<()> {
                 .long("node-url")
                 .value_name("URL")
                 .help("Hyperion node URL")
-                .default_value("http://127.0.0.1:6001")
         )
         .arg(
             Arg::new("threads")
                 .short('t')
                 .long("threads")
                 .value_name("NUMBER")
-                .help("Number of mining threads")
+                .help("Number of mining threads; defaults to one per physical core, less one")
+        )
+        .arg(
+            Arg::new("low-priority")
+                .long("low-priority")
+                .action(clap::ArgAction::SetTrue)
+                .help("Run mining threads at the lowest scheduling priority")
+        )
+        .arg(
+            Arg::new("fail-after")
+                .long("fail-after")
+                .value_name("N")
+                .help("Exit non-zero after N consecutive failed attempts to reconnect to the node")
+        )
+        .arg(
+            Arg::new("once")
+                .long("once")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("max-blocks")
+                .help("Exit after the first accepted block")
+        )
+        .arg(
+            Arg::new("max-blocks")
+                .long("max-blocks")
+                .value_name("N")
+                .help("Exit after N accepted blocks")
+        )
+        .arg(
+            Arg::new("max-duration")
+                .long("max-duration")
+                .value_name("SECS")
+                .help("Exit after mining for SECS seconds, with code 5 if no block was accepted")
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the summary on exit as a JSON object rather than a table")
+        )
+        .arg(
+            Arg::new("resume-session")
+                .long("resume-session")
+                .action(clap::ArgAction::SetTrue)
+                .help("Carry on the totals saved in session_file rather than starting afresh")
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Check the blocks recorded in a solutions.jsonl again, then exit")
         )
         .get_matches();
 
-    // Load configuration
-    let config_path = matches.get_one::<String>("config").unwrap();
-    let mut config = MiningConfig::load(config_path)?;
+    if let Some(path) = matches.get_one::<String>("replay") {
+        let all_valid = replay(path)?;
+        std::process::exit(if all_valid { 0 } else { 1 });
+    }
 
-    // Override config with CLI arguments
-    if let Some(node_url) = matches.get_one::<String>("node-url") {
-        config.node_url = node_url.clone();
+    // Settings come from, in increasing precedence: the defaults, the
+    // config file, HYPERION_MINER_* environment variables and command-line
+    // arguments. Reloads apply the same overrides.
+    let config_path = matches.get_one::<String>("config").unwrap().clone();
+    let mut args: Vec<(&str, String)> = ["node_url", "threads", "fail_after", "max_blocks", "max_duration"]
+        .into_iter()
+        .filter_map(|field| Some((field, matches.get_one::<String>(&field.replace('_', "-"))?.clone())))
+        .collect();
+    if matches.get_flag("once") {
+        args.push(("max_blocks", "1".to_string()));
     }
-    if let Some(threads_str) = matches.get_one::<String>("threads") {
-        config.threads = threads_str.parse()?;
+    if matches.get_flag("low-priority") {
+        args.push(("low_priority", "true".to_string()));
     }
-
-    info!("Starting Hyperion Miner...");
-    info!("Node URL: {}", config.node_url);
-    info!("Mining threads: {}", config.threads);
-
-    // Start mining
-    let mut miner = SoloMiner::new(config).await?;
-    
-    // Handle graceful shutdown
-    let shutdown = tokio::signal::ctrl_c();
-    
-    tokio::select! {
-        result = miner.start_mining() => {
-            if let Err(e) = result {
-                error!("Mining error: {}", e);
+    if matches.get_flag("resume-session") {
+        args.push(("resume_session", "true".to_string()));
+    }
+    let load_config = {
+        let config_path = config_path.clone();
+        move || -> Result<MiningConfig> {
+            let mut config = MiningConfig::load(&config_path)?;
+            let from_env = config.apply_env(std::env::vars())?;
+            config.apply_args(&args)?;
+            debug!(
+                "Config from {}, environment [{}], command line [{}]",
+                config_path,
+                from_env.join(", "),
+                args.iter().map(|(field, _)| *field).collect::<Vec<_>>().join(", ")
+            );
+            config.validate()?;
+            for warning in config.warnings() {
+                warn!("{}", warning);
             }
+            Ok(config)
         }
-        _ = shutdown => {
-            info!("Received shutdown signal, stopping miner...");
-            miner.stop().await?;
+    };
+    // Warnings from loading the config go to the console, before the
+    // configured logging is up
+    let (console, _) = logging::build_subscriber(&MiningConfig::default())?;
+    let config = tracing::subscriber::with_default(console, &load_config).unwrap_or_else(|e| {
+        eprintln!("Invalid config: {:#}", e);
+        std::process::exit(exit::CONFIG_ERROR);
+    });
+    let log_guard = logging::init(&config).unwrap_or_else(|e| {
+        eprintln!("Failed to initialize logging: {:#}", e);
+        std::process::exit(exit::CONFIG_ERROR);
+    });
+
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    let reload = ConfigReload::new(config_path.into(), load_config);
+    let (stats, code) = match Miner::new(config).with_reload(reload).run(shutdown).await {
+        Ok(stopped) => stopped,
+        Err(e) => {
+            error!("{:#}", e);
+            drop(log_guard);
+            std::process::exit(exit::startup_error_code(&e));
         }
-    }
+    };
 
-    info!("Miner stopped.");
+    let summary = stats.shutdown_summary(code);
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string(&summary)?);
+    } else {
+        print!("{}", stats.format_summary(&summary));
+    }
+    if code != exit::SUCCESS {
+        // Exiting skips destructors, so flush the log file first
+        drop(log_guard);
+        std::process::exit(code);
+    }
     Ok(())
 }
 
-fn init_logging() -> Result<()> {
-    // let file_appender = RollingFileAppender::builder()
-    //     .filename("logs/hyperion-node.log".to_string())
-    //     .max_filecount(9)
-    //     .condition_max_file_size(10 * 1024 * 1024)
-    //     .build()
-    //     .map_err(|e| anyhow::anyhow!("Failed to create file appender: {}", e))?;
-
-    // let (file_writer, guard) = non_blocking(file_appender);
-
-    let console_layer = tracing_subscriber::fmt::layer()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_level(false)
-        .with_ansi(true)
-        .compact();
-
-    // let file_layer = tracing_subscriber::fmt::layer()
-    //     .with_writer(file_writer)
-    //     .json()
-    //     .with_target(true)
-    //     .with_thread_ids(true)
-    //     .with_current_span(false);
-
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("hyperion_miner=info,hyperion_core=info"));
-
-    Registry::default()
-        .with(env_filter)
-        .with(console_layer)
-        //.with(file_layer)
-        .init();
-
-    Ok(())
-}
\ No newline at end of file
+/// Check every block in the solution log at `path` again, printing a line
+/// for each. Returns whether they all passed.
+fn replay(path: &str) -> Result<bool> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let mut all_valid = true;
+    for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match audit::replay_record(line) {
+            Ok(record) => println!(
+                "line {}: block {} at height {} ({:?}{}): valid",
+                index + 1,
+                record.block_hash,
+                record.height.map_or("?".to_string(), |height| height.to_string()),
+                record.outcome,
+                record.reject_reason.map(|reason| format!(", {}", reason)).unwrap_or_default(),
+            ),
+            Err(e) => {
+                all_valid = false;
+                println!("line {}: {:#}", index + 1, e);
+            }
+        }
+    }
+    Ok(all_valid)
+}
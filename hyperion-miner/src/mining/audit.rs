@@ -0,0 +1,207 @@
+//! Audit log of found blocks: one JSON line in `solutions.jsonl` for every
+//! solution a worker finds and every answer to its submission, with the
+//! serialized block so `--replay` can check it again later.
+
+use super::worker::MiningResult;
+
+use anyhow::{bail, Context, Result};
+use hyperion_core::block::{Block, Serializable};
+use hyperion_core::crypto::{hex32, parse_hex, to_hex, Hashable};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Name of the log file in `solution_log_dir`
+pub const SOLUTION_LOG_FILE: &str = "solutions.jsonl";
+
+/// What became of a solution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SolutionOutcome {
+    Accepted,
+    /// Lost a race for the tip
+    Stale,
+    Rejected,
+    /// The node didn't answer; the block may be submitted again
+    Failed,
+    /// Given up on without an answer from the node
+    Lost,
+    /// Found for replaced work, or after another solution to the same
+    /// work, so never submitted
+    Dropped,
+}
+
+/// One line of the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolutionRecord {
+    /// When this happened, in ms since the Unix epoch
+    pub timestamp_ms: u64,
+    pub work_id: u64,
+    pub worker_id: usize,
+    pub nonce: u64,
+    pub block_hash: String,
+    /// Height the block was mined at, unknown for replaced work
+    pub height: Option<u64>,
+    pub outcome: SolutionOutcome,
+    /// The node's reject reason, or why the block was never answered for
+    pub reject_reason: Option<String>,
+    /// Round trip of the submission
+    pub latency_ms: Option<f64>,
+    pub block_hex: String,
+}
+
+impl SolutionRecord {
+    /// A record of `result`, mined at `height`
+    pub fn new(result: &MiningResult, height: Option<u64>, outcome: SolutionOutcome) -> Self {
+        Self {
+            timestamp_ms: now_ms(),
+            work_id: result.work_id,
+            worker_id: result.worker_id,
+            nonce: result.nonce,
+            block_hash: hex32(&result.block.double_sha256()),
+            height,
+            outcome,
+            reject_reason: None,
+            latency_ms: None,
+            block_hex: result.block.serialize().map(to_hex).unwrap_or_default(),
+        }
+    }
+
+    /// The same solution, now with `outcome`
+    pub fn with_outcome(&self, outcome: SolutionOutcome, reason: Option<String>, latency: Option<Duration>) -> Self {
+        Self {
+            timestamp_ms: now_ms(),
+            outcome,
+            reject_reason: reason,
+            latency_ms: latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            ..self.clone()
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Appends records to `solutions.jsonl`. Failures to write are logged and
+/// otherwise ignored, so they never get in the way of mining.
+pub struct SolutionLog {
+    path: PathBuf,
+    /// Opened on first use, and again after a failed write
+    file: Mutex<Option<File>>,
+}
+
+impl SolutionLog {
+    pub fn new(dir: &Path) -> Self {
+        Self { path: dir.join(SOLUTION_LOG_FILE), file: Mutex::new(None) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &SolutionRecord) {
+        if let Err(e) = self.write(record) {
+            warn!("Failed to write to solution log {}: {:#}", self.path.display(), e);
+        }
+    }
+
+    /// Each line goes out in a single append, so a crash can't leave half
+    /// a line before the next one
+    fn write(&self, record: &SolutionRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            *file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        let result = file.as_mut().unwrap().write_all(&line);
+        if result.is_err() {
+            *file = None;
+        }
+        Ok(result?)
+    }
+}
+
+/// Check a line of the log again: that its block decodes, has the hash
+/// recorded for it, and passes `Block::validate`
+pub fn replay_record(line: &str) -> Result<SolutionRecord> {
+    let record: SolutionRecord = serde_json::from_str(line).context("Not a solution record")?;
+    let bytes = parse_hex(&record.block_hex).map_err(|e| anyhow::anyhow!("block_hex: {}", e))?;
+    let block = Block::from_bytes(&bytes).map_err(|e| anyhow::anyhow!("Block does not decode: {}", e))?;
+
+    let hash = hex32(&block.double_sha256());
+    if hash != record.block_hash {
+        bail!("Block hashes to {}, not the recorded {}", hash, record.block_hash);
+    }
+    block.validate().map_err(|e| anyhow::anyhow!("Block is invalid: {}", e))?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::SolutionKind;
+    use hyperion_core::block::Header;
+
+    fn result(difficulty_compact: u32) -> MiningResult {
+        // No transactions, so the zero merkle root is right
+        let mut header = Header::new(1, 0, difficulty_compact, 0, [0; 32], [0; 32]);
+        MiningResult {
+            block: Block::new(hyperion_core::consensus::mine_block(&mut header), Vec::new()),
+            nonce: 0,
+            extranonce: 0,
+            worker_id: 1,
+            work_id: 2,
+            kind: SolutionKind::Block,
+            span: tracing::Span::none(),
+        }
+    }
+
+    #[test]
+    fn test_records_are_appended_as_lines() {
+        let dir = std::env::temp_dir().join(format!("hyperion-miner-audit-{}", std::process::id()));
+        let log = SolutionLog::new(&dir);
+        let found = SolutionRecord::new(&result(0x207f_ffff), Some(5), SolutionOutcome::Failed);
+        log.append(&found);
+        log.append(&found.with_outcome(SolutionOutcome::Rejected, Some("high-hash".to_string()), None));
+
+        let content = fs::read_to_string(log.path()).unwrap();
+        let records: Vec<SolutionRecord> = content.lines().map(|line| replay_record(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].outcome, records[0].height), (SolutionOutcome::Failed, Some(5)));
+        assert_eq!(records[1].reject_reason.as_deref(), Some("high-hash"));
+        fs::remove_dir_all(dir).unwrap();
+
+        // Nowhere to write is only logged
+        SolutionLog::new(Path::new("/dev/null/solutions")).append(&found);
+    }
+
+    #[test]
+    fn test_replay_catches_bad_blocks() {
+        let mut record = SolutionRecord::new(&result(0x207f_ffff), Some(1), SolutionOutcome::Accepted);
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(replay_record(&line).is_ok());
+
+        record.block_hash = "00".repeat(32);
+        let error = replay_record(&serde_json::to_string(&record).unwrap()).unwrap_err();
+        assert!(error.to_string().contains("not the recorded"), "{}", error);
+
+        // Mined at one target but claiming a harder one
+        let mut bad = result(0x207f_ffff);
+        bad.block.header.difficulty_compact = 0x0300_ffff;
+        let line = serde_json::to_string(&SolutionRecord::new(&bad, Some(1), SolutionOutcome::Rejected)).unwrap();
+        let error = replay_record(&line).unwrap_err();
+        assert!(error.to_string().contains("InvalidPoW"), "{}", error);
+
+        assert!(replay_record("not json").is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Compute backends for mining workers. A worker owns one backend and hands
+//! it a batch of nonces at a time; the backend reports the first nonce whose
+//! hash meets a target, and the worker checks that hash itself before
+//! building a block from it.
+//!
+//! `ExternalBackend` runs a program that speaks JSON lines on its stdin and
+//! stdout, one request and one response per batch:
+//!
+//! ```text
+//! > {"header":"<hex>","target":"<hex>","start":0,"stride":1,"from":0,"to":10000}
+//! < {"found":1234,"hashes":1235}
+//! ```
+//!
+//! `header` is the bincode header with the nonce to be replaced, and
+//! `target` the big-endian hash a solution must not exceed. The nonces are
+//! `start + index * stride` for `index` in `from..to`, wrapping at 2^64.
+//! `found` is the first solving index, or null, and `hashes` how many
+//! hashes were computed. A batch the worker cancels is abandoned without
+//! waiting, and its response skipped when it arrives; a backend that takes
+//! longer than `RESPONSE_TIMEOUT` to answer stops the worker.
+
+use super::worker::NonceRange;
+use crate::config::{BackendKind, MiningConfig};
+
+use anyhow::{bail, Context, Result};
+use hyperion_core::block::{Header, Serializable};
+use hyperion_core::consensus::PowScratch;
+use hyperion_core::crypto::{hex32, to_hex, HASH_SIZE};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Longest an external backend may take to answer a batch
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often a worker waiting on an external backend checks for cancellation
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// Hashes nonces for one worker thread
+pub trait MiningBackend: Send {
+    /// Label for the backend in stats
+    fn name(&self) -> &'static str;
+
+    /// Hash `header` with the nonces at `indices` of `nonces`, in order,
+    /// until one meets `target`, and return that index. Backends that take
+    /// long over a range should give up early once `cancel` is set.
+    fn scan_nonces(
+        &mut self,
+        header: &Header,
+        target: &[u8; HASH_SIZE],
+        nonces: NonceRange,
+        indices: Range<u64>,
+        cancel: &watch::Receiver<bool>,
+    ) -> Result<Option<u64>>;
+
+    /// Hashes computed so far
+    fn hash_count(&self) -> u64;
+}
+
+/// The backend `config` asks for
+pub fn create(config: &MiningConfig) -> Result<Box<dyn MiningBackend>> {
+    Ok(match config.backend {
+        BackendKind::Cpu => Box::new(CpuBackend::default()),
+        BackendKind::External => Box::new(ExternalBackend::spawn(&config.backend_command)?),
+    })
+}
+
+/// Hashes on the worker's own thread
+#[derive(Debug, Default)]
+pub struct CpuBackend {
+    hashes: u64,
+}
+
+impl MiningBackend for CpuBackend {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn scan_nonces(
+        &mut self,
+        header: &Header,
+        target: &[u8; HASH_SIZE],
+        nonces: NonceRange,
+        indices: Range<u64>,
+        _cancel: &watch::Receiver<bool>,
+    ) -> Result<Option<u64>> {
+        let mut scratch = PowScratch::new(header);
+        let from = indices.start;
+        for index in indices.clone() {
+            // Big-endian hashes and targets compare like the numbers they encode
+            if scratch.hash(nonces.nonce(index)) <= *target {
+                self.hashes += index - from + 1;
+                return Ok(Some(index));
+            }
+        }
+        self.hashes += indices.end.saturating_sub(from);
+        Ok(None)
+    }
+
+    fn hash_count(&self) -> u64 {
+        self.hashes
+    }
+}
+
+#[derive(Serialize)]
+struct ScanRequest {
+    header: String,
+    target: String,
+    start: u64,
+    stride: u64,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Deserialize)]
+struct ScanResponse {
+    found: Option<u64>,
+    hashes: u64,
+}
+
+/// Hands each batch to a program speaking the protocol in the module docs.
+/// The program runs as long as the backend, one per worker thread, and its
+/// output is read on a thread of its own so waiting on it can be cut short.
+pub struct ExternalBackend {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of the program's output; closed once it exits
+    lines: mpsc::Receiver<std::io::Result<String>>,
+    /// Responses still due for cancelled batches
+    abandoned: usize,
+    hashes: u64,
+}
+
+impl ExternalBackend {
+    /// Start `command`, a program followed by its arguments
+    pub fn spawn(command: &[String]) -> Result<Self> {
+        let (program, args) = command.split_first().context("backend_command is empty")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start mining backend {}", program))?;
+        let stdin = child.stdin.take().context("Backend has no stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("Backend has no stdout")?);
+        let (line_tx, lines) = mpsc::channel();
+        thread::Builder::new()
+            .name(format!("backend-{}", child.id()))
+            .spawn(move || {
+                for line in stdout.lines() {
+                    if line_tx.send(line).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("Failed to start backend reader")?;
+        Ok(Self { child, stdin, lines, abandoned: 0, hashes: 0 })
+    }
+
+    /// Wait for the next response until `deadline`, or `None` once `cancel`
+    /// is set
+    fn next_response(&mut self, deadline: Instant, cancel: &watch::Receiver<bool>) -> Result<Option<ScanResponse>> {
+        loop {
+            if *cancel.borrow() {
+                return Ok(None);
+            }
+            let wait = deadline.saturating_duration_since(Instant::now()).min(CANCEL_POLL);
+            let line = match self.lines.recv_timeout(wait) {
+                Ok(line) => line.context("Backend stopped writing")?,
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => continue,
+                Err(RecvTimeoutError::Timeout) => bail!("Backend didn't answer within {:?}", RESPONSE_TIMEOUT),
+                Err(RecvTimeoutError::Disconnected) => bail!("Backend exited"),
+            };
+            let response: ScanResponse = serde_json::from_str(&line)
+                .with_context(|| format!("Bad response from backend: {}", line.trim()))?;
+            return Ok(Some(response));
+        }
+    }
+}
+
+impl MiningBackend for ExternalBackend {
+    fn name(&self) -> &'static str {
+        "external"
+    }
+
+    fn scan_nonces(
+        &mut self,
+        header: &Header,
+        target: &[u8; HASH_SIZE],
+        nonces: NonceRange,
+        indices: Range<u64>,
+        cancel: &watch::Receiver<bool>,
+    ) -> Result<Option<u64>> {
+        // Skip the answers to batches given up on, counting their hashes
+        while self.abandoned > 0 {
+            let Some(response) = self.next_response(Instant::now() + RESPONSE_TIMEOUT, cancel)? else {
+                return Ok(None);
+            };
+            self.abandoned -= 1;
+            self.hashes += response.hashes;
+        }
+
+        let header = header.serialize().map_err(|e| anyhow::anyhow!("Failed to encode header: {}", e))?;
+        let request = ScanRequest {
+            header: to_hex(header),
+            target: hex32(target),
+            start: nonces.start,
+            stride: nonces.stride,
+            from: indices.start,
+            to: indices.end,
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).context("Backend stopped reading")?;
+        self.stdin.flush().context("Backend stopped reading")?;
+
+        let Some(response) = self.next_response(Instant::now() + RESPONSE_TIMEOUT, cancel)? else {
+            self.abandoned += 1;
+            return Ok(None);
+        };
+        if response.found.is_some_and(|index| !indices.contains(&index)) {
+            bail!("Backend found index {:?} outside {:?}", response.found, indices);
+        }
+
+        self.hashes += response.hashes;
+        Ok(response.found)
+    }
+
+    fn hash_count(&self) -> u64 {
+        self.hashes
+    }
+}
+
+impl Drop for ExternalBackend {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every index of `indices` whose hash meets `target`, hashed directly
+    fn direct_hits(header: &Header, target: &[u8; HASH_SIZE], nonces: NonceRange, indices: Range<u64>) -> Vec<u64> {
+        let mut scratch = PowScratch::new(header);
+        indices.filter(|&index| scratch.hash(nonces.nonce(index)) <= *target).collect()
+    }
+
+    #[test]
+    fn test_cpu_backend_matches_direct_hashing() {
+        // About one hash in 256 meets this
+        let header = Header::new(1, 1_700_000_000, 0x2000_ffff, 0, [3; 32], [7; 32]);
+        let target = header.compact_to_target();
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+
+        for nonces in [
+            NonceRange { start: 0, count: 4096, stride: 1 },
+            NonceRange { start: u64::MAX - 100, count: 4096, stride: 1 },
+            NonceRange { start: 5, count: 4096, stride: 7 },
+        ] {
+            let mut backend: Box<dyn MiningBackend> = Box::new(CpuBackend::default());
+            let mut hits = Vec::new();
+            let mut from = 0;
+            while let Some(index) = backend.scan_nonces(&header, &target, nonces, from..nonces.count, &cancel_rx).unwrap() {
+                hits.push(index);
+                from = index + 1;
+            }
+
+            let expected = direct_hits(&header, &target, nonces, 0..nonces.count);
+            assert!(!expected.is_empty());
+            assert_eq!(hits, expected, "{:?}", nonces);
+            assert_eq!(backend.hash_count(), nonces.count);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_backend_speaks_json_lines() {
+        let responder = r#"while read -r line; do echo '{"found": 3, "hashes": 4}'; done"#;
+        let command = ["sh", "-c", responder].map(String::from);
+        let mut backend = ExternalBackend::spawn(&command).unwrap();
+        let header = Header::new(1, 0, 0x207f_ffff, 0, [0; 32], [0; 32]);
+        let target = header.compact_to_target();
+        let nonces = NonceRange { start: 0, count: 100, stride: 1 };
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+
+        assert_eq!(backend.scan_nonces(&header, &target, nonces, 0..10, &cancel_rx).unwrap(), Some(3));
+        assert_eq!(backend.hash_count(), 4);
+
+        // Answers outside the batch are refused
+        let error = backend.scan_nonces(&header, &target, nonces, 5..10, &cancel_rx).unwrap_err();
+        assert!(error.to_string().contains("outside"), "{}", error);
+
+        let error = ExternalBackend::spawn(&["/nonexistent/backend".to_string()]).err().unwrap();
+        assert!(error.to_string().contains("/nonexistent/backend"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_backend_gives_up_on_cancel() {
+        // Answers each request a second late
+        let responder = r#"while read -r line; do sleep 1; echo '{"found": null, "hashes": 10}'; done"#;
+        let command = ["sh", "-c", responder].map(String::from);
+        let mut backend = ExternalBackend::spawn(&command).unwrap();
+        let header = Header::new(1, 0, 0x207f_ffff, 0, [0; 32], [0; 32]);
+        let target = header.compact_to_target();
+        let nonces = NonceRange { start: 0, count: 100, stride: 1 };
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel_tx.send(true).unwrap();
+            cancel_tx
+        });
+        let started = Instant::now();
+        assert_eq!(backend.scan_nonces(&header, &target, nonces, 0..10, &cancel_rx).unwrap(), None);
+        assert!(started.elapsed() < Duration::from_millis(500), "waited {:?} after cancelling", started.elapsed());
+
+        // The next batch skips the abandoned answer but counts its hashes
+        let cancel_tx = canceller.join().unwrap();
+        cancel_tx.send(false).unwrap();
+        assert_eq!(backend.scan_nonces(&header, &target, nonces, 10..20, &cancel_rx).unwrap(), None);
+        assert_eq!(backend.hash_count(), 20);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_backend_exit_is_an_error() {
+        let command = ["sh", "-c", "read -r line"].map(String::from);
+        let mut backend = ExternalBackend::spawn(&command).unwrap();
+        let header = Header::new(1, 0, 0x207f_ffff, 0, [0; 32], [0; 32]);
+        let target = header.compact_to_target();
+        let nonces = NonceRange { start: 0, count: 100, stride: 1 };
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+
+        let error = backend.scan_nonces(&header, &target, nonces, 0..10, &cancel_rx).unwrap_err();
+        assert!(error.to_string().contains("exited"), "{}", error);
+    }
+}
@@ -0,0 +1,166 @@
+//! Pausing, resuming and resizing a running miner: SIGUSR1 toggles pausing
+//! and SIGUSR2 steps through `thread_presets` on unix, and the stats
+//! endpoint takes `POST /control/pause`, `POST /control/resume` and
+//! `POST /control/threads`.
+
+use crate::config::MiningConfig;
+
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Shared switch between mining and paused, and the worker count asked for
+#[derive(Clone)]
+pub struct MiningControl {
+    paused_tx: Arc<watch::Sender<bool>>,
+    threads_tx: Arc<watch::Sender<Option<usize>>>,
+}
+
+impl Default for MiningControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MiningControl {
+    pub fn new() -> Self {
+        Self {
+            paused_tx: Arc::new(watch::channel(false).0),
+            threads_tx: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused_tx.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused_tx.send_replace(false);
+    }
+
+    pub fn toggle(&self) {
+        self.paused_tx.send_modify(|paused| *paused = !*paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused_tx.borrow()
+    }
+
+    /// Receiver that sees every change to the paused state
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.paused_tx.subscribe()
+    }
+
+    /// Ask for `count` workers. The miner also sets this whenever its
+    /// worker set is resized otherwise, so it tracks the current count.
+    pub fn set_threads(&self, count: usize) {
+        self.threads_tx.send_replace(Some(count));
+    }
+
+    /// Worker count last asked for or resized to, if any
+    pub fn threads(&self) -> Option<usize> {
+        *self.threads_tx.borrow()
+    }
+
+    /// Receiver that sees every change to the worker count asked for
+    pub fn subscribe_threads(&self) -> watch::Receiver<Option<usize>> {
+        self.threads_tx.subscribe()
+    }
+}
+
+/// The preset after `current` in `presets`, wrapping around, or the first
+/// when `current` isn't one of them
+pub fn next_preset(presets: &[usize], current: Option<usize>) -> Option<usize> {
+    let next = current
+        .and_then(|current| presets.iter().position(|&preset| preset == current))
+        .map_or(0, |i| (i + 1) % presets.len());
+    presets.get(next).copied()
+}
+
+/// Toggle `control` on each SIGUSR1, and move it to the next of the
+/// config's `thread_presets` on each SIGUSR2
+#[cfg(unix)]
+pub fn spawn_signal_listener(control: MiningControl, config_rx: watch::Receiver<MiningConfig>) -> JoinHandle<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let (mut user1, mut user2) = match (signal(SignalKind::user_defined1()), signal(SignalKind::user_defined2())) {
+            (Ok(user1), Ok(user2)) => (user1, user2),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!("Failed to listen for SIGUSR1 and SIGUSR2: {}", e);
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                Some(()) = user1.recv() => {
+                    info!("Received SIGUSR1, {} mining", if control.is_paused() { "resuming" } else { "pausing" });
+                    control.toggle();
+                }
+                Some(()) = user2.recv() => {
+                    let presets = config_rx.borrow().thread_presets.clone();
+                    match next_preset(&presets, control.threads()) {
+                        Some(threads) => {
+                            info!("Received SIGUSR2, switching to {} threads", threads);
+                            control.set_threads(threads);
+                        }
+                        None => tracing::warn!("Received SIGUSR2, but no thread_presets are configured"),
+                    }
+                }
+                else => break,
+            }
+        }
+    })
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signal_listener(_control: MiningControl, _config_rx: watch::Receiver<MiningConfig>) -> JoinHandle<()> {
+    tokio::spawn(async {})
+}
+
+/// Catch SIGUSR1 and SIGUSR2 for miners that can't pause or resize, so
+/// they're logged rather than ending the process
+#[cfg(unix)]
+pub fn spawn_signal_sink(mode: &'static str) -> JoinHandle<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let (mut user1, mut user2) = match (signal(SignalKind::user_defined1()), signal(SignalKind::user_defined2())) {
+            (Ok(user1), Ok(user2)) => (user1, user2),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!("Failed to listen for SIGUSR1 and SIGUSR2: {}", e);
+                return;
+            }
+        };
+        loop {
+            let name = tokio::select! {
+                Some(()) = user1.recv() => "SIGUSR1",
+                Some(()) = user2.recv() => "SIGUSR2",
+                else => break,
+            };
+            tracing::warn!("Received {}, but {} mode can't be paused or resized; ignoring it", name, mode);
+        }
+    })
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signal_sink(_mode: &'static str) -> JoinHandle<()> {
+    tokio::spawn(async {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_cycle_from_the_current_count() {
+        let presets = [8, 4, 2];
+        assert_eq!(next_preset(&presets, Some(8)), Some(4));
+        assert_eq!(next_preset(&presets, Some(2)), Some(8));
+        // A count set some other way starts the cycle over
+        assert_eq!(next_preset(&presets, Some(6)), Some(8));
+        assert_eq!(next_preset(&presets, None), Some(8));
+        assert_eq!(next_preset(&[], Some(8)), None);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod audit;
+pub mod backend;
+pub mod control;
+pub mod pool;
 pub mod solo;
 pub mod worker;
 
-pub use solo::SoloMiner;
-pub use worker::MiningWorker;
\ No newline at end of file
+pub use control::MiningControl;
+pub use pool::PoolMiner;
+pub use solo::{MiningOutcome, SoloMiner};
+pub use worker::{partition_nonces, MiningWorker, SolutionKind, ThreadOptions, WorkItem};
\ No newline at end of file
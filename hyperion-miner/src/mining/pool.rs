@@ -0,0 +1,378 @@
+use super::{backend, control, partition_nonces, MiningWorker, SolutionKind, ThreadOptions, WorkItem, worker};
+use super::worker::MiningResult;
+use crate::config::MiningConfig;
+use crate::network::stratum::{Job, StratumClient};
+use crate::utils::{hardware, stats_server, MiningStats, StatsSnapshot};
+
+use anyhow::Result;
+use hyperion_core::block::Header;
+use hyperion_core::consensus::compact_to_target;
+use hyperion_core::crypto::parse_hex32;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// Longest wait between attempts to reach the pool
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// How many recent jobs shares are still submitted for, when the pool
+/// hasn't voided them with `clean_jobs`
+const MAX_OPEN_JOBS: u64 = 16;
+
+/// Mines jobs pushed by a pool and submits shares back to it
+pub struct PoolMiner {
+    config: MiningConfig,
+    /// Cleared while the pool is unreachable, which pauses the workers
+    pool_connected: Arc<AtomicBool>,
+    workers: Vec<MiningWorker>,
+    worker_threads: Vec<JoinHandle<()>>,
+    stats: MiningStats,
+    running: Arc<AtomicBool>,
+    /// ID of the work most recently distributed
+    work_counter: u64,
+    /// Pool job ID for each open work ID
+    jobs: HashMap<u64, String>,
+    cancel_tx: Option<watch::Sender<bool>>,
+    /// Varies this miner's random-offset nonce ranges from other miners'
+    nonce_seed: u64,
+}
+
+impl PoolMiner {
+    pub fn new(config: MiningConfig) -> Self {
+        let pool_connected = Arc::new(AtomicBool::new(false));
+        let workers = (0..config.threads)
+            .map(|i| {
+                let worker = MiningWorker::new(i, pool_connected.clone());
+                worker.set_intensity(config.intensity);
+                worker.set_batching(config.batch_size, config.batch_time);
+                worker
+            })
+            .collect();
+
+        Self {
+            config,
+            pool_connected,
+            workers,
+            worker_threads: Vec::new(),
+            stats: MiningStats::new(),
+            running: Arc::new(AtomicBool::new(false)),
+            work_counter: 0,
+            jobs: HashMap::new(),
+            cancel_tx: None,
+            nonce_seed: rand::random(),
+        }
+    }
+
+    pub async fn start_mining(&mut self) -> Result<()> {
+        self.running.store(true, Ordering::SeqCst);
+        let pool = self.config.pool.clone();
+        info!("Starting pool mining on {} with {} threads", pool.address, self.config.threads);
+
+        let (result_tx, mut result_rx) = mpsc::channel(10);
+        let mut work_senders = Vec::new();
+        let cores = hardware::pinned_cores(&self.config);
+        for worker in &self.workers {
+            let (work_tx, work_rx) = mpsc::channel::<WorkItem>(10);
+            work_senders.push(work_tx);
+
+            let options = ThreadOptions {
+                core: cores.as_deref().and_then(|cores| hardware::core_for_worker(worker.id, cores)),
+                low_priority: self.config.low_priority,
+            };
+            let handle = worker.spawn(work_rx, result_tx.clone(), options, backend::create(&self.config)?)?;
+            self.worker_threads.push(handle);
+        }
+        drop(result_tx);
+
+        let stats_server = stats_server::spawn(&self.config, self.stats.clone(), None).await?;
+        let signal_sink = control::spawn_signal_sink("pool");
+        let stats_handle = {
+            let stats = self.stats.clone();
+            let workers = self.workers.clone();
+            let pool_connected = self.pool_connected.clone();
+            let interval = self.config.stats_interval;
+            let address = pool.address.clone();
+            let stats_file = self.config.stats_file.clone();
+
+            tokio::spawn(async move {
+                let mut stats_timer = tokio::time::interval(Duration::from_secs(interval));
+                loop {
+                    stats_timer.tick().await;
+
+                    let connected = pool_connected.load(Ordering::SeqCst);
+                    let snapshot = stats.take_snapshot(&workers, &address, connected);
+                    if connected {
+                        Self::report_stats(&stats, &snapshot);
+                    } else {
+                        debug!("Stats paused: pool offline");
+                    }
+
+                    if let Some(path) = &stats_file
+                        && let Err(e) = stats_server::write_stats_file(path, &snapshot).await {
+                        warn!("Failed to write stats to {}: {}", path.display(), e);
+                    }
+                }
+            })
+        };
+
+        let initial_delay = Duration::from_secs(self.config.reconnect_delay);
+        let mut reconnect_delay = initial_delay;
+        while self.running.load(Ordering::SeqCst) {
+            match StratumClient::connect(&pool.address, &pool.worker, &pool.password).await {
+                Ok(mut client) => {
+                    info!("Connected to pool {} as {}", pool.address, pool.worker);
+                    reconnect_delay = initial_delay;
+                    self.pool_connected.store(true, Ordering::SeqCst);
+
+                    if let Err(e) = self.run_session(&mut client, &work_senders, &mut result_rx).await {
+                        warn!("Lost connection to pool: {}", e);
+                    }
+
+                    // Jobs don't outlive the connection they came on
+                    self.pool_connected.store(false, Ordering::SeqCst);
+                    self.cancel_work();
+                    self.jobs.clear();
+                }
+                Err(e) => warn!("Failed to connect to pool {}: {}", pool.address, e),
+            }
+
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            debug!("Reconnecting to pool in {:?}", reconnect_delay);
+            sleep(reconnect_delay).await;
+            reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+
+        drop(work_senders);
+        self.stop_worker_threads().await;
+
+        stats_handle.abort();
+        signal_sink.abort();
+        if let Some(stats_server) = stats_server {
+            stats_server.abort();
+        }
+        info!("Pool miner stopped");
+        Ok(())
+    }
+
+    /// Mine the pool's jobs until the connection drops
+    async fn run_session(
+        &mut self,
+        client: &mut StratumClient,
+        work_senders: &[mpsc::Sender<WorkItem>],
+        result_rx: &mut mpsc::Receiver<MiningResult>,
+    ) -> Result<()> {
+        while self.running.load(Ordering::SeqCst) {
+            tokio::select! {
+                job = client.next_job() => self.distribute_job(job?, work_senders).await?,
+                Some(result) = result_rx.recv() => self.submit_result(client, result).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancel the current job and split `job` across the workers
+    async fn distribute_job(&mut self, job: Job, work_senders: &[mpsc::Sender<WorkItem>]) -> Result<()> {
+        let prev_hash = parse_hex32(&job.previous_block_hash).map_err(|e| anyhow::anyhow!("previous_block_hash: {}", e))?;
+        let merkle_root = parse_hex32(&job.merkle_root).map_err(|e| anyhow::anyhow!("merkle_root: {}", e))?;
+
+        let header = Header::new(
+            job.version,
+            job.timestamp,
+            job.difficulty_compact,
+            0, // nonce starts at 0
+            prev_hash,
+            merkle_root,
+        );
+
+        self.cancel_work();
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        self.cancel_tx = Some(cancel_tx);
+
+        self.work_counter += 1;
+        let work_id = self.work_counter;
+        if job.clean_jobs {
+            self.jobs.clear();
+        }
+        self.jobs.retain(|id, _| id + MAX_OPEN_JOBS > work_id);
+        self.jobs.insert(work_id, job.job_id.clone());
+
+        let share_target = Some(compact_to_target(job.share_difficulty));
+        let rates: Vec<Option<f64>> = (0..work_senders.len())
+            .map(|i| self.workers.get(i).and_then(MiningWorker::measured_hashrate))
+            .collect();
+        let nonces = partition_nonces(self.config.nonce_strategy, self.nonce_seed, work_id, &rates);
+        debug!("Nonce shares for work ID {}: {}", work_id, worker::format_nonce_shares(&nonces));
+        let items = WorkItem::split(header, Arc::new(job.transactions), work_id, cancel_rx, share_target, nonces);
+        for (i, (sender, work_item)) in work_senders.iter().zip(items).enumerate() {
+            if sender.send(work_item).await.is_err() {
+                error!("Failed to send work to worker {}", i);
+            }
+        }
+
+        debug!("Distributed job {} as work ID {} to {} workers", job.job_id, work_id, work_senders.len());
+        Ok(())
+    }
+
+    async fn submit_result(&mut self, client: &mut StratumClient, result: MiningResult) -> Result<()> {
+        let Some(job_id) = self.jobs.get(&result.work_id).cloned() else {
+            debug!("Dropping share from worker {} for closed work ID {}", result.worker_id, result.work_id);
+            return Ok(());
+        };
+
+        if !client.submit(&job_id, result.nonce, result.extranonce, result.block.header.time).await? {
+            self.stats.shares_rejected.fetch_add(1, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        self.stats.shares_accepted.fetch_add(1, Ordering::SeqCst);
+        if result.kind == SolutionKind::Block {
+            info!("Share from worker {} solved a block!", result.worker_id);
+            self.stats.blocks_found.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn cancel_work(&mut self) {
+        if let Some(cancel_tx) = self.cancel_tx.take() {
+            let _ = cancel_tx.send(true);
+        }
+    }
+
+    fn report_stats(stats: &MiningStats, snapshot: &StatsSnapshot) {
+        stats.report(snapshot, &format!(
+            "Shares: {} accepted / {} rejected, Blocks: {}",
+            snapshot.shares_accepted, snapshot.shares_rejected, snapshot.blocks_found,
+        ));
+    }
+
+    pub fn stats(&self) -> &MiningStats {
+        &self.stats
+    }
+
+    pub async fn stop(&mut self) -> Result<()> {
+        info!("Stopping miner...");
+        self.running.store(false, Ordering::SeqCst);
+        self.stop_worker_threads().await;
+        // Take the hashes since the last report, so the totals are complete
+        let connected = self.pool_connected.load(Ordering::SeqCst);
+        self.stats.take_snapshot(&self.workers, &self.config.pool.address, connected);
+        Ok(())
+    }
+
+    async fn stop_worker_threads(&mut self) {
+        worker::stop_all(&self.workers, std::mem::take(&mut self.worker_threads)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::crypto::Hashable;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Target of 1, which no hash will meet
+    const IMPOSSIBLE: u32 = 0x0300_0001;
+    /// Target so high nearly every nonce meets it
+    const TRIVIAL: u32 = 0x207f_ffff;
+
+    fn job() -> Job {
+        Job {
+            job_id: "job1".to_string(),
+            version: 1,
+            previous_block_hash: hex::encode([7u8; 32]),
+            merkle_root: hex::encode([0u8; 32]),
+            timestamp: 1_700_000_000,
+            difficulty_compact: IMPOSSIBLE,
+            share_difficulty: TRIVIAL,
+            transactions: Vec::new(),
+            clean_jobs: true,
+        }
+    }
+
+    /// Accept one connection and drop it, then serve a second: answer every
+    /// request with `true`, push a job once authorized, and forward each
+    /// request to `requests`
+    async fn serve_pool(listener: TcpListener, requests: mpsc::Sender<Value>) {
+        let (first, _) = listener.accept().await.unwrap();
+        drop(first);
+
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let mut reply = json!({ "id": request["id"], "result": true, "error": null }).to_string();
+            if request["method"] == "mining.authorize" {
+                reply += &format!("\n{}", json!({ "id": null, "method": "mining.notify", "params": job() }));
+            }
+            if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() || requests.send(request).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_subscribe_notify_submit_cycle() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = MiningConfig { threads: 2, reconnect_delay: 0, ..Default::default() };
+        config.pool.address = listener.local_addr().unwrap().to_string();
+        config.pool.worker = "rig1".to_string();
+
+        let (requests_tx, mut requests) = mpsc::channel(100);
+        tokio::spawn(serve_pool(listener, requests_tx));
+
+        let mut miner = PoolMiner::new(config);
+        let stats = miner.stats.clone();
+        let workers = miner.workers.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        let mut next = async || {
+            tokio::time::timeout(Duration::from_secs(10), requests.recv())
+                .await
+                .expect("pool heard nothing from the miner")
+                .unwrap()
+        };
+        assert_eq!(next().await["method"], "mining.subscribe");
+        let authorize = next().await;
+        assert_eq!(authorize["method"], "mining.authorize");
+        assert_eq!(authorize["params"][0], "rig1");
+
+        let submit = next().await;
+        assert_eq!(submit["method"], "mining.submit");
+        assert_eq!(submit["params"][0], "rig1");
+        assert_eq!(submit["params"][1], "job1");
+
+        // The nonce meets the share target but not the block's, at the
+        // submitted time rather than the job's, which the worker may have rolled
+        let job = job();
+        let ntime = submit["params"][4].as_u64().unwrap() as u32;
+        assert!(ntime >= job.timestamp);
+        let mut header = Header::new(job.version, ntime, job.difficulty_compact, 0, [7; 32], [0; 32]);
+        header.nonce = submit["params"][2].as_u64().unwrap();
+        assert!(header.double_sha256() <= compact_to_target(TRIVIAL));
+        assert!(header.validate_pow().is_err());
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while stats.shares_accepted.load(Ordering::SeqCst) == 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("accepted share not counted");
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+
+        assert_eq!(stats.shares_rejected.load(Ordering::SeqCst), 0);
+        assert_eq!(stats.blocks_found.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,303 +1,2274 @@
-use super::worker::{MiningWorker, WorkItem, MiningResult};
-use crate::config::MiningConfig;
-use crate::network::NodeClient;
-use crate::utils::stats::MiningStats;
+use super::audit::{SolutionLog, SolutionOutcome, SolutionRecord};
+use super::{backend, control, partition_nonces, MiningControl, MiningWorker, ThreadOptions, WorkItem, worker};
+use super::worker::MiningResult;
+use crate::config::{reload, ConfigReload, MiningConfig};
+use crate::network::{NodeClient, NodeError, SubmitOutcome};
+use crate::utils::{hardware, stats_server, MiningStats, StatsSnapshot};
 
 use anyhow::Result;
-use hyperion_core::block::Header;
-use std::sync::atomic::{AtomicBool, Ordering};
+use hyperion_core::block::block::compute_merkle_root;
+use hyperion_core::block::{Block, Header};
+use hyperion_core::consensus::{compact_to_target, ChainWork, MAX_FUTURE_BLOCK_TIME};
+use hyperion_core::crypto::{hex32, parse_hex32, Hashable, HASH_SIZE};
+use hyperion_rpc::BlockTemplate;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::cell::RefCell;
-use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, watch};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, watch, Mutex};
 use tokio::time::sleep;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn};
+
+/// Client-side limit on a longpoll. Nodes answer sooner, with an unchanged
+/// template, when their own hold time runs out.
+const LONGPOLL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Consecutive failed health checks before the node counts as gone
+const FAILURES_BEFORE_RECONNECT: u32 = 2;
+
+/// Longest wait between attempts to reach the node again
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Wait before the first retry of a failed submission, doubling up to
+/// `MAX_SUBMIT_RETRY_DELAY`
+const SUBMIT_RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_SUBMIT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Furthest a template's time may be from the local clock, either way
+const MAX_TEMPLATE_TIME_SKEW: u32 = MAX_FUTURE_BLOCK_TIME;
+
+/// A found block the node didn't answer for, waiting to be submitted again
+struct PendingSubmission {
+    block: Block,
+    /// Height the block was mined for
+    height: u64,
+    /// The solution as first logged, for logging each attempt
+    record: SolutionRecord,
+    /// End of the retry window
+    deadline: Instant,
+    next_attempt: Instant,
+    delay: Duration,
+}
+
+/// Transitions reported by the connection monitor
+#[derive(Debug, PartialEq, Eq)]
+enum ConnectionEvent {
+    /// The node stopped answering; work is cancelled and workers paused
+    Lost,
+    /// The node answers again and needs to be asked for fresh work
+    Restored,
+    /// `fail_after` consecutive reconnect attempts failed
+    GaveUp(u32),
+}
+
+/// Why `start_mining` returned without an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MiningOutcome {
+    /// Mining was stopped from outside
+    Stopped,
+    /// The node accepted `max_blocks` blocks
+    BlockTarget(u64),
+    /// `max_duration` passed with this many blocks accepted
+    DurationExpired(u64),
+    /// The node stayed unreachable through this many reconnect attempts
+    GaveUp(u32),
+}
 
 pub struct SoloMiner {
     config: MiningConfig,
+    /// Publishes config changes to the stats task and tip watcher
+    config_tx: watch::Sender<MiningConfig>,
+    /// Set by `watch_config` to reload the config while mining
+    config_reload: Option<ConfigReload>,
     node_client: NodeClient,
-    node_connected: Arc<std::sync::atomic::AtomicBool>,
+    node_connected: Arc<AtomicBool>,
     workers: Vec<MiningWorker>,
+    /// Publishes the worker set to the stats task as it's resized
+    workers_tx: watch::Sender<Vec<MiningWorker>>,
+    worker_threads: Vec<JoinHandle<()>>,
+    /// Cores workers are pinned to, when pinning
+    cores: Option<Vec<usize>>,
+    /// Work channel of each running worker thread
+    work_senders: Vec<mpsc::Sender<WorkItem>>,
+    /// Handed to worker threads started while mining
+    result_tx: Option<mpsc::Sender<MiningResult>>,
+    /// Asks the template fetcher for fresh work while mining
+    fetch_tx: Option<mpsc::Sender<()>>,
     stats: MiningStats,
-    running: Arc<std::sync::atomic::AtomicBool>,
-    work_counter: Arc<std::sync::atomic::AtomicU64>,
-    cancel_tx: RefCell<Option<watch::Sender<bool>>>,
-    solution_found: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    /// ID of the work most recently distributed
+    work_counter: AtomicU64,
+    /// Height of the tip the current work builds on
+    work_tip_height: Arc<AtomicU64>,
+    /// Longpoll ID of the template the current work came from
+    work_longpollid: Mutex<Option<String>>,
+    /// Template the current work came from
+    work_template: Mutex<Option<BlockTemplate>>,
+    /// Times the current template has been redistributed
+    work_redistributions: AtomicU64,
+    /// Whether the node's clock was last seen off by more than
+    /// `clock_skew_warning`
+    clock_skewed: AtomicBool,
+    /// Cancels the current work batch. Shared with the connection monitor,
+    /// which cancels work when the node goes offline.
+    cancel_tx: Arc<Mutex<Option<watch::Sender<bool>>>>,
+    /// Set while a solution for the current work is being submitted, and
+    /// cleared once the workers have the next block's work
+    solution_found: AtomicBool,
+    /// Found blocks to submit again, oldest first
+    pending_submissions: Mutex<VecDeque<PendingSubmission>>,
+    /// When to ask for work again after the node rejected a block
+    work_backoff: Mutex<Option<Instant>>,
+    /// Where solutions and their submissions are recorded, if anywhere
+    solution_log: Option<SolutionLog>,
+    control: MiningControl,
+    /// Varies this miner's random-offset nonce ranges from other miners'
+    nonce_seed: u64,
+    /// Background tasks of the current `start_mining`, aborted on shutdown
+    tasks: Vec<tokio::task::JoinHandle<()>>,
 }
 
 impl SoloMiner {
     pub async fn new(config: MiningConfig) -> Result<Self> {
-        let node_client = NodeClient::new(config.node_url.clone());
-        
+        let stats = MiningStats::new();
+        let node_client = NodeClient::new(&config)?.with_stats(stats.clone());
+
         // Test connection to node
         //node_client.test_connection().await?;
 
-        let node_connected = Arc::new(std::sync::atomic::AtomicBool::new(false));
-        if let Ok(()) = node_client.test_connection().await {
-            node_connected.store(true, std::sync::atomic::Ordering::SeqCst);
+        let node_connected = Arc::new(AtomicBool::new(false));
+        match node_client.test_connection().await {
+            Ok(()) => node_connected.store(true, Ordering::Release),
+            // Waiting won't fix bad credentials or the wrong node, so say
+            // so before mining
+            Err(e @ (NodeError::Unauthorized(_) | NodeError::Incompatible(_))) => return Err(e.into()),
+            Err(_) => {}
         }
 
         // Create workers
         let mut workers = Vec::new();
         for i in 0..config.threads {
-            workers.push(MiningWorker::new(i, node_connected.clone()));
+            let worker = MiningWorker::new(i, node_connected.clone());
+            worker.set_intensity(config.intensity);
+            worker.set_batching(config.batch_size, config.batch_time);
+            workers.push(worker);
         }
+        let solution_log = config.solution_log_dir.as_deref().map(SolutionLog::new);
+        if let Some(log) = &solution_log {
+            info!("Recording solutions in {}", log.path().display());
+        }
+        let control = MiningControl::new();
+        control.set_threads(workers.len());
 
         Ok(Self {
+            config_tx: watch::channel(config.clone()).0,
             config,
+            config_reload: None,
             node_client,
             node_connected,
+            workers_tx: watch::channel(workers.clone()).0,
             workers,
-            stats: MiningStats::new(),
-            running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
-            work_counter: Arc::new(std::sync::atomic::AtomicU64::new(0)),
-            cancel_tx: RefCell::new(None),
-            solution_found: Arc::new(AtomicBool::new(false)),
+            worker_threads: Vec::new(),
+            cores: None,
+            work_senders: Vec::new(),
+            result_tx: None,
+            fetch_tx: None,
+            stats,
+            running: Arc::new(AtomicBool::new(false)),
+            work_counter: AtomicU64::new(0),
+            work_tip_height: Arc::new(AtomicU64::new(0)),
+            work_longpollid: Mutex::new(None),
+            work_template: Mutex::new(None),
+            work_redistributions: AtomicU64::new(0),
+            clock_skewed: AtomicBool::new(false),
+            cancel_tx: Arc::new(Mutex::new(None)),
+            solution_found: AtomicBool::new(false),
+            pending_submissions: Mutex::new(VecDeque::new()),
+            work_backoff: Mutex::new(None),
+            solution_log,
+            control,
+            nonce_seed: rand::random(),
+            tasks: Vec::new(),
         })
     }
 
-    pub async fn start_mining(&mut self) -> Result<()> {
-        self.running.store(true, std::sync::atomic::Ordering::SeqCst);
-        info!("Starting solo mining with {} threads", self.config.threads);
+    /// Reload the config with `reload` while mining, when its file changes
+    /// or on SIGHUP
+    pub fn watch_config(&mut self, reload: ConfigReload) {
+        self.config_reload = Some(reload);
+    }
 
-        let node_connected = self.node_connected.clone();
-        let cancel_tx_clone = self.cancel_tx.clone();
-        let node_client = self.node_client.clone();
+    /// Handle for pausing, resuming and resizing this miner
+    #[cfg(test)]
+    pub fn control(&self) -> MiningControl {
+        self.control.clone()
+    }
 
-        tokio::spawn(async move {
-            loop {
-                match node_client.test_connection().await {
-                    Ok(_) => {
-                        node_connected.store(true, Ordering::SeqCst);
-                    },
-                    Err(_) => {
-                        warn!("Mining paused: Node offline");
-                        node_connected.store(false, Ordering::SeqCst);
-                        if let Some(ref tx) = *cancel_tx_clone.borrow() {
-                            let _ = tx.send(true);
-                        }
-                    }
-                }
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
-        });
+    pub fn stats(&self) -> &MiningStats {
+        &self.stats
+    }
 
-        // Create channels for work distribution
-        let (result_tx, mut result_rx) = mpsc::channel(10);
-        
-        // Create work channels for each worker
-        let mut work_senders = Vec::new();
-        let mut worker_handles = Vec::new();
+    /// Mine until stopped, the node is given up on, or the `max_blocks`
+    /// or `max_duration` limit is reached
+    pub async fn start_mining(&mut self) -> Result<MiningOutcome> {
+        let started = Instant::now();
+        self.running.store(true, Ordering::Release);
+        info!("Starting solo mining with {} threads", self.config.threads);
 
-        // Start worker tasks
-        for worker in &self.workers {
-            let (work_tx, work_rx) = mpsc::channel::<WorkItem>(10);
-            work_senders.push(work_tx);
-            
-            let worker_clone = worker.clone();
-            let result_tx_clone = result_tx.clone();
-            
-            let handle = tokio::spawn(async move {
-                worker_clone.start(work_rx, result_tx_clone).await;
-            });
-            worker_handles.push(handle);
+        // Start worker threads; the runtime only handles RPC and orchestration
+        let (result_tx, mut result_rx) = mpsc::channel(10);
+        self.result_tx = Some(result_tx);
+        self.cores = hardware::pinned_cores(&self.config);
+        for worker in self.workers.clone() {
+            self.spawn_worker(&worker)?;
         }
 
-        drop(result_tx);
-
         // Start stats reporting task
-        let node_connected = self.node_connected.clone();
-        let stats_handle = {
-            let stats = self.stats.clone();
-            let workers = self.workers.clone();
-            let interval = self.config.stats_interval;
-            
-            tokio::spawn(async move {
-                let mut stats_timer = tokio::time::interval(Duration::from_secs(interval));
-                loop {
-                    stats_timer.tick().await;
+        // Tasks are kept on the miner so `stop` can end them even when this
+        // future is dropped mid-loop
+        if let Some(stats_server) = stats_server::spawn(&self.config, self.stats.clone(), Some(self.control.clone())).await? {
+            self.tasks.push(stats_server);
+        }
+        self.tasks.push(self.spawn_stats_task());
+        let mut paused_rx = self.control.subscribe();
+        let mut threads_rx = self.control.subscribe_threads();
+        self.tasks.push(control::spawn_signal_listener(self.control.clone(), self.config_tx.subscribe()));
 
-                    if node_connected.load(Ordering::SeqCst) {
-                        Self::report_stats(&stats, &workers).await;
-                    } else {
-                        debug!("Stats paused: node offline");
-                    }
-                }
-            })
-        };
+        let (reload_tx, mut reload_rx) = mpsc::channel(1);
+        if let Some(config_reload) = &self.config_reload {
+            self.tasks.push(reload::spawn_watcher(config_reload.path.clone(), reload_tx));
+        }
 
         // Get initial work
-        self.get_and_distribute_work(&work_senders).await?;
+        if let Err(e) = self.get_and_distribute_work(&self.work_senders).await {
+            self.shut_down().await;
+            return Err(e);
+        }
+        self.node_connected.store(true, Ordering::Release);
+
+        // Later templates are fetched off this loop, so a slow node never
+        // holds up the results it has to handle
+        let (fetch_tx, fetch_rx) = mpsc::channel(1);
+        let (template_tx, mut template_rx) = watch::channel(None);
+        self.fetch_tx = Some(fetch_tx);
+        self.tasks.push(self.spawn_template_fetcher(fetch_rx, template_tx));
+
+        let (connection_tx, mut connection_rx) = mpsc::channel(1);
+        self.tasks.push(self.spawn_connection_monitor(connection_tx));
+        let mut outcome = Ok(MiningOutcome::Stopped);
         let mut last_template_time = Instant::now();
 
-        while self.running.load(std::sync::atomic::Ordering::SeqCst) {
+        // Nodes that support longpolling push new work the moment their tip
+        // changes; others are polled for their tip and their work refreshed
+        // when it goes stale
+        let (tip_tx, mut tip_rx) = mpsc::channel(1);
+        let (longpoll_tx, mut longpoll_rx) = mpsc::channel(1);
+        let longpollid = self.work_longpollid.lock().await.clone();
+        let longpolling = longpollid.is_some();
+        let tip_watcher = match longpollid {
+            Some(longpollid) => {
+                info!("Node supports longpoll; waiting on it for new work");
+                self.spawn_longpoll(longpollid, longpoll_tx)
+            }
+            None => self.spawn_tip_watcher(tip_tx),
+        };
+        self.tasks.push(tip_watcher);
+
+        let mut stale_timer = tokio::time::interval(Duration::from_secs(30));
+        stale_timer.tick().await;
+
+        // While paused, no work is fetched and solutions wait for the resume,
+        // which replaces the work they came from
+        while self.running.load(Ordering::Acquire) {
+            let blocks = self.stats.blocks_found.load(Ordering::Relaxed);
+            if let Some(max_blocks) = self.config.max_blocks
+                && blocks >= max_blocks {
+                info!("Reached {} accepted blocks, stopping", blocks);
+                outcome = Ok(MiningOutcome::BlockTarget(blocks));
+                break;
+            }
+
+            // Time paused counts towards the limit
+            let deadline = self.config.max_duration.map(|secs| started + Duration::from_secs(secs));
+            let paused = self.control.is_paused();
+            let retry_at = self.pending_submissions.lock().await.iter().map(|pending| pending.next_attempt).min();
+            let backoff = *self.work_backoff.lock().await;
             tokio::select! {
-                // Check for mining results
-                result = result_rx.recv() => {
-                    if let Some(mining_result) = result {
-                        info!("Block found by worker {}!", mining_result.worker_id);
-                        
-                        // Set solution found flag to prevent other workers from submitting
-                        // TODO: Figure out why this is not working on new chain and causing rejected blocks
-                        self.solution_found.store(true, std::sync::atomic::Ordering::SeqCst);
-
-                        // Cancel other workers
-                        if let Some(ref cancel_tx) = *self.cancel_tx.borrow() {
+                Ok(()) = paused_rx.changed() => {
+                    let paused = *paused_rx.borrow_and_update();
+                    if paused == self.stats.is_paused() {
+                        continue;
+                    }
+                    self.stats.set_paused(paused);
+                    if paused {
+                        info!("Mining paused");
+                        if let Some(ref cancel_tx) = *self.cancel_tx.lock().await {
                             let _ = cancel_tx.send(true);
-                            debug!("Cancelled all current work");
                         }
+                    } else {
+                        info!("Mining resumed");
+                        // Whatever was fetched before or during the pause is replaced
+                        template_rx.borrow_and_update();
+                        self.request_work();
+                    }
+                }
+
+                // Check for mining results
+                result = result_rx.recv(), if !paused => {
+                    if let Some(mining_result) = result {
+                        self.handle_solution(mining_result, &self.work_senders).await;
+                    }
+                }
+
+                Ok(()) = template_rx.changed(), if !paused => {
+                    let Some(template) = template_rx.borrow_and_update().clone() else { continue };
+                    // Fetched before the work it would replace
+                    if template.height <= self.work_tip_height.load(Ordering::Acquire) {
+                        debug!("Dropping template for height {}, behind the current work", template.height);
+                        continue;
+                    }
+                    match self.distribute_work(&template, &self.work_senders).await {
+                        Ok(()) => last_template_time = Instant::now(),
+                        Err(e) => error!("Failed to distribute fetched work: {}", e),
+                    }
+                }
 
-                        if let Err(e) = self.node_client.submit_block(mining_result.block).await {
-                            error!("Failed to submit block: {}", e);
-                        } else {
-                            self.stats.blocks_found.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                            debug!("Block submitted successfully!");
+                Some((height, hash)) = tip_rx.recv(), if !paused => {
+                    // Our own block may already have moved the work onto this tip
+                    let work_tip = self.work_template.lock().await.as_ref().map(|template| template.previous_block_hash.clone());
+                    if work_tip.is_some_and(|work_tip| work_tip != hash) {
+                        info!("New chain tip {} at height {}, abandoning current work", hash, height);
+                        self.stats.stale_work.fetch_add(1, Ordering::Relaxed);
+                        self.request_work();
+                    }
+                }
+
+                Some(template) = longpoll_rx.recv(), if !paused => {
+                    // Our own block may already have moved the work onto this template
+                    if template.longpollid != *self.work_longpollid.lock().await {
+                        info!("Node pushed new work at height {}, abandoning current work", template.height);
+                        self.stats.stale_work.fetch_add(1, Ordering::Relaxed);
+                        match self.distribute_work(&template, &self.work_senders).await {
+                            Ok(()) => last_template_time = Instant::now(),
+                            Err(e) => error!("Failed to distribute pushed work: {}", e),
                         }
-                        
-                        // Small delay to ensure other workers stop
-                        tokio::time::sleep(Duration::from_millis(10)).await;
-                        
-                        // Get fresh work
-                        debug!("Restarting mining with fresh work...");
-                        match self.get_and_distribute_work(&work_senders).await {
-                            Ok(()) => {
-                                debug!("All workers restarted with new work");
-                                last_template_time = Instant::now();
-                            }
-                            Err(e) => {
-                                error!("Failed to restart workers with new work: {}", e);
-                            }
+                    }
+                }
+
+                Ok(()) = threads_rx.changed() => {
+                    let Some(threads) = *threads_rx.borrow_and_update() else { continue };
+                    // Resizes made otherwise are reported back here too
+                    if threads == self.workers.len() {
+                        continue;
+                    }
+                    if let Err(e) = self.set_threads(threads).await {
+                        warn!("Failed to resize to {} workers: {:#}", threads, e);
+                    }
+                }
+
+                Some(event) = connection_rx.recv() => match event {
+                    ConnectionEvent::Lost => {}
+                    ConnectionEvent::Restored => {
+                        self.stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                        if !paused {
+                            self.request_work();
                         }
                     }
+                    ConnectionEvent::GaveUp(attempts) => {
+                        error!("Node unreachable after {} reconnect attempts, giving up", attempts);
+                        outcome = Ok(MiningOutcome::GaveUp(attempts));
+                        break;
+                    }
+                },
+
+                Some(()) = reload_rx.recv() => {
+                    let Some(config_reload) = &self.config_reload else { continue };
+                    let applied = match config_reload.load() {
+                        Ok(config) => self.reload_config(config).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = applied {
+                        warn!("Keeping the current config: {:#}", e);
+                    }
+                }
+
+                _ = tokio::time::sleep_until(retry_at.unwrap_or_else(Instant::now).into()), if retry_at.is_some() => {
+                    self.retry_submissions().await;
                 }
-                
-                _ = sleep(Duration::from_secs(30)) => { 
-                    if last_template_time.elapsed() > Duration::from_secs(60) { 
+
+                _ = tokio::time::sleep_until(backoff.unwrap_or_else(Instant::now).into()), if backoff.is_some() && !paused => {
+                    *self.work_backoff.lock().await = None;
+                    self.request_work();
+                }
+
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                    info!("Mined for {:?} with {} accepted blocks, stopping", started.elapsed(), blocks);
+                    outcome = Ok(MiningOutcome::DurationExpired(blocks));
+                    break;
+                }
+
+                _ = stale_timer.tick(), if !longpolling && !paused => {
+                    if last_template_time.elapsed() > Duration::from_secs(60) {
                         debug!("Work is very stale, getting fresh template...");
-                        match self.get_and_distribute_work(&work_senders).await {
-                            Ok(()) => {
-                                last_template_time = Instant::now();
-                                debug!("Updated stale work");
-                            }
-                            Err(e) => {
-                                error!("Failed to get fresh work: {}", e);
-                                sleep(Duration::from_secs(self.config.reconnect_delay)).await;
-                            }
-                        }
+                        self.stats.stale_work.fetch_add(1, Ordering::Relaxed);
+                        self.request_work();
+                    }
+                }
+            }
+        }
+
+        self.shut_down().await;
+        info!("Solo miner stopped");
+        outcome
+    }
+
+    /// Check the node every `tip_poll_interval`. After repeated failures,
+    /// pause mining and retry with exponential backoff from
+    /// `reconnect_delay`, logging only the transitions.
+    fn spawn_connection_monitor(&self, event_tx: mpsc::Sender<ConnectionEvent>) -> tokio::task::JoinHandle<()> {
+        let node_client = self.node_client.clone();
+        let node_connected = self.node_connected.clone();
+        let cancel_tx = self.cancel_tx.clone();
+        let config_rx = self.config_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let (check_interval, reconnect_delay, fail_after) = {
+                    let config = config_rx.borrow();
+                    (
+                        Duration::from_millis(config.tip_poll_interval),
+                        Duration::from_secs(config.reconnect_delay),
+                        config.fail_after,
+                    )
+                };
+
+                sleep(check_interval).await;
+                let Err(e) = node_client.test_connection().await else {
+                    failures = 0;
+                    continue;
+                };
+                failures += 1;
+                if failures < FAILURES_BEFORE_RECONNECT {
+                    continue;
+                }
+
+                warn!("Lost connection to node ({}), pausing mining until it's back", e);
+                node_connected.store(false, Ordering::Release);
+                if let Some(ref tx) = *cancel_tx.lock().await {
+                    let _ = tx.send(true);
+                }
+                if event_tx.send(ConnectionEvent::Lost).await.is_err() {
+                    break;
+                }
+
+                let mut delay = reconnect_delay;
+                let mut attempts = 0;
+                loop {
+                    sleep(delay.max(check_interval)).await;
+                    if node_client.test_connection().await.is_ok() {
+                        break;
+                    }
+
+                    attempts += 1;
+                    if fail_after.is_some_and(|limit| attempts >= limit) {
+                        let _ = event_tx.send(ConnectionEvent::GaveUp(attempts)).await;
+                        return;
                     }
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    debug!("Reconnect attempt {} failed, retrying in {:?}", attempts, delay.max(check_interval));
                 }
-                
-                // Just a periodic check without doing anything
-                _ = sleep(Duration::from_millis(100)) => {
-                    // This keeps the select loop responsive but doesn't do any work updates
+
+                info!("Reconnected to node after {} failed attempts, resuming mining", attempts);
+                failures = 0;
+                node_connected.store(true, Ordering::Release);
+                if event_tx.send(ConnectionEvent::Restored).await.is_err() {
+                    break;
                 }
             }
+        })
+    }
+
+    /// Start a thread for `worker` with its own work channel
+    fn spawn_worker(&mut self, worker: &MiningWorker) -> Result<()> {
+        let result_tx = self.result_tx.clone().ok_or_else(|| anyhow::anyhow!("Miner is not running"))?;
+        let (work_tx, work_rx) = mpsc::channel::<WorkItem>(10);
+        let options = ThreadOptions {
+            core: self.cores.as_deref().and_then(|cores| hardware::core_for_worker(worker.id, cores)),
+            low_priority: self.config.low_priority,
+        };
+        let backend = backend::create(&self.config)?;
+        self.worker_threads.push(worker.spawn(work_rx, result_tx, options, backend)?);
+        self.work_senders.push(work_tx);
+        Ok(())
+    }
+
+    /// Report stats every `stats_interval`, following config reloads and
+    /// changes to the worker set
+    fn spawn_stats_task(&self) -> tokio::task::JoinHandle<()> {
+        let stats = self.stats.clone();
+        let node_connected = self.node_connected.clone();
+        let node_url = self.config.node_url.clone();
+        let mut config_rx = self.config_tx.subscribe();
+        let mut workers_rx = self.workers_tx.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                let connected = node_connected.load(Ordering::Acquire);
+                let workers = workers_rx.borrow_and_update().clone();
+                let snapshot = stats.take_snapshot(&workers, &node_url, connected);
+                if connected {
+                    Self::report_stats(&stats, &snapshot);
+                } else {
+                    debug!("Stats paused: node offline");
+                }
+
+                let (interval, stats_file) = {
+                    let config = config_rx.borrow_and_update();
+                    (Duration::from_secs(config.stats_interval), config.stats_file.clone())
+                };
+                if let Some(path) = &stats_file
+                    && let Err(e) = stats_server::write_stats_file(path, &snapshot).await {
+                    warn!("Failed to write stats to {}: {}", path.display(), e);
+                }
+
+                // A new interval replaces the current wait, and a resized
+                // worker set is reported straight away
+                tokio::select! {
+                    _ = sleep(interval) => {}
+                    Ok(()) = config_rx.changed() => {}
+                    Ok(()) = workers_rx.changed() => {}
+                }
+            }
+        })
+    }
+
+    /// Apply a reloaded config. Threads, intervals and other settings that
+    /// only steer this process take effect immediately; changes to where
+    /// the miner connects or listens are reported and wait for a restart.
+    pub async fn reload_config(&mut self, config: MiningConfig) -> Result<()> {
+        config.validate()?;
+
+        let current = &self.config;
+        let needs_restart: Vec<&str> = [
+            ("mode", current.mode != config.mode),
+            ("node_url", current.node_url != config.node_url),
+            ("connect_timeout", current.connect_timeout != config.connect_timeout),
+            ("request_timeout", current.request_timeout != config.request_timeout),
+            ("request_retries", current.request_retries != config.request_retries),
+            ("submit_latency_warning", current.submit_latency_warning != config.submit_latency_warning),
+            ("allow_incompatible_node", current.allow_incompatible_node != config.allow_incompatible_node),
+            (
+                "RPC credentials",
+                (&current.rpc_user, &current.rpc_password, &current.rpc_cookie_path)
+                    != (&config.rpc_user, &config.rpc_password, &config.rpc_cookie_path),
+            ),
+            ("pin_threads", current.pin_threads != config.pin_threads),
+            ("cpu_set", current.cpu_set != config.cpu_set),
+            ("low_priority", current.low_priority != config.low_priority),
+            ("backend", (current.backend, &current.backend_command) != (config.backend, &config.backend_command)),
+            ("stats_bind", current.stats_bind != config.stats_bind),
+            ("control_token", current.control_token != config.control_token),
+            ("solution_log_dir", current.solution_log_dir != config.solution_log_dir),
+            (
+                "session settings",
+                (&current.session_file, current.resume_session) != (&config.session_file, config.resume_session),
+            ),
+            ("log_level", current.log_level != config.log_level),
+            (
+                "log file settings",
+                (current.log_file, &current.log_dir, current.log_max_file_size_mb, current.log_max_files)
+                    != (config.log_file, &config.log_dir, config.log_max_file_size_mb, config.log_max_files),
+            ),
+            ("pool", format!("{:?}", current.pool) != format!("{:?}", config.pool)),
+        ]
+        .into_iter()
+        .filter_map(|(field, changed)| changed.then_some(field))
+        .collect();
+        if !needs_restart.is_empty() {
+            warn!("Restart the miner to apply changes to {}", needs_restart.join(", "));
         }
 
-        // Clean shutdown
-        info!("Stopping workers...");
+        // Resizing only when these change keeps the sizes workers have settled on
+        let rebatched = (current.batch_size, current.batch_time) != (config.batch_size, config.batch_time);
+        let config = MiningConfig {
+            mode: current.mode,
+            node_url: current.node_url.clone(),
+            connect_timeout: current.connect_timeout,
+            request_timeout: current.request_timeout,
+            request_retries: current.request_retries,
+            submit_latency_warning: current.submit_latency_warning,
+            allow_incompatible_node: current.allow_incompatible_node,
+            rpc_user: current.rpc_user.clone(),
+            rpc_password: current.rpc_password.clone(),
+            rpc_cookie_path: current.rpc_cookie_path.clone(),
+            pin_threads: current.pin_threads,
+            cpu_set: current.cpu_set.clone(),
+            low_priority: current.low_priority,
+            backend: current.backend,
+            backend_command: current.backend_command.clone(),
+            stats_bind: current.stats_bind.clone(),
+            control_token: current.control_token.clone(),
+            solution_log_dir: current.solution_log_dir.clone(),
+            session_file: current.session_file.clone(),
+            resume_session: current.resume_session,
+            log_level: current.log_level.clone(),
+            log_file: current.log_file,
+            log_dir: current.log_dir.clone(),
+            log_max_file_size_mb: current.log_max_file_size_mb,
+            log_max_files: current.log_max_files,
+            pool: current.pool.clone(),
+            ..config
+        };
+        let resized = config.threads != self.workers.len();
+        info!(
+            "Config reloaded: {} threads at {:.0}% intensity, stats every {} s, tip polled every {} ms",
+            config.threads, config.intensity * 100.0, config.stats_interval, config.tip_poll_interval
+        );
         for worker in &self.workers {
-            worker.stop();
+            worker.set_intensity(config.intensity);
+            if rebatched {
+                worker.set_batching(config.batch_size, config.batch_time);
+            }
         }
+        self.config = config.clone();
+        self.config_tx.send_replace(config);
 
-        drop(work_senders);
-        for handle in worker_handles {
-            let _ = handle.await;
+        if resized {
+            self.resize_workers(self.config.threads)?;
+            if !self.control.is_paused() {
+                self.redistribute_work().await?;
+            }
         }
+        Ok(())
+    }
 
-        stats_handle.abort();
-        info!("Solo miner stopped");
+    /// Scale to `threads` workers as asked through the control, splitting
+    /// the current template across the new set rather than fetching another.
+    /// A later config reload applies the file's `threads` again.
+    async fn set_threads(&mut self, threads: usize) -> Result<()> {
+        self.resize_workers(threads)?;
+        self.config.threads = threads;
+        if !self.control.is_paused() {
+            self.redistribute_work().await?;
+        }
         Ok(())
     }
 
-    async fn get_and_distribute_work(&self, work_senders: &[mpsc::Sender<WorkItem>]) -> Result<()> {
-        // Reset the solution found flag for new work
-        self.solution_found.store(false, std::sync::atomic::Ordering::SeqCst);
-        
-        // Cancel any existing work
-        if let Some(ref cancel_tx) = *self.cancel_tx.borrow() {
+    /// Start or stop workers until there are `threads` of them. Stopped
+    /// workers finish with the current work, which the caller replaces;
+    /// their hashes stay in the stats' totals.
+    fn resize_workers(&mut self, threads: usize) -> Result<()> {
+        info!("Resizing from {} to {} workers", self.workers.len(), threads);
+        while self.workers.len() > threads {
+            if let Some(worker) = self.workers.pop() {
+                worker.stop();
+                self.stats.retire_worker(worker);
+            }
+            // Closing its work channel lets the thread exit
+            self.work_senders.truncate(self.workers.len());
+        }
+        while self.workers.len() < threads {
+            let worker = MiningWorker::new(self.workers.len(), self.node_connected.clone());
+            worker.set_intensity(self.config.intensity);
+            worker.set_batching(self.config.batch_size, self.config.batch_time);
+            if self.result_tx.is_some() {
+                self.spawn_worker(&worker)?;
+            }
+            self.workers.push(worker);
+        }
+
+        self.workers_tx.send_replace(self.workers.clone());
+        self.control.set_threads(threads);
+        Ok(())
+    }
+
+    /// Split the current template's nonce space across the current workers.
+    /// Each redistribution starts the workers 2^32 extranonces past the last,
+    /// so no hash computed for the template before is repeated.
+    async fn redistribute_work(&self) -> Result<()> {
+        let Some(template) = self.work_template.lock().await.clone() else {
+            return Ok(());
+        };
+
+        let redistributions = self.work_redistributions.fetch_add(1, Ordering::Relaxed) + 1;
+        self.send_work(&template, &self.work_senders, redistributions << 32).await
+    }
+
+    /// Submit a worker's solution, then move every worker onto the block
+    /// after it. Returns whether new work was distributed or, while mining,
+    /// requested from the template fetcher.
+    async fn handle_solution(&self, result: MiningResult, work_senders: &[mpsc::Sender<WorkItem>]) -> bool {
+        // Drop solutions for replaced work, and any after the first for the current work
+        let height = self.work_tip_height.load(Ordering::Acquire) + 1;
+        if result.work_id != self.work_counter.load(Ordering::Acquire) {
+            debug!(worker_id = result.worker_id, work_id = result.work_id, "Dropping stale solution");
+            self.log_solution(&SolutionRecord::new(&result, None, SolutionOutcome::Dropped));
+            return false;
+        }
+        if self.solution_found.swap(true, Ordering::AcqRel) {
+            debug!(worker_id = result.worker_id, work_id = result.work_id, "Dropping another solution for the same work");
+            self.log_solution(&SolutionRecord::new(&result, Some(height), SolutionOutcome::Dropped));
+            return false;
+        }
+        info!(
+            parent: &result.span,
+            worker_id = result.worker_id,
+            work_id = result.work_id,
+            nonce = result.nonce,
+            block_hash = %hex32(&result.block.double_sha256()),
+            height,
+            "Block found"
+        );
+
+        // Cancel other workers
+        if let Some(ref cancel_tx) = *self.cancel_tx.lock().await {
             let _ = cancel_tx.send(true);
+            debug!("Cancelled all current work");
+        }
+
+        // Only ask for the next template once the node has answered, so it
+        // builds on this block rather than racing it
+        self.stats.blocks_submitted.fetch_add(1, Ordering::Relaxed);
+        let found = SolutionRecord::new(&result, Some(height), SolutionOutcome::Failed);
+        let started = Instant::now();
+        let submitted = self.node_client.submit_block(result.block.clone()).await;
+        self.log_submission(&found, &submitted, started.elapsed());
+        match submitted {
+            Ok(outcome) => {
+                if matches!(outcome, SubmitOutcome::Rejected(_)) {
+                    // The template itself may be bad; back off rather than feed the node more blocks from it
+                    let resume_at = Instant::now() + Duration::from_secs(self.config.reconnect_delay);
+                    *self.work_backoff.lock().await = Some(resume_at);
+                }
+                self.record_outcome(outcome, false);
+            }
+            Err(e) => self.queue_retry(result.block, height, found, e).await,
+        }
+
+        if self.fetch_tx.is_some() {
+            // The mining loop asks for work once any backoff has passed
+            if self.work_backoff.lock().await.is_none() {
+                debug!("Restarting mining with fresh work...");
+                self.request_work();
+            }
+            return true;
+        }
+        debug!("Restarting mining with fresh work...");
+        match self.get_and_distribute_work(work_senders).await {
+            Ok(()) => {
+                debug!("All workers restarted with new work");
+                true
+            }
+            Err(e) => {
+                error!("Failed to restart workers with new work: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Count the node's answer to a submission. When `retry` is set, a
+    /// "duplicate" rejection means an earlier attempt reached the node after
+    /// all, so the block counts as found.
+    fn record_outcome(&self, outcome: SubmitOutcome, retry: bool) {
+        match outcome {
+            SubmitOutcome::Accepted => {
+                self.stats.blocks_found.fetch_add(1, Ordering::Relaxed);
+                debug!("Block submitted successfully!");
+            }
+            SubmitOutcome::Stale(reason) if retry && reason == "duplicate" => {
+                self.stats.blocks_found.fetch_add(1, Ordering::Relaxed);
+                info!("An earlier submission of the block was accepted");
+            }
+            SubmitOutcome::Stale(reason) => {
+                self.stats.record_rejection(&reason);
+                warn!("Block went stale before submission ({}), fetching new work", reason);
+            }
+            SubmitOutcome::Rejected(reason) => {
+                self.stats.record_rejection(&reason);
+                error!("Block rejected: {}", reason);
+            }
+        }
+    }
+
+    /// Record a solution in the solution log, if there is one
+    fn log_solution(&self, record: &SolutionRecord) {
+        if let Some(log) = &self.solution_log {
+            log.append(record);
+        }
+    }
+
+    /// Record an attempt to submit the solution `found`
+    fn log_submission(&self, found: &SolutionRecord, submitted: &Result<SubmitOutcome, NodeError>, latency: Duration) {
+        let (outcome, reason) = match submitted {
+            Ok(SubmitOutcome::Accepted) => (SolutionOutcome::Accepted, None),
+            Ok(SubmitOutcome::Stale(reason)) => (SolutionOutcome::Stale, Some(reason.clone())),
+            Ok(SubmitOutcome::Rejected(reason)) => (SolutionOutcome::Rejected, Some(reason.clone())),
+            Err(e) => (SolutionOutcome::Failed, Some(e.to_string())),
+        };
+        self.log_solution(&found.with_outcome(outcome, reason, Some(latency)));
+    }
+
+    /// Count a block as lost, giving up on it
+    fn lose_block(&self, record: &SolutionRecord, reason: String) {
+        self.stats.blocks_lost.fetch_add(1, Ordering::Relaxed);
+        self.log_solution(&record.with_outcome(SolutionOutcome::Lost, Some(reason), None));
+    }
+
+    /// Keep a block the node didn't answer for, to submit again within
+    /// `submit_retry_window`
+    async fn queue_retry(&self, block: Block, height: u64, record: SolutionRecord, error: NodeError) {
+        let window = Duration::from_secs(self.config.submit_retry_window);
+        if window.is_zero() {
+            error!("Failed to submit block: {}", error);
+            self.lose_block(&record, "submit_retry_window is 0".to_string());
+            return;
+        }
+
+        warn!("Failed to submit block at height {} ({}), retrying for up to {:?}", height, error, window);
+        let now = Instant::now();
+        self.pending_submissions.lock().await.push_back(PendingSubmission {
+            block,
+            height,
+            record,
+            deadline: now + window,
+            next_attempt: now + SUBMIT_RETRY_DELAY,
+            delay: SUBMIT_RETRY_DELAY,
+        });
+    }
+
+    /// Submit the pending blocks that are due again. Each is retried until
+    /// the node answers, its window closes, or the node's chain reaches its
+    /// height. A chain that got there with the block on it took an earlier
+    /// attempt after all; otherwise, as when the window closes, the block is
+    /// lost.
+    async fn retry_submissions(&self) {
+        let now = Instant::now();
+        let due = {
+            let mut pending = self.pending_submissions.lock().await;
+            let (due, waiting) = pending.drain(..).partition(|pending| pending.next_attempt <= now);
+            *pending = waiting;
+            due
+        };
+
+        for mut pending in due {
+            if now >= pending.deadline {
+                error!("Giving up on the block at height {}: the node didn't answer in time", pending.height);
+                self.lose_block(&pending.record, "no answer within submit_retry_window".to_string());
+                continue;
+            }
+
+            let result = match self.node_client.get_block_count().await {
+                Ok(tip) if tip >= pending.height => match self.node_client.has_block(&pending.record.block_hash).await {
+                    Ok(true) => {
+                        self.stats.blocks_found.fetch_add(1, Ordering::Relaxed);
+                        info!("The block at height {} is on the node's chain: an earlier submission was accepted", pending.height);
+                        continue;
+                    }
+                    Ok(false) => {
+                        warn!("Dropping the block at height {}: the node's chain is at {}", pending.height, tip);
+                        self.lose_block(&pending.record, format!("node's chain reached height {} without it", tip));
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                },
+                Ok(_) => {
+                    let started = Instant::now();
+                    let submitted = self.node_client.submit_block(pending.block.clone()).await;
+                    self.log_submission(&pending.record, &submitted, started.elapsed());
+                    submitted
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(outcome) => self.record_outcome(outcome, true),
+                Err(e) => {
+                    pending.delay = (pending.delay * 2).min(MAX_SUBMIT_RETRY_DELAY);
+                    pending.next_attempt = Instant::now() + pending.delay;
+                    debug!("Retry of the block at height {} failed ({}), next in {:?}", pending.height, e, pending.delay);
+                    self.pending_submissions.lock().await.push_back(pending);
+                }
+            }
+        }
+    }
+
+    /// Watch for blocks found elsewhere so workers don't hash on a dead tip.
+    /// Sends the node's tip height and hash whenever the hash changes, which
+    /// also catches a reorg to a tip at the same height.
+    fn spawn_tip_watcher(&self, tip_tx: mpsc::Sender<(u64, String)>) -> tokio::task::JoinHandle<()> {
+        let node_client = self.node_client.clone();
+        let mut config_rx = self.config_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut last_sent = None;
+            loop {
+                let interval = Duration::from_millis(config_rx.borrow_and_update().tip_poll_interval);
+                tokio::select! {
+                    _ = sleep(interval) => {}
+                    // Start over with the new interval
+                    Ok(()) = config_rx.changed() => continue,
+                }
+
+                // An offline node is the connection monitor's concern
+                let Ok(info) = node_client.get_blockchain_info().await else {
+                    continue;
+                };
+                if last_sent.as_ref() == Some(&info.best_blockhash) {
+                    continue;
+                }
+                match tip_tx.try_send((info.blocks, info.best_blockhash.clone())) {
+                    Ok(()) => last_sent = Some(info.best_blockhash),
+                    // Sent again on the next poll
+                    Err(mpsc::error::TrySendError::Full(_)) => {}
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+        })
+    }
+
+    /// Keep one longpoll in flight, sending each template that replaces the
+    /// one polled against
+    fn spawn_longpoll(&self, mut longpollid: String, template_tx: mpsc::Sender<BlockTemplate>) -> tokio::task::JoinHandle<()> {
+        let node_client = self.node_client.clone();
+        let retry_delay = Duration::from_secs(self.config.reconnect_delay);
+
+        tokio::spawn(async move {
+            loop {
+                match node_client.get_block_template(Some(&longpollid), Some(LONGPOLL_TIMEOUT)).await {
+                    // The node's hold time ran out with nothing new; poll again
+                    Ok(template) if template.longpollid.as_deref() == Some(longpollid.as_str()) => {}
+                    Ok(template) => {
+                        if let Some(next) = &template.longpollid {
+                            longpollid = next.clone();
+                        }
+                        if template_tx.send(template).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(NodeError::Timeout) => {
+                        debug!("Longpoll timed out, reissuing");
+                    }
+                    Err(e) => {
+                        debug!("Longpoll failed: {}", e);
+                        sleep(retry_delay).await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Fetch a template for each request, publishing it in place of any the
+    /// mining loop hasn't taken yet. Requests are queued one deep: any made
+    /// while one waits are merged into it, so after a slow answer the node
+    /// is asked once more and the loop mines the newest template.
+    fn spawn_template_fetcher(
+        &self,
+        mut request_rx: mpsc::Receiver<()>,
+        template_tx: watch::Sender<Option<BlockTemplate>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let node_client = self.node_client.clone();
+
+        tokio::spawn(async move {
+            while request_rx.recv().await.is_some() {
+                match node_client.get_block_template(None, None).await {
+                    Ok(template) => {
+                        if template_tx.send(Some(template)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("Failed to get work: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Ask the template fetcher for fresh work, unless a request is
+    /// already waiting
+    fn request_work(&self) {
+        if let Some(fetch_tx) = &self.fetch_tx {
+            let _ = fetch_tx.try_send(());
         }
+    }
 
-        // Create new cancellation token for this work batch
+    async fn get_and_distribute_work(&self, work_senders: &[mpsc::Sender<WorkItem>]) -> Result<()> {
+        let template = self.node_client.get_block_template(None, None).await?;
+        self.distribute_work(&template, work_senders).await
+    }
+
+    /// Cancel the current work batch and split `template` across the
+    /// workers. Templates failing `verify_template` are refused and the
+    /// current work left running.
+    async fn distribute_work(&self, template: &BlockTemplate, work_senders: &[mpsc::Sender<WorkItem>]) -> Result<()> {
+        let now = current_timestamp();
+        if let Err(e) = verify_template(template, now) {
+            self.stats.templates_rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow::anyhow!("Refusing template for height {}: {}", template.height, e));
+        }
+        self.check_clock_skew(template, now);
+        *self.work_template.lock().await = Some(template.clone());
+        self.work_redistributions.store(0, Ordering::Relaxed);
+        self.send_work(template, work_senders, 0).await
+    }
+
+    /// Warn once the node's clock is off from ours by more than
+    /// `clock_skew_warning`, and say so again once it's back
+    fn check_clock_skew(&self, template: &BlockTemplate, now: u32) {
+        let skew = template.cur_time.unwrap_or(template.timestamp) as i64 - now as i64;
+        let limit = self.config.clock_skew_warning;
+        let skewed = limit > 0 && skew.unsigned_abs() > limit;
+        if skewed == self.clock_skewed.swap(skewed, Ordering::Relaxed) {
+            return;
+        }
+        if !skewed {
+            info!("The node's clock is back within {} s of ours", limit);
+        } else if self.config.use_local_time {
+            warn!("The node's clock is {:+} s off from ours; mining with ours where the node allows", skew);
+        } else {
+            warn!("The node's clock is {:+} s off from ours; set use_local_time to mine with ours", skew);
+        }
+    }
+
+    async fn send_work(
+        &self,
+        template: &BlockTemplate,
+        work_senders: &[mpsc::Sender<WorkItem>],
+        extranonce_start: u64,
+    ) -> Result<()> {
+        // Cancel any existing work and create a new cancellation token for this batch
         let (cancel_tx, cancel_rx) = watch::channel(false);
-        *self.cancel_tx.borrow_mut() = Some(cancel_tx);
+        if let Some(previous) = self.cancel_tx.lock().await.replace(cancel_tx) {
+            let _ = previous.send(true);
+        }
 
-        let template = self.node_client.get_block_template().await?;
-        let work_id = self.work_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let work_id = self.work_counter.fetch_add(1, Ordering::AcqRel) + 1;
+        // Lives as long as any item or solution of this work
+        let span = info_span!("work", work_id, height = template.height);
+        self.work_tip_height.store(template.height.saturating_sub(1), Ordering::Release);
+        self.stats.template_height.store(template.height, Ordering::Relaxed);
+        *self.work_longpollid.lock().await = template.longpollid.clone();
 
         // Convert template to work item
-        let prev_hash = hex::decode(&template.previous_block_hash)?
-            .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid previous block hash length"))?;
-
-        let merkle_root = hex::decode(&template.merkle_root)?
-            .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid merkle root length"))?;
+        let prev_hash = parse_hex32(&template.previous_block_hash)
+            .map_err(|e| anyhow::anyhow!("previous_block_hash: {}", e))?;
+        let merkle_root = parse_hex32(&template.merkle_root).map_err(|e| anyhow::anyhow!("merkle_root: {}", e))?;
 
+        let timestamp = template_time(template, current_timestamp(), self.config.use_local_time);
+        if timestamp != template.timestamp {
+            debug!(parent: &span, "Mining at local time {} rather than the template's {}", timestamp, template.timestamp);
+        }
         let header = Header::new(
             template.version,
-            template.timestamp,
+            timestamp,
             template.difficulty_compact,
             0, // nonce starts at 0
             prev_hash,
             merkle_root,
         );
+        *self.stats.block_work.lock().unwrap() = Some(ChainWork::of_block(header.difficulty_compact).to_f64());
 
         // Distribute work across workers
-        let nonce_range_per_worker = u64::MAX / work_senders.len() as u64;
-        
-        for (i, sender) in work_senders.iter().enumerate() {
-            let work_item = WorkItem {
-                header: header.clone(),
-                nonce_start: i as u64 * nonce_range_per_worker,
-                nonce_range: nonce_range_per_worker,
-                transactions: template.transactions.clone(),
-                work_id,
-                cancel_rx: cancel_rx.clone(),
-                solution_found: self.solution_found.clone(),
-            };
-
+        // Roll the time no further than the node allows or we're configured to
+        let max_time = template
+            .max_time
+            .unwrap_or(timestamp)
+            .min(timestamp.saturating_add(self.config.max_time_roll))
+            .max(timestamp);
+        let rates: Vec<Option<f64>> = (0..work_senders.len())
+            .map(|i| self.workers.get(i).and_then(MiningWorker::measured_hashrate))
+            .collect();
+        let nonces = partition_nonces(self.config.nonce_strategy, self.nonce_seed, work_id, &rates);
+        debug!(parent: &span, "Nonce shares: {}", worker::format_nonce_shares(&nonces));
+        let items = WorkItem::split(header, template.transactions.clone(), work_id, cancel_rx, None, nonces)
+            .into_iter()
+            .map(|item| WorkItem { max_time, extranonce_start, span: span.clone(), ..item });
+        for (i, (sender, work_item)) in work_senders.iter().zip(items).enumerate() {
             if sender.send(work_item).await.is_err() {
-                error!("Failed to send work to worker {}", i);
+                error!(parent: &span, worker_id = i, "Failed to send work to worker");
             }
         }
 
-        debug!("Distributed work ID {} to {} workers", work_id, work_senders.len());
+        // Results for this work can be submitted again
+        self.solution_found.store(false, Ordering::Release);
+
+        debug!(parent: &span, workers = work_senders.len(), "Distributed work");
         Ok(())
     }
 
-    async fn report_stats(stats: &MiningStats, workers: &[MiningWorker]) {
-        let total_hashes: u64 = workers
+    fn report_stats(stats: &MiningStats, snapshot: &StatsSnapshot) {
+        let efficiency = snapshot.efficiency.map_or("-".to_string(), |e| format!("{:.1}%", e));
+        let rejected: Vec<String> = snapshot
+            .blocks_rejected
             .iter()
-            .map(|w| w.hashes_computed.load(std::sync::atomic::Ordering::SeqCst))
-            .sum();
-
-        let hashrate = stats.format_hashrate(stats.calculate_hashrate(total_hashes));
-        let blocks_found = stats.blocks_found.load(std::sync::atomic::Ordering::SeqCst);
-        let uptime = stats.start_time.elapsed().as_secs_f64();
+            .map(|(reason, count)| format!("{} {}", reason, count))
+            .collect();
 
-        info!(
-            "Hashrate: {}, Blocks: {}, Uptime: {:.2} s",
-            hashrate, blocks_found, uptime
-        );
+        stats.report(snapshot, &format!(
+            "Blocks: {} accepted / {} submitted ({}){}, Rejected: [{}], Stale work: {}{}",
+            snapshot.blocks_found,
+            snapshot.blocks_submitted,
+            efficiency,
+            stats.format_block_eta(snapshot),
+            rejected.join(", "),
+            snapshot.stale_work,
+            stats.format_latency(snapshot),
+        ));
     }
 
     pub async fn stop(&mut self) -> Result<()> {
         info!("Stopping miner...");
-        self.running.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.shut_down().await;
         Ok(())
     }
 
-    fn stop_all_workers(&self) {
-        for worker in &self.workers {
-            worker.stop();
+    /// Cancel the current work, wait for the worker threads, end the
+    /// background tasks and log a final stats summary. Does nothing more
+    /// than stop the workers if mining never started or already shut down.
+    async fn shut_down(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(cancel_tx) = self.cancel_tx.lock().await.take() {
+            let _ = cancel_tx.send(true);
+        }
+        self.stop_worker_threads().await;
+
+        let unsubmitted = self.pending_submissions.lock().await.drain(..).count();
+        if unsubmitted > 0 {
+            warn!("Dropping {} found blocks still waiting to be submitted", unsubmitted);
+            self.stats.blocks_lost.fetch_add(unsubmitted as u64, Ordering::Relaxed);
+        }
+
+        if self.tasks.is_empty() {
+            return;
+        }
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+
+        let connected = self.node_connected.load(Ordering::Acquire);
+        let snapshot = self.stats.take_snapshot(&self.workers, &self.config.node_url, connected);
+        info!("Final stats:");
+        Self::report_stats(&self.stats, &snapshot);
+        if let Some(path) = &self.config.stats_file
+            && let Err(e) = stats_server::write_stats_file(path, &snapshot).await {
+            warn!("Failed to write stats to {}: {}", path.display(), e);
         }
     }
-    
-    fn restart_all_workers(&self) {
-        for worker in &self.workers {
-            worker.restart();
+
+    async fn stop_worker_threads(&mut self) {
+        self.work_senders.clear();
+        self.result_tx = None;
+        self.fetch_tx = None;
+        worker::stop_all(&self.workers, std::mem::take(&mut self.worker_threads)).await;
+    }
+}
+
+/// Check that a block can be mined from `template`: its hashes are well
+/// formed, its merkle root commits to its transactions, its difficulty bits
+/// encode a target and its time is within its own window and
+/// `MAX_TEMPLATE_TIME_SKEW` of `now`
+fn verify_template(template: &BlockTemplate, now: u32) -> Result<()> {
+    let decode = |field: &str, value: &str| -> Result<[u8; HASH_SIZE]> {
+        parse_hex32(value).map_err(|e| anyhow::anyhow!("{} {:?}: {}", field, value, e))
+    };
+    decode("previous_block_hash", &template.previous_block_hash)?;
+
+    let merkle_root = decode("merkle_root", &template.merkle_root)?;
+    let computed = compute_merkle_root(&template.transactions);
+    if merkle_root != computed {
+        anyhow::bail!(
+            "merkle_root {} does not match its {} transactions, which give {}",
+            template.merkle_root,
+            template.transactions.len(),
+            hex32(&computed)
+        );
+    }
+
+    // The exponent counts the mantissa's three bytes, so a target is as wide
+    // as the exponent less the mantissa's leading zero bytes. Retargets to
+    // the easiest target give 0x2100ffff, wide as a hash exactly.
+    let bits = template.difficulty_compact;
+    let mantissa_zero_bytes = ((bits & 0x007f_ffff).leading_zeros() - 8) / 8;
+    if (bits >> 24).saturating_sub(mantissa_zero_bytes) > HASH_SIZE as u32 || compact_to_target(bits) == [0; HASH_SIZE] {
+        anyhow::bail!("difficulty bits {:#010x} do not encode a target", bits);
+    }
+
+    if let Some(min_time) = template.min_time
+        && template.timestamp < min_time {
+        anyhow::bail!("timestamp {} is before the template's min_time {}", template.timestamp, min_time);
+    }
+    if let Some(max_time) = template.max_time
+        && template.timestamp > max_time {
+        anyhow::bail!("timestamp {} is after the template's max_time {}", template.timestamp, max_time);
+    }
+    let skew = template.timestamp.abs_diff(now);
+    if skew > MAX_TEMPLATE_TIME_SKEW {
+        anyhow::bail!("timestamp {} is {} s away from the local clock", template.timestamp, skew);
+    }
+    Ok(())
+}
+
+/// Time to mine `template` with at `now`: its own, or with `use_local_time`
+/// the later of it and `now`, no later than the template's `max_time`
+fn template_time(template: &BlockTemplate, now: u32, use_local_time: bool) -> u32 {
+    if !use_local_time {
+        return template.timestamp;
+    }
+    let latest = template.max_time.unwrap_or(template.timestamp);
+    now.min(latest).max(template.timestamp)
+}
+
+/// Seconds since the Unix epoch, as block headers count time
+fn current_timestamp() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::audit::SOLUTION_LOG_FILE;
+    use crate::mining::SolutionKind;
+    use crate::testsupport::{serve_mock, template, wait_until, IMPOSSIBLE, TRIVIAL};
+    use hyperion_core::block::{Block, Serializable, Transaction};
+    use hyperion_core::consensus::create_genesis_block;
+    use hyperion_core::crypto::Hashable;
+    use std::collections::HashMap;
+
+    fn assert_send<T: Send>() {}
+
+    /// Nothing listens here, so every request fails fast
+    fn offline_config() -> MiningConfig {
+        MiningConfig {
+            node_url: "http://127.0.0.1:1".to_string(),
+            threads: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_miner_is_send() {
+        assert_send::<SoloMiner>();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_start_mining_runs_on_spawned_task() {
+        let mut miner = SoloMiner::new(offline_config()).await.unwrap();
+
+        let handle = tokio::spawn(async move { miner.start_mining().await });
+        let result = tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("miner did not give up on an offline node")
+            .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_work_cancels_previous_batch() {
+        let miner = SoloMiner::new(offline_config()).await.unwrap();
+        let (senders, mut receivers): (Vec<_>, Vec<_>) = (0..2).map(|_| mpsc::channel(10)).unzip();
+
+        miner.distribute_work(&template(1), &senders).await.unwrap();
+        let first: Vec<WorkItem> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+        assert!(first.iter().all(|work| !*work.cancel_rx.borrow()));
+
+        miner.distribute_work(&template(2), &senders).await.unwrap();
+        let second: Vec<WorkItem> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+
+        assert!(first.iter().all(|work| *work.cancel_rx.borrow()), "previous batch still running");
+        assert!(second.iter().all(|work| !*work.cancel_rx.borrow()));
+        assert!(second.iter().all(|work| work.work_id == first[0].work_id + 1));
+        assert_ne!(first[0].nonces.start, first[1].nonces.start);
+        assert_ne!(first[0].nonces.start, second[0].nonces.start, "refreshed work restarted on the same nonces");
+    }
+
+    #[tokio::test]
+    async fn test_workers_share_template_transactions() {
+        let miner = SoloMiner::new(offline_config()).await.unwrap();
+        let (senders, mut receivers): (Vec<_>, Vec<_>) = (0..16).map(|_| mpsc::channel(10)).unzip();
+        let transactions: Vec<Transaction> = (0..100)
+            .map(|i: u32| Transaction::new(vec![i.to_le_bytes().to_vec()], vec![b"out".to_vec()]).unwrap())
+            .collect();
+        let template = BlockTemplate {
+            merkle_root: hex::encode(compute_merkle_root(&transactions)),
+            transactions: Arc::new(transactions),
+            ..template(1)
+        };
+
+        miner.distribute_work(&template, &senders).await.unwrap();
+        let items: Vec<WorkItem> = receivers.iter_mut().map(|rx| rx.try_recv().unwrap()).collect();
+
+        // One allocation, held here, by the stored template and by each item
+        assert!(items.iter().all(|work| Arc::ptr_eq(&work.transactions, &template.transactions)));
+        assert_eq!(Arc::strong_count(&template.transactions), 2 + items.len());
+    }
+
+    #[test]
+    fn test_verify_template() {
+        let now = current_timestamp();
+        assert!(verify_template(&template(0), now).is_ok());
+        // The easiest target the node retargets to
+        assert!(verify_template(&BlockTemplate { difficulty_compact: 0x2100_ffff, ..template(0) }, now).is_ok());
+
+        let refused = [
+            ("previous_block_hash", BlockTemplate { previous_block_hash: "00ff".to_string(), ..template(0) }),
+            ("merkle_root", BlockTemplate { merkle_root: hex::encode([1u8; 32]), ..template(0) }),
+            ("difficulty bits", BlockTemplate { difficulty_compact: 0xff7f_ffff, ..template(0) }),
+            ("difficulty bits", BlockTemplate { difficulty_compact: 0x2101_0000, ..template(0) }),
+            ("difficulty bits", BlockTemplate { difficulty_compact: 0x0000_00ff, ..template(0) }),
+            ("timestamp", BlockTemplate { timestamp: now - MAX_TEMPLATE_TIME_SKEW - 60, ..template(0) }),
+            ("timestamp", template(MAX_TEMPLATE_TIME_SKEW + 60)),
+            ("min_time", BlockTemplate { min_time: Some(now + 60), ..template(0) }),
+            ("max_time", BlockTemplate { max_time: Some(now - 60), ..template(0) }),
+        ];
+        for (field, template) in refused {
+            let error = verify_template(&template, now).unwrap_err().to_string();
+            assert!(error.contains(field), "{}", error);
+        }
+
+        // Hex errors say what was expected
+        let short = BlockTemplate { previous_block_hash: "00ff".to_string(), ..template(0) };
+        assert_eq!(
+            verify_template(&short, now).unwrap_err().to_string(),
+            "previous_block_hash \"00ff\": expected 32 bytes (64 hex digits), got 2"
+        );
+    }
+
+    #[test]
+    fn test_template_time_follows_local_clock_within_window() {
+        let now = current_timestamp();
+        // 90 s behind, as from a node whose clock drifted
+        let lagging = BlockTemplate { timestamp: now - 90, max_time: Some(now - 90 + MAX_FUTURE_BLOCK_TIME), ..template(0) };
+        assert_eq!(template_time(&lagging, now, false), now - 90);
+        assert_eq!(template_time(&lagging, now, true), now);
+
+        // Never past max_time, nor behind the template
+        let capped = BlockTemplate { max_time: Some(now - 30), ..lagging.clone() };
+        assert_eq!(template_time(&capped, now, true), now - 30);
+        assert_eq!(template_time(&BlockTemplate { max_time: None, ..lagging }, now, true), now - 90);
+        assert_eq!(template_time(&template(60), now, true), now + 60);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_template_is_mined_at_local_time() {
+        let miner = SoloMiner::new(MiningConfig { use_local_time: true, ..offline_config() }).await.unwrap();
+        let (senders, mut receivers): (Vec<_>, Vec<_>) = (0..2).map(|_| mpsc::channel(10)).unzip();
+        let now = current_timestamp();
+        let lagging = BlockTemplate {
+            timestamp: now - 90,
+            cur_time: Some(now - 90),
+            min_time: Some(now - 600),
+            max_time: Some(now - 90 + MAX_FUTURE_BLOCK_TIME),
+            ..template(0)
+        };
+
+        miner.distribute_work(&lagging, &senders).await.unwrap();
+        assert!(miner.clock_skewed.load(Ordering::Relaxed));
+        for work in receivers.iter_mut().map(|rx| rx.try_recv().unwrap()) {
+            assert!((now..now + 5).contains(&work.header.time), "mined at {} rather than {}", work.header.time, now);
+            assert!(work.max_time >= work.header.time);
+        }
+
+        // Outside its own window a template is refused, and the work kept
+        let out_of_window = BlockTemplate { min_time: Some(now + 60), ..template(0) };
+        let error = miner.distribute_work(&out_of_window, &senders).await.unwrap_err();
+        assert!(error.to_string().contains("min_time"), "{}", error);
+        assert!(receivers.iter_mut().all(|rx| rx.try_recv().is_err()));
+        assert_eq!(miner.stats.templates_rejected.load(Ordering::SeqCst), 1);
+
+        // Back in step
+        miner.distribute_work(&template(0), &senders).await.unwrap();
+        assert!(!miner.clock_skewed.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mismatched_merkle_root_refuses_work() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        node.lock().unwrap().bad_merkle_root = true;
+        let config = MiningConfig { node_url, threads: 2, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+
+        let error = miner.start_mining().await.unwrap_err().to_string();
+        assert!(error.contains("merkle_root"), "{}", error);
+        assert_eq!(miner.stats.templates_rejected.load(Ordering::SeqCst), 1);
+        assert!(miner.workers.iter().all(|w| w.current_work_id.load(Ordering::SeqCst) == 0));
+        assert!(miner.workers.iter().all(|w| w.hashes_computed.load(Ordering::Relaxed) == 0));
+    }
+
+    /// Workers used to check replaced work against a work ID they set
+    /// themselves, which never differed; the cancel channel alone has to
+    /// stop them
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replaced_work_is_abandoned_mid_batch() {
+        let mut miner = SoloMiner::new(offline_config()).await.unwrap();
+        miner.node_connected.store(true, Ordering::SeqCst);
+        let (result_tx, _result_rx) = mpsc::channel(10);
+        miner.result_tx = Some(result_tx);
+        for worker in miner.workers.clone() {
+            miner.spawn_worker(&worker).unwrap();
+        }
+        let workers = miner.workers.clone();
+        let on_work = |work_id| workers.iter().all(|w| w.current_work_id.load(Ordering::SeqCst) == work_id);
+        let hashes = || workers.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).collect::<Vec<_>>();
+
+        let unsolvable = BlockTemplate { difficulty_compact: IMPOSSIBLE, ..template(0) };
+        miner.distribute_work(&unsolvable, &miner.work_senders).await.unwrap();
+        wait_until("every worker hashes work 1", || on_work(1) && hashes().iter().all(|&h| h > 0)).await;
+
+        let replaced = Instant::now();
+        miner.distribute_work(&BlockTemplate { timestamp: unsolvable.timestamp + 1, ..unsolvable }, &miner.work_senders)
+            .await
+            .unwrap();
+        wait_until("every worker moves to work 2", || on_work(2)).await;
+        assert!(replaced.elapsed() < Duration::from_secs(1), "work 1 ran on for {:?}", replaced.elapsed());
+
+        miner.stop().await.unwrap();
+    }
+
+    /// Every worker solving the same template used to submit its own block,
+    /// and all but the first were rejected by the node
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_one_submission_per_height() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 4, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        wait_until("ten blocks are mined", || node.lock().unwrap().chain.len() > 10).await;
+        mining.abort();
+
+        let node = node.lock().unwrap();
+        for (height, count) in &node.submissions {
+            assert_eq!(*count, 1, "{} submissions for height {}", count, height);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_new_tip_replaces_work() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig { node_url, threads: 2, tip_poll_interval: 20, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let work_id = workers[0].current_work_id.clone();
+        let stale_work = miner.stats.stale_work.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        wait_until("the first work arrives", || work_id.load(Ordering::SeqCst) == 1).await;
+
+        // Another miner extends the chain
+        node.lock().unwrap().chain.push([1; 32]);
+        wait_until("work for the new tip arrives", || work_id.load(Ordering::SeqCst) == 2).await;
+        assert_eq!(stale_work.load(Ordering::SeqCst), 1);
+
+        // A reorg to another block at the same height is a new tip too
+        *node.lock().unwrap().chain.last_mut().unwrap() = [2; 32];
+        wait_until("work for the reorged tip arrives", || work_id.load(Ordering::SeqCst) == 3).await;
+        assert_eq!(stale_work.load(Ordering::SeqCst), 2);
+
+        // And nothing changes while the tip stays put
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(work_id.load(Ordering::SeqCst), 3);
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+        assert!(node.lock().unwrap().submissions.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_longpoll_pushes_new_work() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, true).await;
+        let config = MiningConfig { node_url, threads: 2, tip_poll_interval: 20, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let work_id = workers[0].current_work_id.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        wait_until("the first work arrives", || work_id.load(Ordering::SeqCst) == 1).await;
+
+        // The node's hold time runs out with no new block; the miner polls again and keeps its work
+        let requests = |method: &str| node.lock().unwrap().requests(method);
+        wait_until("the longpoll is reissued", || requests("longpoll") >= 3).await;
+        assert_eq!(work_id.load(Ordering::SeqCst), 1);
+
+        // Another miner extends the chain and the held longpoll answers with new work
+        node.lock().unwrap().chain.push([1; 32]);
+        wait_until("work for the new tip arrives", || work_id.load(Ordering::SeqCst) == 2).await;
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+
+        // Only the initial template was fetched, and the tip was never polled
+        assert_eq!(requests("get_block_template"), 1);
+        assert_eq!(requests("get_blockchain_info"), 0);
+    }
+
+    /// Fetching the next template used to hold up the whole loop, so found
+    /// blocks waited on a slow node's template before being submitted
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_template_does_not_hold_up_solutions() {
+        let (node, node_url) = serve_mock(TRIVIAL, true).await;
+        node.lock().unwrap().template_latency = Duration::from_secs(10);
+        let config = MiningConfig { node_url, threads: 2, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        // The template asked for after the first block stalls, while the
+        // longpoll keeps pushing work and its blocks are submitted
+        wait_until("blocks are mined past the stalled template", || node.lock().unwrap().chain.len() > 4).await;
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+        assert_eq!(node.lock().unwrap().requests("get_block_template"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_template_requests_are_queued_one_deep() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let mut miner = SoloMiner::new(MiningConfig { node_url, ..Default::default() }).await.unwrap();
+        let (fetch_tx, fetch_rx) = mpsc::channel(1);
+        let (template_tx, template_rx) = watch::channel(None);
+        miner.fetch_tx = Some(fetch_tx);
+        let fetcher = miner.spawn_template_fetcher(fetch_rx, template_tx);
+        let height = || template_rx.borrow().as_ref().map(|template: &BlockTemplate| template.height);
+        let requests = || node.lock().unwrap().requests("get_block_template");
+
+        miner.request_work();
+        wait_until("the first template arrives", || height() == Some(1)).await;
+
+        // Requests made while a slow fetch is in flight are merged into one
+        node.lock().unwrap().template_latency = Duration::from_millis(300);
+        node.lock().unwrap().chain.push([1; 32]);
+        miner.request_work();
+        sleep(Duration::from_millis(50)).await;
+        node.lock().unwrap().chain.push([2; 32]);
+        for _ in 0..5 {
+            miner.request_work();
+        }
+        wait_until("the queued fetch is answered", || requests() == 3).await;
+
+        // Only the newest template is left for the loop
+        sleep(Duration::from_millis(400)).await;
+        assert_eq!((requests(), height()), (3, Some(3)));
+        fetcher.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stats_endpoint_and_file() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let stats_bind = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let stats_file = std::env::temp_dir().join(format!("hyperion-miner-stats-{}.json", std::process::id()));
+        let config = MiningConfig {
+            node_url: node_url.clone(),
+            threads: 2,
+            stats_interval: 1,
+            stats_bind: Some(stats_bind.clone()),
+            stats_file: Some(stats_file.clone()),
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        // Blocks found before a report show up in the next one
+        wait_until("a block is mined", || node.lock().unwrap().chain.len() > 2).await;
+        let snapshot: StatsSnapshot = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Ok(response) = reqwest::get(format!("http://{}/stats", stats_bind)).await {
+                    let snapshot: StatsSnapshot = response.json().await.unwrap();
+                    if snapshot.blocks_found > 0 {
+                        return snapshot;
+                    }
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("stats never reported a block");
+        mining.abort();
+
+        assert_eq!(snapshot.upstream, node_url);
+        assert!(snapshot.connected);
+        assert!(snapshot.template_height.unwrap() > 1);
+        assert_eq!(snapshot.workers.iter().map(|w| w.id).collect::<Vec<_>>(), [0, 1]);
+        assert!(snapshot.workers.iter().map(|w| w.hashes).sum::<u64>() > 0);
+        assert!(snapshot.uptime_secs > 0.0);
+
+        let written: StatsSnapshot = serde_json::from_slice(&std::fs::read(&stats_file).unwrap()).unwrap();
+        assert!(written.connected);
+        std::fs::remove_file(stats_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_submission_outcomes_are_counted() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 1, reconnect_delay: 0, ..Default::default() };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        // Rejected, stale, then accepted twice
+        node.lock().unwrap().reject_reasons.extend(["high-hash", "stale-prevblk"]);
+        for _ in 0..4 {
+            let work = work_rx.recv().await.unwrap();
+            let result = MiningResult {
+                block: Block::new(work.header, work.transactions.to_vec()),
+                nonce: 0,
+                extranonce: 0,
+                worker_id: 0,
+                work_id: work.work_id,
+                kind: SolutionKind::Block,
+                span: work.span,
+            };
+            assert!(miner.handle_solution(result, &senders).await);
+        }
+
+        let stats = &miner.stats;
+        assert_eq!(stats.blocks_submitted.load(Ordering::SeqCst), 4);
+        assert_eq!(stats.blocks_found.load(Ordering::SeqCst), 2);
+        let rejected = stats.blocks_rejected.lock().unwrap().clone();
+        assert_eq!(rejected, [("high-hash".to_string(), 1), ("stale-prevblk".to_string(), 1)].into());
+        assert_eq!(stats.efficiency(), Some(50.0));
+
+        let snapshot = stats.take_snapshot(&miner.workers, "node", true);
+        assert_eq!((snapshot.blocks_submitted, snapshot.efficiency), (4, Some(50.0)));
+        assert_eq!(snapshot.blocks_rejected, rejected);
+    }
+
+    /// Log sink shared with the test
+    #[derive(Clone, Default)]
+    struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_found_event_carries_work_fields() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt().json().with_writer(captured.clone()).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (_node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 1, reconnect_delay: 0, ..Default::default() };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        let work = work_rx.recv().await.unwrap();
+        let block = Block::new(work.header, work.transactions.to_vec());
+        let block_hash = hex32(&block.double_sha256());
+        let result = MiningResult {
+            block,
+            nonce: 7,
+            extranonce: 0,
+            worker_id: 3,
+            work_id: work.work_id,
+            kind: SolutionKind::Block,
+            span: work.span,
+        };
+        miner.handle_solution(result, &senders).await;
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = logs.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let found = events
+            .iter()
+            .find(|event| event["fields"]["message"] == "Block found")
+            .unwrap_or_else(|| panic!("No block found event in {}", logs));
+        let fields = &found["fields"];
+        assert_eq!((fields["worker_id"].as_u64(), fields["work_id"].as_u64()), (Some(3), Some(work.work_id)));
+        assert_eq!((fields["nonce"].as_u64(), fields["height"].as_u64()), (Some(7), Some(1)));
+        assert_eq!(fields["block_hash"], block_hash);
+
+        // Recorded inside the span of the distribution the work came from
+        assert_eq!(found["span"]["name"], "work");
+        assert_eq!(found["span"]["work_id"].as_u64(), Some(work.work_id));
+    }
+
+    /// Submit the block mined from the next work `work_rx` gets
+    async fn submit_next(miner: &SoloMiner, work_rx: &mut mpsc::Receiver<WorkItem>, senders: &[mpsc::Sender<WorkItem>]) {
+        let work = work_rx.recv().await.unwrap();
+        let result = MiningResult {
+            block: Block::new(work.header, work.transactions.to_vec()),
+            nonce: 0,
+            extranonce: 0,
+            worker_id: 0,
+            work_id: work.work_id,
+            kind: SolutionKind::Block,
+            span: work.span,
+        };
+        miner.handle_solution(result, senders).await;
+    }
+
+    /// Run due retries until none are pending
+    async fn drain_retries(miner: &SoloMiner) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !miner.pending_submissions.lock().await.is_empty() {
+                sleep(Duration::from_millis(50)).await;
+                miner.retry_submissions().await;
+            }
+        })
+        .await
+        .expect("submissions still pending");
+    }
+
+    #[tokio::test]
+    async fn test_failed_submission_is_retried_once_accepted() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 1, ..Default::default() };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        node.lock().unwrap().submit_failures = 2;
+        submit_next(&miner, &mut work_rx, &senders).await;
+        assert_eq!(miner.pending_submissions.lock().await.len(), 1);
+        drain_retries(&miner).await;
+
+        // Two failures, then one submission the node took, and no more after it
+        sleep(Duration::from_millis(300)).await;
+        miner.retry_submissions().await;
+        let node = node.lock().unwrap();
+        assert_eq!(node.requests("failed_submit"), 2);
+        assert_eq!(node.submissions, HashMap::from([(1, 1)]));
+        assert_eq!(node.chain.len(), 2);
+
+        let snapshot = miner.stats.take_snapshot(&miner.workers, "node", true);
+        assert_eq!((snapshot.blocks_found, snapshot.blocks_submitted, snapshot.blocks_lost), (1, 1, 0));
+        assert!(snapshot.blocks_rejected.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_solutions_are_audited() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let dir = std::env::temp_dir().join(format!("hyperion-miner-solutions-{}", std::process::id()));
+        let config = MiningConfig {
+            node_url,
+            threads: 1,
+            reconnect_delay: 0,
+            solution_log_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        // Accepted on the retry after a failure, then rejected
+        node.lock().unwrap().submit_failures = 1;
+        submit_next(&miner, &mut work_rx, &senders).await;
+        drain_retries(&miner).await;
+        node.lock().unwrap().reject_reasons.push_back("high-hash");
+        submit_next(&miner, &mut work_rx, &senders).await;
+
+        // And one for replaced work
+        let work = work_rx.recv().await.unwrap();
+        let stale = MiningResult {
+            block: Block::new(work.header, Vec::new()),
+            nonce: 9,
+            extranonce: 0,
+            worker_id: 0,
+            work_id: work.work_id - 1,
+            kind: SolutionKind::Block,
+            span: work.span,
+        };
+        miner.handle_solution(stale, &senders).await;
+
+        let content = std::fs::read_to_string(dir.join(SOLUTION_LOG_FILE)).unwrap();
+        let records: Vec<SolutionRecord> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let outcomes: Vec<SolutionOutcome> = records.iter().map(|record| record.outcome).collect();
+        use SolutionOutcome::*;
+        assert_eq!(outcomes, [Failed, Accepted, Rejected, Dropped]);
+
+        let (failed, accepted, rejected, dropped) = (&records[0], &records[1], &records[2], &records[3]);
+        assert_eq!(failed.block_hash, accepted.block_hash);
+        assert_eq!((accepted.height, accepted.work_id), (Some(1), 1));
+        assert!(failed.reject_reason.is_some() && accepted.reject_reason.is_none());
+        assert!(accepted.latency_ms.is_some());
+        // Fetched before the retry went through, so for the same height
+        assert_eq!((rejected.height, rejected.work_id), (Some(1), 2));
+        assert_eq!(rejected.reject_reason.as_deref(), Some("high-hash"));
+        assert_eq!((dropped.height, dropped.nonce, dropped.latency_ms), (None, 9, None));
+        assert_eq!(hex::encode(Block::from_bytes(&hex::decode(&accepted.block_hex).unwrap()).unwrap().double_sha256()), accepted.block_hash);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_once_the_chain_moves_on() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 1, ..Default::default() };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        node.lock().unwrap().submit_failures = 1;
+        submit_next(&miner, &mut work_rx, &senders).await;
+        // Another miner's block lands at the same height first
+        node.lock().unwrap().chain.push([9; 32]);
+        drain_retries(&miner).await;
+
+        assert!(node.lock().unwrap().submissions.is_empty());
+        let snapshot = miner.stats.take_snapshot(&miner.workers, "node", true);
+        assert_eq!((snapshot.blocks_found, snapshot.blocks_submitted, snapshot.blocks_lost), (0, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_retry_finds_the_block_on_the_chain() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 1, ..Default::default() };
+        let miner = SoloMiner::new(config).await.unwrap();
+        let (work_tx, mut work_rx) = mpsc::channel(10);
+        let senders = [work_tx];
+        miner.get_and_distribute_work(&senders).await.unwrap();
+
+        node.lock().unwrap().submit_failures = 1;
+        submit_next(&miner, &mut work_rx, &senders).await;
+        // The failed attempt reached the node after all; only its answer was lost
+        let block = miner.pending_submissions.lock().await[0].block.double_sha256();
+        node.lock().unwrap().chain.push(block);
+        drain_retries(&miner).await;
+
+        assert!(node.lock().unwrap().submissions.is_empty());
+        let snapshot = miner.stats.take_snapshot(&miner.workers, "node", true);
+        assert_eq!((snapshot.blocks_found, snapshot.blocks_submitted, snapshot.blocks_lost), (1, 1, 0));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_reload_resizes_workers_and_intervals() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig { node_url: node_url.clone(), threads: 2, tip_poll_interval: 60_000, ..Default::default() };
+        let mut miner = SoloMiner::new(config.clone()).await.unwrap();
+        let (result_tx, _result_rx) = mpsc::channel(10);
+        miner.result_tx = Some(result_tx);
+        for worker in miner.workers.clone() {
+            miner.spawn_worker(&worker).unwrap();
+        }
+        let (tip_tx, _tip_rx) = mpsc::channel(1);
+        let tip_watcher = miner.spawn_tip_watcher(tip_tx);
+        miner.get_and_distribute_work(&miner.work_senders).await.unwrap();
+        let polls = || node.lock().unwrap().requests("get_blockchain_info");
+
+        // More threads, a faster tip poll, and a node_url that needs a restart
+        let reloaded = MiningConfig {
+            threads: 4,
+            tip_poll_interval: 20,
+            node_url: "http://127.0.0.1:1".to_string(),
+            ..config.clone()
+        };
+        miner.reload_config(reloaded).await.unwrap();
+        assert_eq!(miner.workers.len(), 4);
+        assert_eq!(miner.workers_tx.borrow().len(), 4);
+        assert_eq!(miner.config.node_url, node_url);
+        assert_eq!(miner.config.tip_poll_interval, 20);
+
+        // Every worker, old and new, moves onto the redistributed work
+        let workers = miner.workers.clone();
+        wait_until("all workers mine the redistributed work", || {
+            workers.iter().all(|w| w.current_work_id.load(Ordering::SeqCst) == 2)
+        })
+        .await;
+        let before = polls();
+        wait_until("the tip is polled at the new interval", || polls() >= before + 3).await;
+
+        miner.reload_config(MiningConfig { threads: 1, ..config.clone() }).await.unwrap();
+        assert_eq!((miner.workers.len(), miner.work_senders.len()), (1, 1));
+        assert!(workers[1..].iter().all(|w| !w.running.load(Ordering::SeqCst)));
+
+        // Invalid configs are refused whole
+        assert!(miner.reload_config(MiningConfig { threads: 0, ..config }).await.is_err());
+        assert_eq!(miner.workers.len(), 1);
+
+        tip_watcher.abort();
+        miner.stop().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_node_restart_reconnects_once_and_resumes() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig {
+            node_url,
+            threads: 2,
+            tip_poll_interval: 20,
+            reconnect_delay: 0,
+            request_retries: 0,
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let work_id = workers[0].current_work_id.clone();
+        let connected = miner.node_connected.clone();
+        let reconnects = miner.stats.reconnects.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+        wait_until("the first work arrives", || work_id.load(Ordering::SeqCst) == 1).await;
+
+        node.lock().unwrap().down = true;
+        wait_until("mining pauses", || !connected.load(Ordering::SeqCst)).await;
+        sleep(Duration::from_millis(200)).await;
+        node.lock().unwrap().down = false;
+
+        wait_until("work is distributed again", || work_id.load(Ordering::SeqCst) == 2).await;
+        assert!(connected.load(Ordering::SeqCst));
+
+        // Steady again: one cycle, and no further work churn
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+        assert_eq!(work_id.load(Ordering::SeqCst), 2);
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_gives_up_after_failed_reconnects() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig {
+            node_url,
+            threads: 1,
+            tip_poll_interval: 20,
+            reconnect_delay: 0,
+            request_retries: 0,
+            fail_after: Some(3),
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+        wait_until("requests arrive", || node.lock().unwrap().requests.contains_key("get_block_template")).await;
+
+        node.lock().unwrap().down = true;
+        let outcome = tokio::time::timeout(Duration::from_secs(10), mining).await.expect("miner kept retrying");
+        assert_eq!(outcome.unwrap().unwrap(), MiningOutcome::GaveUp(3));
+    }
+
+    #[tokio::test]
+    async fn test_node_on_another_chain_is_refused() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        node.lock().unwrap().genesis_hash = "ee".repeat(32);
+        let config = MiningConfig { node_url, threads: 1, ..Default::default() };
+
+        let error = SoloMiner::new(config.clone()).await.err().expect("Expected the node to be refused");
+        let message = error.to_string();
+        assert!(message.contains(&"ee".repeat(32)) && message.contains("allow_incompatible_node"), "{}", message);
+        assert_eq!(node.lock().unwrap().requests("get_block_template"), 0);
+
+        let miner = SoloMiner::new(MiningConfig { allow_incompatible_node: true, ..config.clone() }).await.unwrap();
+        assert!(miner.node_connected.load(Ordering::Acquire));
+
+        // Nodes from before the handshake are only warned about
+        {
+            let mut node = node.lock().unwrap();
+            node.genesis_hash = hex::encode(create_genesis_block().double_sha256());
+            node.unversioned = true;
+        }
+        let miner = SoloMiner::new(config).await.unwrap();
+        assert!(miner.node_connected.load(Ordering::Acquire));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_once_returns_after_first_accepted_block() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let config = MiningConfig { node_url, threads: 2, max_blocks: Some(1), ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+
+        let outcome = tokio::time::timeout(Duration::from_secs(10), miner.start_mining())
+            .await
+            .expect("miner kept mining past its block target")
+            .unwrap();
+        assert_eq!(outcome, MiningOutcome::BlockTarget(1));
+        assert_eq!(node.lock().unwrap().chain.len(), 2);
+        assert!(miner.worker_threads.is_empty() && miner.tasks.is_empty());
+        assert!(workers.iter().all(|w| !w.running.load(Ordering::SeqCst)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_max_duration_without_blocks_exits_distinctly() {
+        let (_node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig { node_url, threads: 1, max_duration: Some(1), ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+
+        let started = Instant::now();
+        let outcome = tokio::time::timeout(Duration::from_secs(10), miner.start_mining())
+            .await
+            .expect("miner kept mining past its duration")
+            .unwrap();
+        assert_eq!(outcome, MiningOutcome::DurationExpired(0));
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rejection_backoff_keeps_the_loop_running() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        node.lock().unwrap().reject_reasons.push_back("high-hash");
+        let config = MiningConfig { node_url, threads: 1, reconnect_delay: 60, max_duration: Some(1), ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+
+        // The deadline still fires while the miner waits out the rejection
+        let outcome = tokio::time::timeout(Duration::from_secs(10), miner.start_mining())
+            .await
+            .expect("backoff held up the mining loop")
+            .unwrap();
+        assert_eq!(outcome, MiningOutcome::DurationExpired(0));
+        assert_eq!(node.lock().unwrap().requests("submit_block"), 1);
+        assert_eq!(node.lock().unwrap().requests("get_block_template"), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_pause_and_resume() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let stats_bind = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let config = MiningConfig {
+            node_url,
+            threads: 2,
+            stats_bind: Some(stats_bind.clone()),
+            control_token: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let control = miner.control();
+        let stats = miner.stats.clone();
+        let workers = miner.workers.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        let hashes = || workers.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).sum::<u64>();
+        let templates = || node.lock().unwrap().requests("get_block_template");
+        wait_until("the workers hash", || hashes() > 0).await;
+
+        control.pause();
+        wait_until("mining pauses", || stats.is_paused()).await;
+        // Let the batches in flight finish
+        sleep(Duration::from_millis(100)).await;
+        let paused_at = hashes();
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(hashes(), paused_at, "workers hashed while paused");
+
+        // Resuming over HTTP needs the token, and then fetches fresh work
+        let fetched = templates();
+        let resume = |token: &str| {
+            reqwest::Client::new()
+                .post(format!("http://{}/control/resume", stats_bind))
+                .bearer_auth(token)
+                .send()
+        };
+        let response = resume("wrong").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(stats.is_paused());
+        let response = resume("hunter2").await.unwrap();
+        assert!(response.status().is_success());
+        wait_until("the workers hash again", || hashes() > paused_at).await;
+        assert!(!stats.is_paused());
+        assert_eq!(templates(), fetched + 1);
+
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_scaling_threads_keeps_work_and_counters() {
+        let (node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let stats_bind = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let config = MiningConfig {
+            node_url,
+            threads: 4,
+            stats_interval: 3600,
+            stats_bind: Some(stats_bind.clone()),
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let control = miner.control();
+        let stats = miner.stats.clone();
+        let workers_rx = miner.workers_tx.subscribe();
+        let first = miner.workers.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        let workers = || workers_rx.borrow().clone();
+        let on_work = |count: usize, work_id: u64| {
+            let workers = workers();
+            workers.len() == count && workers.iter().all(|w| w.current_work_id.load(Ordering::SeqCst) == work_id)
+        };
+        let listed = || stats.snapshot.lock().unwrap().workers.len();
+        let templates = || node.lock().unwrap().requests("get_block_template");
+        wait_until("four workers hash the first work", || {
+            on_work(4, 1) && first.iter().all(|w| w.hashes_computed.load(Ordering::Relaxed) > 0)
+        })
+        .await;
+        let fetched = templates();
+
+        // Scaling down stops the last two; the rest take the template up again
+        control.set_threads(2);
+        wait_until("two workers mine the redistributed work", || on_work(2, 2)).await;
+        wait_until("the stats list two workers", || listed() == 2).await;
+        assert!(first[2..].iter().all(|w| !w.running.load(Ordering::SeqCst)));
+
+        // Scaling up over HTTP starts four fresh workers on it
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/control/threads", stats_bind))
+            .json(&serde_json::json!({ "count": 6 }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        wait_until("six workers mine the redistributed work", || on_work(6, 3)).await;
+        wait_until("the stats list six workers", || listed() == 6).await;
+        assert_eq!(templates(), fetched, "resizing fetched a new template");
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/control/threads", stats_bind))
+            .json(&serde_json::json!({ "count": 0 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // Once the workers are idle, every hash of the removed ones is still
+        // in the total
+        control.pause();
+        wait_until("mining pauses", || stats.is_paused()).await;
+        sleep(Duration::from_millis(200)).await;
+        let snapshot = stats.take_snapshot(&workers(), "node", true);
+        let removed: u64 = first[2..].iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).sum();
+        let listed_hashes: u64 = snapshot.workers.iter().map(|w| w.hashes).sum();
+        assert_eq!(snapshot.workers.len(), 6);
+        assert_eq!(snapshot.retired_hashes, removed);
+        assert_eq!(snapshot.total_hashes, listed_hashes + removed);
+
+        mining.abort();
+        workers().iter().for_each(MiningWorker::stop);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stop_joins_workers_and_ends_tasks() {
+        let (_node, node_url) = serve_mock(IMPOSSIBLE, false).await;
+        let config = MiningConfig { node_url, threads: 2, ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let hashes = || workers.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).sum::<u64>();
+
+        // Dropped mid-loop, as main's ctrl_c branch drops it
+        tokio::select! {
+            result = miner.start_mining() => panic!("mining ended early: {:?}", result),
+            _ = wait_until("the workers hash", || hashes() > 0) => {}
         }
+
+        let started = Instant::now();
+        miner.stop().await.unwrap();
+        assert!(started.elapsed() < worker::WORKER_JOIN_TIMEOUT, "worker threads outlived the timeout");
+        assert!(miner.worker_threads.is_empty() && miner.tasks.is_empty());
+        assert!(workers.iter().all(|w| !w.running.load(Ordering::SeqCst)));
+
+        let stopped_at = hashes();
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(hashes(), stopped_at, "workers hashed after stop");
     }
-}
\ No newline at end of file
+
+    /// Blocks go from the workers through submission and the node's own
+    /// checks, and each accepted one moves the work onto the next height
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mines_and_submits_valid_blocks() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        {
+            let mut node = node.lock().unwrap();
+            node.check_blocks = true;
+            node.latency = Duration::from_millis(20);
+        }
+        let config = MiningConfig { node_url, threads: 2, max_blocks: Some(3), ..Default::default() };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+
+        let outcome = tokio::time::timeout(Duration::from_secs(10), miner.start_mining())
+            .await
+            .expect("miner never reached its block target")
+            .unwrap();
+        assert_eq!(outcome, MiningOutcome::BlockTarget(3));
+
+        let node = node.lock().unwrap();
+        assert_eq!(node.chain.len(), 4);
+        assert_eq!(node.submissions, HashMap::from([(1, 1), (2, 1), (3, 1)]));
+        let snapshot = miner.stats.take_snapshot(&miner.workers, "node", true);
+        assert!(snapshot.blocks_rejected.is_empty());
+        assert!(snapshot.submit_latency.unwrap().p50_ms >= 20.0);
+    }
+
+    /// A template the chain has already moved past is mined, refused as
+    /// stale, and replaced with one on the tip
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stale_template_is_refreshed() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        {
+            let mut node = node.lock().unwrap();
+            node.check_blocks = true;
+            // Another miner's block lands after the first template is built
+            let stale = node.template_on(0);
+            node.templates.push_back(stale);
+            node.chain.push([1; 32]);
+        }
+        let config = MiningConfig {
+            node_url,
+            threads: 2,
+            tip_poll_interval: 60_000,
+            max_blocks: Some(1),
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+
+        let outcome = tokio::time::timeout(Duration::from_secs(10), miner.start_mining())
+            .await
+            .expect("miner never mined on the new tip")
+            .unwrap();
+        assert_eq!(outcome, MiningOutcome::BlockTarget(1));
+
+        let node = node.lock().unwrap();
+        assert_eq!(node.submissions, HashMap::from([(1, 1), (2, 1)]));
+        assert_eq!(node.chain.len(), 3);
+        let rejected = miner.stats.blocks_rejected.lock().unwrap().clone();
+        assert_eq!(rejected, [("stale-prevblk".to_string(), 1)].into());
+    }
+
+    /// The node goes away mid-run: mining pauses, picks up again once it's
+    /// back, and blocks are accepted as before
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mining_resumes_after_outage() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        {
+            let mut node = node.lock().unwrap();
+            node.check_blocks = true;
+            node.latency = Duration::from_millis(10);
+            node.outage(Duration::from_millis(300), Duration::from_millis(500));
+        }
+        let config = MiningConfig {
+            node_url,
+            threads: 2,
+            tip_poll_interval: 20,
+            reconnect_delay: 0,
+            request_retries: 0,
+            ..Default::default()
+        };
+        let mut miner = SoloMiner::new(config).await.unwrap();
+        let workers = miner.workers.clone();
+        let connected = miner.node_connected.clone();
+        let reconnects = miner.stats.reconnects.clone();
+        let mining = tokio::spawn(async move { miner.start_mining().await });
+
+        wait_until("blocks are accepted", || node.lock().unwrap().chain.len() > 2).await;
+        wait_until("mining pauses for the outage", || !connected.load(Ordering::SeqCst)).await;
+        let before = node.lock().unwrap().chain.len();
+        wait_until("the miner reconnects", || reconnects.load(Ordering::SeqCst) == 1).await;
+        wait_until("blocks are accepted again", || node.lock().unwrap().chain.len() > before + 2).await;
+        mining.abort();
+        workers.iter().for_each(MiningWorker::stop);
+
+        assert!(connected.load(Ordering::SeqCst));
+        assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+        assert!(node.lock().unwrap().requests("get_block_template") > before);
+    }
+}
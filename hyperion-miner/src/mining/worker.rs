@@ -1,35 +1,267 @@
+use super::backend::MiningBackend;
+use crate::config::NonceStrategy;
+
+use hyperion_core::block::block::compute_merkle_root;
 use hyperion_core::block::{Block, Header, Transaction};
-use hyperion_core::consensus::mine_block;
+use hyperion_core::crypto::{Hashable, HASH_SIZE};
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, watch};
-use tracing::{debug, info, error};
+use tracing::{debug, error, info, warn, Span};
+
+
+/// How often a paused worker checks whether the node is back
+const OFFLINE_POLL: Duration = Duration::from_millis(250);
+
+/// How often `join_all` checks whether the threads have exited
+const JOIN_POLL: Duration = Duration::from_millis(10);
+
+/// Longest a throttled worker sleeps before checking for cancellation or
+/// shutdown again
+const THROTTLE_SLICE: Duration = Duration::from_millis(10);
+
+/// Batches hashed between each one-second advance of the header time
+const TIME_ROLL_BATCHES: u64 = 100;
+
+/// Smallest and largest number of nonces hashed between checks for
+/// cancellation, and between flushes of the worker's hash count
+pub const MIN_BATCH_SIZE: u64 = 100;
+pub const MAX_BATCH_SIZE: u64 = 1 << 32;
+
+/// How long shutdown waits for mining threads to finish their current batch
+pub const WORKER_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Leads the input of the extranonce transaction, which sits where a
+/// coinbase would
+pub const EXTRANONCE_TAG: &[u8] = b"hyperion-extranonce";
+
+/// Nonces in a header
+const NONCE_SPACE: u128 = 1 << 64;
+
+/// Weight of the fastest worker when sizing nonce ranges by hashrate
+const WEIGHT_SCALE: u128 = 1 << 32;
+
+/// Pieces of work a worker's hashrate is measured over
+const RATE_WINDOW: usize = 5;
+
+/// Odd multiplier spreading random-offset bases for successive work IDs
+/// across the nonce space (2^64 over the golden ratio)
+const OFFSET_STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Nonces one worker covers: `count` of them, `stride` apart from `start`,
+/// wrapping around the nonce space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceRange {
+    pub start: u64,
+    /// All 2^64 nonces don't fit; a lone worker stops one short
+    pub count: u64,
+    pub stride: u64,
+}
+
+impl NonceRange {
+    /// The `index`th nonce of the range
+    pub fn nonce(&self, index: u64) -> u64 {
+        self.start.wrapping_add(index.wrapping_mul(self.stride))
+    }
+}
+
+/// Divide the nonce space between workers measured at `rates` for work
+/// `work_id`, with no nonce in two ranges and none left out. `seed` varies
+/// the random-offset base between miners.
+///
+/// Contiguous ranges are sized in proportion to the rates, so every worker
+/// gets through its share in about the same time; workers not yet measured
+/// are taken to be as fast as the average of the others. Interleaved
+/// ranges can't be weighted and are always even.
+pub fn partition_nonces(strategy: NonceStrategy, seed: u64, work_id: u64, rates: &[Option<f64>]) -> Vec<NonceRange> {
+    let workers = rates.len().max(1) as u128;
+    let base = match strategy {
+        NonceStrategy::RandomOffset => seed.wrapping_add(work_id.wrapping_mul(OFFSET_STEP)),
+        NonceStrategy::Split | NonceStrategy::Interleave => 0,
+    };
+    let weights = nonce_weights(rates);
+    let total: u128 = weights.iter().sum();
+    let bounds: Vec<u128> = std::iter::once(0)
+        .chain(weights.iter().scan(0, |sum, weight| {
+            *sum += weight;
+            Some(*sum * NONCE_SPACE / total)
+        }))
+        .collect();
+
+    (0..workers)
+        .map(|i| {
+            let (offset, count, stride) = match strategy {
+                NonceStrategy::Interleave => (i, (NONCE_SPACE - i).div_ceil(workers), workers),
+                NonceStrategy::Split | NonceStrategy::RandomOffset => {
+                    let (offset, end) = (bounds[i as usize], bounds[i as usize + 1]);
+                    (offset, end - offset, 1)
+                }
+            };
+            NonceRange {
+                start: base.wrapping_add(offset as u64),
+                count: count.min(u64::MAX as u128) as u64,
+                stride: stride as u64,
+            }
+        })
+        .collect()
+}
+
+/// `rates` as whole-number weights, the fastest worker at `WEIGHT_SCALE`
+/// and none below 1. Unmeasured workers get the average of the measured
+/// ones, and if there are none every worker weighs the same.
+fn nonce_weights(rates: &[Option<f64>]) -> Vec<u128> {
+    if rates.is_empty() {
+        return vec![1];
+    }
+    let measured = |rate: &Option<f64>| rate.filter(|rate| rate.is_finite() && *rate > 0.0);
+    let known: Vec<f64> = rates.iter().filter_map(measured).collect();
+    let fill = if known.is_empty() { 1.0 } else { known.iter().sum::<f64>() / known.len() as f64 };
+    let rates: Vec<f64> = rates.iter().map(|rate| measured(rate).unwrap_or(fill)).collect();
+    let fastest = rates.iter().copied().fold(fill, f64::max);
+    rates.iter().map(|rate| ((rate / fastest * WEIGHT_SCALE as f64) as u128).max(1)).collect()
+}
+
+/// Size of the batch after one of `size` nonces that hashed `hashed` in
+/// `took`, aiming for `target`. Moves at most a factor of 2 at a time, so
+/// one batch slowed by something else doesn't throw the size far off.
+pub fn next_batch_size(size: u64, hashed: u64, took: Duration, target: Duration) -> u64 {
+    if hashed == 0 || took.is_zero() {
+        return size;
+    }
+    let ideal = hashed as f64 / took.as_secs_f64() * target.as_secs_f64();
+    let next = ideal.clamp(size as f64 / 2.0, size as f64 * 2.0) as u64;
+    next.clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE)
+}
 
+/// Each worker's share of the nonce space, for logs
+pub fn format_nonce_shares(nonces: &[NonceRange]) -> String {
+    nonces
+        .iter()
+        .enumerate()
+        .map(|(i, range)| format!("{} {:.1}%", i, range.count as f64 / NONCE_SPACE as f64 * 100.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
 #[derive(Clone)]
 pub struct WorkItem {
     pub header: Header,
-    pub nonce_start: u64,
-    pub nonce_range: u64,
-    pub transactions: Vec<Transaction>,
+    pub nonces: NonceRange,
+    /// Shared by every item split from the same work; copied only into a
+    /// solved block
+    pub transactions: Arc<Vec<Transaction>>,
     pub work_id: u64,
     pub cancel_rx: watch::Receiver<bool>,
-    pub solution_found: Arc<AtomicBool>,
+    /// Pool share target. Hashes meeting it but not the block target are
+    /// reported as shares and mining carries on.
+    pub share_target: Option<[u8; HASH_SIZE]>,
+    /// Latest time the worker may roll the header to; the header's own time
+    /// disables rolling
+    pub max_time: u32,
+    /// First extranonce to mine with; 0 mines the work's transactions as they are
+    pub extranonce_start: u64,
+    /// Span of the distribution this item came from; worker events and
+    /// solutions are recorded inside it
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionKind {
+    /// Meets the header's difficulty
+    Block,
+    /// Meets only the pool's share target
+    Share,
 }
 
 pub struct MiningResult {
     pub block: Block,
     pub nonce: u64,
+    /// 0 when the block holds just the work's transactions, otherwise the
+    /// value in its leading extranonce transaction
+    pub extranonce: u64,
     pub worker_id: usize,
+    /// The work the block was mined from, so stale solutions can be dropped
+    pub work_id: u64,
+    pub kind: SolutionKind,
+    /// The work's span, so the solution can be traced back to its distribution
+    pub span: Span,
+}
+
+impl WorkItem {
+    /// One item per range of `nonces`, each mining `header` over its range
+    pub fn split(
+        header: Header,
+        transactions: Arc<Vec<Transaction>>,
+        work_id: u64,
+        cancel_rx: watch::Receiver<bool>,
+        share_target: Option<[u8; HASH_SIZE]>,
+        nonces: Vec<NonceRange>,
+    ) -> Vec<WorkItem> {
+        nonces
+            .into_iter()
+            .map(|nonces| WorkItem {
+                header: header.clone(),
+                nonces,
+                transactions: transactions.clone(),
+                work_id,
+                cancel_rx: cancel_rx.clone(),
+                share_target,
+                max_time: header.time,
+                extranonce_start: 0,
+                span: Span::none(),
+            })
+            .collect()
+    }
+}
+
+/// Keeps a value on its own cache line, so counters each worker thread
+/// writes don't contend with their neighbours'
+#[derive(Debug, Default)]
+#[repr(align(128))]
+pub struct CachePadded<T>(pub T);
+
+impl<T> std::ops::Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// How a worker's OS thread is set up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadOptions {
+    /// Core to pin the thread to
+    pub core: Option<usize>,
+    /// Run at the lowest scheduling priority
+    pub low_priority: bool,
 }
 
 #[derive(Clone)]
 pub struct MiningWorker {
     pub id: usize,
     pub running: Arc<AtomicBool>,
-    pub hashes_computed: Arc<AtomicU64>,
+    pub hashes_computed: Arc<CachePadded<AtomicU64>>,
+    /// Hashes computed since the stats task last took them
+    pub interval_hashes: Arc<CachePadded<AtomicU64>>,
+    /// Work most recently received. Replaced work is stopped through its
+    /// cancel channel, which whoever sends the new work signals first.
     pub current_work_id: Arc<AtomicU64>,
     pub node_connected: Arc<AtomicBool>,
+    /// Fraction of the time spent hashing, as `f64` bits
+    intensity: Arc<AtomicU64>,
+    /// Nonces per batch, resized after every batch when `batch_time` is set
+    batch_size: Arc<AtomicU64>,
+    /// Wall time to size batches for, in ms; 0 keeps `batch_size` fixed
+    batch_time: Arc<AtomicU64>,
+    /// Name of the backend the thread hashes with, set when it's spawned
+    backend: Arc<Mutex<&'static str>>,
+    /// Hashes computed and time taken on each of the latest `RATE_WINDOW`
+    /// pieces of work
+    work_rates: Arc<Mutex<VecDeque<(u64, Duration)>>>,
 }
 
 impl MiningWorker {
@@ -37,141 +269,808 @@ impl MiningWorker {
         Self {
             id,
             running: Arc::new(AtomicBool::new(false)),
-            hashes_computed: Arc::new(AtomicU64::new(0)),
+            hashes_computed: Arc::default(),
+            interval_hashes: Arc::default(),
             current_work_id: Arc::new(AtomicU64::new(0)),
             node_connected,
+            intensity: Arc::new(AtomicU64::new(1.0f64.to_bits())),
+            batch_size: Arc::new(AtomicU64::new(10_000)),
+            batch_time: Arc::default(),
+            backend: Arc::new(Mutex::new("cpu")),
+            work_rates: Arc::default(),
         }
     }
 
-    pub async fn start(
+    /// Run the worker on its own OS thread, hashing with `backend`, so
+    /// hashing never competes with the tokio runtime. The thread exits once
+    /// stopped and its work channel is closed.
+    pub fn spawn(
+        &self,
+        work_rx: mpsc::Receiver<WorkItem>,
+        result_tx: mpsc::Sender<MiningResult>,
+        options: ThreadOptions,
+        mut backend: Box<dyn MiningBackend>,
+    ) -> std::io::Result<JoinHandle<()>> {
+        *self.backend.lock().unwrap() = backend.name();
+        let worker = self.clone();
+        thread::Builder::new()
+            .name(format!("miner-{}", self.id))
+            .spawn(move || {
+                if let Some(id) = options.core
+                    && !core_affinity::set_for_current(core_affinity::CoreId { id })
+                {
+                    warn!(worker_id = worker.id, core = id, "Failed to pin worker to its core, mining unpinned");
+                }
+                if options.low_priority && !lower_thread_priority() {
+                    warn!(worker_id = worker.id, "Failed to lower the worker's priority");
+                }
+                worker.run(work_rx, result_tx, backend.as_mut())
+            })
+    }
+
+    fn run(
         &self,
         mut work_rx: mpsc::Receiver<WorkItem>,
         result_tx: mpsc::Sender<MiningResult>,
+        backend: &mut dyn MiningBackend,
     ) {
-        self.running.store(true, Ordering::SeqCst);
-        debug!("Mining worker {} started", self.id);
-
-        while self.running.load(Ordering::SeqCst) {
-            tokio::select! {
-                work_item = work_rx.recv() => {
-                    match work_item {
-                        Some(work) => {
-                            self.current_work_id.store(work.work_id, Ordering::SeqCst);
-
-                            if let Some(result) = self.mine_work(work).await {
-                                if result_tx.send(result).await.is_err() {
-                                    error!("Failed to send mining result");
-                                    break;
-                                }
-                            }
-                        },
-                        None => break,  // Channel closed
-                    }
-                },
-                _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {
-                    // Periodic check to ensure we're responsive to shutdown
-                }
+        self.running.store(true, Ordering::Release);
+        debug!(worker_id = self.id, "Mining worker started");
+
+        // Blocks until new work arrives or the sender side is dropped
+        while let Some(work) = work_rx.blocking_recv() {
+            if !self.running.load(Ordering::Acquire) {
+                break;
+            }
+            self.current_work_id.store(work.work_id, Ordering::Release);
+
+            let started = Instant::now();
+            let hashed_before = backend.hash_count();
+            let sent = self.mine_work(backend, work, &result_tx);
+            self.record_work(backend.hash_count() - hashed_before, started.elapsed());
+            if !sent {
+                error!(worker_id = self.id, "Failed to send mining result");
+                break;
             }
         }
 
-        debug!("Mining worker {} stopped", self.id);
+        debug!(worker_id = self.id, "Mining worker stopped");
     }
 
-    pub async fn mine_work(&self, work: WorkItem) -> Option<MiningResult> {
+    /// Hash `work` with `backend` until it is cancelled or a block is found,
+    /// sending every solution to `result_tx`. Returns false once nothing is
+    /// receiving results. A backend that fails stops the worker.
+    ///
+    /// When the nonce range runs out the worker rolls its extranonce: a
+    /// transaction placed ahead of the work's own, which changes the merkle
+    /// root and so gives the same nonces fresh hashes. The header time also
+    /// advances a second every `TIME_ROLL_BATCHES` batches, up to the work's
+    /// `max_time`.
+    pub fn mine_work(
+        &self,
+        backend: &mut dyn MiningBackend,
+        work: WorkItem,
+        result_tx: &mpsc::Sender<MiningResult>,
+    ) -> bool {
         let mut header = work.header.clone();
-        let start_nonce = work.nonce_start;
-        let end_nonce = start_nonce + work.nonce_range;
+        let block_target = header.compact_to_target();
+        // Backends look for the easier of the targets; the hash tells which was met
+        let scan_target = work.share_target.map_or(block_target, |share| share.max(block_target));
+        let nonces = work.nonces;
         let work_id = work.work_id;
         let cancel_rx = work.cancel_rx;
+        let mut transactions = work.transactions.clone();
+        let mut batches: u64 = 0;
+        let span = work.span;
+        let _entered = span.enter();
 
         debug!(
-            "Worker {} mining nonce range {} to {}",
-            self.id, start_nonce, end_nonce
+            worker_id = self.id,
+            work_id,
+            start = nonces.start,
+            count = nonces.count,
+            stride = nonces.stride,
+            "Mining nonce range"
         );
 
-        const BATCH_SIZE: u64 = 10000;
-        
-        for batch_start in (start_nonce..end_nonce).step_by(BATCH_SIZE as usize) {
-            // println!("BATCH: {}", batch_start);
-            // println!("STATUS: {}", self.node_connected.load(Ordering::SeqCst));
-
-            // Pause if node if offline
-            while !self.node_connected.load(Ordering::SeqCst) {
-                debug!("Worker {} paused (node offline)", self.id);
-                tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        for extranonce in work.extranonce_start.. {
+            if extranonce > 0 {
+                if extranonce > work.extranonce_start {
+                    debug!(worker_id = self.id, work_id, extranonce, "Nonces exhausted, rolling extranonce");
+                }
+                transactions = Arc::new(with_extranonce(&work.transactions, extranonce));
+                header.merkle_root = compute_merkle_root(&transactions);
             }
 
-            // Check if we should continue with this work
-            if !self.running.load(Ordering::SeqCst)
-                || *cancel_rx.borrow()
-                || work.solution_found.load(Ordering::SeqCst) {
-                debug!("Worker {} work cancelled or stopped", self.id);
-                return None;
-            }
+            // Batches run over indexes into the range, which map to nonces
+            let mut batch_start = 0;
+            while batch_start < nonces.count {
+                // Pause while the node is offline
+                if !self.node_connected.load(Ordering::Acquire) {
+                    debug!(worker_id = self.id, "Worker paused, node offline");
+                    while !self.node_connected.load(Ordering::Acquire) {
+                        if !self.running.load(Ordering::Acquire) {
+                            return true;
+                        }
+                        thread::sleep(OFFLINE_POLL);
+                    }
+                }
 
-            // Check if work is stale (new work arrived)
-            if self.current_work_id.load(Ordering::SeqCst) != work_id {
-                debug!("Worker {} abandoning state work ID {}", self.id, work_id);
-                return None;
-            }
+                // Check if we should continue with this work
+                if !self.running.load(Ordering::Acquire) || *cancel_rx.borrow() {
+                    debug!(worker_id = self.id, work_id, "Work cancelled or worker stopped");
+                    return true;
+                }
+
+                batches += 1;
+                if batches.is_multiple_of(TIME_ROLL_BATCHES) && header.time < work.max_time {
+                    header.time += 1;
+                }
+
+                let batch_size = self.batch_size.load(Ordering::Relaxed);
+                let batch_end = batch_start.saturating_add(batch_size).min(nonces.count);
+                let batch_started = Instant::now();
+                let hashed_before = backend.hash_count();
+                let mut from = batch_start;
+
+                while from < batch_end {
+                    let index = match backend.scan_nonces(&header, &scan_target, nonces, from..batch_end, &cancel_rx) {
+                        Ok(Some(index)) => index,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!(worker_id = self.id, backend = backend.name(), "Backend failed, stopping worker: {:#}", e);
+                            self.count_hashes(backend.hash_count() - hashed_before);
+                            self.stop();
+                            return true;
+                        }
+                    };
+                    from = index + 1;
+
+                    // Check the backend's answer before building a block on it
+                    let nonce = nonces.nonce(index);
+                    header.nonce = nonce;
+                    let hash = header.double_sha256();
+                    let kind = if hash <= block_target {
+                        SolutionKind::Block
+                    } else if work.share_target.is_some_and(|target| hash <= target) {
+                        SolutionKind::Share
+                    } else {
+                        warn!(worker_id = self.id, backend = backend.name(), nonce, "Backend reported a nonce that misses the target");
+                        continue;
+                    };
 
-            let batch_end = (batch_start + BATCH_SIZE).min(end_nonce);
-            
-            for nonce in batch_start..batch_end {
-                header.nonce = nonce;
-                
-                if header.validate_pow().is_ok() {
                     // Double-check cancellation before submitting result
                     if *cancel_rx.borrow() {
-                        debug!("Work cancelled just before solution submission");
-                        return None;
+                        self.count_hashes(backend.hash_count() - hashed_before);
+                        debug!(worker_id = self.id, work_id, "Work cancelled just before solution submission");
+                        return true;
                     }
 
-                    debug!("Worker {} found solution! Nonce: {}", self.id, nonce);
-                    
+                    debug!(worker_id = self.id, work_id, nonce, ?kind, "Found solution");
+
                     // Create the complete block with transactions
-                    let block = Block::new(header, work.transactions.clone());
-                    
-                    return Some(MiningResult {
-                        block,
+                    let result = MiningResult {
+                        block: Block::new(header.clone(), transactions.to_vec()),
                         nonce,
+                        extranonce,
                         worker_id: self.id,
-                    });
+                        work_id,
+                        kind,
+                        span: span.clone(),
+                    };
+                    if result_tx.blocking_send(result).is_err() {
+                        return false;
+                    }
+
+                    if kind == SolutionKind::Block {
+                        self.count_hashes(backend.hash_count() - hashed_before);
+                        return true;
+                    }
                 }
-                
-                self.hashes_computed.fetch_add(1, Ordering::SeqCst);
+                let hashed = backend.hash_count() - hashed_before;
+                self.count_hashes(hashed);
+                self.throttle(batch_started.elapsed(), &cancel_rx);
+                self.resize_batch(batch_size, hashed, batch_started.elapsed());
+                batch_start = batch_end;
             }
+        }
 
-            // Check for cancellation between batches
-            if cancel_rx.has_changed().unwrap_or(false) {
-                if *cancel_rx.borrow() {
-                    debug!("Worker {} work cancelled mid-batch", self.id);
-                    return None;
-                }
+        true
+    }
+
+    /// After a batch that took `busy`, idle long enough that hashing only
+    /// fills the configured fraction of the time. Sleeps in slices so a
+    /// cancelled work or a stopped worker doesn't wait out a long idle.
+    fn throttle(&self, busy: Duration, cancel_rx: &watch::Receiver<bool>) {
+        let intensity = self.intensity();
+        if intensity >= 1.0 {
+            return;
+        }
+
+        let until = Instant::now() + busy.mul_f64((1.0 - intensity) / intensity);
+        while self.running.load(Ordering::Acquire) && !*cancel_rx.borrow() {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
             }
+            thread::sleep(left.min(THROTTLE_SLICE));
+        }
+    }
 
-            // Yield control periodically
-            tokio::task::yield_now().await;
+    /// After a batch of `size` nonces that took `took` to hash `hashed` of
+    /// them, throttling included, size the next for `batch_time`
+    fn resize_batch(&self, size: u64, hashed: u64, took: Duration) {
+        let target = self.batch_time.load(Ordering::Relaxed);
+        if target > 0 {
+            let next = next_batch_size(size, hashed, took, Duration::from_millis(target));
+            self.batch_size.store(next, Ordering::Relaxed);
         }
+    }
 
-        None
+    /// Hash `size` nonces a batch, or start from `size` and resize batches
+    /// to take `time_ms` each when it's non-zero. Takes effect from the
+    /// next batch.
+    pub fn set_batching(&self, size: u64, time_ms: u64) {
+        self.batch_size.store(size.clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE), Ordering::Relaxed);
+        self.batch_time.store(time_ms, Ordering::Relaxed);
     }
 
-    pub fn stop(&self) {
-        self.running.store(false, Ordering::SeqCst);
+    pub fn batch_size(&self) -> u64 {
+        self.batch_size.load(Ordering::Relaxed)
     }
 
-    pub fn restart(&self) {
-        // Stop current work
-        self.running.store(false, Ordering::SeqCst);
-        // Small delay to ensure worker stops
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        // Restart
-        self.running.store(true, Ordering::SeqCst);
+    pub fn intensity(&self) -> f64 {
+        f64::from_bits(self.intensity.load(Ordering::Relaxed))
+    }
+
+    /// Takes effect from the next batch
+    pub fn set_intensity(&self, intensity: f64) {
+        self.intensity.store(intensity.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn count_hashes(&self, hashes: u64) {
+        self.hashes_computed.fetch_add(hashes, Ordering::Relaxed);
+        self.interval_hashes.fetch_add(hashes, Ordering::Relaxed);
+    }
+
+    /// Hashes computed since the last call
+    pub fn take_interval_hashes(&self) -> u64 {
+        self.interval_hashes.swap(0, Ordering::Relaxed)
+    }
+
+    /// Count a piece of work that took `elapsed` to compute `hashes`
+    pub fn record_work(&self, hashes: u64, elapsed: Duration) {
+        let mut work_rates = self.work_rates.lock().unwrap();
+        if work_rates.len() == RATE_WINDOW {
+            work_rates.pop_front();
+        }
+        work_rates.push_back((hashes, elapsed));
+    }
+
+    /// Hashrate over the latest pieces of work, once there are any
+    pub fn measured_hashrate(&self) -> Option<f64> {
+        let work_rates = self.work_rates.lock().unwrap();
+        let hashes: u64 = work_rates.iter().map(|(hashes, _)| hashes).sum();
+        let elapsed: Duration = work_rates.iter().map(|(_, elapsed)| *elapsed).sum();
+        (hashes > 0 && !elapsed.is_zero()).then(|| hashes as f64 / elapsed.as_secs_f64())
+    }
+
+    /// Name of the backend the worker hashes with
+    pub fn backend(&self) -> &'static str {
+        *self.backend.lock().unwrap()
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Release);
     }
 
     // pub fn get_hashrate(&self, duration_secs: f64) -> f64 {
     //     let hashes = self.hashes_computed.load(Ordering::SeqCst) as f64;
     //     hashes / duration_secs
     // }
-}
\ No newline at end of file
+}
+
+/// `transactions` behind a transaction carrying `extranonce`
+pub fn with_extranonce(transactions: &[Transaction], extranonce: u64) -> Vec<Transaction> {
+    let extranonce_tx = Transaction {
+        inputs: vec![[EXTRANONCE_TAG, &extranonce.to_le_bytes()].concat()],
+        outputs: vec![Vec::new()],
+    };
+
+    std::iter::once(extranonce_tx)
+        .chain(transactions.iter().cloned())
+        .collect()
+}
+
+/// Give the calling thread the lowest scheduling priority, returning
+/// whether that worked. On Linux the nice value is per thread.
+#[cfg(unix)]
+fn lower_thread_priority() -> bool {
+    // SAFETY: setpriority only reads its arguments
+    unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) == 0 }
+}
+
+#[cfg(not(unix))]
+fn lower_thread_priority() -> bool {
+    false
+}
+
+/// Wait for worker threads to exit, returning false if any is still running
+/// after `timeout`. Polls rather than blocking so a stuck thread can't hold up
+/// the runtime.
+pub async fn join_all(threads: Vec<JoinHandle<()>>, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while threads.iter().any(|thread| !thread.is_finished()) {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(JOIN_POLL).await;
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+    true
+}
+
+/// Stop `workers` and wait for their threads, warning about any still
+/// running after `WORKER_JOIN_TIMEOUT`
+pub async fn stop_all(workers: &[MiningWorker], threads: Vec<JoinHandle<()>>) {
+    info!("Stopping workers...");
+    for worker in workers {
+        worker.stop();
+    }
+
+    if !join_all(threads, WORKER_JOIN_TIMEOUT).await {
+        warn!("Timed out waiting for mining threads to stop");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::backend::CpuBackend;
+
+    /// Target of 1, which no hash will meet
+    const IMPOSSIBLE: u32 = 0x0300_0001;
+    /// Target so high the first nonce meets it
+    const TRIVIAL: u32 = 0x207f_ffff;
+
+    fn work(difficulty: u32, cancel_rx: watch::Receiver<bool>) -> WorkItem {
+        WorkItem {
+            header: Header::new(1, 0, difficulty, 0, [0; 32], [0; 32]),
+            nonces: NonceRange { start: 0, count: u64::MAX / 4, stride: 1 },
+            transactions: Arc::default(),
+            work_id: 0,
+            cancel_rx,
+            share_target: None,
+            max_time: 0,
+            extranonce_start: 0,
+            span: Span::none(),
+        }
+    }
+
+    fn cpu() -> Box<dyn MiningBackend> {
+        Box::new(CpuBackend::default())
+    }
+
+    fn single_nonce() -> NonceRange {
+        NonceRange { start: 0, count: 1, stride: 1 }
+    }
+
+    /// Whether `nonce` is in `range`
+    fn covers(range: &NonceRange, nonce: u64) -> bool {
+        let offset = nonce.wrapping_sub(range.start);
+        offset.is_multiple_of(range.stride) && offset / range.stride < range.count
+    }
+
+    /// `ranges` hold every nonce, and none twice
+    fn assert_covered_once(ranges: &[NonceRange], strategy: NonceStrategy) {
+        assert_eq!(ranges.iter().map(|r| r.count as u128).sum::<u128>(), NONCE_SPACE, "{:?}", strategy);
+
+        // Range ends, their neighbours and the space's edges each fall in exactly one range
+        let probes = ranges
+            .iter()
+            .flat_map(|r| [r.start, r.nonce(r.count - 1), r.nonce(r.count), r.start.wrapping_sub(1)])
+            .chain([0, 1, u64::MAX, u64::MAX / 3, 0xdead_beef]);
+        for nonce in probes {
+            let owners = ranges.iter().filter(|r| covers(r, nonce)).count();
+            assert_eq!(owners, 1, "{:?} over {} workers: nonce {} in {} ranges", strategy, ranges.len(), nonce, owners);
+        }
+    }
+
+    #[test]
+    fn test_partitions_cover_every_nonce_once() {
+        let strategies = [NonceStrategy::Split, NonceStrategy::Interleave, NonceStrategy::RandomOffset];
+        for (strategy, workers) in strategies.into_iter().flat_map(|s| [2, 3, 7, 16].map(|w| (s, w))) {
+            let ranges = partition_nonces(strategy, 12345, 9, &vec![None; workers]);
+            assert_eq!(ranges.len(), workers);
+            assert_covered_once(&ranges, strategy);
+        }
+
+        let lone = partition_nonces(NonceStrategy::Split, 0, 1, &[None]);
+        assert_eq!(lone, [NonceRange { start: 0, count: u64::MAX, stride: 1 }]);
+    }
+
+    #[test]
+    fn test_partitions_follow_measured_rates() {
+        let share = |range: &NonceRange| range.count as f64 / NONCE_SPACE as f64;
+        for strategy in [NonceStrategy::Split, NonceStrategy::RandomOffset] {
+            let ranges = partition_nonces(strategy, 12345, 9, &[Some(3e6), Some(1e6)]);
+            assert_covered_once(&ranges, strategy);
+            assert!((share(&ranges[0]) - 0.75).abs() < 1e-9, "{:?}", ranges);
+
+            // Workers not yet measured count as average
+            let ranges = partition_nonces(strategy, 12345, 9, &[Some(3e6), Some(1e6), None, Some(0.0)]);
+            assert_covered_once(&ranges, strategy);
+            let shares: Vec<f64> = ranges.iter().map(share).collect();
+            for (share, expected) in shares.iter().zip([0.375, 0.125, 0.25, 0.25]) {
+                assert!((share - expected).abs() < 1e-9, "{:?}", shares);
+            }
+        }
+
+        // Interleaving stays even
+        let ranges = partition_nonces(NonceStrategy::Interleave, 0, 1, &[Some(3e6), Some(1e6)]);
+        assert_eq!(ranges, partition_nonces(NonceStrategy::Interleave, 0, 1, &[None; 2]));
+        assert_eq!(format_nonce_shares(&ranges), "0 50.0%, 1 50.0%");
+    }
+
+    #[test]
+    fn test_hashrate_measured_over_recent_work() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        assert_eq!(worker.measured_hashrate(), None);
+
+        worker.record_work(1000, Duration::from_secs(1));
+        worker.record_work(3000, Duration::from_secs(1));
+        assert_eq!(worker.measured_hashrate(), Some(2000.0));
+
+        // Older work drops out of the window
+        for _ in 0..RATE_WINDOW {
+            worker.record_work(500, Duration::from_secs(1));
+        }
+        assert_eq!(worker.measured_hashrate(), Some(500.0));
+    }
+
+    #[test]
+    fn test_random_offsets_move_with_work_id() {
+        let starts = |seed, work_id| partition_nonces(NonceStrategy::RandomOffset, seed, work_id, &[None; 4])[0].start;
+        let bases: Vec<u64> = (1..=100).map(|work_id| starts(7, work_id)).collect();
+        let mut distinct = bases.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), bases.len());
+
+        // Deterministic for a seed, different between seeds
+        assert_eq!(starts(7, 3), starts(7, 3));
+        assert_ne!(starts(7, 3), starts(8, 3));
+
+        // The other strategies start from 0 every time
+        for strategy in [NonceStrategy::Split, NonceStrategy::Interleave] {
+            assert_eq!(partition_nonces(strategy, 7, 1, &[None; 4]), partition_nonces(strategy, 8, 2, &[None; 4]));
+        }
+    }
+
+    #[test]
+    fn test_mine_work_wraps_around_the_nonce_space() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        worker.running.store(true, Ordering::SeqCst);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+
+        // Starting a few nonces short of the end, the first solution is past the wrap
+        let header = Header::new(1, 0, 0x2000_ffff, 0, [0; 32], [7; 32]);
+        let solves = |nonce| Header { nonce, ..header.clone() }.validate_pow().is_ok();
+        assert!(!(0..3).any(|back| solves(u64::MAX - back)), "pick another header");
+        let expected = (0..).find(|&nonce| solves(nonce)).unwrap();
+        let nonces = NonceRange { start: u64::MAX - 2, count: 1 << 20, stride: 1 };
+
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        assert!(worker.mine_work(&mut CpuBackend::default(), WorkItem { header, nonces, ..work(TRIVIAL, cancel_rx) }, &result_tx));
+        assert_eq!(result_rx.try_recv().expect("no solution delivered").nonce, expected);
+    }
+
+    #[tokio::test]
+    async fn test_solution_is_delivered_from_thread() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        let (work_tx, work_rx) = mpsc::channel(1);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let thread = worker.spawn(work_rx, result_tx, ThreadOptions::default(), cpu()).unwrap();
+
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        work_tx.send(work(TRIVIAL, cancel_rx)).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), result_rx.recv())
+            .await
+            .expect("no solution delivered")
+            .unwrap();
+        assert_eq!(result.worker_id, 0);
+        assert!(result.block.header.validate_pow().is_ok());
+
+        drop(work_tx);
+        assert!(join_all(vec![thread], Duration::from_secs(5)).await);
+    }
+
+    #[test]
+    fn test_finds_the_first_solving_nonce() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        worker.running.store(true, Ordering::SeqCst);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+
+        // About one hash in 256 meets this
+        let header = Header::new(1, 0, 0x2000_ffff, 0, [0; 32], [7; 32]);
+        let expected = (0..)
+            .find(|&nonce| Header { nonce, ..header.clone() }.validate_pow().is_ok())
+            .unwrap();
+
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        assert!(worker.mine_work(&mut CpuBackend::default(), WorkItem { header, ..work(TRIVIAL, cancel_rx) }, &result_tx));
+        let result = result_rx.try_recv().expect("no solution delivered");
+        assert_eq!((result.nonce, result.block.header.nonce), (expected, expected));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_joins_busy_threads() {
+        let connected = Arc::new(AtomicBool::new(true));
+        let (result_tx, _result_rx) = mpsc::channel(1);
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+
+        let mut workers = Vec::new();
+        let mut senders = Vec::new();
+        let mut threads = Vec::new();
+        for id in 0..4 {
+            let worker = MiningWorker::new(id, connected.clone());
+            let (work_tx, work_rx) = mpsc::channel(1);
+            threads.push(worker.spawn(work_rx, result_tx.clone(), ThreadOptions::default(), cpu()).unwrap());
+            work_tx.send(work(IMPOSSIBLE, cancel_rx.clone())).await.unwrap();
+            workers.push(worker);
+            senders.push(work_tx);
+        }
+
+        // Every thread is grinding through its range
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while workers.iter().any(|w| w.hashes_computed.load(Ordering::Relaxed) == 0) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("workers did not start hashing");
+
+        for worker in &workers {
+            worker.stop();
+        }
+        drop(senders);
+        assert!(join_all(threads, Duration::from_secs(5)).await, "mining threads did not exit");
+    }
+
+    /// Counts are flushed once per batch with relaxed ordering; after the
+    /// threads are joined every hash they computed must still be accounted for
+    #[tokio::test]
+    async fn test_hash_counts_add_up_after_join() {
+        let connected = Arc::new(AtomicBool::new(true));
+        let (result_tx, _result_rx) = mpsc::channel(1);
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        let workers: Vec<MiningWorker> = (0..4).map(|id| MiningWorker::new(id, connected.clone())).collect();
+
+        let mut senders = Vec::new();
+        let mut threads = Vec::new();
+        for worker in &workers {
+            let (work_tx, work_rx) = mpsc::channel(1);
+            threads.push(worker.spawn(work_rx, result_tx.clone(), ThreadOptions::default(), cpu()).unwrap());
+            work_tx.send(work(IMPOSSIBLE, cancel_rx.clone())).await.unwrap();
+            senders.push(work_tx);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        workers.iter().for_each(MiningWorker::stop);
+        drop(senders);
+        assert!(join_all(threads, Duration::from_secs(5)).await);
+
+        let totals: Vec<u64> = workers.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).collect();
+        let whole_batches = workers.iter().zip(&totals).all(|(w, &total)| total > 0 && total % w.batch_size() == 0);
+        assert!(whole_batches, "{:?}", totals);
+        let interval: u64 = workers.iter().map(MiningWorker::take_interval_hashes).sum();
+        assert_eq!(interval, totals.iter().sum::<u64>());
+        assert!(std::mem::align_of::<CachePadded<AtomicU64>>() >= 64);
+    }
+
+    #[tokio::test]
+    async fn test_shares_reported_until_cancelled() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        let (work_tx, work_rx) = mpsc::channel(1);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let thread = worker.spawn(work_rx, result_tx, ThreadOptions::default(), cpu()).unwrap();
+
+        let share_target = hyperion_core::consensus::compact_to_target(TRIVIAL);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        work_tx.send(WorkItem { share_target: Some(share_target), ..work(IMPOSSIBLE, cancel_rx) }).await.unwrap();
+
+        let mut nonces = Vec::new();
+        for _ in 0..3 {
+            let result = tokio::time::timeout(Duration::from_secs(5), result_rx.recv())
+                .await
+                .expect("no share delivered")
+                .unwrap();
+            assert_eq!(result.kind, SolutionKind::Share);
+            assert!(result.block.header.double_sha256() <= share_target);
+            nonces.push(result.nonce);
+        }
+        assert!(nonces.windows(2).all(|pair| pair[0] < pair[1]), "worker restarted its range");
+
+        cancel_tx.send(true).unwrap();
+        drop(work_tx);
+        while result_rx.recv().await.is_some() {}
+        assert!(join_all(vec![thread], Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_range_rolls_extranonce() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        let (work_tx, work_rx) = mpsc::channel(1);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let thread = worker.spawn(work_rx, result_tx, ThreadOptions::default(), cpu()).unwrap();
+
+        // About one hash in 64 meets this, so a single nonce rarely does
+        let transactions = vec![Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap()];
+        let header = Header::new(1, 0, 0x2003_ffff, 0, [0; 32], compute_merkle_root(&transactions));
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        let item = WorkItem { header, nonces: single_nonce(), transactions: Arc::new(transactions.clone()), ..work(TRIVIAL, cancel_rx) };
+        work_tx.send(item).await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), result_rx.recv())
+            .await
+            .expect("no solution delivered")
+            .unwrap();
+        assert!(result.extranonce > 0, "solved without rolling");
+        assert_eq!(result.block.transactions, with_extranonce(&transactions, result.extranonce));
+        assert!(result.block.validate_merkle_root().is_ok());
+        assert!(result.block.header.validate_pow().is_ok());
+
+        drop(work_tx);
+        assert!(join_all(vec![thread], Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn test_rolled_time_stays_in_window() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        let (work_tx, work_rx) = mpsc::channel(1);
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let thread = worker.spawn(work_rx, result_tx, ThreadOptions::default(), cpu()).unwrap();
+
+        // Single-nonce batches roll the time every TIME_ROLL_BATCHES hashes,
+        // long before this target (about one hash in 65536) is met
+        let time = 1_700_000_000;
+        let header = Header::new(1, time, 0x1f00_ffff, 0, [0; 32], [0; 32]);
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        let item = WorkItem { header, nonces: single_nonce(), max_time: time + 5, ..work(TRIVIAL, cancel_rx) };
+        work_tx.send(item).await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(30), result_rx.recv())
+            .await
+            .expect("no solution delivered")
+            .unwrap();
+        let header = &result.block.header;
+        assert!(header.time > time && header.time <= time + 5, "time {} outside window", header.time);
+        assert!(header.validate_pow().is_ok());
+        assert!(result.block.validate_merkle_root().is_ok());
+
+        drop(work_tx);
+        assert!(join_all(vec![thread], Duration::from_secs(5)).await);
+    }
+
+    #[test]
+    fn test_throttle_ends_on_cancel() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        worker.running.store(true, Ordering::Release);
+        worker.set_intensity(0.1);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+
+        // A second's batch at 10% intensity idles for nine
+        let started = Instant::now();
+        let throttled = thread::spawn(move || worker.throttle(Duration::from_secs(1), &cancel_rx));
+        thread::sleep(Duration::from_millis(50));
+        cancel_tx.send(true).unwrap();
+        throttled.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "throttle slept {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_intensity_throttles_hashing() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        let (work_tx, work_rx) = mpsc::channel(1);
+        let (result_tx, _result_rx) = mpsc::channel(1);
+        let thread = worker.spawn(work_rx, result_tx, ThreadOptions::default(), cpu()).unwrap();
+
+        let (_cancel_tx, cancel_rx) = watch::channel(false);
+        work_tx.send(work(IMPOSSIBLE, cancel_rx)).await.unwrap();
+
+        // Alternate full and half intensity so load from other tests skews
+        // both alike
+        let mut hashes = [0u64; 2];
+        for round in 0..6 {
+            let throttled = round % 2;
+            worker.set_intensity(if throttled == 1 { 0.5 } else { 1.0 });
+            let before = worker.hashes_computed.load(Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            hashes[throttled] += worker.hashes_computed.load(Ordering::Relaxed) - before;
+        }
+
+        let ratio = hashes[1] as f64 / hashes[0] as f64;
+        assert!((0.3..0.75).contains(&ratio), "throttled/full = {} / {} = {:.2}", hashes[1], hashes[0], ratio);
+
+        worker.stop();
+        drop(work_tx);
+        assert!(join_all(vec![thread], Duration::from_secs(5)).await);
+    }
+
+    #[test]
+    fn test_batch_size_converges_on_target_time() {
+        let target = Duration::from_millis(50);
+        // From a slow core to a fast one, and from either side of the target
+        for (rate, start) in [(2e4, 10_000), (3e6, 10_000), (5e8, 10_000), (3e6, MAX_BATCH_SIZE)] {
+            let mut size = start;
+            for _ in 0..30 {
+                let took = Duration::from_secs_f64(size as f64 / rate);
+                size = next_batch_size(size, size, took, target);
+            }
+            let took = size as f64 / rate;
+            assert!((took - 0.05).abs() < 0.005, "{} H/s settled on {} nonces, {:.3} s", rate, size, took);
+        }
+
+        // Beyond what a target allows, the size stops at the bounds
+        assert_eq!(next_batch_size(MIN_BATCH_SIZE, MIN_BATCH_SIZE, Duration::from_secs(1), target), MIN_BATCH_SIZE);
+        assert_eq!(next_batch_size(10_000, 0, Duration::ZERO, target), 10_000);
+    }
+
+    /// Hashes nothing, but takes as long as hashing at `rate` would
+    struct PacedBackend {
+        rate: f64,
+        batches: Vec<u64>,
+        cancel_after: usize,
+        cancel_tx: watch::Sender<bool>,
+    }
+
+    impl MiningBackend for PacedBackend {
+        fn name(&self) -> &'static str {
+            "paced"
+        }
+
+        fn scan_nonces(
+            &mut self,
+            _header: &Header,
+            _target: &[u8; HASH_SIZE],
+            _nonces: NonceRange,
+            indices: std::ops::Range<u64>,
+            _cancel: &watch::Receiver<bool>,
+        ) -> anyhow::Result<Option<u64>> {
+            let count = indices.end - indices.start;
+            thread::sleep(Duration::from_secs_f64(count as f64 / self.rate));
+            self.batches.push(count);
+            if self.batches.len() == self.cancel_after {
+                let _ = self.cancel_tx.send(true);
+            }
+            Ok(None)
+        }
+
+        fn hash_count(&self) -> u64 {
+            self.batches.iter().sum()
+        }
+    }
+
+    #[test]
+    fn test_mine_work_sizes_batches_for_batch_time() {
+        let worker = MiningWorker::new(0, Arc::new(AtomicBool::new(true)));
+        worker.running.store(true, Ordering::Release);
+        worker.set_batching(1000, 20);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let mut backend = PacedBackend { rate: 1e6, batches: Vec::new(), cancel_after: 15, cancel_tx };
+        let (result_tx, _result_rx) = mpsc::channel(1);
+
+        assert!(worker.mine_work(&mut backend, work(IMPOSSIBLE, cancel_rx), &result_tx));
+        assert_eq!(backend.batches[..3], [1000, 2000, 4000]);
+        // 20 ms at 1 MH/s, a little under for the time sleeping overshoots
+        let last = *backend.batches.last().unwrap();
+        assert!((14_000..=20_000).contains(&last), "{:?}", backend.batches);
+
+        // Without a batch time every batch is the configured size
+        worker.set_batching(3000, 0);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let mut backend = PacedBackend { rate: 1e6, batches: Vec::new(), cancel_after: 5, cancel_tx };
+        assert!(worker.mine_work(&mut backend, work(IMPOSSIBLE, cancel_rx), &result_tx));
+        assert_eq!(backend.batches, [3000; 5]);
+    }
+}
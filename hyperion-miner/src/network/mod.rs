@@ -1,5 +1,4 @@
 pub mod node_client;
-pub mod rpc;
+pub mod stratum;
 
-pub use node_client::NodeClient;
-pub use rpc::{BlockTemplate, MiningInfo, SubmitBlockRequest};
\ No newline at end of file
+pub use node_client::{NodeClient, NodeError, SubmitOutcome};
\ No newline at end of file
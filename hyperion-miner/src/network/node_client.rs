@@ -1,136 +1,485 @@
 use hyperion_core::block::{Block, Serializable};
+use hyperion_core::consensus::create_genesis_block;
+use hyperion_core::crypto::{hex32, to_hex, Hashable};
+use hyperion_rpc::{
+    BlockTemplate, ChainInfo, MiningInfo, RawBlock, RpcError, RpcRequest, RpcResponse, SubmitBlockParams, SubmitBlockResult, VersionInfo,
+    PROTOCOL_MAJOR, PROTOCOL_MINOR,
+};
 
+use std::fmt;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, Ordering};
-use super::rpc::{
-    BlockTemplate, MiningInfo, RpcRequest, RpcResponse, SubmitBlockRequest, SubmitBlockResponse
-};
-use anyhow::{anyhow, Result};
-use reqwest::Client;
-use tracing::{debug, error, info};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::MiningConfig;
+use crate::utils::MiningStats;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{debug, error, info, warn};
+
+/// Delay before the first retry of an idempotent call, doubling for each
+/// retry after
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// The node's error code for a block that isn't on its active chain
+const BLOCK_NOT_FOUND: i32 = -5;
+
+/// How the node answered a submitted block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    Accepted,
+    /// The block lost a race: the node already has it or has moved past its parent
+    Stale(String),
+    /// The block itself is bad, so the template it came from is suspect
+    Rejected(String),
+}
+
+/// Why a call to the node failed
+#[derive(Debug)]
+pub enum NodeError {
+    /// No connection or response within the configured timeout
+    Timeout,
+    /// The connection was refused or couldn't be established
+    Connect(String),
+    /// The node answered with a non-success HTTP status
+    Http(StatusCode),
+    /// The node answered with a JSON-RPC error
+    Rpc(RpcError),
+    /// The response wasn't the JSON-RPC the client expected
+    Protocol(String),
+    /// The node refused the credentials; names the settings to check
+    Unauthorized(&'static str),
+    /// The node's cookie file couldn't be read
+    Cookie(String),
+    /// The node is on another chain or speaks an incompatible protocol
+    Incompatible(String),
+}
+
+impl NodeError {
+    /// Whether the same call may succeed if tried again shortly
+    pub fn is_transient(&self) -> bool {
+        match self {
+            NodeError::Timeout | NodeError::Connect(_) => true,
+            NodeError::Http(status) => status.is_server_error(),
+            NodeError::Rpc(_)
+            | NodeError::Protocol(_)
+            | NodeError::Unauthorized(_)
+            | NodeError::Cookie(_)
+            | NodeError::Incompatible(_) => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for NodeError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            NodeError::Timeout
+        } else if e.is_connect() {
+            NodeError::Connect(e.to_string())
+        } else if let Some(status) = e.status() {
+            NodeError::Http(status)
+        } else {
+            NodeError::Protocol(e.to_string())
+        }
+    }
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Timeout => write!(f, "request to node timed out"),
+            NodeError::Connect(e) => write!(f, "failed to connect to node: {}", e),
+            NodeError::Http(status) => write!(f, "HTTP error: {}", status),
+            NodeError::Rpc(error) => write!(f, "RPC error: {}", error.message),
+            NodeError::Protocol(e) => write!(f, "unexpected response from node: {}", e),
+            NodeError::Unauthorized(settings) => write!(f, "authentication failed — check {}", settings),
+            NodeError::Cookie(e) => write!(f, "failed to read RPC cookie: {}", e),
+            NodeError::Incompatible(e) => {
+                write!(f, "incompatible node: {} (set allow_incompatible_node to mine anyway)", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+/// How the client authenticates to the node
+#[derive(Clone)]
+enum Credentials {
+    None,
+    Password { user: String, password: String },
+    /// Read from the cookie file on first use, and again whenever the node
+    /// refuses it, since a restarted node writes a new cookie
+    Cookie { path: PathBuf, cached: Arc<Mutex<Option<(String, String)>>> },
+}
+
+impl Credentials {
+    fn from_config(config: &MiningConfig) -> Self {
+        match (&config.rpc_user, &config.rpc_password, &config.rpc_cookie_path) {
+            (Some(user), Some(password), _) => Credentials::Password {
+                user: user.clone(),
+                password: password.clone(),
+            },
+            (_, _, Some(path)) => Credentials::Cookie { path: path.clone(), cached: Arc::default() },
+            _ => Credentials::None,
+        }
+    }
+
+    /// User and password to send, if any. The cookie file is read without
+    /// holding the cache lock, so concurrent first requests may each read it.
+    async fn basic_auth(&self) -> Result<Option<(String, String)>, NodeError> {
+        match self {
+            Credentials::None => Ok(None),
+            Credentials::Password { user, password } => Ok(Some((user.clone(), password.clone()))),
+            Credentials::Cookie { path, cached } => {
+                let current = cached.lock().unwrap().clone();
+                if current.is_some() {
+                    return Ok(current);
+                }
+
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| NodeError::Cookie(format!("{}: {}", path.display(), e)))?;
+                let (user, password) = content
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| NodeError::Cookie(format!("{} is not user:password", path.display())))?;
+                let credentials = (user.to_string(), password.to_string());
+                *cached.lock().unwrap() = Some(credentials.clone());
+                Ok(Some(credentials))
+            }
+        }
+    }
+
+    /// Drop a cached cookie so the next request re-reads it. Returns whether
+    /// there was one to drop.
+    fn forget_cookie(&self) -> bool {
+        match self {
+            Credentials::Cookie { cached, .. } => cached.lock().unwrap().take().is_some(),
+            _ => false,
+        }
+    }
+
+    /// The settings to check when the node refuses these credentials
+    fn settings(&self) -> &'static str {
+        match self {
+            Credentials::Cookie { .. } => "rpc_cookie_path",
+            _ => "rpc_user/rpc_password",
+        }
+    }
+}
 
 pub struct NodeClient {
     client: Client,
     base_url: String,
     request_id: AtomicU32,
+    /// Extra attempts for idempotent calls that fail transiently
+    retries: u32,
+    credentials: Credentials,
+    /// Where round trips and clock skew are recorded, once set with `with_stats`
+    stats: Option<MiningStats>,
+    /// Submissions slower than this are logged, if set
+    slow_submit: Option<Duration>,
+    /// Hash of the genesis block the node must have
+    expected_genesis: String,
+    /// Warn about an incompatible node instead of refusing it
+    allow_incompatible: bool,
+    /// The node as last logged, shared by clones so each change is logged once
+    node_identity: Arc<Mutex<Option<String>>>,
 }
 
 impl NodeClient {
-    pub fn new(base_url: String) -> Self {
-        Self {
-            client: Client::new(),
-            base_url,
+    pub fn new(config: &MiningConfig) -> Result<Self, NodeError> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_millis(config.connect_timeout))
+            .timeout(Duration::from_millis(config.request_timeout))
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: config.node_url.clone(),
             request_id: AtomicU32::new(1),
-        }
+            retries: config.request_retries,
+            credentials: Credentials::from_config(config),
+            stats: None,
+            slow_submit: (config.submit_latency_warning > 0)
+                .then(|| Duration::from_millis(config.submit_latency_warning)),
+            expected_genesis: hex32(&create_genesis_block().double_sha256()),
+            allow_incompatible: config.allow_incompatible_node,
+            node_identity: Arc::default(),
+        })
     }
 
-    pub async fn get_block_template(&self) -> Result<BlockTemplate> {
+    /// Record template and submission round trips, and the node's clock
+    /// skew, in `stats`
+    pub fn with_stats(mut self, stats: MiningStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Fetch a block template. With a `longpollid` the node holds the request
+    /// until its work changes from that template, so `timeout` should be
+    /// longer than the node's own hold time. Longpolls aren't retried.
+    pub async fn get_block_template(
+        &self,
+        longpollid: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<BlockTemplate, NodeError> {
         debug!("Requesting block template from node");
 
-        let request = RpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id: self.request_id.fetch_add(1, Ordering::SeqCst),
-            method: "get_block_template".to_string(),
-            params: serde_json::Value::Null,
+        // A longpoll's round trip is mostly the node holding it, so only
+        // plain fetches count towards latency; retries included
+        let started = Instant::now();
+        let template: BlockTemplate = match longpollid {
+            Some(longpollid) => {
+                let params = serde_json::json!({ "longpollid": longpollid });
+                self.call("get_block_template", params, timeout).await?
+            }
+            None => self.call_with_retries("get_block_template", Value::Null, timeout).await?,
         };
 
-        let response = self
-            .client
-            .post(&format!("{}/rpc", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow!("HTTP error: {}", response.status()));
+        if let Some(stats) = &self.stats {
+            if longpollid.is_none() {
+                stats.template_latency.record(started.elapsed());
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let node_time = template.cur_time.unwrap_or(template.timestamp);
+            *stats.clock_skew.lock().unwrap() = Some(node_time as i64 - now as i64);
         }
+        Ok(template)
+    }
 
-        let rpc_response: RpcResponse<BlockTemplate> = response.json().await?;
+    /// Submit a block. Never retried: a block the node received but didn't
+    /// answer for would come back as a duplicate.
+    pub async fn submit_block(&self, block: Block) -> Result<SubmitOutcome, NodeError> {
+        // Serialize block to hex
+        let block_hash = hex32(&block.double_sha256());
+        debug!(%block_hash, "Submitting block to node");
+        let block_bytes = block.serialize().unwrap();  // TODO: Remove unwrap
+        let block_hex = to_hex(block_bytes);
+
+        let params = serde_json::to_value(SubmitBlockParams { block_hex }).map_err(|e| NodeError::Protocol(e.to_string()))?;
+        let started = Instant::now();
+        let rpc_response: RpcResponse<SubmitBlockResult> = self.request("submit_block", params, None).await?;
+        let latency = started.elapsed();
+        if let Some(stats) = &self.stats {
+            stats.submit_latency.record(latency);
+        }
+        if let Some(slow_submit) = self.slow_submit.filter(|&slow_submit| latency > slow_submit) {
+            warn!(%block_hash, "Block submission took {:?}, over the {:?} warning threshold", latency, slow_submit);
+        }
 
         if let Some(error) = rpc_response.error {
-            return Err(anyhow!("RPC error: {}", error.message));
+            error!(%block_hash, "Block submission failed: {}", error.message);
+            return Ok(SubmitOutcome::Rejected(error.message));
         }
 
-        rpc_response
+        let result = rpc_response
             .result
-            .ok_or_else(|| anyhow!("Missing result in RPC response"))
+            .ok_or_else(|| NodeError::Protocol("missing result in RPC response".to_string()))?;
+        self.check_block_hash(&block_hash, &result);
+        if result.accepted {
+            debug!(%block_hash, "Block accepted by node");
+            return Ok(SubmitOutcome::Accepted);
+        }
+
+        let reason = result.reject_reason.or(result.message).unwrap_or_default();
+        match reason.as_str() {
+            "duplicate" | "stale-prevblk" => Ok(SubmitOutcome::Stale(reason)),
+            _ => Ok(SubmitOutcome::Rejected(reason)),
+        }
     }
 
-    pub async fn submit_block(&self, block: Block) -> Result<bool> {
-        debug!("Submitting mined block to node");
+    /// The node decodes submitted blocks itself, so a different hash means
+    /// the two sides serialize blocks differently: a consensus bug, not a
+    /// lost race. The node's verdict stands either way.
+    fn check_block_hash(&self, block_hash: &str, result: &SubmitBlockResult) {
+        let Some(node_hash) = result.block_hash.as_deref().filter(|&node_hash| node_hash != block_hash) else {
+            return;
+        };
 
-        // Serialize block to hex
-        let block_bytes = block.serialize().unwrap();  // TODO: Remove unwrap
-        let block_hex = hex::encode(block_bytes);
+        error!(
+            "Node hashed submitted block {} as {}{}; miner and node disagree on block serialization",
+            block_hash,
+            node_hash,
+            result.height.map_or(String::new(), |height| format!(" at height {}", height)),
+        );
+        if let Some(stats) = &self.stats {
+            stats.hash_mismatches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 
-        let submit_request = SubmitBlockRequest { block_hex };
+    pub async fn get_mining_info(&self) -> Result<MiningInfo, NodeError> {
+        self.call_with_retries("get_mining_info", Value::Null, None).await
+    }
 
-        let request = RpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id: self.request_id.fetch_add(1, Ordering::SeqCst),
-            method: "submit_block".to_string(),
-            params: serde_json::to_value(submit_request)?,
-        };
+    /// The node's version, protocol version and chain
+    pub async fn get_version_info(&self) -> Result<VersionInfo, NodeError> {
+        self.call_with_retries("get_version_info", Value::Null, None).await
+    }
 
-        let response = self
-            .client
-            .post(&format!("{}/rpc", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
+    /// Height of the node's best chain
+    pub async fn get_block_count(&self) -> Result<u64, NodeError> {
+        self.call_with_retries("get_block_count", Value::Null, None).await
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow!("HTTP error: {}", response.status()));
+    /// The node's best chain, including its height and tip hash
+    pub async fn get_blockchain_info(&self) -> Result<ChainInfo, NodeError> {
+        self.call_with_retries("get_blockchain_info", Value::Null, None).await
+    }
+
+    /// Whether the block with `hash` is on the node's active chain
+    pub async fn has_block(&self, hash: &str) -> Result<bool, NodeError> {
+        match self.call_with_retries::<RawBlock>("get_block", serde_json::json!({ "hash": hash }), None).await {
+            Ok(_) => Ok(true),
+            Err(NodeError::Rpc(error)) if error.code == BLOCK_NOT_FOUND => Ok(false),
+            Err(e) => Err(e),
         }
+    }
 
-        let rpc_response: RpcResponse<SubmitBlockResponse> = response.json().await?;
+    /// Check the node answers, is on this miner's chain and speaks a
+    /// compatible protocol. The node is logged whenever it changes.
+    pub async fn test_connection(&self) -> Result<(), NodeError> {
+        debug!("Testing connection to node");
+        let info = match self.get_version_info().await {
+            Ok(info) => info,
+            // Nodes from before the handshake can't be checked; tolerated
+            // with a warning until the next release
+            Err(NodeError::Rpc(error)) if error.code == RpcError::METHOD_NOT_FOUND => {
+                self.get_mining_info().await?;
+                if self.note_identity("unversioned node") {
+                    warn!("Node predates get_version_info, so its chain and protocol can't be checked; upgrade it before the next release");
+                }
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
 
-        if let Some(error) = rpc_response.error {
-            error!("Block submission failed: {}", error.message);
-            return Ok(false);
+        let problem = if info.genesis_hash != self.expected_genesis {
+            Some(format!(
+                "node has genesis block {} on {}, expected {}",
+                info.genesis_hash, info.network, self.expected_genesis
+            ))
+        } else if info.protocol_major != PROTOCOL_MAJOR {
+            Some(format!(
+                "node speaks RPC protocol {}.{}, this miner {}.{}",
+                info.protocol_major, info.protocol_minor, PROTOCOL_MAJOR, PROTOCOL_MINOR
+            ))
+        } else {
+            None
+        };
+        if let Some(problem) = &problem
+            && !self.allow_incompatible
+        {
+            return Err(NodeError::Incompatible(problem.clone()));
         }
 
-        if let Some(result) = rpc_response.result {
-            if result.accepted {
-                debug!("Block accepted by node!");
-            } else {
-                error!("Block rejected: {}", result.message.unwrap());
+        let identity = format!(
+            "node {} on {} (RPC protocol {}.{}, genesis {})",
+            info.version, info.network, info.protocol_major, info.protocol_minor, info.genesis_hash
+        );
+        if self.note_identity(&identity) {
+            info!("Connected to {}", identity);
+            if let Some(problem) = problem {
+                warn!("Mining anyway as allow_incompatible_node is set: {}", problem);
             }
-            Ok(result.accepted)
-        } else {
-            Err(anyhow!("Missing result in RPC response"))
         }
+        Ok(())
     }
 
-    pub async fn get_mining_info(&self) -> Result<MiningInfo> {
-        let request = RpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id: self.request_id.fetch_add(1, Ordering::SeqCst),
-            method: "get_mining_info".to_string(),
-            params: serde_json::Value::Null,
-        };
+    /// Remember `identity` as the node's, returning whether it changed
+    fn note_identity(&self, identity: &str) -> bool {
+        let mut known = self.node_identity.lock().unwrap();
+        if known.as_deref() == Some(identity) {
+            return false;
+        }
+        *known = Some(identity.to_string());
+        true
+    }
 
-        let response = self
-            .client
-            .post(&format!("{}/rpc", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
+    /// `call` an idempotent method, retrying transient failures up to
+    /// `retries` times with jittered exponential backoff
+    async fn call_with_retries<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<T, NodeError> {
+        let mut attempt = 0;
+        loop {
+            match self.call(method, params.clone(), timeout).await {
+                Err(e) if e.is_transient() && attempt < self.retries => {
+                    let backoff = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    let delay = backoff + backoff.mul_f64(rand::random::<f64>() / 2.0);
+                    attempt += 1;
+                    debug!("{} failed ({}), retry {} in {:?}", method, e, attempt, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
 
-        let rpc_response: RpcResponse<MiningInfo> = response.json().await?;
+    /// Send one request, turning a JSON-RPC error into `NodeError::Rpc`
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<T, NodeError> {
+        let rpc_response = self.request(method, params, timeout).await?;
         if let Some(error) = rpc_response.error {
-            return Err(anyhow!("RPC error: {}", error.message));
+            return Err(NodeError::Rpc(error));
         }
 
         rpc_response
             .result
-            .ok_or_else(|| anyhow!("Missing result in RPC response"))
+            .ok_or_else(|| NodeError::Protocol("missing result in RPC response".to_string()))
     }
 
-    pub async fn test_connection(&self) -> Result<()> {
-        debug!("Testing connection to node");
-        self.get_mining_info().await?;
-        //info!("Successfully connected to node");
-        Ok(())
+    /// Send one request. `timeout` replaces the client's request timeout.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Option<Duration>,
+    ) -> Result<RpcResponse<T>, NodeError> {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: self.request_id.fetch_add(1, Ordering::SeqCst).into(),
+            method: method.to_string(),
+            params: Some(params),
+        };
+
+        let mut response = self.send(&request, timeout).await?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.credentials.forget_cookie() {
+            debug!("Node refused the RPC cookie, re-reading it");
+            response = self.send(&request, timeout).await?;
+        }
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(NodeError::Unauthorized(self.credentials.settings()));
+        }
+        if !response.status().is_success() {
+            return Err(NodeError::Http(response.status()));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    async fn send(&self, request: &RpcRequest<Value>, timeout: Option<Duration>) -> Result<reqwest::Response, NodeError> {
+        let mut builder = self
+            .client
+            .post(format!("{}/rpc", self.base_url))
+            .json(request);
+        if let Some((user, password)) = self.credentials.basic_auth().await? {
+            builder = builder.basic_auth(user, Some(password));
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        Ok(builder.send().await?)
     }
 }
 
@@ -140,6 +489,183 @@ impl Clone for NodeClient {
             client: self.client.clone(),
             base_url: self.base_url.clone(),
             request_id: AtomicU32::new(self.request_id.load(Ordering::SeqCst)),
+            retries: self.retries,
+            credentials: self.credentials.clone(),
+            stats: self.stats.clone(),
+            slow_submit: self.slow_submit,
+            expected_genesis: self.expected_genesis.clone(),
+            allow_incompatible: self.allow_incompatible,
+            node_identity: self.node_identity.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{serve_mock, template, TRIVIAL};
+    use axum::http::StatusCode as HttpStatus;
+    use hyperion_core::block::Header;
+    use std::time::Instant;
+
+    /// How long the mock takes to answer when slow, far past the client's timeout
+    const SLOW_RESPONSE: Duration = Duration::from_secs(5);
+
+    fn client(node_url: String) -> NodeClient {
+        NodeClient::new(&MiningConfig {
+            node_url,
+            connect_timeout: 100,
+            request_timeout: 100,
+            request_retries: 1,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Empty block on `prev_hash`
+    fn block_on(prev_hash: [u8; 32]) -> Block {
+        Block::new(Header::new(1, 0, TRIVIAL, 0, prev_hash, [0; 32]), Vec::new())
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_node_times_out() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        node.lock().unwrap().latency = SLOW_RESPONSE;
+        let client = client(node_url);
+        let received = || node.lock().unwrap().received;
+
+        let started = Instant::now();
+        let result = client.get_block_template(None, None).await;
+        assert!(matches!(result, Err(NodeError::Timeout)), "{:?}", result);
+        assert!(started.elapsed() < SLOW_RESPONSE);
+        assert_eq!(received(), 2, "one retry expected");
+
+        // Blocks are never sent twice
+        let result = client.submit_block(block_on([0; 32])).await;
+        assert!(matches!(result, Err(NodeError::Timeout)), "{:?}", result);
+        assert_eq!(received(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_classified() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        node.lock().unwrap().down = true;
+        let result = client(node_url).get_mining_info().await;
+        assert!(matches!(result, Err(NodeError::Http(HttpStatus::SERVICE_UNAVAILABLE))), "{:?}", result);
+        assert_eq!(node.lock().unwrap().received, 2);
+
+        // RPC errors are answers, not outages, so they aren't retried
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        node.lock().unwrap().unversioned = true;
+        let result = client(node_url).get_version_info().await;
+        assert!(matches!(result, Err(NodeError::Rpc(ref error)) if error.message == "Method not found"), "{:?}", result);
+        assert_eq!(node.lock().unwrap().received, 1);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let node_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let result = client(node_url).test_connection().await;
+        assert!(matches!(result, Err(NodeError::Connect(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_round_trips_are_recorded() {
+        const DELAY: Duration = Duration::from_millis(30);
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        {
+            // Templates an hour behind the local clock
+            let mut node = node.lock().unwrap();
+            node.latency = DELAY;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+            node.templates = (0..11).map(|_| BlockTemplate { timestamp: now - 3600, ..template(0) }).collect();
+        }
+        let stats = MiningStats::new();
+        let client = client(node_url).with_stats(stats.clone());
+
+        for _ in 0..10 {
+            client.get_block_template(None, None).await.unwrap();
+        }
+        let mut prev_hash = [0; 32];
+        for _ in 0..5 {
+            let block = block_on(prev_hash);
+            prev_hash = block.double_sha256();
+            assert_eq!(client.submit_block(block).await.unwrap(), SubmitOutcome::Accepted);
+        }
+        // Longpolls don't count
+        client.get_block_template(Some("id"), None).await.unwrap();
+
+        let delay_ms = DELAY.as_secs_f64() * 1000.0;
+        for (latency, samples) in [(stats.template_latency.snapshot(), 10), (stats.submit_latency.snapshot(), 5)] {
+            let latency = latency.unwrap();
+            assert_eq!(latency.samples, samples);
+            assert!(latency.p50_ms >= delay_ms && latency.p50_ms < delay_ms + 40.0, "{:?}", latency);
+            assert!(latency.p95_ms >= latency.p50_ms && latency.p95_ms < delay_ms + 60.0, "{:?}", latency);
+        }
+
+        let skew = stats.clock_skew.lock().unwrap().unwrap();
+        assert!((-3601..=-3599).contains(&skew), "{}", skew);
+    }
+
+    #[tokio::test]
+    async fn test_node_hash_mismatch_is_counted() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        node.lock().unwrap().misread_blocks = true;
+        let stats = MiningStats::new();
+        let client = client(node_url).with_stats(stats.clone());
+
+        // The node's verdict stands, but the disagreement is recorded
+        assert_eq!(client.submit_block(block_on([0; 32])).await.unwrap(), SubmitOutcome::Accepted);
+        assert_eq!(stats.hash_mismatches.load(Ordering::SeqCst), 1);
+        assert_eq!(stats.take_snapshot(&[], "node", true).hash_mismatches, 1);
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        // miner:secret
+        node.lock().unwrap().authorization = Some("Basic bWluZXI6c2VjcmV0".to_string());
+        let client = |password: &str| {
+            NodeClient::new(&MiningConfig {
+                node_url: node_url.clone(),
+                rpc_user: Some("miner".to_string()),
+                rpc_password: Some(password.to_string()),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        assert_eq!(client("secret").get_block_count().await.unwrap(), 0);
+
+        let error = client("wrong").test_connection().await.unwrap_err();
+        assert!(matches!(error, NodeError::Unauthorized(_)));
+        assert_eq!(error.to_string(), "authentication failed — check rpc_user/rpc_password");
+    }
+
+    #[tokio::test]
+    async fn test_cookie_is_reread_when_refused() {
+        let (node, node_url) = serve_mock(TRIVIAL, false).await;
+        let authorize = |value: &str| node.lock().unwrap().authorization = Some(value.to_string());
+        // __cookie__:one
+        authorize("Basic X19jb29raWVfXzpvbmU=");
+        let cookie = std::env::temp_dir().join(format!("hyperion-miner-cookie-{}", std::process::id()));
+        std::fs::write(&cookie, "__cookie__:one\n").unwrap();
+
+        let client = NodeClient::new(&MiningConfig {
+            node_url,
+            rpc_cookie_path: Some(cookie.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.get_block_count().await.unwrap(), 0);
+
+        // The node restarts with a new cookie, __cookie__:two
+        std::fs::write(&cookie, "__cookie__:two").unwrap();
+        authorize("Basic X19jb29raWVfXzp0d28=");
+        assert_eq!(client.get_block_count().await.unwrap(), 0);
+
+        std::fs::remove_file(&cookie).unwrap();
+        authorize("Basic other");
+        let error = client.get_block_count().await.unwrap_err();
+        assert!(matches!(error, NodeError::Cookie(_)), "{:?}", error);
+    }
+}
@@ -0,0 +1,170 @@
+//! Client for a stratum-like pool protocol: one JSON-RPC message per line
+//! over a persistent TCP connection.
+//!
+//! The miner sends `mining.subscribe [agent]`, `mining.authorize [worker,
+//! password]` and `mining.submit [worker, job_id, nonce, extranonce,
+//! ntime]`, and the pool answers each with a `result` of `true` or an
+//! `error`. Work arrives as `mining.notify` notifications (no `id`) whose
+//! params are a [`Job`].
+//!
+//! A non-zero extranonce means the share's block leads with the transaction
+//! built by `mining::worker::with_extranonce`, ahead of the job's own. The
+//! ntime is the header time the share was found at, which differs from the
+//! job's `timestamp` once the worker has rolled it.
+
+use anyhow::{anyhow, bail, Result};
+use hyperion_core::block::Transaction;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+/// Work pushed by the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub version: u32,
+    pub previous_block_hash: String,
+    pub merkle_root: String,
+    pub timestamp: u32,
+    /// Difficulty of a block, as in the header
+    pub difficulty_compact: u32,
+    /// Compact target a hash must meet to count as a share
+    pub share_difficulty: u32,
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    /// Earlier jobs are void and shares for them will be rejected
+    #[serde(default)]
+    pub clean_jobs: bool,
+}
+
+/// A line from the pool: a response when `id` is set, otherwise a notification
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Value,
+}
+
+pub struct StratumClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+    worker: String,
+    /// Jobs that arrived while waiting on a response
+    pending_jobs: VecDeque<Job>,
+}
+
+impl StratumClient {
+    /// Connect to the pool at `address`, subscribe and authorize `worker`
+    pub async fn connect(address: &str, worker: &str, password: &str) -> Result<Self> {
+        let (reader, writer) = TcpStream::connect(address).await?.into_split();
+        let mut client = Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+            worker: worker.to_string(),
+            pending_jobs: VecDeque::new(),
+        };
+
+        let agent = concat!("hyperion-miner/", env!("CARGO_PKG_VERSION"));
+        client.call("mining.subscribe", json!([agent])).await?;
+        client.call("mining.authorize", json!([worker, password])).await?;
+        Ok(client)
+    }
+
+    /// Wait for the next job. Cancel safe, so it can be raced against other
+    /// work in `select!`.
+    pub async fn next_job(&mut self) -> Result<Job> {
+        if let Some(job) = self.pending_jobs.pop_front() {
+            return Ok(job);
+        }
+
+        loop {
+            let message = self.read_message().await?;
+            if let Some(job) = Self::job(message)? {
+                return Ok(job);
+            }
+        }
+    }
+
+    /// Submit a share for `job_id` found at header time `ntime`, returning
+    /// whether the pool accepted it
+    pub async fn submit(&mut self, job_id: &str, nonce: u64, extranonce: u64, ntime: u32) -> Result<bool> {
+        let id = self.send("mining.submit", json!([self.worker, job_id, nonce, extranonce, ntime])).await?;
+        let response = self.response(id).await?;
+
+        if !response.error.is_null() {
+            warn!("Pool rejected share for job {}: {}", job_id, response.error);
+            return Ok(false);
+        }
+        Ok(response.result == Value::Bool(true))
+    }
+
+    /// Send a request and wait for a successful response
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.send(method, params).await?;
+        let response = self.response(id).await?;
+
+        if !response.error.is_null() {
+            bail!("Pool refused {}: {}", method, response.error);
+        }
+        if response.result == Value::Bool(false) {
+            bail!("Pool refused {}", method);
+        }
+        Ok(response.result)
+    }
+
+    async fn send(&mut self, method: &str, params: Value) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut line = json!({ "id": id, "method": method, "params": params }).to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        Ok(id)
+    }
+
+    /// Read until the response to `id`, queueing any jobs pushed meanwhile
+    async fn response(&mut self, id: u64) -> Result<Message> {
+        loop {
+            let message = self.read_message().await?;
+            if message.id == Some(id) {
+                return Ok(message);
+            }
+            if let Some(job) = Self::job(message)? {
+                self.pending_jobs.push_back(job);
+            }
+        }
+    }
+
+    async fn read_message(&mut self) -> Result<Message> {
+        let line = self
+            .lines
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow!("Pool closed the connection"))?;
+        debug!("Pool message: {}", line);
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    fn job(message: Message) -> Result<Option<Job>> {
+        match message.method.as_deref() {
+            Some("mining.notify") => Ok(Some(serde_json::from_value(message.params)?)),
+            _ => {
+                debug!("Ignoring pool message {:?}", message);
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! In-process stand-in for the node's RPC server, for tests that run the
+//! miner end to end. What it serves can be scripted: a sequence of
+//! templates, reject reasons for the next submissions, latency on every
+//! answer or on templates alone, windows in which it is down, and the
+//! credentials it demands.
+
+use axum::{extract::State, http::{header, HeaderMap, StatusCode}, routing::post, Json, Router};
+use hyperion_core::block::{Block, Serializable};
+use hyperion_core::consensus::create_genesis_block;
+use hyperion_core::crypto::Hashable;
+use hyperion_rpc::{BlockTemplate, RpcError, VersionInfo, PROTOCOL_MAJOR, PROTOCOL_MINOR};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+/// Target so high nearly every nonce meets it, so every worker finds a
+/// block for every template
+pub const TRIVIAL: u32 = 0x207f_ffff;
+/// Target of 1, which no hash will meet
+pub const IMPOSSIBLE: u32 = 0x0300_0001;
+
+/// How long the mock holds a longpoll before answering with the same template
+pub const LONGPOLL_HOLD: Duration = Duration::from_millis(200);
+
+/// Accepts blocks on its tip and counts every submission by the height it
+/// was mined for
+pub struct MockNode {
+    /// Block hashes by height
+    pub chain: Vec<[u8; 32]>,
+    pub submissions: HashMap<usize, usize>,
+    pub difficulty: u32,
+    /// Hand out longpoll IDs and hold longpolls open
+    pub longpoll: bool,
+    /// Calls by method, with longpolls counted as `longpoll`
+    pub requests: HashMap<String, usize>,
+    /// Every request on arrival, including those never answered
+    pub received: usize,
+    /// Templates to serve, in order, before building them from the tip
+    pub templates: VecDeque<BlockTemplate>,
+    /// Reject reasons to answer the next submissions with
+    pub reject_reasons: VecDeque<&'static str>,
+    /// Reject blocks failing `Block::validate` as `high-hash`, as a real
+    /// node would; off for tests that submit unmined blocks
+    pub check_blocks: bool,
+    /// Delay before every answer
+    pub latency: Duration,
+    /// Further delay before answering every template request after the
+    /// first, longpolls excepted
+    pub template_latency: Duration,
+    /// Answer every request with 503, as a restarting node would
+    pub down: bool,
+    /// Times to be down for, as `down`
+    pub outages: Vec<Range<Instant>>,
+    /// Serve templates whose merkle root doesn't match their transactions
+    pub bad_merkle_root: bool,
+    /// Answer this many block submissions with 503 before taking them
+    pub submit_failures: usize,
+    /// Genesis hash `get_version_info` reports
+    pub genesis_hash: String,
+    /// Answer `get_version_info` as a node that predates it would
+    pub unversioned: bool,
+    /// Accept submissions, echoing back a hash other than the block's
+    pub misread_blocks: bool,
+    /// `Authorization` header to demand, answering 401 without it
+    pub authorization: Option<String>,
+}
+
+impl MockNode {
+    /// Be down for `length`, starting `after` from now
+    pub fn outage(&mut self, after: Duration, length: Duration) {
+        let start = Instant::now() + after;
+        self.outages.push(start..start + length);
+    }
+
+    pub fn is_down(&self) -> bool {
+        let now = Instant::now();
+        self.down || self.outages.iter().any(|outage| outage.contains(&now))
+    }
+
+    /// Calls of `method` answered so far
+    pub fn requests(&self, method: &str) -> usize {
+        self.requests.get(method).copied().unwrap_or(0)
+    }
+
+    /// The template the node builds on the block at `parent`
+    pub fn template_on(&self, parent: usize) -> BlockTemplate {
+        BlockTemplate {
+            difficulty_compact: self.difficulty,
+            previous_block_hash: hex::encode(self.chain[parent]),
+            height: parent as u64 + 1,
+            longpollid: self.longpoll.then(|| hex::encode(self.chain[parent])),
+            merkle_root: hex::encode(if self.bad_merkle_root { [1u8; 32] } else { [0u8; 32] }),
+            ..template(parent as u32)
+        }
+    }
+}
+
+/// Empty template timed `offset` seconds from now
+pub fn template(offset: u32) -> BlockTemplate {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+    BlockTemplate {
+        version: 1,
+        previous_block_hash: hex::encode([0u8; 32]),
+        transactions: Arc::default(),
+        difficulty_compact: TRIVIAL,
+        timestamp: now + offset,
+        cur_time: None,
+        min_time: None,
+        max_time: None,
+        height: 1,
+        merkle_root: hex::encode([0u8; 32]),
+        size_estimate: None,
+        tx_count: None,
+        longpollid: None,
+    }
+}
+
+/// Serve a mock node with templates at `difficulty`, returning it and its URL
+pub async fn serve_mock(difficulty: u32, longpoll: bool) -> (Arc<Mutex<MockNode>>, String) {
+    let node = Arc::new(Mutex::new(MockNode {
+        chain: vec![[0; 32]],
+        submissions: HashMap::new(),
+        difficulty,
+        longpoll,
+        requests: HashMap::new(),
+        received: 0,
+        templates: VecDeque::new(),
+        reject_reasons: VecDeque::new(),
+        check_blocks: false,
+        latency: Duration::ZERO,
+        template_latency: Duration::ZERO,
+        down: false,
+        outages: Vec::new(),
+        bad_merkle_root: false,
+        submit_failures: 0,
+        genesis_hash: hex::encode(create_genesis_block().double_sha256()),
+        unversioned: false,
+        misread_blocks: false,
+        authorization: None,
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let node_url = format!("http://{}", listener.local_addr().unwrap());
+    let router = Router::new().route("/rpc", post(mock_rpc)).with_state(node.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (node, node_url)
+}
+
+pub async fn wait_until(what: &str, condition: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !condition() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting until {}", what));
+}
+
+async fn mock_rpc(
+    State(node): State<Arc<Mutex<MockNode>>>,
+    headers: HeaderMap,
+    Json(request): Json<Value>,
+) -> Result<Json<Value>, StatusCode> {
+    let longpollid = request["params"]["longpollid"].as_str();
+    let latency = {
+        let mut node = node.lock().unwrap();
+        node.received += 1;
+        if let Some(authorization) = &node.authorization
+            && headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()) != Some(authorization.as_str()) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        let slow_template = request["method"] == "get_block_template"
+            && longpollid.is_none()
+            && node.requests("get_block_template") > 0;
+        node.latency + if slow_template { node.template_latency } else { Duration::ZERO }
+    };
+    sleep(latency).await;
+
+    if let Some(longpollid) = longpollid {
+        let deadline = Instant::now() + LONGPOLL_HOLD;
+        while Instant::now() < deadline && hex::encode(node.lock().unwrap().chain.last().unwrap()) == longpollid {
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let mut node = node.lock().unwrap();
+    if node.is_down() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let height = node.chain.len() - 1;
+    let method = request["method"].as_str().unwrap();
+    if method == "submit_block" && node.submit_failures > 0 {
+        node.submit_failures -= 1;
+        *node.requests.entry("failed_submit".to_string()).or_default() += 1;
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    *node.requests.entry(if longpollid.is_some() { "longpoll" } else { method }.to_string()).or_default() += 1;
+
+    let result = match method {
+        "get_version_info" if node.unversioned => {
+            let error = RpcError::method_not_found();
+            return Ok(Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": null, "error": error })));
+        }
+        "get_version_info" => json!(VersionInfo {
+            version: "0.1.0".to_string(),
+            protocol_major: PROTOCOL_MAJOR,
+            protocol_minor: PROTOCOL_MINOR,
+            network: "regtest".to_string(),
+            genesis_hash: node.genesis_hash.clone(),
+        }),
+        "get_mining_info" => json!({
+            "blocks": height, "current_block_size": 0, "current_block_tx": 0, "difficulty": 1.0,
+            "network_hashps": 0.0, "pooled_tx": 0, "chain": "regtest",
+        }),
+        "get_block_count" => json!(height),
+        "get_blockchain_info" => json!({
+            "chain": "regtest", "blocks": height, "headers": height, "difficulty": 1.0, "median_time": 0,
+            "best_blockhash": hex::encode(node.chain[height]), "verification_progress": 1.0,
+        }),
+        "get_block" => {
+            let hash = request["params"]["hash"].as_str().unwrap();
+            match node.chain.iter().position(|block| hex::encode(block) == hash) {
+                Some(height) => json!({ "hash": hash, "hex": "", "height": height }),
+                None => {
+                    let error = RpcError::custom(-5, "No such block");
+                    return Ok(Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": null, "error": error })));
+                }
+            }
+        }
+        "get_block_template" => {
+            let template = node.templates.pop_front().unwrap_or_else(|| node.template_on(height));
+            json!(template)
+        }
+        "submit_block" => {
+            let bytes = hex::decode(request["params"]["block_hex"].as_str().unwrap()).unwrap();
+            let block = Block::from_bytes(&bytes).unwrap();
+            let parent = node.chain.iter().position(|hash| *hash == block.header.prev_hash).unwrap();
+            *node.submissions.entry(parent + 1).or_default() += 1;
+
+            if node.misread_blocks {
+                json!({ "accepted": true, "message": null, "block_hash": "00".repeat(32), "height": parent + 1 })
+            } else if let Some(reason) = node.reject_reasons.pop_front() {
+                json!({ "accepted": false, "message": reason, "reject_reason": reason })
+            } else if node.check_blocks && block.validate().is_err() {
+                json!({ "accepted": false, "message": "InvalidPoW", "reject_reason": "high-hash" })
+            } else if parent == height {
+                node.chain.push(block.double_sha256());
+                json!({ "accepted": true, "message": null, "reject_reason": null })
+            } else {
+                json!({ "accepted": false, "message": "InvalidPreviousHash", "reject_reason": "stale-prevblk" })
+            }
+        }
+        method => panic!("unexpected method {}", method),
+    };
+
+    Ok(Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result, "error": null })))
+}
@@ -1,25 +1,305 @@
+use crate::config::MiningConfig;
+
 use num_cpus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{info, warn};
 
+/// Default mining threads: one per physical core, since a second thread on
+/// an SMT sibling adds little hashrate, leaving one core free for system
+/// tasks on machines with more than two
 pub fn detect_optimal_threads() -> usize {
-    let cpu_count = num_cpus::get();
-    
-    // Leave one core free for system tasks
-    if cpu_count > 2 {
-        cpu_count - 1
+    optimal_threads(core_topology())
+}
+
+fn optimal_threads(topology: CoreTopology) -> usize {
+    // Physical cores aren't limited by affinity or cgroups the way the
+    // logical count is
+    let cores = topology.physical.min(topology.logical).max(1);
+    if cores > 2 {
+        cores - 1
     } else {
-        cpu_count
+        cores
     }
 }
 
 pub fn get_system_info() -> SystemInfo {
+    let topology = core_topology();
     SystemInfo {
-        cpu_cores: num_cpus::get(),
-        optimal_threads: detect_optimal_threads(),
+        cpu_model: cpu_model(),
+        topology,
+        cpu_features: cpu_features(),
+        total_memory: total_memory(),
+        optimal_threads: optimal_threads(topology),
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SystemInfo {
-    pub cpu_cores: usize,
+    /// CPU model name, where the platform reports it
+    pub cpu_model: Option<String>,
+    pub topology: CoreTopology,
+    /// SIMD and hashing extensions the CPU supports
+    pub cpu_features: Vec<String>,
+    /// Total memory in bytes, where the platform reports it
+    pub total_memory: Option<u64>,
+    /// Default thread count when the config doesn't set one
     pub optimal_threads: usize,
-}
\ No newline at end of file
+}
+
+impl SystemInfo {
+    /// One-line summary, e.g. `AMD Ryzen 7 5800X, 8 cores / 16 threads,
+    /// 31.3 GiB, features: sse4.1 avx2 sha`
+    pub fn summary(&self) -> String {
+        let memory = self
+            .total_memory
+            .map_or("unknown memory".to_string(), |bytes| format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64));
+        let features = match self.cpu_features.is_empty() {
+            true => "none detected".to_string(),
+            false => self.cpu_features.join(" "),
+        };
+        format!(
+            "{}, {} cores / {} threads, {}, features: {}",
+            self.cpu_model.as_deref().unwrap_or("unknown CPU"),
+            self.topology.physical,
+            self.topology.logical,
+            memory,
+            features,
+        )
+    }
+}
+
+/// The first `model name` in `/proc/cpuinfo`
+#[cfg(target_os = "linux")]
+fn cpu_model() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == "model name")
+        .map(|(_, model)| model.trim().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_model() -> Option<String> {
+    None
+}
+
+/// `MemTotal` from `/proc/meminfo`
+#[cfg(target_os = "linux")]
+fn total_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kib = meminfo.lines().find_map(|line| line.strip_prefix("MemTotal:"))?;
+    let kib: u64 = kib.trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn total_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpu_features() -> Vec<String> {
+    let detected = [
+        ("sse2", is_x86_feature_detected!("sse2")),
+        ("ssse3", is_x86_feature_detected!("ssse3")),
+        ("sse4.1", is_x86_feature_detected!("sse4.1")),
+        ("avx", is_x86_feature_detected!("avx")),
+        ("avx2", is_x86_feature_detected!("avx2")),
+        ("avx512f", is_x86_feature_detected!("avx512f")),
+        ("sha", is_x86_feature_detected!("sha")),
+    ];
+    detected.into_iter().filter(|&(_, found)| found).map(|(name, _)| name.to_string()).collect()
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_features() -> Vec<String> {
+    let detected = [
+        ("neon", std::arch::is_aarch64_feature_detected!("neon")),
+        ("sha2", std::arch::is_aarch64_feature_detected!("sha2")),
+        ("sha3", std::arch::is_aarch64_feature_detected!("sha3")),
+    ];
+    detected.into_iter().filter(|&(_, found)| found).map(|(name, _)| name.to_string()).collect()
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn cpu_features() -> Vec<String> {
+    Vec::new()
+}
+
+/// How many cores the machine has, and how many hardware threads they run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreTopology {
+    pub physical: usize,
+    pub logical: usize,
+}
+
+impl CoreTopology {
+    /// Whether cores run more than one hardware thread each
+    pub fn has_smt(&self) -> bool {
+        self.logical > self.physical
+    }
+}
+
+pub fn core_topology() -> CoreTopology {
+    CoreTopology {
+        physical: num_cpus::get_physical(),
+        logical: num_cpus::get(),
+    }
+}
+
+/// The cores to pin workers to, or `None` to leave them unpinned because
+/// pinning is off or unsupported here. Logs the worker to core mapping.
+pub fn pinned_cores(config: &MiningConfig) -> Option<Vec<usize>> {
+    if !config.pin_threads {
+        return None;
+    }
+
+    let Some(available) = core_affinity::get_core_ids() else {
+        warn!("Pinning threads is not supported on this platform, mining unpinned");
+        return None;
+    };
+    let available: Vec<usize> = available.into_iter().map(|core| core.id).collect();
+
+    let cores = if config.cpu_set.is_empty() {
+        default_cores(&available, core_topology(), config.threads, thread_siblings)
+    } else {
+        config.cpu_set.clone()
+    };
+    let mapping: Vec<String> = (0..config.threads)
+        .filter_map(|worker| core_for_worker(worker, &cores).map(|core| format!("{}→{}", worker, core)))
+        .collect();
+    info!("Pinning workers to cores: {}", mapping.join(", "));
+
+    Some(cores)
+}
+
+/// Cores to use when `cpu_set` isn't given. With SMT and no more workers
+/// than physical cores, one hardware thread per core is kept, going by
+/// `siblings`, so each worker gets a core to itself. Where siblings can't
+/// be read, the first `physical` IDs are used instead, as most systems
+/// number one thread per core before any siblings.
+pub fn default_cores(
+    available: &[usize],
+    topology: CoreTopology,
+    threads: usize,
+    siblings: impl Fn(usize) -> Option<Vec<usize>>,
+) -> Vec<usize> {
+    if !topology.has_smt() || threads > topology.physical {
+        return available.to_vec();
+    }
+
+    let mut taken = HashSet::new();
+    let mut cores = Vec::new();
+    for &core in available {
+        let Some(group) = siblings(core) else {
+            return available.iter().copied().take(topology.physical).collect();
+        };
+        if !taken.contains(&core) {
+            cores.push(core);
+        }
+        taken.extend(group);
+    }
+    cores
+}
+
+/// Hardware threads sharing a core with `core`, itself included, as Linux
+/// lists them in sysfs; `None` elsewhere or if unreadable
+pub fn thread_siblings(core: usize) -> Option<Vec<usize>> {
+    let path = format!("/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list", core);
+    parse_cpu_list(&std::fs::read_to_string(path).ok()?)
+}
+
+/// Parse a kernel CPU list such as `0,8` or `0-1,4-5`
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cores = Vec::new();
+    for part in list.trim().split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => cores.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cores.push(part.parse().ok()?),
+        }
+    }
+    Some(cores)
+}
+
+/// The core for worker `worker`: the worker'th of `cores`, wrapping round
+/// when there are more workers than cores
+pub fn core_for_worker(worker: usize, cores: &[usize]) -> Option<usize> {
+    if cores.is_empty() {
+        return None;
+    }
+    Some(cores[worker % cores.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workers_spread_over_cores() {
+        let cores = [0, 2, 4];
+        let assigned: Vec<_> = (0..7).map(|worker| core_for_worker(worker, &cores).unwrap()).collect();
+        assert_eq!(assigned, [0, 2, 4, 0, 2, 4, 0]);
+        assert_eq!(core_for_worker(3, &[]), None);
+    }
+
+    #[test]
+    fn test_default_cores_avoid_smt_siblings() {
+        let available: Vec<usize> = (0..16).collect();
+        let smt = CoreTopology { physical: 8, logical: 16 };
+        let unknown = |_| None;
+
+        // Siblings numbered after every core, as on most x86 machines
+        let split = |core: usize| Some(vec![core % 8, core % 8 + 8]);
+        assert_eq!(default_cores(&available, smt, 8, split), (0..8).collect::<Vec<_>>());
+        // Siblings numbered next to each other
+        let adjacent = |core: usize| Some(vec![core / 2 * 2, core / 2 * 2 + 1]);
+        assert_eq!(default_cores(&available, smt, 8, adjacent), (0..16).step_by(2).collect::<Vec<_>>());
+        // Without sysfs, the first IDs are assumed to be one per core
+        assert_eq!(default_cores(&available, smt, 8, unknown), (0..8).collect::<Vec<_>>());
+
+        assert_eq!(default_cores(&available, smt, 12, adjacent), available);
+        let no_smt = CoreTopology { physical: 16, logical: 16 };
+        assert_eq!(default_cores(&available, no_smt, 4, unknown), available);
+    }
+
+    #[test]
+    fn test_cpu_lists_parse() {
+        assert_eq!(parse_cpu_list("0,8\n"), Some(vec![0, 8]));
+        assert_eq!(parse_cpu_list("2-3"), Some(vec![2, 3]));
+        assert_eq!(parse_cpu_list("0-1,4-5"), Some(vec![0, 1, 4, 5]));
+        assert_eq!(parse_cpu_list("cpu0"), None);
+    }
+
+    #[test]
+    fn test_default_threads_follow_physical_cores() {
+        assert_eq!(optimal_threads(CoreTopology { physical: 8, logical: 16 }), 7);
+        assert_eq!(optimal_threads(CoreTopology { physical: 2, logical: 4 }), 2);
+        // A container limited to fewer logical CPUs than the host has cores
+        assert_eq!(optimal_threads(CoreTopology { physical: 32, logical: 4 }), 3);
+        assert_eq!(optimal_threads(CoreTopology { physical: 0, logical: 1 }), 1);
+
+        let info = get_system_info();
+        assert!(info.optimal_threads >= 1 && info.optimal_threads <= info.topology.logical);
+        assert_eq!(detect_optimal_threads(), info.optimal_threads);
+    }
+
+    #[test]
+    fn test_system_info_serializes() {
+        let info = get_system_info();
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["topology"]["logical"], info.topology.logical);
+        assert_eq!(json["optimal_threads"], info.optimal_threads);
+        assert_eq!(serde_json::from_value::<SystemInfo>(json).unwrap(), info);
+
+        let info = SystemInfo {
+            cpu_model: Some("Test CPU".to_string()),
+            topology: CoreTopology { physical: 4, logical: 8 },
+            cpu_features: vec!["avx2".to_string(), "sha".to_string()],
+            total_memory: Some(16 << 30),
+            optimal_threads: 3,
+        };
+        assert_eq!(info.summary(), "Test CPU, 4 cores / 8 threads, 16.0 GiB, features: avx2 sha");
+    }
+}
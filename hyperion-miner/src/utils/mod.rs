@@ -1,5 +1,7 @@
 pub mod hardware;
+pub mod session;
 pub mod stats;
+pub mod stats_server;
 
 pub use hardware::detect_optimal_threads;
-pub use stats::MiningStats;
\ No newline at end of file
+pub use stats::{MiningStats, StatsSnapshot};
\ No newline at end of file
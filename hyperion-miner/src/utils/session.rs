@@ -0,0 +1,140 @@
+//! Counters carried across runs of the miner in `session_file`, so luck and
+//! block counts can be tracked over longer than one run. Rates aren't
+//! carried: each run's hashrate windows start empty.
+
+use super::stats::StatsSnapshot;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Format of the session file. Bumped only for changes older miners can't
+/// read; new counters are added with defaults instead.
+pub const SESSION_VERSION: u32 = 1;
+
+/// Totals over every run of a session, written on a clean shutdown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    /// Runs the totals cover
+    pub runs: u64,
+    pub runtime_secs: f64,
+    pub total_hashes: u64,
+    /// Blocks submitted, and found blocks the node never answered for
+    pub blocks_found: u64,
+    pub blocks_accepted: u64,
+    pub blocks_rejected: u64,
+    /// Node URL, or pool address in pool mode, of the latest run
+    pub upstream: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            runs: 0,
+            runtime_secs: 0.0,
+            total_hashes: 0,
+            blocks_found: 0,
+            blocks_accepted: 0,
+            blocks_rejected: 0,
+            upstream: None,
+        }
+    }
+}
+
+impl Session {
+    /// Read the session at `path`, or `None` if there isn't one yet
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let session: Self = serde_json::from_slice(&content)
+            .with_context(|| format!("Invalid session file {}", path.display()))?;
+        if session.version > SESSION_VERSION {
+            bail!(
+                "Session file {} is version {}, newer than the {} this miner reads",
+                path.display(),
+                session.version,
+                SESSION_VERSION
+            );
+        }
+        Ok(Some(session))
+    }
+
+    /// Replace `path` with this session, via a temporary file so a crash
+    /// mid-write leaves the previous one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// These totals with the run summed up in `snapshot` added on
+    pub fn continued(&self, snapshot: &StatsSnapshot) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            runs: self.runs + 1,
+            runtime_secs: self.runtime_secs + snapshot.uptime_secs,
+            total_hashes: self.total_hashes + snapshot.total_hashes,
+            blocks_found: self.blocks_found + snapshot.blocks_submitted + snapshot.blocks_lost,
+            blocks_accepted: self.blocks_accepted + snapshot.blocks_found,
+            blocks_rejected: self.blocks_rejected + snapshot.blocks_rejected.values().sum::<u64>(),
+            upstream: Some(snapshot.upstream.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MiningStats;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_resumed_session_carries_counters_not_rates() {
+        let path = std::env::temp_dir().join(format!("hyperion-miner-session-{}.json", std::process::id()));
+        assert_eq!(Session::load(&path).unwrap(), None);
+
+        let start = Instant::now() - Duration::from_secs(100);
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        stats.total_hashes.store(200_000, Ordering::SeqCst);
+        stats.blocks_submitted.store(3, Ordering::SeqCst);
+        stats.blocks_found.store(2, Ordering::SeqCst);
+        stats.record_rejection("high-hash");
+        stats.take_snapshot(&[], "http://127.0.0.1:6001", true);
+        stats.session().save(&path).unwrap();
+
+        let resumed = MiningStats::new();
+        resumed.resume(Session::load(&path).unwrap().unwrap());
+        let snapshot = resumed.take_snapshot(&[], "http://127.0.0.1:6001", true);
+        let session = snapshot.session.unwrap();
+        assert_eq!(session.runs, 2);
+        assert!(session.runtime_secs >= 100.0);
+        assert_eq!(
+            (session.total_hashes, session.blocks_found, session.blocks_accepted, session.blocks_rejected),
+            (200_000, 3, 2, 1)
+        );
+        assert_eq!(session.upstream.as_deref(), Some("http://127.0.0.1:6001"));
+
+        // This run's own figures start from nothing
+        assert_eq!((snapshot.total_hashes, snapshot.blocks_found), (0, 0));
+        assert_eq!((snapshot.hashrate_1m, snapshot.hashrate_avg), (0.0, 0.0));
+
+        // Counters missing from older files are defaulted, newer formats refused
+        std::fs::write(&path, r#"{ "version": 1, "runs": 4, "blocks_accepted": 9 }"#).unwrap();
+        let old = Session::load(&path).unwrap().unwrap();
+        assert_eq!((old.runs, old.blocks_accepted, old.total_hashes), (4, 9, 0));
+        std::fs::write(&path, r#"{ "version": 2 }"#).unwrap();
+        assert!(Session::load(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,13 +1,246 @@
+use super::hardware::{self, SystemInfo};
+use super::session::Session;
+use crate::mining::MiningWorker;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Longest window a hashrate is averaged over; older samples are dropped
+const LONGEST_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Round trips a latency percentile is taken over; older ones are dropped
+const LATENCY_SAMPLES: usize = 100;
+
+const YEAR_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Units block ETAs are shown in, largest first
+const ETA_UNITS: [(&str, u64); 5] = [("y", YEAR_SECS), ("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)];
+
+/// Point-in-time view of the miner, served at `GET /stats`, written to
+/// `stats_file`, logged and summed up on exit. Hashrates are in H/s.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    pub uptime_secs: f64,
+    /// Hashes taken from workers up to this snapshot, including workers
+    /// since removed
+    #[serde(default)]
+    pub total_hashes: u64,
+    /// Hashes of workers since removed, included in `total_hashes` but not
+    /// listed in `workers`
+    #[serde(default)]
+    pub retired_hashes: u64,
+    /// Aggregate hashrate since the previous snapshot
+    pub hashrate: f64,
+    pub hashrate_1m: f64,
+    pub hashrate_5m: f64,
+    pub hashrate_15m: f64,
+    pub hashrate_avg: f64,
+    pub workers: Vec<WorkerSnapshot>,
+    /// Fraction of the time workers spend hashing, below 1.0 when throttled
+    pub intensity: f64,
+    pub blocks_found: u64,
+    pub blocks_submitted: u64,
+    /// Rejected blocks by the node's reject reason
+    pub blocks_rejected: BTreeMap<String, u64>,
+    /// Found blocks the node never answered for within the retry window
+    pub blocks_lost: u64,
+    /// Percentage of submitted blocks the node accepted
+    pub efficiency: Option<f64>,
+    /// Expected seconds to find a block at the current target and the
+    /// 5-minute hashrate
+    pub block_eta_secs: Option<f64>,
+    /// Blocks found as a percentage of the blocks the hashes so far should
+    /// have found; above 100 is lucky
+    pub luck: Option<f64>,
+    /// Work abandoned for newer work before it yielded a block
+    pub stale_work: u64,
+    /// Templates refused for failing local checks
+    pub templates_rejected: u64,
+    /// Submitted blocks the node hashed differently than the miner, a sign
+    /// the two disagree on block serialization
+    #[serde(default)]
+    pub hash_mismatches: u64,
+    /// Round trips of template fetches, longpolls aside
+    pub template_latency: Option<LatencySnapshot>,
+    pub submit_latency: Option<LatencySnapshot>,
+    /// Latest template's timestamp less the local clock, in seconds
+    pub clock_skew_secs: Option<i64>,
+    pub reconnects: u64,
+    pub shares_accepted: u64,
+    pub shares_rejected: u64,
+    /// Height of the block being mined, unknown in pool mode
+    pub template_height: Option<u64>,
+    /// Node URL, or pool address in pool mode
+    pub upstream: String,
+    pub connected: bool,
+    /// Mining was paused by request
+    pub paused: bool,
+    pub system: SystemInfo,
+    /// Totals of the resumed session, this run included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<Session>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerSnapshot {
+    pub id: usize,
+    /// Backend the worker hashes with
+    pub backend: String,
+    /// Hashrate since the previous snapshot
+    pub hashrate: f64,
+    pub hashes: u64,
+    /// Nonces the worker hashes between checks for new work
+    #[serde(default)]
+    pub batch_size: u64,
+}
+
+/// Percentiles of recent round trips, in ms
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencySnapshot {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Round trips the percentiles are taken over
+    pub samples: usize,
+}
+
+/// What a mining session came to, printed when the miner exits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownSummary {
+    pub runtime_secs: f64,
+    pub total_hashes: u64,
+    /// Hashes over the time spent mining, pauses left out, in H/s
+    pub hashrate_avg: f64,
+    /// Blocks submitted, and found blocks the node never answered for
+    pub blocks_found: u64,
+    pub blocks_accepted: u64,
+    pub blocks_rejected: u64,
+    pub reconnects: u64,
+    /// Code the process exits with
+    pub exit_code: i32,
+}
+
+impl ShutdownSummary {
+    pub fn from_snapshot(snapshot: &StatsSnapshot, exit_code: i32) -> Self {
+        Self {
+            runtime_secs: snapshot.uptime_secs,
+            total_hashes: snapshot.total_hashes,
+            hashrate_avg: snapshot.hashrate_avg,
+            blocks_found: snapshot.blocks_submitted + snapshot.blocks_lost,
+            blocks_accepted: snapshot.blocks_found,
+            blocks_rejected: snapshot.blocks_rejected.values().sum(),
+            reconnects: snapshot.reconnects,
+            exit_code,
+        }
+    }
+}
+
+/// The latest `LATENCY_SAMPLES` round trips of one kind of call
+#[derive(Debug, Default)]
+pub struct LatencyWindow {
+    samples: Mutex<VecDeque<Duration>>,
+}
+
+impl LatencyWindow {
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// 50th and 95th percentiles, nearest rank, once there is a sample
+    pub fn snapshot(&self) -> Option<LatencySnapshot> {
+        let mut sorted: Vec<Duration> = self.samples.lock().unwrap().iter().copied().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort();
+
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1].as_secs_f64() * 1000.0
+        };
+        Some(LatencySnapshot { p50_ms: percentile(0.5), p95_ms: percentile(0.95), samples: sorted.len() })
+    }
+}
 
 #[derive(Clone)]
 pub struct MiningStats {
     pub start_time: Instant,
+    /// Blocks submitted and accepted
     pub blocks_found: Arc<AtomicU64>,
-    pub last_hash_count: Arc<AtomicU64>,
+    pub blocks_submitted: Arc<AtomicU64>,
+    /// Rejected blocks by the node's reject reason
+    pub blocks_rejected: Arc<Mutex<BTreeMap<String, u64>>>,
+    /// Found blocks the node never answered for within the retry window
+    pub blocks_lost: Arc<AtomicU64>,
+    /// Work abandoned for newer work before it yielded a block
+    pub stale_work: Arc<AtomicU64>,
+    /// Templates refused for failing local checks
+    pub templates_rejected: Arc<AtomicU64>,
+    /// Submitted blocks the node hashed differently than the miner
+    pub hash_mismatches: Arc<AtomicU64>,
+    /// Round trips of template fetches, longpolls aside
+    pub template_latency: Arc<LatencyWindow>,
+    pub submit_latency: Arc<LatencyWindow>,
+    /// Latest template's timestamp less the local clock, in seconds
+    pub clock_skew: Arc<Mutex<Option<i64>>>,
+    /// Expected hashes to find a block at the current template's target
+    pub block_work: Arc<Mutex<Option<f64>>>,
+    /// Blocks the hashes taken so far should have found on average, each
+    /// interval's hashes counted at the target of the time
+    pub expected_blocks: Arc<Mutex<f64>>,
+    /// Times the connection to the node was lost and restored
+    pub reconnects: Arc<AtomicU64>,
+    pub shares_accepted: Arc<AtomicU64>,
+    pub shares_rejected: Arc<AtomicU64>,
     pub last_stats_time: Arc<Mutex<Instant>>,
+    /// (time, cumulative hashes) samples covering at least `LONGEST_WINDOW`
+    pub samples: Arc<Mutex<VecDeque<(Instant, u64)>>>,
+    /// Hashes taken from workers by snapshots so far, including workers
+    /// since removed
+    pub total_hashes: Arc<AtomicU64>,
+    /// Workers removed from the worker set whose threads may still be
+    /// finishing a batch
+    pub retired_workers: Arc<Mutex<Vec<MiningWorker>>>,
+    /// Hashes of removed workers whose threads have exited
+    pub retired_hashes: Arc<AtomicU64>,
+    /// Height of the current template, 0 before the first
+    pub template_height: Arc<AtomicU64>,
+    /// Latest snapshot, shared with the stats endpoint
+    pub snapshot: Arc<Mutex<StatsSnapshot>>,
+    /// Time spent paused, left out of the session average
+    pub paused: Arc<Mutex<PausedTime>>,
+    /// The machine mined on, detected at startup
+    pub system: Arc<SystemInfo>,
+    /// Totals of earlier runs, when resuming a session
+    pub resumed: Arc<Mutex<Option<Session>>>,
+}
+
+/// Time spent paused: finished pauses, and the start of the current one
+#[derive(Debug, Default)]
+pub struct PausedTime {
+    pub total: Duration,
+    pub since: Option<Instant>,
+}
+
+impl PausedTime {
+    /// Time paused up to `at`
+    fn until(&self, at: Instant) -> Duration {
+        let current = self.since.map_or(Duration::ZERO, |since| at.saturating_duration_since(since));
+        self.total + current
+    }
+}
+
+impl Default for MiningStats {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MiningStats {
@@ -15,28 +248,402 @@ impl MiningStats {
         Self {
             start_time: Instant::now(),
             blocks_found: Arc::new(AtomicU64::new(0)),
-            last_hash_count: Arc::new(AtomicU64::new(0)),
+            blocks_submitted: Arc::new(AtomicU64::new(0)),
+            blocks_rejected: Arc::new(Mutex::new(BTreeMap::new())),
+            blocks_lost: Arc::new(AtomicU64::new(0)),
+            stale_work: Arc::new(AtomicU64::new(0)),
+            templates_rejected: Arc::new(AtomicU64::new(0)),
+            hash_mismatches: Arc::new(AtomicU64::new(0)),
+            template_latency: Arc::new(LatencyWindow::default()),
+            submit_latency: Arc::new(LatencyWindow::default()),
+            clock_skew: Arc::new(Mutex::new(None)),
+            block_work: Arc::new(Mutex::new(None)),
+            expected_blocks: Arc::new(Mutex::new(0.0)),
+            reconnects: Arc::new(AtomicU64::new(0)),
+            shares_accepted: Arc::new(AtomicU64::new(0)),
+            shares_rejected: Arc::new(AtomicU64::new(0)),
             last_stats_time: Arc::new(Mutex::new(Instant::now())),
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+            total_hashes: Arc::new(AtomicU64::new(0)),
+            retired_workers: Arc::new(Mutex::new(Vec::new())),
+            retired_hashes: Arc::new(AtomicU64::new(0)),
+            template_height: Arc::new(AtomicU64::new(0)),
+            snapshot: Arc::new(Mutex::new(StatsSnapshot::default())),
+            paused: Arc::new(Mutex::new(PausedTime::default())),
+            system: Arc::new(hardware::get_system_info()),
+            resumed: Arc::new(Mutex::new(None)),
         }
     }
 
-    pub fn calculate_hashrate(&self, total_hashes: u64) -> f64 {
-        let mut last_time = self.last_stats_time.lock().unwrap();
-        let now = Instant::now();
-        let duration = now.duration_since(*last_time).as_secs_f64();
-        
-        let last_hashes = self.last_hash_count.swap(total_hashes, Ordering::SeqCst);
-        let hash_diff = total_hashes.saturating_sub(last_hashes);
-        
-        *last_time = now;
-        
+    /// Start or finish a pause
+    pub fn set_paused(&self, paused: bool) {
+        let mut paused_time = self.paused.lock().unwrap();
+        match (paused, paused_time.since) {
+            (true, None) => paused_time.since = Some(Instant::now()),
+            (false, Some(since)) => {
+                paused_time.total += since.elapsed();
+                paused_time.since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.lock().unwrap().since.is_some()
+    }
+
+    pub fn record_rejection(&self, reason: &str) {
+        *self.blocks_rejected.lock().unwrap().entry(reason.to_string()).or_default() += 1;
+    }
+
+    /// Percentage of submitted blocks that were accepted, if any were submitted
+    pub fn efficiency(&self) -> Option<f64> {
+        match self.blocks_submitted.load(Ordering::SeqCst) {
+            0 => None,
+            submitted => Some(self.blocks_found.load(Ordering::SeqCst) as f64 * 100.0 / submitted as f64),
+        }
+    }
+
+    /// Summary of the session, from the latest snapshot, for a process
+    /// exiting with `exit_code`. Take a final snapshot after stopping the
+    /// workers so it counts every hash.
+    pub fn shutdown_summary(&self, exit_code: i32) -> ShutdownSummary {
+        ShutdownSummary::from_snapshot(&self.snapshot.lock().unwrap(), exit_code)
+    }
+
+    /// Carry on the counters of `session` from earlier runs. This run's
+    /// rates and counters still start from nothing.
+    pub fn resume(&self, session: Session) {
+        *self.resumed.lock().unwrap() = Some(session);
+    }
+
+    /// Totals of the session so far, from the latest snapshot: this run
+    /// alone unless resuming one
+    pub fn session(&self) -> Session {
+        let resumed = self.resumed.lock().unwrap().clone().unwrap_or_default();
+        resumed.continued(&self.snapshot.lock().unwrap())
+    }
+
+    /// Keep counting the hashes of `worker`, removed from the worker set,
+    /// towards the totals, including those of the batch it's finishing
+    pub fn retire_worker(&self, worker: MiningWorker) {
+        self.retired_workers.lock().unwrap().push(worker);
+    }
+
+    /// Take the hashes of retired workers since the previous snapshot, and
+    /// return them with every hash retired workers computed. Once a
+    /// worker's thread has exited, nothing else holds its counters and
+    /// they're folded into `retired_hashes`.
+    fn take_retired_hashes(&self) -> (u64, u64) {
+        let mut retired = self.retired_workers.lock().unwrap();
+        // Checked before taking its hashes, so a thread exiting in between
+        // has its last batch taken too
+        let exited: Vec<bool> = retired.iter().map(|w| Arc::strong_count(&w.hashes_computed) == 1).collect();
+        let taken = retired.iter().map(MiningWorker::take_interval_hashes).sum();
+
+        let mut exited = exited.into_iter();
+        retired.retain(|worker| {
+            let keep = !exited.next().unwrap_or(false);
+            if !keep {
+                self.retired_hashes.fetch_add(worker.hashes_computed.load(Ordering::Relaxed), Ordering::SeqCst);
+            }
+            keep
+        });
+        let live: u64 = retired.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).sum();
+        (taken, self.retired_hashes.load(Ordering::SeqCst) + live)
+    }
+
+    /// Take the workers' hashes since the previous snapshot and publish a
+    /// new snapshot built from them
+    pub fn take_snapshot(&self, workers: &[MiningWorker], upstream: &str, connected: bool) -> StatsSnapshot {
+        self.take_snapshot_at(Instant::now(), workers, upstream, connected)
+    }
+
+    /// `take_snapshot` as if taken at `at`. Each lock is held only while
+    /// its figure is read, never across building the snapshot.
+    fn take_snapshot_at(&self, at: Instant, workers: &[MiningWorker], upstream: &str, connected: bool) -> StatsSnapshot {
+        let interval: Vec<u64> = workers.iter().map(MiningWorker::take_interval_hashes).collect();
+        let rates = self.worker_hashrates_at(at, &interval);
+        let hashes: Vec<u64> = workers.iter().map(|w| w.hashes_computed.load(Ordering::Relaxed)).collect();
+        let (retired_taken, retired_hashes) = self.take_retired_hashes();
+        let taken: u64 = interval.iter().sum::<u64>() + retired_taken;
+        let total_hashes = self.total_hashes.fetch_add(taken, Ordering::SeqCst) + taken;
+        self.record_sample(at, total_hashes);
+        let block_work = *self.block_work.lock().unwrap();
+        let expected_blocks = {
+            let mut expected_blocks = self.expected_blocks.lock().unwrap();
+            if let Some(work) = block_work {
+                *expected_blocks += taken as f64 / work;
+            }
+            *expected_blocks
+        };
+        let hashrate_5m = self.hashrate_5m();
+        let blocks_found = self.blocks_found.load(Ordering::SeqCst);
+
+        let mut snapshot = StatsSnapshot {
+            uptime_secs: at.saturating_duration_since(self.start_time).as_secs_f64(),
+            total_hashes,
+            retired_hashes,
+            hashrate: rates.iter().sum(),
+            hashrate_1m: self.hashrate_1m(),
+            hashrate_5m,
+            hashrate_15m: self.hashrate_15m(),
+            hashrate_avg: self.session_hashrate(),
+            workers: workers
+                .iter()
+                .zip(rates.iter().zip(hashes))
+                .map(|(worker, (&hashrate, hashes))| WorkerSnapshot {
+                    id: worker.id,
+                    backend: worker.backend().to_string(),
+                    hashrate,
+                    hashes,
+                    batch_size: worker.batch_size(),
+                })
+                .collect(),
+            intensity: workers.first().map_or(1.0, MiningWorker::intensity),
+            blocks_found,
+            blocks_submitted: self.blocks_submitted.load(Ordering::SeqCst),
+            blocks_rejected: self.blocks_rejected.lock().unwrap().clone(),
+            blocks_lost: self.blocks_lost.load(Ordering::SeqCst),
+            efficiency: self.efficiency(),
+            block_eta_secs: block_work.and_then(|work| expected_block_secs(work, hashrate_5m)),
+            luck: luck(blocks_found, expected_blocks),
+            stale_work: self.stale_work.load(Ordering::SeqCst),
+            templates_rejected: self.templates_rejected.load(Ordering::SeqCst),
+            hash_mismatches: self.hash_mismatches.load(Ordering::SeqCst),
+            template_latency: self.template_latency.snapshot(),
+            submit_latency: self.submit_latency.snapshot(),
+            clock_skew_secs: *self.clock_skew.lock().unwrap(),
+            reconnects: self.reconnects.load(Ordering::SeqCst),
+            shares_accepted: self.shares_accepted.load(Ordering::SeqCst),
+            shares_rejected: self.shares_rejected.load(Ordering::SeqCst),
+            template_height: match self.template_height.load(Ordering::SeqCst) {
+                0 => None,
+                height => Some(height),
+            },
+            upstream: upstream.to_string(),
+            connected,
+            paused: self.is_paused(),
+            system: (*self.system).clone(),
+            session: None,
+        };
+        snapshot.session = self.resumed.lock().unwrap().as_ref().map(|resumed| resumed.continued(&snapshot));
+
+        *self.snapshot.lock().unwrap() = snapshot.clone();
+        snapshot
+    }
+
+    /// Record the cumulative number of hashes computed across all workers
+    pub fn record_hashes(&self, total_hashes: u64) {
+        self.record_sample(Instant::now(), total_hashes);
+    }
+
+    fn record_sample(&self, at: Instant, total_hashes: u64) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back((at, total_hashes));
+
+        // Keep the newest sample at least `LONGEST_WINDOW` old as the base
+        // of the longest window
+        while samples.len() > 2 && at.duration_since(samples[1].0) >= LONGEST_WINDOW {
+            samples.pop_front();
+        }
+    }
+
+    /// Average hashrate over the `window` up to the latest sample, or over
+    /// every sample while fewer than `window` have been recorded
+    pub fn hashrate_over(&self, window: Duration) -> f64 {
+        let samples = self.samples.lock().unwrap();
+        let Some(&(latest_time, latest_hashes)) = samples.back() else {
+            return 0.0;
+        };
+
+        let (base_time, base_hashes) = samples
+            .iter()
+            .rev()
+            .find(|(time, _)| latest_time.duration_since(*time) >= window)
+            .or(samples.front())
+            .copied()
+            .unwrap();
+
+        let duration = latest_time.duration_since(base_time).as_secs_f64();
         if duration > 0.0 {
-            hash_diff as f64 / duration
+            latest_hashes.saturating_sub(base_hashes) as f64 / duration
         } else {
             0.0
         }
     }
 
+    pub fn hashrate_1m(&self) -> f64 {
+        self.hashrate_over(Duration::from_secs(60))
+    }
+
+    pub fn hashrate_5m(&self) -> f64 {
+        self.hashrate_over(Duration::from_secs(5 * 60))
+    }
+
+    pub fn hashrate_15m(&self) -> f64 {
+        self.hashrate_over(LONGEST_WINDOW)
+    }
+
+    /// Average hashrate since mining started, up to the latest sample and
+    /// leaving out time spent paused
+    pub fn session_hashrate(&self) -> f64 {
+        let samples = self.samples.lock().unwrap();
+        let Some(&(latest_time, latest_hashes)) = samples.back() else {
+            return 0.0;
+        };
+
+        let paused = self.paused.lock().unwrap().until(latest_time);
+        let duration = latest_time.duration_since(self.start_time).saturating_sub(paused).as_secs_f64();
+        if duration > 0.0 {
+            latest_hashes as f64 / duration
+        } else {
+            0.0
+        }
+    }
+
+    /// Log the periodic stats line: hashrates, then `results`, the counts
+    /// that matter in the current mode, then uptime
+    pub fn report(&self, snapshot: &StatsSnapshot, results: &str) {
+        let rates: Vec<f64> = snapshot.workers.iter().map(|w| w.hashrate).collect();
+        info!(
+            "{}Hashrate: {}{} ({}), {}, Uptime: {:.2} s",
+            if snapshot.paused { "Paused, " } else { "" },
+            self.format_worker_hashrates(&rates),
+            self.format_intensity(snapshot),
+            self.format_hashrate_windows(snapshot),
+            results,
+            snapshot.uptime_secs
+        );
+        if snapshot.hash_mismatches > 0 {
+            error!(
+                "{} submitted blocks were hashed differently by the node; miner and node disagree on block serialization",
+                snapshot.hash_mismatches
+            );
+        }
+    }
+
+    /// Windowed hashrates, e.g. `1m 1.20 KH/s, 5m 1.10 KH/s, 15m 1.00 KH/s, avg 1.00 KH/s`
+    pub fn format_hashrate_windows(&self, snapshot: &StatsSnapshot) -> String {
+        format!(
+            "1m {}, 5m {}, 15m {}, avg {}",
+            self.format_hashrate(snapshot.hashrate_1m),
+            self.format_hashrate(snapshot.hashrate_5m),
+            self.format_hashrate(snapshot.hashrate_15m),
+            self.format_hashrate(snapshot.hashrate_avg),
+        )
+    }
+
+    /// ` at 50% intensity` when the workers are throttled, otherwise empty,
+    /// so a throttled hashrate isn't mistaken for a regression
+    pub fn format_intensity(&self, snapshot: &StatsSnapshot) -> String {
+        if snapshot.intensity < 1.0 {
+            format!(" at {:.0}% intensity", snapshot.intensity * 100.0)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Node round trips and clock skew, e.g. `, Latency: template 12/30 ms,
+    /// submit 40/95 ms (p50/p95), clock skew +2 s`, or empty before any
+    pub fn format_latency(&self, snapshot: &StatsSnapshot) -> String {
+        let parts: Vec<String> = [("template", &snapshot.template_latency), ("submit", &snapshot.submit_latency)]
+            .into_iter()
+            .filter_map(|(call, latency)| {
+                latency.as_ref().map(|l| format!("{} {:.0}/{:.0} ms", call, l.p50_ms, l.p95_ms))
+            })
+            .collect();
+        let mut latency = if parts.is_empty() {
+            String::new()
+        } else {
+            format!(", Latency: {} (p50/p95)", parts.join(", "))
+        };
+        if let Some(skew) = snapshot.clock_skew_secs {
+            latency.push_str(&format!(", clock skew {:+} s", skew));
+        }
+        latency
+    }
+
+    /// Expected time to the next block and luck so far, e.g. `, Next block
+    /// ~3h 12m, Luck 120%`, or empty when neither is known
+    pub fn format_block_eta(&self, snapshot: &StatsSnapshot) -> String {
+        let mut eta = String::new();
+        if let Some(secs) = snapshot.block_eta_secs {
+            eta.push_str(&format!(", Next block {}", self.format_eta(secs)));
+        }
+        if let Some(luck) = snapshot.luck {
+            eta.push_str(&format!(", Luck {:.0}%", luck));
+        }
+        eta
+    }
+
+    /// `secs` in its two largest units, e.g. `~3h 12m`
+    pub fn format_eta(&self, secs: f64) -> String {
+        if secs < 1.0 {
+            return "< 1s".to_string();
+        }
+        if secs >= 1000.0 * YEAR_SECS as f64 {
+            return "> 1000y".to_string();
+        }
+
+        let secs = secs.round() as u64;
+        let first = ETA_UNITS.iter().position(|&(_, size)| secs >= size).unwrap_or(ETA_UNITS.len() - 1);
+        let (unit, size) = ETA_UNITS[first];
+        let mut eta = format!("~{}{}", secs / size, unit);
+        if let Some(&(next_unit, next_size)) = ETA_UNITS.get(first + 1) {
+            let next = secs % size / next_size;
+            if next > 0 {
+                eta.push_str(&format!(" {}{}", next, next_unit));
+            }
+        }
+        eta
+    }
+
+    /// `summary` as a two-column table, one line per figure
+    pub fn format_summary(&self, summary: &ShutdownSummary) -> String {
+        [
+            ("Runtime", format!("{:.1} s", summary.runtime_secs)),
+            ("Total hashes", summary.total_hashes.to_string()),
+            ("Average hashrate", self.format_hashrate(summary.hashrate_avg)),
+            ("Blocks found", summary.blocks_found.to_string()),
+            ("Blocks accepted", summary.blocks_accepted.to_string()),
+            ("Blocks rejected", summary.blocks_rejected.to_string()),
+            ("Reconnects", summary.reconnects.to_string()),
+            ("Exit code", summary.exit_code.to_string()),
+        ]
+        .iter()
+        .map(|(label, value)| format!("{:<18}{}\n", label, value))
+        .collect()
+    }
+
+    /// Hashrate of each worker from the hashes it computed since the last
+    /// report, starting a new reporting interval
+    pub fn worker_hashrates(&self, interval_hashes: &[u64]) -> Vec<f64> {
+        self.worker_hashrates_at(Instant::now(), interval_hashes)
+    }
+
+    fn worker_hashrates_at(&self, at: Instant, interval_hashes: &[u64]) -> Vec<f64> {
+        let mut last_time = self.last_stats_time.lock().unwrap();
+        let duration = at.saturating_duration_since(*last_time).as_secs_f64();
+        *last_time = at;
+
+        interval_hashes
+            .iter()
+            .map(|&hashes| if duration > 0.0 { hashes as f64 / duration } else { 0.0 })
+            .collect()
+    }
+
+    /// Aggregate and per-worker hashrates, e.g. `2.00 KH/s [#0 1.20 KH/s, #1 800.00 H/s]`
+    pub fn format_worker_hashrates(&self, rates: &[f64]) -> String {
+        let workers: Vec<String> = rates
+            .iter()
+            .enumerate()
+            .map(|(id, &rate)| format!("#{} {}", id, self.format_hashrate(rate)))
+            .collect();
+
+        format!("{} [{}]", self.format_hashrate(rates.iter().sum()), workers.join(", "))
+    }
+
     pub fn format_hashrate(&self, h: f64) -> String {
         let units = ["H/s", "KH/s", "MH/s", "GH/s", "TH/s", "PH/s"];
         let mut value = h;
@@ -52,4 +659,282 @@ impl MiningStats {
 
         format!("{:.2} {}", value, unit)
     }
-}
\ No newline at end of file
+}
+
+/// Expected seconds to find a block needing `work` hashes at `hashrate`
+/// H/s, unless nothing is being hashed
+pub fn expected_block_secs(work: f64, hashrate: f64) -> Option<f64> {
+    let secs = work / hashrate;
+    (hashrate > 0.0 && secs.is_finite()).then_some(secs)
+}
+
+/// `blocks_found` as a percentage of `expected_blocks`, once a block has
+/// been found
+pub fn luck(blocks_found: u64, expected_blocks: f64) -> Option<f64> {
+    (blocks_found > 0 && expected_blocks > 0.0).then(|| blocks_found as f64 / expected_blocks * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::consensus::ChainWork;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn test_per_worker_hashrates_follow_hash_counts() {
+        let stats = MiningStats::new();
+        let connected = Arc::new(AtomicBool::new(true));
+        let workers = [MiningWorker::new(0, connected.clone()), MiningWorker::new(1, connected)];
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        workers[0].count_hashes(1_000);
+        workers[1].count_hashes(3_000);
+
+        let interval: Vec<u64> = workers.iter().map(MiningWorker::take_interval_hashes).collect();
+        let rates = stats.worker_hashrates(&interval);
+        assert!(rates[0] > 0.0);
+        assert!((rates[1] / rates[0] - 3.0).abs() < 1e-9);
+
+        // Counters restart each interval, totals keep growing
+        workers[0].count_hashes(500);
+        assert_eq!(workers[0].take_interval_hashes(), 500);
+        assert_eq!(workers[1].take_interval_hashes(), 0);
+        assert_eq!(workers[0].hashes_computed.load(Ordering::Relaxed), 1_500);
+
+        let summary = stats.format_worker_hashrates(&[1_500.0, 500.0]);
+        assert_eq!(summary, "2.00 KH/s [#0 1.50 KH/s, #1 500.00 H/s]");
+    }
+
+    #[test]
+    fn test_snapshots_derive_rates_from_interval() {
+        let start = Instant::now();
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        *stats.last_stats_time.lock().unwrap() = start;
+        let connected = Arc::new(AtomicBool::new(true));
+        let workers = [MiningWorker::new(0, connected.clone()), MiningWorker::new(1, connected)];
+
+        workers[0].count_hashes(10_000);
+        workers[1].count_hashes(30_000);
+        let first = stats.take_snapshot_at(start + Duration::from_secs(10), &workers, "node", true);
+        assert_rate(first.uptime_secs, 10.0);
+        assert_eq!(first.total_hashes, 40_000);
+        assert_rate(first.hashrate, 4_000.0);
+        assert_rate(first.workers[0].hashrate, 1_000.0);
+        assert_rate(first.workers[1].hashrate, 3_000.0);
+
+        // Only worker 0 hashes in the second interval
+        workers[0].count_hashes(50_000);
+        let second = stats.take_snapshot_at(start + Duration::from_secs(20), &workers, "node", true);
+        assert_rate(second.uptime_secs, 20.0);
+        assert_eq!(second.total_hashes, 90_000);
+        assert_rate(second.hashrate, 5_000.0);
+        assert_rate(second.workers[0].hashrate, 5_000.0);
+        assert_rate(second.workers[1].hashrate, 0.0);
+        assert_eq!((second.workers[0].hashes, second.workers[1].hashes), (60_000, 30_000));
+        assert_rate(second.hashrate_1m, 5_000.0);
+        assert_rate(second.hashrate_avg, 4_500.0);
+
+        // The published snapshot is the one consumers render
+        assert_eq!(stats.shutdown_summary(0).total_hashes, 90_000);
+        assert_rate(stats.shutdown_summary(0).runtime_secs, 20.0);
+    }
+
+    #[test]
+    fn test_removed_workers_stay_in_totals() {
+        let stats = MiningStats::new();
+        let connected = Arc::new(AtomicBool::new(true));
+        let workers = [MiningWorker::new(0, connected.clone()), MiningWorker::new(1, connected)];
+        workers[0].count_hashes(1_000);
+        workers[1].count_hashes(3_000);
+        stats.take_snapshot(&workers, "node", true);
+
+        // Worker 1 is removed, finishing a batch after the resize
+        let [kept, removed] = workers;
+        let finishing = removed.clone();
+        stats.retire_worker(removed);
+        finishing.count_hashes(500);
+        kept.count_hashes(2_000);
+        let snapshot = stats.take_snapshot(std::slice::from_ref(&kept), "node", true);
+        assert_eq!(snapshot.workers.len(), 1);
+        assert_eq!((snapshot.total_hashes, snapshot.retired_hashes), (6_500, 3_500));
+
+        // Its thread exits and the worker is let go, its hashes kept
+        drop(finishing);
+        let snapshot = stats.take_snapshot(std::slice::from_ref(&kept), "node", true);
+        assert!(stats.retired_workers.lock().unwrap().is_empty());
+        assert_eq!((snapshot.total_hashes, snapshot.retired_hashes), (6_500, 3_500));
+        assert_eq!(snapshot.workers[0].hashes + snapshot.retired_hashes, snapshot.total_hashes);
+    }
+
+    /// Stats whose session started at `start`, sampled every 10 s at `rate`
+    /// H/s for `secs` seconds, returning the time and total of the last sample
+    fn sample_steady(stats: &MiningStats, start: Instant, base: u64, rate: u64, secs: u64) -> (Instant, u64) {
+        let mut last = (start, base);
+        for t in (0..=secs).step_by(10) {
+            last = (start + Duration::from_secs(t), base + rate * t);
+            stats.record_sample(last.0, last.1);
+        }
+        last
+    }
+
+    fn assert_rate(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "expected {} H/s, got {}", expected, actual);
+    }
+
+    #[test]
+    fn test_windows_converge_on_steady_rate() {
+        let start = Instant::now();
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        sample_steady(&stats, start, 0, 1_000, 20 * 60);
+
+        assert_rate(stats.hashrate_1m(), 1_000.0);
+        assert_rate(stats.hashrate_5m(), 1_000.0);
+        assert_rate(stats.hashrate_15m(), 1_000.0);
+        assert_rate(stats.session_hashrate(), 1_000.0);
+
+        // Only what the longest window needs is kept
+        let samples = stats.samples.lock().unwrap();
+        let span = samples.back().unwrap().0.duration_since(samples.front().unwrap().0);
+        assert_eq!(span, LONGEST_WINDOW);
+    }
+
+    #[test]
+    fn test_windows_weight_recent_rate_change() {
+        let start = Instant::now();
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        let (time, total) = sample_steady(&stats, start, 0, 1_000, 15 * 60);
+        sample_steady(&stats, time, total, 4_000, 60);
+
+        assert_rate(stats.hashrate_1m(), 4_000.0);
+        assert_rate(stats.hashrate_5m(), (4_000.0 * 60.0 + 1_000.0 * 240.0) / 300.0);
+        assert_rate(stats.hashrate_15m(), (4_000.0 * 60.0 + 1_000.0 * 840.0) / 900.0);
+        assert_rate(stats.session_hashrate(), (4_000.0 * 60.0 + 1_000.0 * 900.0) / 960.0);
+    }
+
+    #[test]
+    fn test_windows_use_partial_history_at_startup() {
+        let start = Instant::now();
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        assert_eq!(stats.hashrate_1m(), 0.0);
+
+        sample_steady(&stats, start, 0, 500, 20);
+
+        assert_rate(stats.hashrate_1m(), 500.0);
+        assert_rate(stats.hashrate_5m(), 500.0);
+        assert_rate(stats.hashrate_15m(), 500.0);
+        assert_rate(stats.session_hashrate(), 500.0);
+    }
+
+    #[test]
+    fn test_latency_percentiles_cover_recent_round_trips() {
+        let window = LatencyWindow::default();
+        assert_eq!(window.snapshot(), None);
+
+        for ms in 1..=20 {
+            window.record(Duration::from_millis(ms));
+        }
+        let latency = window.snapshot().unwrap();
+        assert_eq!((latency.p50_ms, latency.p95_ms, latency.samples), (10.0, 19.0, 20));
+
+        // Only the latest round trips count
+        for _ in 0..LATENCY_SAMPLES {
+            window.record(Duration::from_millis(100));
+        }
+        let latency = window.snapshot().unwrap();
+        assert_eq!((latency.p50_ms, latency.p95_ms, latency.samples), (100.0, 100.0, LATENCY_SAMPLES));
+
+        let stats = MiningStats::new();
+        let snapshot = StatsSnapshot { submit_latency: Some(latency), clock_skew_secs: Some(-3), ..Default::default() };
+        assert_eq!(stats.format_latency(&snapshot), ", Latency: submit 100/100 ms (p50/p95), clock skew -3 s");
+        assert_eq!(stats.format_latency(&StatsSnapshot::default()), "");
+    }
+
+    #[test]
+    fn test_session_average_leaves_out_paused_time() {
+        let start = Instant::now();
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        let (time, total) = sample_steady(&stats, start, 0, 1_000, 200);
+
+        // No hashes for 100 s: a finished 60 s pause, then one still going
+        sample_steady(&stats, time, total, 0, 100);
+        stats.paused.lock().unwrap().total = Duration::from_secs(60);
+        stats.paused.lock().unwrap().since = Some(time + Duration::from_secs(60));
+
+        assert_rate(stats.session_hashrate(), 1_000.0);
+        assert!(stats.is_paused());
+    }
+
+    #[test]
+    fn test_block_eta_from_work_and_hashrate() {
+        let stats = MiningStats::new();
+        for (bits, hashrate, expected_secs, shown) in [
+            // 2^32 hashes at 1 MH/s
+            (0x1d00ffff, 1_000_000.0, 4_295.032_833, "~1h 11m"),
+            (0x1d00ffff, 1_000.0, 4_295_032.833, "~49d 17h"),
+            (0x207fffff, 1_000.0, 0.002, "< 1s"),
+            (0x0300ffff, 1e12, 2f64.powi(240) / 1e12, "> 1000y"),
+        ] {
+            let work = ChainWork::of_block(bits).to_f64();
+            let secs = expected_block_secs(work, hashrate).unwrap();
+            assert!((secs - expected_secs).abs() / expected_secs < 1e-6, "{:#x}: {} s", bits, secs);
+            assert_eq!(stats.format_eta(secs), shown);
+        }
+
+        assert_eq!(stats.format_eta(3.0 * 3600.0 + 12.0 * 60.0 + 29.0), "~3h 12m");
+        assert_eq!(stats.format_eta(3.0 * 3600.0 + 20.0), "~3h");
+        assert_eq!(stats.format_eta(45.4), "~45s");
+
+        // Nothing hashed, or a target no hashrate will meet
+        assert_eq!(expected_block_secs(2.0, 0.0), None);
+        assert_eq!(expected_block_secs(f64::MAX, 1e-300), None);
+        let zero_target = ChainWork::of_block(0x01000000).to_f64();
+        assert_eq!(stats.format_eta(expected_block_secs(zero_target, 1.0).unwrap()), "> 1000y");
+    }
+
+    #[test]
+    fn test_luck_compares_found_to_expected_blocks() {
+        assert_eq!(luck(0, 0.0), None);
+        assert_eq!(luck(0, 3.0), None);
+        assert_eq!(luck(2, 1.0), Some(200.0));
+        assert_eq!(luck(1, 4.0), Some(25.0));
+
+        let stats = MiningStats::new();
+        let snapshot = StatsSnapshot { block_eta_secs: Some(11_520.0), luck: Some(120.0), ..Default::default() };
+        assert_eq!(stats.format_block_eta(&snapshot), ", Next block ~3h 12m, Luck 120%");
+        assert_eq!(stats.format_block_eta(&StatsSnapshot::default()), "");
+    }
+
+    #[test]
+    fn test_shutdown_summary_as_json_and_table() {
+        let start = Instant::now() - Duration::from_secs(100);
+        let stats = MiningStats { start_time: start, ..MiningStats::new() };
+        stats.total_hashes.store(200_000, Ordering::SeqCst);
+        stats.blocks_submitted.store(4, Ordering::SeqCst);
+        stats.blocks_found.store(2, Ordering::SeqCst);
+        stats.blocks_lost.store(1, Ordering::SeqCst);
+        stats.record_rejection("high-hash");
+        stats.record_rejection("stale-prevblk");
+        stats.reconnects.store(3, Ordering::SeqCst);
+        stats.take_snapshot_at(start + Duration::from_secs(100), &[], "node", true);
+
+        let summary = stats.shutdown_summary(4);
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "runtime_secs": 100.0, "total_hashes": 200_000, "hashrate_avg": 2000.0, "blocks_found": 5,
+                "blocks_accepted": 2, "blocks_rejected": 2, "reconnects": 3, "exit_code": 4,
+            })
+        );
+        assert_eq!(
+            stats.format_summary(&summary),
+            "Runtime           100.0 s\n\
+             Total hashes      200000\n\
+             Average hashrate  2.00 KH/s\n\
+             Blocks found      5\n\
+             Blocks accepted   2\n\
+             Blocks rejected   2\n\
+             Reconnects        3\n\
+             Exit code         4\n"
+        );
+    }
+}
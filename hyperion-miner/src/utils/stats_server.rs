@@ -0,0 +1,132 @@
+//! Optional publishing of the miner's stats: a `GET /stats` JSON endpoint on
+//! `stats_bind` and a copy of the same JSON at `stats_file`, rewritten every
+//! stats interval. Miners that can pause also get `POST /control/pause`,
+//! `POST /control/resume` and `POST /control/threads` on the same address,
+//! answered only with the configured `control_token` or, without one, to
+//! loopback clients.
+
+use super::{MiningStats, StatsSnapshot};
+use crate::config::mining_config::MAX_THREADS;
+use crate::config::MiningConfig;
+use crate::mining::MiningControl;
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Serve the stats endpoint if `stats_bind` is configured, with control
+/// routes when given `control`
+pub async fn spawn(
+    config: &MiningConfig,
+    stats: MiningStats,
+    control: Option<MiningControl>,
+) -> Result<Option<JoinHandle<()>>> {
+    let Some(bind) = &config.stats_bind else {
+        return Ok(None);
+    };
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to bind stats endpoint to {}", bind))?;
+    info!("Serving stats on http://{}/stats", listener.local_addr()?);
+
+    let mut router = Router::new().route("/stats", get(get_stats)).with_state(stats);
+    if let Some(control) = control {
+        router = router.merge(
+            Router::new()
+                .route("/control/pause", post(pause))
+                .route("/control/resume", post(resume))
+                .route("/control/threads", post(set_threads))
+                .route_layer(middleware::from_fn_with_state(config.control_token.clone(), authorize_control))
+                .with_state(control),
+        );
+    }
+    Ok(Some(tokio::spawn(async move {
+        let service = router.into_make_service_with_connect_info::<SocketAddr>();
+        if let Err(e) = axum::serve(listener, service).await {
+            tracing::error!("Stats endpoint failed: {}", e);
+        }
+    })))
+}
+
+async fn get_stats(State(stats): State<MiningStats>) -> Json<StatsSnapshot> {
+    Json(stats.snapshot.lock().unwrap().clone())
+}
+
+/// Let a control request through if it carries `token` as a bearer token,
+/// or when there is no token and it comes from a loopback address
+async fn authorize_control(
+    State(token): State<Option<String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allowed = match &token {
+        Some(token) => request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| given == token),
+        None => peer.ip().is_loopback(),
+    };
+    if !allowed {
+        warn!("Refused {} {} from {}", request.method(), request.uri().path(), peer);
+        let error = if token.is_some() { "missing or wrong control token" } else { "control is only open to loopback clients" };
+        return (StatusCode::FORBIDDEN, Json(json!({ "error": error }))).into_response();
+    }
+    next.run(request).await
+}
+
+async fn pause(State(control): State<MiningControl>) -> Json<Value> {
+    info!("Pause requested over HTTP");
+    control.pause();
+    Json(json!({ "paused": true }))
+}
+
+async fn resume(State(control): State<MiningControl>) -> Json<Value> {
+    info!("Resume requested over HTTP");
+    control.resume();
+    Json(json!({ "paused": false }))
+}
+
+#[derive(Deserialize)]
+struct ThreadsRequest {
+    count: usize,
+}
+
+async fn set_threads(
+    State(control): State<MiningControl>,
+    Json(request): Json<ThreadsRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if request.count == 0 || request.count > MAX_THREADS {
+        let error = format!("count must be between 1 and {}, got {}", MAX_THREADS, request.count);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": error }))));
+    }
+    info!("{} threads requested over HTTP", request.count);
+    control.set_threads(request.count);
+    Ok(Json(json!({ "threads": request.count })))
+}
+
+/// Replace `path` with `snapshot` as JSON, via a temporary file so readers
+/// never see a partial write
+pub async fn write_stats_file(path: &Path, snapshot: &StatsSnapshot) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(snapshot)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
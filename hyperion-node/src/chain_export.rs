@@ -0,0 +1,224 @@
+//! Portable chain exports: a versioned envelope of serialized blocks, for
+//! moving a chain between nodes without copying `blockchain.dat`.
+
+use crate::chain_manager::{BlockSource, ChainManager, ConnectError};
+
+use hyperion_core::block::{Block, Serializable};
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::crypto::{hex32, Hashable, HASH_SIZE};
+
+use bincode::{Decode, Encode};
+use std::fmt;
+
+
+/// First bytes of every export
+const EXPORT_MAGIC: [u8; 4] = *b"HYPX";
+/// Bumped whenever the envelope changes shape
+const EXPORT_VERSION: u32 = 1;
+
+/// A run of consecutive blocks from one chain
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ChainExport {
+    magic: [u8; 4],
+    version: u32,
+    /// Genesis of the exporting chain, which the importing one must share
+    pub genesis_hash: [u8; HASH_SIZE],
+    /// Height of the first block
+    pub start_height: u64,
+    /// Each block serialized on its own, so one that doesn't decode stops an
+    /// import there rather than before it starts
+    blocks: Vec<Vec<u8>>,
+}
+
+impl Serializable for ChainExport {}
+
+/// Why an export can't be imported at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportError {
+    /// The data isn't an export, or is one from a newer version
+    Envelope(String),
+    /// The export is of a chain with another genesis block
+    WrongChain { genesis_hash: [u8; HASH_SIZE] },
+}
+
+/// The block an import stopped at, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportStop {
+    Undecodable { height: u64, reason: String },
+    Invalid { height: u64, hash: [u8; HASH_SIZE], reason: String },
+}
+
+/// What became of each block in an export
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub accepted: u64,
+    /// Already in the chain
+    pub skipped: u64,
+    /// The block the import stopped at, if any; blocks after it weren't tried
+    pub rejected: u64,
+    pub stopped: Option<ImportStop>,
+}
+
+impl ChainExport {
+    /// Blocks `from..=to` of `chain`
+    pub fn of(chain: &Blockchain, from: usize, to: usize) -> Self {
+        let genesis = chain.get_block_by_height(0).expect("Chain has a genesis block");
+        let blocks = (from..=to)
+            .filter_map(|height| chain.get_block_by_height(height))
+            .map(|block| block.serialize().expect("Blocks always serialize"))
+            .collect();
+
+        Self {
+            magic: EXPORT_MAGIC,
+            version: EXPORT_VERSION,
+            genesis_hash: genesis.double_sha256(),
+            start_height: from as u64,
+            blocks,
+        }
+    }
+
+    /// Read an export, checking its envelope but not its blocks
+    pub fn decode(bytes: &[u8]) -> Result<Self, ExportError> {
+        let export = Self::from_bytes(bytes)
+            .map_err(|e| ExportError::Envelope(format!("Not a chain export: {}", e)))?;
+        if export.magic != EXPORT_MAGIC {
+            return Err(ExportError::Envelope("Not a chain export".to_string()));
+        }
+        if export.version > EXPORT_VERSION {
+            return Err(ExportError::Envelope(format!(
+                "Export version {} is newer than this node's {}",
+                export.version, EXPORT_VERSION
+            )));
+        }
+        Ok(export)
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Connect the exported blocks in order through `chain_manager`, skipping
+    /// those already in the chain and stopping at the first that fails
+    pub async fn import(&self, chain_manager: &ChainManager) -> Result<ImportReport, ExportError> {
+        let genesis_hash = chain_manager.chain().await.get_block_by_height(0)
+            .expect("Chain has a genesis block")
+            .double_sha256();
+        if genesis_hash != self.genesis_hash {
+            return Err(ExportError::WrongChain { genesis_hash: self.genesis_hash });
+        }
+
+        let mut report = ImportReport::default();
+        for (height, bytes) in (self.start_height..).zip(&self.blocks) {
+            let block = match Block::from_bytes(bytes) {
+                Ok(block) => block,
+                Err(e) => {
+                    report.rejected += 1;
+                    report.stopped = Some(ImportStop::Undecodable { height, reason: e.to_string() });
+                    break;
+                }
+            };
+
+            let hash = block.double_sha256();
+            match chain_manager.connect_block_from(block, BlockSource::Import, None).await {
+                Ok(_) => report.accepted += 1,
+                Err(ConnectError::Known) => report.skipped += 1,
+                Err(ConnectError::Invalid(e)) => {
+                    report.rejected += 1;
+                    report.stopped = Some(ImportStop::Invalid { height, hash, reason: format!("{:?}", e) });
+                    break;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Envelope(reason) => write!(f, "{}", reason),
+            ExportError::WrongChain { genesis_hash } => {
+                write!(f, "Export is of the chain with genesis {}", hex32(genesis_hash))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ImportStop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportStop::Undecodable { height, reason } => {
+                write!(f, "Block at height {} does not decode: {}", height, reason)
+            }
+            ImportStop::Invalid { height, hash, reason } => {
+                write!(f, "Block {} at height {} is invalid: {}", hex32(hash), height, reason)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::Mempool;
+    use hyperion_core::testutil::ChainBuilder;
+
+    fn mined_chain(blocks: usize) -> Blockchain {
+        ChainBuilder::new(0).mine_blocks(blocks, 1).build()
+    }
+
+    #[tokio::test]
+    async fn test_import_rebuilds_exported_chain() {
+        let source = mined_chain(4);
+        let bytes = ChainExport::of(&source, 0, 4).serialize().unwrap();
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let export = ChainExport::decode(&bytes).unwrap();
+        let report = export.import(&chain_manager).await.unwrap();
+        assert_eq!(report, ImportReport { accepted: 4, skipped: 1, rejected: 0, stopped: None });
+        assert_eq!(chain_manager.tip().hash, source.latest_block().double_sha256());
+
+        // Importing again finds every block already there
+        let report = export.import(&chain_manager).await.unwrap();
+        assert_eq!((report.accepted, report.skipped), (0, 5));
+    }
+
+    #[tokio::test]
+    async fn test_import_stops_at_corrupt_block() {
+        let source = mined_chain(4);
+        let mut export = ChainExport::of(&source, 1, 4);
+        export.blocks[2].truncate(10);
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let report = export.import(&chain_manager).await.unwrap();
+        assert_eq!((report.accepted, report.skipped, report.rejected), (2, 0, 1));
+        assert!(matches!(report.stopped, Some(ImportStop::Undecodable { height: 3, .. })), "{:?}", report.stopped);
+        assert_eq!(chain_manager.tip().height, 2);
+
+        // A block that decodes but doesn't connect is named by its hash
+        let mut export = ChainExport::of(&source, 3, 4);
+        let mut block = Block::from_bytes(&export.blocks[1]).unwrap();
+        block.header.merkle_root = [0; HASH_SIZE];
+        export.blocks[1] = block.serialize().unwrap();
+        let report = export.import(&chain_manager).await.unwrap();
+        assert_eq!((report.accepted, report.rejected), (1, 1));
+        let stopped = report.stopped.unwrap();
+        assert!(matches!(stopped, ImportStop::Invalid { height: 4, hash, .. } if hash == block.double_sha256()));
+    }
+
+    #[tokio::test]
+    async fn test_export_envelope_is_checked() {
+        assert!(matches!(ChainExport::decode(b"not an export"), Err(ExportError::Envelope(_))));
+
+        let mut export = ChainExport::of(&mined_chain(1), 0, 1);
+        export.version = EXPORT_VERSION + 1;
+        let err = ChainExport::decode(&export.serialize().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{}", err);
+
+        let mut export = ChainExport::of(&mined_chain(1), 0, 1);
+        export.genesis_hash = [7; HASH_SIZE];
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        assert!(matches!(export.import(&chain_manager).await, Err(ExportError::WrongChain { .. })));
+    }
+}
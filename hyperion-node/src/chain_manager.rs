@@ -0,0 +1,756 @@
+use crate::mempool::Mempool;
+use crate::storage;
+
+use hyperion_core::block::block::compute_merkle_root;
+use hyperion_core::block::{Block, Serializable, Transaction};
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::consensus::{adjust_difficulty, chain_work, project_retarget, ChainWork, ConsensusParams, RetargetProjection};
+use hyperion_core::crypto::{Hashable, HASH_SIZE};
+use hyperion_core::error::blockchain::BlockchainError;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
+
+
+/// Owns the chain and the mempool and keeps the two consistent: transactions
+/// leave the pool when their block connects and return to it when the block
+/// is disconnected.
+///
+/// Lock ordering: the chain lock is always taken before the mempool lock, and
+/// never while the mempool lock is held. Every method here follows that order.
+/// Callers using [`chain`](Self::chain) and [`mempool`](Self::mempool) must
+/// too — it is fine to read the mempool while holding a chain guard, but not
+/// the other way round.
+///
+/// The hot paths avoid the chain lock: templates are built from a tip snapshot
+/// republished on every connect, and [`persist`](Self::persist) writes the
+/// chain to disk after releasing it.
+#[derive(Clone)]
+pub struct ChainManager {
+    chain: Arc<RwLock<Blockchain>>,
+    mempool: Arc<RwLock<Mempool>>,
+    /// Replaced, never mutated, while both the chain and mempool write locks are held
+    tip: Arc<StdRwLock<Arc<TipSnapshot>>>,
+    /// Orders concurrent writes of the chain file so an older chain never
+    /// overwrites a newer one
+    persist: Arc<Mutex<()>>,
+    /// The latest template selection, reused until the tip or the mempool
+    /// generation moves on
+    template_cache: Arc<StdMutex<Option<(TemplateKey, TemplateTxs)>>>,
+    /// Replaced or updated only while both the chain and mempool write locks
+    /// are held, and read under the mempool lock
+    recent: Arc<StdRwLock<RecentTxids>>,
+    /// The latest `BLOCK_HISTORY` connected blocks, oldest first; appended
+    /// to only while the chain write lock is held
+    arrivals: Arc<StdMutex<VecDeque<BlockArrival>>>,
+}
+
+/// Connected blocks remembered for `get_recent_blocks`
+pub const BLOCK_HISTORY: usize = 100;
+
+/// How a connected block reached the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSource {
+    /// `submit_block`
+    Rpc,
+    /// Relayed by a peer
+    P2p,
+    /// Connected directly by a test
+    #[cfg(test)]
+    Generate,
+    /// Replayed by a reindex or `import_chain`
+    Import,
+}
+
+/// A connected block and how it arrived
+#[derive(Debug, Clone)]
+pub struct BlockArrival {
+    pub hash: [u8; HASH_SIZE],
+    pub height: u64,
+    /// Unix time the block connected
+    pub received_time: u64,
+    /// Seconds from the block's header time to `received_time`
+    pub propagation_secs: i64,
+    pub source: BlockSource,
+    /// RPC client or peer address that handed the block over, where known
+    pub submitter: Option<String>,
+}
+
+/// Txids mined in the latest blocks, so transactions confirmed there aren't
+/// pooled or templated again
+#[derive(Debug, Default)]
+struct RecentTxids {
+    /// Blocks remembered; 0 remembers none
+    depth: usize,
+    /// Each remembered block's txids, oldest block first
+    blocks: VecDeque<Vec<[u8; HASH_SIZE]>>,
+    /// Remembered blocks each txid appears in
+    counts: HashMap<[u8; HASH_SIZE], usize>,
+}
+
+/// What a template selection depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TemplateKey {
+    tip: [u8; HASH_SIZE],
+    generation: u64,
+    max_count: usize,
+    max_size: usize,
+}
+
+/// What a block template needs from the chain
+#[derive(Debug)]
+pub struct TipSnapshot {
+    pub genesis_hash: [u8; HASH_SIZE],
+    pub hash: [u8; HASH_SIZE],
+    pub height: u64,
+    /// Time of the tip block
+    pub time: u32,
+    /// Difficulty required of the next block
+    pub next_difficulty: u32,
+    pub retarget: RetargetProjection,
+    /// Total work of the chain up to and including the tip
+    pub chain_work: ChainWork,
+    /// Rules the next block is checked against
+    pub params: ConsensusParams,
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The block is already in the chain
+    Known,
+    Invalid(BlockchainError),
+}
+
+/// Why a transaction wasn't pooled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmitError {
+    AlreadyPooled,
+    /// The pool holds its `max_txs`
+    PoolFull,
+    /// Mined in one of the latest `recent_txid_blocks` blocks
+    AlreadyConfirmed,
+}
+
+/// Transactions for a block template, with the tip they were selected against
+#[derive(Debug, Clone)]
+pub struct TemplateTxs {
+    pub prev_hash: [u8; HASH_SIZE],
+    /// Height the new block would have
+    pub height: u64,
+    pub difficulty: u32,
+    /// Earliest time the new block may carry, a second after its parent
+    pub min_time: u32,
+    /// Shared with the cached selection rather than copied
+    pub transactions: Arc<Vec<Transaction>>,
+    pub merkle_root: [u8; HASH_SIZE],
+    /// Serialized size of `transactions`, in bytes
+    pub size: usize,
+}
+
+impl ChainManager {
+    /// Manager with the default `recent_txid_blocks` window
+    #[cfg(test)]
+    pub fn new(chain: Blockchain, mempool: Mempool) -> Self {
+        let recent_blocks = crate::config::NodeConfig::default().mempool.recent_txid_blocks;
+        Self::with_recent_blocks(chain, mempool, recent_blocks)
+    }
+
+    /// Manager that keeps transactions mined in the latest `recent_blocks`
+    /// blocks out of the pool and templates. Any already in `mempool`, as
+    /// one reloaded from disk may be, are dropped.
+    pub fn with_recent_blocks(chain: Blockchain, mut mempool: Mempool, recent_blocks: usize) -> Self {
+        let recent = RecentTxids::of(&chain, recent_blocks);
+        let confirmed: Vec<Transaction> = mempool
+            .txs()
+            .iter()
+            .filter(|tx| recent.contains(&tx.double_sha256()))
+            .cloned()
+            .collect();
+        for tx in &confirmed {
+            mempool.remove_tx(tx);
+        }
+
+        Self {
+            tip: Arc::new(StdRwLock::new(Arc::new(TipSnapshot::of(&chain)))),
+            chain: Arc::new(RwLock::new(chain)),
+            mempool: Arc::new(RwLock::new(mempool)),
+            persist: Arc::new(Mutex::new(())),
+            template_cache: Arc::new(StdMutex::new(None)),
+            recent: Arc::new(StdRwLock::new(recent)),
+            arrivals: Arc::new(StdMutex::new(VecDeque::new())),
+        }
+    }
+
+    /// The current tip, without waiting on the chain lock
+    pub fn tip(&self) -> Arc<TipSnapshot> {
+        self.tip.read().unwrap().clone()
+    }
+
+    pub async fn chain(&self) -> RwLockReadGuard<'_, Blockchain> {
+        self.chain.read().await
+    }
+
+    pub async fn mempool(&self) -> RwLockReadGuard<'_, Mempool> {
+        self.mempool.read().await
+    }
+
+    /// Validate and append `block`, dropping its transactions from the
+    /// mempool. Returns the new tip height.
+    #[cfg(test)]
+    pub async fn connect_block(&self, block: Block) -> Result<u64, ConnectError> {
+        self.connect_block_from(block, BlockSource::Generate, None).await
+    }
+
+    /// Connect `block`, recording where it came from and who handed it over
+    pub async fn connect_block_from(
+        &self,
+        block: Block,
+        source: BlockSource,
+        submitter: Option<String>,
+    ) -> Result<u64, ConnectError> {
+        let mut chain = self.chain.write().await;
+        let hash = block.double_sha256();
+        // A block extending the tip can't be on the chain already, so only
+        // others are looked for, and those handed over again are recent ones
+        let extends_tip = block.header.prev_hash == chain.latest_block().double_sha256();
+        if !extends_tip && chain.height_of(hash).is_some() {
+            return Err(ConnectError::Known);
+        }
+
+        let confirmed = block.transactions.clone();
+        let header_time = block.header.time;
+        chain.add_block(block, false).map_err(ConnectError::Invalid)?;
+        let height = chain.len() as u64 - 1;
+
+        let received_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.record_arrival(BlockArrival {
+            hash,
+            height,
+            received_time,
+            propagation_secs: received_time as i64 - header_time as i64,
+            source,
+            submitter,
+        });
+
+        let mut mempool = self.mempool.write().await;
+        for tx in &confirmed {
+            mempool.remove_tx(tx);
+        }
+        self.recent.write().unwrap().push(chain.latest_block());
+        self.publish_tip(&chain);
+
+        Ok(height)
+    }
+
+    fn record_arrival(&self, arrival: BlockArrival) {
+        let mut arrivals = self.arrivals.lock().unwrap();
+        if arrivals.len() == BLOCK_HISTORY {
+            arrivals.pop_front();
+        }
+        arrivals.push_back(arrival);
+    }
+
+    /// Up to `count` of the latest connected blocks, newest first
+    pub fn recent_blocks(&self, count: usize) -> Vec<BlockArrival> {
+        self.arrivals.lock().unwrap().iter().rev().take(count).cloned().collect()
+    }
+
+    /// Remove the tip block, returning its transactions to the mempool.
+    /// The genesis block is never disconnected.
+    #[cfg(test)]
+    pub async fn disconnect_tip(&self) -> Option<Block> {
+        let mut chain = self.chain.write().await;
+        let block = chain.pop_tip()?;
+
+        let mut mempool = self.mempool.write().await;
+        // The window slides back a block, so the one before it returns
+        let mut recent = self.recent.write().unwrap();
+        *recent = RecentTxids::of(&chain, recent.depth);
+        drop(recent);
+        for tx in &block.transactions {
+            if !mempool.contains(tx) {
+                mempool.add_tx(tx.clone());
+            }
+        }
+        self.publish_tip(&chain);
+
+        Some(block)
+    }
+
+    /// Pick pooled transactions for a block on the current tip, parents
+    /// before children and otherwise in pool order, leaving out any mined in
+    /// the latest `recent_txid_blocks` blocks and stopping before the first
+    /// that would take the selection past `max_count` transactions or
+    /// `max_size` serialized bytes, so no child is selected without its
+    /// parents. The transactions stay pooled until a block containing them
+    /// connects. The selection is reused while the tip and mempool
+    /// generation stay put.
+    ///
+    /// Never waits on the chain lock. The tip is read before the mempool, so a
+    /// block connecting in between can only remove transactions from the
+    /// selection, never leave confirmed ones in it.
+    pub async fn select_template_txs(&self, max_count: usize, max_size: usize) -> TemplateTxs {
+        let tip = self.tip();
+        let mempool = self.mempool.read().await;
+        let key = TemplateKey { tip: tip.hash, generation: mempool.generation(), max_count, max_size };
+        if let Some((cached_key, cached)) = &*self.template_cache.lock().unwrap()
+            && *cached_key == key {
+            return cached.clone();
+        }
+
+        let recent = self.recent.read().unwrap();
+        let pooled = mempool
+            .txs_by_dependency()
+            .into_iter()
+            .filter(|tx| recent.is_empty() || !recent.contains(&tx.double_sha256()));
+
+        let mut transactions = Vec::new();
+        let mut size = 0;
+        for tx in pooled.take(max_count) {
+            let tx_size = tx.byte_size();
+            if size + tx_size > max_size {
+                break;
+            }
+            size += tx_size;
+            transactions.push(tx.clone());
+        }
+        drop(recent);
+        drop(mempool);
+
+        let selected = TemplateTxs {
+            prev_hash: tip.hash,
+            height: tip.height + 1,
+            difficulty: tip.next_difficulty,
+            min_time: tip.time.saturating_add(1),
+            merkle_root: compute_merkle_root(&transactions),
+            transactions: Arc::new(transactions),
+            size,
+        };
+        *self.template_cache.lock().unwrap() = Some((key, selected.clone()));
+        selected
+    }
+
+    /// Pool `tx`, unless it is already pooled or recently mined, or the
+    /// pool is full
+    pub async fn add_mempool_tx(&self, tx: Transaction) -> Result<(), AdmitError> {
+        let mut mempool = self.mempool.write().await;
+        if mempool.contains(&tx) {
+            return Err(AdmitError::AlreadyPooled);
+        }
+        if self.recent.read().unwrap().contains(&tx.double_sha256()) {
+            return Err(AdmitError::AlreadyConfirmed);
+        }
+        if !mempool.add_tx(tx) {
+            return Err(AdmitError::PoolFull);
+        }
+        Ok(())
+    }
+
+    pub async fn expire_mempool(&self) -> usize {
+        self.mempool.write().await.expire()
+    }
+
+    /// Restart the chain from `genesis`, as a reindex does before replaying
+    /// stored blocks
+    pub async fn reset_chain(&self, genesis: Block) {
+        let mut chain = self.chain.write().await;
+        let params = *chain.params();
+        *chain = Blockchain::new(genesis);
+        chain.set_params(params);
+        self.arrivals.lock().unwrap().clear();
+        let _mempool = self.mempool.write().await;
+        let mut recent = self.recent.write().unwrap();
+        *recent = RecentTxids::of(&chain, recent.depth);
+        drop(recent);
+        self.publish_tip(&chain);
+    }
+
+    /// Replace the chain and the pooled transactions together, as restoring
+    /// a snapshot does. The chain's blocks aren't checked again, and keep the
+    /// consensus rules of the chain they replace.
+    pub async fn restore(&self, mut chain: Blockchain, txs: Vec<Transaction>) {
+        let mut current = self.chain.write().await;
+        chain.set_params(*current.params());
+        *current = chain;
+        self.arrivals.lock().unwrap().clear();
+
+        let mut mempool = self.mempool.write().await;
+        mempool.clear();
+        for tx in txs {
+            mempool.add_tx(tx);
+        }
+        let mut recent = self.recent.write().unwrap();
+        *recent = RecentTxids::of(&current, recent.depth);
+        drop(recent);
+        // The old tip's work says nothing about the new chain
+        *self.tip.write().unwrap() = Arc::new(TipSnapshot::of(&current));
+    }
+
+    /// Save the chain to `dir`. The chain is serialized under a read lock,
+    /// which is released before the disk write.
+    pub async fn persist(&self, dir: &Path) -> io::Result<()> {
+        let _ordered = self.persist.lock().await;
+        let bytes = self.chain.read().await.serialize().map_err(|e| io::Error::other(e.to_string()))?;
+
+        let dir = dir.to_path_buf();
+        tokio::task::spawn_blocking(move || storage::write_chain(&bytes, &dir))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Callers hold the chain and mempool write locks, so template builders
+    /// never see the new tip alongside a mempool that still holds its transactions
+    fn publish_tip(&self, chain: &Blockchain) {
+        let mut tip = self.tip.write().unwrap();
+        *tip = Arc::new(TipSnapshot::after(chain, &tip));
+    }
+
+    /// Hold the chain write lock, to keep requests pending in tests
+    #[cfg(test)]
+    pub async fn lock_chain(&self) -> tokio::sync::RwLockWriteGuard<'_, Blockchain> {
+        self.chain.write().await
+    }
+}
+
+impl TipSnapshot {
+    fn of(chain: &Blockchain) -> Self {
+        Self::with_work(chain, chain_work(chain))
+    }
+
+    /// Snapshot of `chain` once it has moved on from `previous`. A single
+    /// block on top adds its work rather than summing the whole chain again.
+    fn after(chain: &Blockchain, previous: &TipSnapshot) -> Self {
+        let tip = &chain.latest_block().header;
+        if tip.prev_hash != previous.hash {
+            return Self::of(chain);
+        }
+
+        Self::with_work(chain, previous.chain_work + ChainWork::of_block(tip.difficulty_compact))
+    }
+
+    fn with_work(chain: &Blockchain, chain_work: ChainWork) -> Self {
+        Self {
+            genesis_hash: chain.get_block_by_height(0).expect("Chain has a genesis block").double_sha256(),
+            hash: chain.latest_block().double_sha256(),
+            height: chain.len() as u64 - 1,
+            time: chain.latest_block().header.time,
+            next_difficulty: adjust_difficulty(chain),
+            retarget: project_retarget(chain),
+            chain_work,
+            params: *chain.params(),
+        }
+    }
+}
+
+impl RecentTxids {
+    /// The txids of `chain`'s latest `depth` blocks
+    fn of(chain: &Blockchain, depth: usize) -> Self {
+        let mut recent = Self { depth, ..Default::default() };
+        for block in chain.iter().skip(chain.len().saturating_sub(depth)) {
+            recent.push(block);
+        }
+        recent
+    }
+
+    /// Remember `block`, forgetting the oldest block beyond `depth`
+    fn push(&mut self, block: &Block) {
+        if self.depth == 0 {
+            return;
+        }
+
+        let txids: Vec<_> = block.iter_txids().collect();
+        for txid in &txids {
+            *self.counts.entry(*txid).or_default() += 1;
+        }
+        self.blocks.push_back(txids);
+
+        while self.blocks.len() > self.depth {
+            for txid in self.blocks.pop_front().unwrap_or_default() {
+                if let Some(count) = self.counts.get_mut(&txid) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.counts.remove(&txid);
+                    }
+                }
+            }
+        }
+    }
+
+    fn contains(&self, txid: &[u8; HASH_SIZE]) -> bool {
+        self.counts.contains_key(txid)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+impl fmt::Display for AdmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdmitError::AlreadyPooled => write!(f, "transaction already pooled"),
+            AdmitError::PoolFull => write!(f, "mempool full"),
+            AdmitError::AlreadyConfirmed => write!(f, "transaction already confirmed"),
+        }
+    }
+}
+
+impl BlockSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockSource::Rpc => "rpc",
+            BlockSource::P2p => "p2p",
+            #[cfg(test)]
+            BlockSource::Generate => "generate",
+            BlockSource::Import => "import",
+        }
+    }
+}
+
+impl ConnectError {
+    /// Short machine-readable reason for `submit_block` responses. Miners
+    /// treat `duplicate` and `stale-prevblk` as a lost race, not a bad block.
+    pub fn reject_reason(&self) -> &'static str {
+        match self {
+            ConnectError::Known => "duplicate",
+            ConnectError::Invalid(BlockchainError::InvalidPreviousHash) => "stale-prevblk",
+            ConnectError::Invalid(BlockchainError::InvalidMerkleRoot) => "bad-txnmrklroot",
+            ConnectError::Invalid(BlockchainError::InvalidPoW) => "high-hash",
+            ConnectError::Invalid(BlockchainError::IncorrectDifficulty { .. }) => "bad-diffbits",
+            ConnectError::Invalid(BlockchainError::EmptyBlock) => "bad-blk-length",
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Known => write!(f, "block already known"),
+            ConnectError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::testutil::{unmined_block, ChainBuilder};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn tx(n: u8) -> Transaction {
+        Transaction::new(vec![vec![n]], vec![vec![n]]).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_templates_during_connect() {
+        let mut mempool = Mempool::new();
+        for n in 0..20 {
+            mempool.add_tx(tx(n));
+        }
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), mempool);
+
+        let templates: Vec<_> = (0..4)
+            .map(|_| {
+                let manager = manager.clone();
+                tokio::spawn(async move {
+                    let mut seen = Vec::new();
+                    for _ in 0..50 {
+                        seen.push(manager.select_template_txs(5, usize::MAX).await);
+                        tokio::task::yield_now().await;
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        // Confirm the pooled transactions five at a time while templates are built
+        let mut confirmed_at = HashMap::new();
+        for batch in 0..4u8 {
+            let txs: Vec<_> = (batch * 5..batch * 5 + 5).map(tx).collect();
+            let block = Blockchain::mine_new_block(&*manager.chain().await, txs.clone(), 1);
+            let height = manager.connect_block(block).await.unwrap();
+            for tx in txs {
+                confirmed_at.insert(tx.double_sha256(), height);
+            }
+            tokio::task::yield_now().await;
+        }
+
+        let chain = manager.chain().await;
+        for task in templates {
+            let seen = tokio::time::timeout(Duration::from_secs(5), task)
+                .await
+                .expect("template requests deadlocked")
+                .unwrap();
+
+            for template in seen {
+                let tip = chain.get_block_by_height(template.height as usize - 1).unwrap();
+                assert_eq!(template.prev_hash, tip.double_sha256());
+                // Nothing already confirmed at or below the template's tip
+                for tx in template.transactions.iter() {
+                    if let Some(&height) = confirmed_at.get(&tx.double_sha256()) {
+                        assert!(height >= template.height, "template at {} reuses tx from block {}", template.height, height);
+                    }
+                }
+            }
+        }
+        assert!(manager.mempool().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_txs_stop_at_limits() {
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        for n in 0..5 {
+            manager.add_mempool_tx(tx(n)).await.unwrap();
+        }
+        let size = tx(0).byte_size();
+
+        let selected = manager.select_template_txs(10, 3 * size).await;
+        assert_eq!((selected.transactions.len(), selected.size), (3, 3 * size));
+        let selected = manager.select_template_txs(10, 3 * size - 1).await;
+        assert_eq!((selected.transactions.len(), selected.size), (2, 2 * size));
+        assert_eq!(*manager.select_template_txs(2, usize::MAX).await.transactions, vec![tx(0), tx(1)]);
+        assert!(manager.select_template_txs(10, size - 1).await.transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_selection_cached_per_generation() {
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        manager.add_mempool_tx(tx(0)).await.unwrap();
+
+        let first = manager.select_template_txs(10, usize::MAX).await;
+        let again = manager.select_template_txs(10, usize::MAX).await;
+        assert!(Arc::ptr_eq(&first.transactions, &again.transactions));
+        // Other limits are another selection
+        assert!(manager.select_template_txs(10, 0).await.transactions.is_empty());
+
+        manager.add_mempool_tx(tx(1)).await.unwrap();
+        let grown = manager.select_template_txs(10, usize::MAX).await;
+        assert_eq!(*grown.transactions, vec![tx(0), tx(1)]);
+        assert_eq!(grown.merkle_root, compute_merkle_root(&grown.transactions));
+
+        // A new tip is a new selection, even with the same pool
+        let block = Blockchain::mine_new_block(&*manager.chain().await, Vec::new(), 1);
+        manager.connect_block(block).await.unwrap();
+        let moved = manager.select_template_txs(10, usize::MAX).await;
+        assert_eq!((moved.height, moved.transactions.len()), (2, 2));
+        assert!(!Arc::ptr_eq(&grown.transactions, &moved.transactions));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_returns_txs_to_mempool() {
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        manager.add_mempool_tx(tx(1)).await.unwrap();
+        assert_eq!(manager.add_mempool_tx(tx(1)).await, Err(AdmitError::AlreadyPooled));
+
+        let block = Blockchain::mine_new_block(&*manager.chain().await, vec![tx(1), tx(2)], 1);
+        let hash = block.double_sha256();
+        let genesis_work = manager.tip().chain_work;
+        manager.connect_block(block.clone()).await.unwrap();
+        assert!(matches!(manager.connect_block(block).await, Err(ConnectError::Known)));
+        assert!(manager.mempool().await.is_empty());
+        assert_eq!(manager.tip().chain_work, chain_work(&*manager.chain().await));
+        assert!(manager.tip().chain_work > genesis_work);
+
+        let disconnected = manager.disconnect_tip().await.unwrap();
+        assert_eq!(disconnected.double_sha256(), hash);
+        assert_eq!(manager.mempool().await.len(), 2);
+        assert_eq!(manager.tip().chain_work, genesis_work);
+        assert!(manager.disconnect_tip().await.is_none(), "genesis was disconnected");
+    }
+
+    #[tokio::test]
+    async fn test_known_blocks_found_at_any_depth() {
+        let blocks: Vec<Block> = ChainBuilder::new(0).mine_blocks(3, 1).build().iter().skip(1).cloned().collect();
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        for block in &blocks {
+            manager.connect_block(block.clone()).await.unwrap();
+        }
+
+        for block in blocks.iter().rev() {
+            assert!(matches!(manager.connect_block(block.clone()).await, Err(ConnectError::Known)));
+        }
+        let orphan = unmined_block([7u8; HASH_SIZE], vec![tx(1)]);
+        assert!(matches!(
+            manager.connect_block(orphan).await,
+            Err(ConnectError::Invalid(BlockchainError::InvalidPreviousHash))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recently_mined_txs_kept_out_of_pool_and_templates() {
+        let mut chain = Blockchain::new_with_genesis();
+        let block = Blockchain::mine_new_block(&chain, vec![tx(1)], 1);
+        chain.add_block(block, false).unwrap();
+
+        // A restarted node drops it from the reloaded pool, and won't pool it again
+        let mut mempool = Mempool::new();
+        mempool.add_tx(tx(1));
+        let manager = ChainManager::with_recent_blocks(chain, mempool, 2);
+        assert!(manager.mempool().await.is_empty());
+        assert_eq!(manager.add_mempool_tx(tx(1)).await, Err(AdmitError::AlreadyConfirmed));
+
+        // Pooled behind admission's back, templates still leave it out
+        manager.mempool.write().await.add_tx(tx(1));
+        manager.add_mempool_tx(tx(2)).await.unwrap();
+        assert_eq!(*manager.select_template_txs(10, usize::MAX).await.transactions, vec![tx(2)]);
+
+        // Disconnecting the block makes it poolable again, as does its
+        // falling out of the window
+        let manager = ChainManager::with_recent_blocks(Blockchain::new_with_genesis(), Mempool::new(), 2);
+        for txs in [vec![tx(1)], vec![tx(2)], vec![]] {
+            let block = Blockchain::mine_new_block(&*manager.chain().await, txs, 1);
+            manager.connect_block(block).await.unwrap();
+        }
+        manager.add_mempool_tx(tx(1)).await.unwrap();
+        assert_eq!(manager.add_mempool_tx(tx(2)).await, Err(AdmitError::AlreadyConfirmed));
+        manager.disconnect_tip().await.unwrap();
+        manager.disconnect_tip().await.unwrap();
+        assert_eq!(manager.mempool().await.len(), 2);
+        // The window slid back over the block holding tx(1)
+        assert_eq!(manager.recent.read().unwrap().blocks.len(), 2);
+        assert!(manager.recent.read().unwrap().contains(&tx(1).double_sha256()));
+    }
+
+    #[tokio::test]
+    async fn test_template_takes_parents_before_children() {
+        let spending = |parent: &Transaction| {
+            Transaction::new(vec![parent.double_sha256().to_vec()], vec![b"out".to_vec()]).unwrap()
+        };
+        let parent = tx(0);
+        let child = spending(&parent);
+        let grandchild = spending(&child);
+
+        // Pooled out of order, as a reorg or relay might leave them
+        let manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        for tx in [&grandchild, &child, &parent] {
+            manager.add_mempool_tx(tx.clone()).await.unwrap();
+        }
+
+        let selected = manager.select_template_txs(2, usize::MAX).await;
+        assert_eq!(*selected.transactions, vec![parent.clone(), child.clone()]);
+        let selected = manager.select_template_txs(10, usize::MAX).await;
+        assert_eq!(*selected.transactions, vec![parent, child, grandchild]);
+    }
+
+    #[tokio::test]
+    async fn test_no_retarget_tip_keeps_genesis_bits() {
+        let mut chain = Blockchain::new_with_genesis();
+        chain.set_params(ConsensusParams { no_retarget: true, ..Default::default() });
+        let genesis_bits = chain.latest_block().header.difficulty_compact;
+        let manager = ChainManager::new(chain, Mempool::new());
+
+        // Blocks a second apart, past several retarget windows
+        for time in 1..10 {
+            let block = Blockchain::mine_new_block(&*manager.chain().await, vec![tx(time as u8)], time);
+            manager.connect_block(block).await.unwrap();
+            let tip = manager.tip();
+            assert_eq!((tip.next_difficulty, tip.retarget.estimated_next_difficulty), (genesis_bits, genesis_bits));
+        }
+    }
+}
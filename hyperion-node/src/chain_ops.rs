@@ -0,0 +1,191 @@
+//! Random operations against a `ChainManager` and the invariants that must
+//! hold between them, for property tests. The generator is kept apart from
+//! any one test so concurrency tests can drive the same operations.
+
+use crate::chain_manager::{ChainManager, TemplateTxs};
+use crate::config::node_config::{MempoolConfig, Network};
+use crate::mempool::Mempool;
+use crate::testutil::{fill_mempool, generate_random_tx};
+
+use hyperion_core::block::{Block, Header, Serializable};
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::consensus::{mine_block, MAX_BLOCK_SIZE};
+use hyperion_core::crypto::Hashable;
+
+use proptest::prelude::*;
+use std::collections::HashSet;
+
+
+/// Seeds of generated transactions; few enough that ops keep meeting the
+/// same ones again
+const TX_SEEDS: u64 = 24;
+
+/// One step a node's chain and mempool may take
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Offer the generated transaction with this seed to the pool
+    AddTx(u64),
+    /// Drop expired transactions
+    Expire,
+    /// Select a template of at most this many transactions, remembering it
+    Template(usize),
+    /// Mine the remembered template and connect it, as a miner would even
+    /// after the tip moved on
+    MineTemplate,
+    /// Disconnect the tip, returning its transactions to the pool
+    Disconnect,
+    /// Restart the node: reload the saved pool, then fill it with this many
+    /// generated transactions as `--regtest-fill-mempool` does
+    Restart(u64),
+}
+
+pub fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..TX_SEEDS).prop_map(Op::AddTx),
+        1 => Just(Op::Expire),
+        3 => (0..6usize).prop_map(Op::Template),
+        3 => Just(Op::MineTemplate),
+        1 => Just(Op::Disconnect),
+        1 => (0..TX_SEEDS).prop_map(Op::Restart),
+    ]
+}
+
+/// A `ChainManager` on a fresh regtest chain, with whatever template was last
+/// selected. Regtest doesn't retarget, so blocks mined a second apart stay
+/// at the genesis difficulty and each takes a handful of hashes.
+pub struct Harness {
+    pub chain_manager: ChainManager,
+    pub mempool_config: MempoolConfig,
+    pub template: Option<TemplateTxs>,
+}
+
+impl Harness {
+    pub fn new(mempool_config: MempoolConfig) -> Self {
+        let mut chain = Blockchain::new_with_genesis();
+        chain.set_params(Network::Regtest.consensus_params());
+        let chain_manager = manager(chain, Mempool::with_config(&mempool_config), &mempool_config);
+        Self { chain_manager, mempool_config, template: None }
+    }
+
+    /// Carry out `op`. Refusals (a full pool, a stale template) are part of
+    /// normal operation and aren't reported.
+    pub async fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::AddTx(seed) => {
+                let _ = self.chain_manager.add_mempool_tx(generate_random_tx(seed)).await;
+            }
+            Op::Expire => {
+                self.chain_manager.expire_mempool().await;
+            }
+            Op::Template(max_count) => {
+                self.template = Some(self.chain_manager.select_template_txs(max_count, MAX_BLOCK_SIZE).await);
+            }
+            Op::MineTemplate => {
+                if let Some(template) = &self.template {
+                    let _ = self.chain_manager.connect_block(mined(template)).await;
+                }
+            }
+            Op::Disconnect => {
+                self.chain_manager.disconnect_tip().await;
+            }
+            Op::Restart(fill) => {
+                let stored = self.chain_manager.chain().await;
+                let mut chain = Blockchain::from_bytes(&stored.serialize().unwrap()).unwrap();
+                chain.set_params(*stored.params());
+                drop(stored);
+                let saved = self.chain_manager.mempool().await.txs().to_vec();
+
+                let mut mempool = Mempool::with_config(&self.mempool_config);
+                for tx in saved {
+                    mempool.add_tx(tx);
+                }
+                fill_mempool(&mut mempool, fill as usize);
+                self.chain_manager = manager(chain, mempool, &self.mempool_config);
+                self.template = None;
+            }
+        }
+    }
+
+    /// Panic unless the chain, pool and a fresh template agree: no txid
+    /// twice across them, the template only takes pooled transactions, and
+    /// the pool's counts match what it holds. Runs are shorter than
+    /// `recent_txid_blocks`, so the whole chain counts as recently mined.
+    pub async fn check_invariants(&self) {
+        let chain = self.chain_manager.chain().await;
+        let mut confirmed = HashSet::new();
+        for (height, _, tx) in chain.iter_transactions() {
+            assert!(confirmed.insert(tx.double_sha256()), "Transaction mined twice, again at height {}", height);
+        }
+        let tip_hash = chain.latest_block().double_sha256();
+        drop(chain);
+
+        let mempool = self.chain_manager.mempool().await;
+        let pooled: HashSet<_> = mempool.txs().iter().map(|tx| tx.double_sha256()).collect();
+        assert_eq!(pooled.len(), mempool.txs().len(), "Transaction pooled twice");
+        assert_eq!(mempool.len(), mempool.txs().len());
+        assert_eq!(mempool.bytes(), mempool.txs().iter().map(|tx| tx.byte_size()).sum::<usize>());
+        assert!(mempool.len() <= mempool.max_txs());
+        assert!(pooled.is_disjoint(&confirmed), "Mined transaction still pooled");
+        drop(mempool);
+
+        let template = self.chain_manager.select_template_txs(usize::MAX, MAX_BLOCK_SIZE).await;
+        assert_eq!(template.prev_hash, tip_hash);
+        let templated: HashSet<_> = template.transactions.iter().map(|tx| tx.double_sha256()).collect();
+        assert_eq!(templated.len(), template.transactions.len(), "Transaction templated twice");
+        assert!(templated.is_subset(&pooled), "Templated transaction isn't pooled");
+    }
+}
+
+fn manager(chain: Blockchain, mempool: Mempool, config: &MempoolConfig) -> ChainManager {
+    ChainManager::with_recent_blocks(chain, mempool, config.recent_txid_blocks)
+}
+
+/// The block a miner would find for `template`
+fn mined(template: &TemplateTxs) -> Block {
+    let mut header = Header::new(
+        1,
+        template.min_time,
+        template.difficulty,
+        0,
+        template.prev_hash,
+        template.merkle_root,
+    );
+    let header = mine_block(&mut header);
+    Block::new(header, template.transactions.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(ops: &[Op], mempool_config: MempoolConfig) {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let mut harness = Harness::new(mempool_config);
+            for op in ops {
+                harness.apply(op).await;
+                harness.check_invariants().await;
+            }
+        });
+    }
+
+    proptest! {
+        // Each case is a whole run of ops, so keep CI's count fixed
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_random_ops_keep_chain_and_pool_consistent(ops in prop::collection::vec(op_strategy(), 1..64)) {
+            run(&ops, MempoolConfig { max_txs: 12, ..Default::default() });
+        }
+
+        #[test]
+        fn test_random_ops_with_everything_expiring(ops in prop::collection::vec(op_strategy(), 1..64)) {
+            run(&ops, MempoolConfig { max_txs: 12, expiry_hours: 0, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn test_restart_over_filled_pool_keeps_one_copy() {
+        run(&[Op::AddTx(3), Op::Restart(5), Op::Restart(5)], MempoolConfig::default());
+    }
+}
@@ -0,0 +1,211 @@
+//! Chain state snapshots: the chain, the mempool and the transaction index
+//! in one file, restored without validating the blocks again. Meant for
+//! regtest setups that would otherwise mine their way to a height.
+
+use crate::chain_manager::ChainManager;
+use crate::config::node_config::Network;
+use crate::txindex::TxIndex;
+
+use hyperion_core::block::{Serializable, Transaction};
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::crypto::{hex32, Hashable, HASH_SIZE};
+
+use bincode::config::standard;
+use bincode::{Decode, Encode};
+use std::fmt;
+use tokio::sync::RwLock;
+
+
+/// First bytes of every snapshot
+const SNAPSHOT_MAGIC: [u8; 4] = *b"HYPS";
+/// Bumped whenever the snapshot changes shape. Only snapshots of exactly
+/// this version are restored, since their contents are trusted as they are.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything a node needs to carry on from where the snapshot was taken
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ChainSnapshot {
+    magic: [u8; 4],
+    version: u32,
+    /// Network the snapshot was taken on, as `Network::as_str`
+    pub network: String,
+    pub genesis_hash: [u8; HASH_SIZE],
+    pub height: u64,
+    pub tip_hash: [u8; HASH_SIZE],
+    /// The serialized chain
+    chain: Vec<u8>,
+    pub mempool: Vec<Transaction>,
+    /// The serialized transaction index, if the node kept one
+    txindex: Option<Vec<u8>>,
+}
+
+impl Serializable for ChainSnapshot {}
+
+/// Why a snapshot can't be restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data isn't a snapshot, or is one of another version
+    Envelope(String),
+    WrongNetwork { network: String },
+    /// The snapshot is of a chain with another genesis block
+    WrongChain { genesis_hash: [u8; HASH_SIZE] },
+    /// The envelope is fine but what it holds doesn't decode or doesn't match it
+    Corrupt(String),
+}
+
+impl ChainSnapshot {
+    /// The state of `chain_manager` and `txindex`, read under their locks
+    /// so all three agree
+    pub async fn take(chain_manager: &ChainManager, network: Network, txindex: Option<&RwLock<TxIndex>>) -> Self {
+        let chain = chain_manager.chain().await;
+        let mempool = chain_manager.mempool().await.txs().to_vec();
+        let txindex = match txindex {
+            Some(index) => Some(
+                bincode::encode_to_vec(&*index.read().await, standard()).expect("Transaction index always encodes"),
+            ),
+            None => None,
+        };
+
+        Self {
+            magic: SNAPSHOT_MAGIC,
+            version: SNAPSHOT_VERSION,
+            network: network.as_str().to_string(),
+            genesis_hash: chain.get_block_by_height(0).expect("Chain has a genesis block").double_sha256(),
+            height: chain.len() as u64 - 1,
+            tip_hash: chain.latest_block().double_sha256(),
+            chain: chain.serialize().expect("Chain always serializes"),
+            mempool,
+            txindex,
+        }
+    }
+
+    /// Read a snapshot, checking its envelope but not its contents
+    pub fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let snapshot = Self::from_bytes(bytes)
+            .map_err(|e| SnapshotError::Envelope(format!("Not a chain snapshot: {}", e)))?;
+        if snapshot.magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::Envelope("Not a chain snapshot".to_string()));
+        }
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Envelope(format!(
+                "Snapshot version {} doesn't match this node's {}",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    /// Replace the state of `chain_manager`, and of `txindex` if the node
+    /// keeps one, with the snapshot's. Nothing changes unless the snapshot
+    /// is of `network` and of the chain `chain_manager` follows. The index
+    /// is rebuilt from the chain if the snapshot holds none.
+    pub async fn restore(
+        self,
+        chain_manager: &ChainManager,
+        network: Network,
+        txindex: Option<&RwLock<TxIndex>>,
+    ) -> Result<(), SnapshotError> {
+        if self.network != network.as_str() {
+            return Err(SnapshotError::WrongNetwork { network: self.network });
+        }
+        if self.genesis_hash != chain_manager.tip().genesis_hash {
+            return Err(SnapshotError::WrongChain { genesis_hash: self.genesis_hash });
+        }
+
+        let chain = Blockchain::from_bytes(&self.chain)
+            .map_err(|e| SnapshotError::Corrupt(format!("Chain does not decode: {}", e)))?;
+        let genesis_hash = chain.get_block_by_height(0).expect("Chain has a genesis block").double_sha256();
+        if genesis_hash != self.genesis_hash || chain.latest_block().double_sha256() != self.tip_hash {
+            return Err(SnapshotError::Corrupt("Chain does not match the snapshot's genesis and tip".to_string()));
+        }
+
+        let index = match (&self.txindex, txindex) {
+            (Some(bytes), Some(_)) => {
+                let (index, _) = bincode::decode_from_slice(bytes, standard())
+                    .map_err(|e| SnapshotError::Corrupt(format!("Transaction index does not decode: {}", e)))?;
+                Some(index)
+            }
+            (None, Some(_)) => {
+                let mut index = TxIndex::new();
+                index.catch_up(&chain);
+                Some(index)
+            }
+            (_, None) => None,
+        };
+
+        chain_manager.restore(chain, self.mempool).await;
+        if let (Some(index), Some(txindex)) = (index, txindex) {
+            *txindex.write().await = index;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Envelope(reason) | SnapshotError::Corrupt(reason) => write!(f, "{}", reason),
+            SnapshotError::WrongNetwork { network } => write!(f, "Snapshot was taken on {}", network),
+            SnapshotError::WrongChain { genesis_hash } => {
+                write!(f, "Snapshot is of the chain with genesis {}", hex32(genesis_hash))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::Mempool;
+    use hyperion_core::testutil::ChainBuilder;
+
+    fn regtest_manager(blocks: usize) -> ChainManager {
+        ChainManager::new(ChainBuilder::new(0).mine_blocks(blocks, 1).build(), Mempool::new())
+    }
+
+    #[tokio::test]
+    async fn test_restore_replaces_chain_and_index() {
+        let source = regtest_manager(3);
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        source.add_mempool_tx(tx.clone()).await.unwrap();
+        let mut index = TxIndex::new();
+        index.catch_up(&*source.chain().await);
+        let index = RwLock::new(index);
+        let bytes = ChainSnapshot::take(&source, Network::Regtest, Some(&index)).await.serialize().unwrap();
+
+        let target = regtest_manager(1);
+        let target_index = RwLock::new(TxIndex::new());
+        let snapshot = ChainSnapshot::decode(&bytes).unwrap();
+        assert_eq!(snapshot.height, 3);
+        snapshot.restore(&target, Network::Regtest, Some(&target_index)).await.unwrap();
+
+        assert_eq!(target.tip().hash, source.tip().hash);
+        assert_eq!(target.tip().chain_work, source.tip().chain_work);
+        assert_eq!(target.mempool().await.txs(), [tx]);
+        let coinbase = source.chain().await.latest_block().transactions[0].double_sha256();
+        assert_eq!(target_index.read().await.height_of(&coinbase), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_restore_refuses_other_network_and_chain() {
+        let source = regtest_manager(2);
+        let snapshot = ChainSnapshot::take(&source, Network::Regtest, None).await;
+
+        let target = regtest_manager(1);
+        let tip = target.tip().hash;
+        let err = snapshot.clone().restore(&target, Network::Testnet, None).await.unwrap_err();
+        assert_eq!(err, SnapshotError::WrongNetwork { network: "regtest".to_string() });
+
+        let mut other = snapshot.clone();
+        other.genesis_hash = [7; HASH_SIZE];
+        assert!(matches!(other.restore(&target, Network::Regtest, None).await, Err(SnapshotError::WrongChain { .. })));
+        assert_eq!(target.tip().hash, tip);
+
+        let mut newer = snapshot;
+        newer.version = SNAPSHOT_VERSION + 1;
+        let err = ChainSnapshot::decode(&newer.serialize().unwrap()).unwrap_err();
+        assert!(matches!(err, SnapshotError::Envelope(_)), "{:?}", err);
+        assert!(ChainSnapshot::decode(b"not a snapshot").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Chain persistence off the request path: connecting a block queues a write
+//! of the chain file, which a dedicated task carries out in order.
+
+use crate::chain_manager::ChainManager;
+use crate::config::node_config::SyncMode;
+use crate::metrics::NodeMetrics;
+
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot};
+use tracing::error;
+
+
+/// Where the writer saves the chain, abstracted so tests can slow it down
+pub trait ChainStore: Send + Sync + 'static {
+    fn save(&self, chain_manager: &ChainManager) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// The chain file in a data directory
+pub struct FileStore(pub PathBuf);
+
+impl ChainStore for FileStore {
+    async fn save(&self, chain_manager: &ChainManager) -> io::Result<()> {
+        chain_manager.persist(&self.0).await
+    }
+}
+
+enum Request {
+    /// Save the chain as it stands when the write starts
+    Write,
+    /// Answer once every write queued before this one is done
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+/// Queues chain writes for the writer task. Each write saves the whole
+/// chain, so writes that queue up behind a slow one are done as one.
+#[derive(Clone)]
+pub struct ChainWriter {
+    queue: mpsc::Sender<Request>,
+    mode: SyncMode,
+}
+
+impl ChainWriter {
+    /// Start the writer task. Once `queue_len` writes are waiting, queueing
+    /// another waits for room rather than dropping it.
+    pub fn spawn<S: ChainStore>(
+        chain_manager: ChainManager,
+        store: S,
+        metrics: Arc<NodeMetrics>,
+        mode: SyncMode,
+        queue_len: usize,
+    ) -> Self {
+        let (queue, requests) = mpsc::channel(queue_len.max(1));
+        tokio::spawn(run_writer(chain_manager, store, metrics, requests));
+        Self { queue, mode }
+    }
+
+    /// Queue a write of the chain. With `SyncMode::PerBlock` this waits for
+    /// the write and reports how it went; otherwise it returns once queued.
+    pub async fn write(&self) -> io::Result<()> {
+        self.queue.send(Request::Write).await.map_err(|_| writer_gone())?;
+        match self.mode {
+            SyncMode::Async => Ok(()),
+            SyncMode::PerBlock => self.flush().await,
+        }
+    }
+
+    /// Wait for every write queued so far, failing if the last of them did
+    pub async fn flush(&self) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.queue.send(Request::Flush(done)).await.map_err(|_| writer_gone())?;
+        result.await.map_err(|_| writer_gone())?
+    }
+}
+
+async fn run_writer<S: ChainStore>(
+    chain_manager: ChainManager,
+    store: S,
+    metrics: Arc<NodeMetrics>,
+    mut requests: mpsc::Receiver<Request>,
+) {
+    // io::Error isn't Clone, so each flush gets its own copy of the last failure
+    let mut last_error: Option<(io::ErrorKind, String)> = None;
+    let result = |last_error: &Option<(io::ErrorKind, String)>| match last_error {
+        Some((kind, message)) => Err(io::Error::new(*kind, message.clone())),
+        None => Ok(()),
+    };
+
+    while let Some(request) = requests.recv().await {
+        let mut flushes = Vec::new();
+        match request {
+            Request::Flush(done) => {
+                let _ = done.send(result(&last_error));
+                continue;
+            }
+            Request::Write => {
+                // Whatever queued meanwhile is covered by this write, which
+                // saves the chain as it stands once the queue is drained
+                while let Ok(request) = requests.try_recv() {
+                    if let Request::Flush(done) = request {
+                        flushes.push(done);
+                    }
+                }
+            }
+        }
+
+        let started = Instant::now();
+        let saved = store.save(&chain_manager).await;
+        metrics.record_flush(saved.is_ok(), started.elapsed());
+        last_error = match saved {
+            Ok(()) => None,
+            Err(e) => {
+                error!("Failed to save blockchain to disk: {}", e);
+                Some((e.kind(), e.to_string()))
+            }
+        };
+
+        for done in flushes {
+            let _ = done.send(result(&last_error));
+        }
+    }
+}
+
+fn writer_gone() -> io::Error {
+    io::Error::other("Chain writer has stopped")
+}
+
+/// Store that records the height it saves, then takes `delay` over writing it
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct SlowStore {
+    pub delay: std::time::Duration,
+    pub saved: Arc<std::sync::Mutex<Vec<u64>>>,
+}
+
+#[cfg(test)]
+impl ChainStore for SlowStore {
+    async fn save(&self, chain_manager: &ChainManager) -> io::Result<()> {
+        let height = chain_manager.tip().height;
+        tokio::time::sleep(self.delay).await;
+        self.saved.lock().unwrap().push(height);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::Mempool;
+    use hyperion_core::block::Block;
+    use hyperion_core::chain::blockchain::Blockchain;
+    use hyperion_core::testutil::ChainBuilder;
+
+    use std::time::Duration;
+    use tokio::time::{Instant, timeout};
+
+    fn slow_writer(mode: SyncMode, queue_len: usize) -> (ChainManager, ChainWriter, SlowStore) {
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let store = SlowStore { delay: Duration::from_secs(1), ..Default::default() };
+        let writer = ChainWriter::spawn(chain_manager.clone(), store.clone(), Arc::new(NodeMetrics::default()), mode, queue_len);
+        (chain_manager, writer, store)
+    }
+
+    /// Blocks 1 to `count` of a seeded chain, to connect in turn
+    fn blocks(count: usize) -> Vec<Block> {
+        ChainBuilder::new(0).mine_blocks(count, 0).build().iter().skip(1).cloned().collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_async_writes_return_at_once_and_flush_drains() {
+        let (chain_manager, writer, store) = slow_writer(SyncMode::Async, 8);
+        let started = Instant::now();
+        for block in blocks(5) {
+            chain_manager.connect_block(block).await.unwrap();
+            writer.write().await.unwrap();
+            // Let the writer pick the first write up before the rest queue
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        // The first write took the first block; the rest queued behind it and
+        // were saved together
+        writer.flush().await.unwrap();
+        assert_eq!(*store.saved.lock().unwrap(), [1, 5]);
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_block_waits_for_the_write() {
+        let (chain_manager, writer, store) = slow_writer(SyncMode::PerBlock, 8);
+        for (height, block) in (1..).zip(blocks(2)) {
+            chain_manager.connect_block(block).await.unwrap();
+            let started = Instant::now();
+            writer.write().await.unwrap();
+            assert_eq!(started.elapsed(), Duration::from_secs(1));
+            assert_eq!(store.saved.lock().unwrap().last(), Some(&height));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_full_queue_holds_writes_back() {
+        let (_chain_manager, writer, store) = slow_writer(SyncMode::Async, 2);
+
+        // One write in progress and two queued fill it up
+        writer.write().await.unwrap();
+        tokio::task::yield_now().await;
+        writer.write().await.unwrap();
+        writer.write().await.unwrap();
+        assert!(timeout(Duration::from_millis(500), writer.write()).await.is_err());
+
+        // Nothing queued was dropped
+        writer.flush().await.unwrap();
+        assert!(!store.saved.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::config::NodeConfig;
+
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+
+pub fn command() -> Command {
+    Command::new("hyperion-node")
+        .version("0.1.0")
+        .about("Hyperion full node")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .value_name("FILE")
+                .help("Configuration file path")
+                .default_value("hyperion.toml")
+        )
+        .arg(
+            Arg::new("datadir")
+                .short('d')
+                .long("datadir")
+                .value_name("DIR")
+                .help("Directory for chain data")
+        )
+        .arg(
+            Arg::new("network")
+                .long("network")
+                .value_name("NETWORK")
+                .help("Network to join")
+                .value_parser(["mainnet", "testnet", "regtest"])
+        )
+        .arg(
+            Arg::new("rpc-port")
+                .long("rpc-port")
+                .value_name("PORT")
+                .help("JSON-RPC server port")
+                .value_parser(clap::value_parser!(u16))
+        )
+        .arg(
+            Arg::new("p2p-port")
+                .long("p2p-port")
+                .value_name("PORT")
+                .help("Port for every P2P listen address")
+                .value_parser(clap::value_parser!(u16))
+        )
+        .arg(
+            Arg::new("connect")
+                .long("connect")
+                .value_name("ADDR")
+                .help("Connect only to this peer (repeatable)")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(SocketAddr))
+        )
+        .arg(
+            Arg::new("txindex")
+                .long("txindex")
+                .help("Maintain a transaction index")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .value_name("MIB")
+                .help("Prune block storage down to this many MiB")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("reindex")
+                .long("reindex")
+                .help("Rebuild the chain and indexes by replaying stored blocks")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("migrate-storage")
+                .long("migrate-storage")
+                .help("Migrate a legacy chain file even when auto_migrate is off")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("regtest-fill-mempool")
+                .long("regtest-fill-mempool")
+                .value_name("N")
+                .help("Fill the mempool with N generated transactions (regtest only)")
+                .value_parser(clap::value_parser!(usize))
+        )
+}
+
+/// Build the node configuration: CLI flags override the config file, which
+/// overrides the defaults
+pub fn load_config(matches: &ArgMatches) -> Result<NodeConfig> {
+    let config_path = matches.get_one::<String>("config").unwrap();
+    let mut config = NodeConfig::load(config_path)?;
+
+    if let Some(datadir) = matches.get_one::<String>("datadir") {
+        config.storage.datadir = PathBuf::from(datadir);
+    }
+    if let Some(network) = matches.get_one::<String>("network") {
+        config.chain = network.parse()?;
+    }
+    if let Some(port) = matches.get_one::<u16>("rpc-port") {
+        config.network.rpc_port = *port;
+    }
+    if let Some(port) = matches.get_one::<u16>("p2p-port") {
+        for addr in &mut config.network.p2p_listen {
+            addr.set_port(*port);
+        }
+    }
+    if let Some(peers) = matches.get_many::<SocketAddr>("connect") {
+        config.network.connect = peers.copied().collect();
+    }
+    if matches.get_flag("txindex") {
+        config.storage.txindex = true;
+    }
+    if let Some(prune) = matches.get_one::<u64>("prune") {
+        config.storage.prune = Some(*prune);
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::node_config::Network;
+
+    use std::fs;
+
+    /// Write `content` to a config file unique to `name` and return its path
+    fn write_config(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("hyperion-cli-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, content).expect("Failed to write config");
+        path.to_string_lossy().into_owned()
+    }
+
+    fn parse(args: &[&str]) -> NodeConfig {
+        let matches = command()
+            .try_get_matches_from(args)
+            .expect("Failed to parse arguments");
+        load_config(&matches).expect("Failed to load config")
+    }
+
+    #[test]
+    fn test_defaults_without_file_or_flags() {
+        let path = std::env::temp_dir().join(format!("hyperion-cli-missing-{}.toml", std::process::id()));
+        let config = parse(&["hyperion-node", "--config", &path.to_string_lossy()]);
+        let default = NodeConfig::default();
+
+        assert_eq!(config.network.rpc_port, default.network.rpc_port);
+        assert_eq!(config.chain, Network::Mainnet);
+        assert!(config.network.connect.is_empty());
+        assert!(!config.storage.txindex);
+
+        // First run writes the defaults out for editing
+        assert!(path.exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_beats_default() {
+        let path = write_config("file", "chain = \"testnet\"\n[network]\nrpc_port = 7001\n[storage]\nprune = 550\n");
+        let config = parse(&["hyperion-node", "--config", &path]);
+
+        assert_eq!(config.network.rpc_port, 7001);
+        assert_eq!(config.chain, Network::Testnet);
+        assert_eq!(config.storage.prune, Some(550));
+        assert_eq!(config.network.p2p_listen, NodeConfig::default().network.p2p_listen);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_flag_beats_file() {
+        let path = write_config("flag", "[network]\nrpc_port = 7001\n[storage]\ndatadir = \"/var/lib/hyperion\"\n");
+        let config = parse(&[
+            "hyperion-node",
+            "--config", &path,
+            "--rpc-port", "8001",
+            "--p2p-port", "8000",
+            "--network", "regtest",
+            "--connect", "10.0.0.1:6000",
+            "--connect", "[::1]:6000",
+            "--txindex",
+        ]);
+
+        assert_eq!(config.network.rpc_port, 8001);
+        assert_eq!(config.storage.datadir, PathBuf::from("/var/lib/hyperion"));
+        assert_eq!(config.chain_dir(), PathBuf::from("/var/lib/hyperion/regtest"));
+        assert!(config.network.p2p_listen.iter().all(|addr| addr.port() == 8000));
+        assert_eq!(config.network.connect.len(), 2);
+        assert!(config.storage.txindex);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_flag_rejected() {
+        assert!(command().try_get_matches_from(["hyperion-node", "--network", "moonnet"]).is_err());
+        assert!(command().try_get_matches_from(["hyperion-node", "--rpc-port", "70000"]).is_err());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod node_config;
+pub use node_config::{ConfigWarning, NodeConfig};
@@ -0,0 +1,659 @@
+use anyhow::{Context, Result};
+use hyperion_core::block::Block;
+use hyperion_core::consensus::{
+    compact_to_target, create_genesis_block, create_genesis_block_with, ConsensusParams, GenesisParams, POW_LIMIT_BITS,
+};
+use hyperion_core::crypto::parse_hex;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Regtest,
+    /// A private network with its genesis block built from `[genesis]`
+    Custom,
+}
+
+impl Network {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+            Network::Custom => "custom",
+        }
+    }
+
+    /// Rules the network's chain is checked against. Regtest blocks may
+    /// carry any difficulty their proof of work meets, so they can be mined
+    /// at whatever bits a test wants.
+    pub fn consensus_params(&self) -> ConsensusParams {
+        ConsensusParams { no_retarget: *self == Network::Regtest, ..Default::default() }
+    }
+}
+
+impl FromStr for Network {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            "custom" => Ok(Network::Custom),
+            other => anyhow::bail!("unknown network '{}'", other),
+        }
+    }
+}
+
+/// Node settings, loaded from `hyperion.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Which chain to follow
+    pub chain: Network,
+    /// Shell command run for every connected block, `%s` replaced by its hash
+    pub block_notify: Option<String>,
+    /// Shell command run for every transaction accepted into the mempool,
+    /// `%s` replaced by its txid
+    pub tx_notify: Option<String>,
+    pub network: NetworkConfig,
+    pub storage: StorageConfig,
+    pub mempool: MempoolConfig,
+    pub logging: LoggingConfig,
+    /// Genesis block of the `custom` network; other networks ignore it
+    pub genesis: GenesisConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Interface the JSON-RPC server binds
+    pub rpc_bind: IpAddr,
+    pub rpc_port: u16,
+    /// Start the P2P listener; false gives an RPC-only node
+    pub p2p_enabled: bool,
+    /// Addresses the P2P listener binds. `[::]` on its own is dual-stack and
+    /// also accepts IPv4; alongside `0.0.0.0` on the same port it is IPv6-only.
+    pub p2p_listen: Vec<SocketAddr>,
+    /// Extra addresses for the address book
+    pub peers: Vec<SocketAddr>,
+    /// Connect only to these peers instead of using the address book and seeds
+    pub connect: Vec<SocketAddr>,
+    /// Invalid `submit_block` calls allowed per source address within
+    /// `rpc_bad_block_window_secs` before further submissions are refused;
+    /// 0 disables the limit
+    pub rpc_max_bad_blocks: usize,
+    pub rpc_bad_block_window_secs: u64,
+    /// RPC methods, or groups of them (`read`, `mining`, `admin`), callers
+    /// may use; empty allows every method
+    pub rpc_allow: Vec<String>,
+    /// Methods or groups refused to every caller, trusted ones included
+    pub rpc_deny: Vec<String>,
+    /// Addresses that may use any method not in `rpc_deny`, e.g. your miners
+    pub rpc_trusted: Vec<IpAddr>,
+    /// Misbehavior score at which a peer is disconnected and banned
+    pub ban_score: u32,
+    /// Score added per second a peer spends throttled for flooding
+    pub flooding_penalty: u32,
+    /// Score added per block failing proof of work or its merkle root
+    pub invalid_block_penalty: u32,
+    pub ban_duration_secs: u64,
+    /// Addresses never banned, e.g. your own nodes on the LAN
+    pub ban_whitelist: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Whole chain serialized to a single file
+    File,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Root directory for chain data
+    pub datadir: PathBuf,
+    pub backend: StorageBackend,
+    /// Maintain a transaction index
+    pub txindex: bool,
+    /// Target size in MiB to prune block storage down to
+    pub prune: Option<u64>,
+    /// Migrate a legacy chain file at startup; when off, the node refuses to
+    /// start on one unless run with `--migrate-storage`
+    pub auto_migrate: bool,
+    /// Whether an accepted block is on disk before `submit_block` answers
+    pub sync_mode: SyncMode,
+    /// Chain writes queued before new blocks wait for the writer
+    pub write_queue_len: usize,
+}
+
+/// When a connected block reaches the disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Queue the write and answer at once; a crash loses blocks still queued
+    #[default]
+    Async,
+    /// Answer once the chain file holding the block is written and synced
+    PerBlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenesisConfig {
+    /// Header time of the genesis block
+    pub time: u32,
+    /// Compact difficulty the genesis block is mined at, and the chain starts from
+    pub bits: u32,
+    /// Input of the genesis transaction
+    pub message: String,
+    /// Hex encoded outputs of the genesis transaction; empty keeps the
+    /// built-in networks' single output
+    pub premine: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolConfig {
+    /// Transactions beyond this count are rejected
+    pub max_txs: usize,
+    /// Transactions older than this are dropped
+    pub expiry_hours: u64,
+    /// Most transactions handed out in one block template
+    pub template_max_txs: usize,
+    /// Transactions mined in this many latest blocks are kept out of the
+    /// pool and templates; 0 turns the check off
+    pub recent_txid_blocks: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleFormat {
+    Compact,
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Default level for hyperion crates; `RUST_LOG` replaces all levels
+    pub level: String,
+    /// Per-target levels, e.g. `"hyperion_node::network" = "debug"`
+    pub targets: BTreeMap<String, String>,
+    pub console: ConsoleFormat,
+    pub file_enabled: bool,
+    /// Log file directory, relative to `storage.datadir` unless absolute
+    pub dir: PathBuf,
+    pub file_name: String,
+    /// Write the log file as JSON lines rather than plain text
+    pub json: bool,
+    /// Rotate once the file reaches this size
+    pub max_file_size_mb: u64,
+    /// Rotated files kept
+    pub max_files: usize,
+    /// Log a periodic status summary
+    pub summary: bool,
+    pub summary_interval_secs: u64,
+    /// Deprecated single path for the log file, relative to the working
+    /// directory; read into `dir` and `file_name`
+    #[serde(skip_serializing)]
+    pub file: Option<PathBuf>,
+}
+
+impl NodeConfig {
+    /// Load from `path`, writing a default config there on first run
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Self::parse(&content).with_context(|| format!("Invalid config file {}", path.display()))
+        } else {
+            // Create default config file
+            let default = Self::default();
+            let content = toml::to_string_pretty(&default)?;
+            fs::write(path, content)
+                .with_context(|| format!("Failed to write default config to {}", path.display()))?;
+            Ok(default)
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(content)?;
+        config.logging.apply_legacy_file()?;
+        Ok(config)
+    }
+
+    /// Directory holding this chain's data. Mainnet uses `datadir` directly,
+    /// other networks a subdirectory named after them.
+    pub fn chain_dir(&self) -> PathBuf {
+        match self.chain {
+            Network::Mainnet => self.storage.datadir.clone(),
+            network => self.storage.datadir.join(network.as_str()),
+        }
+    }
+
+    /// The genesis block of the configured network. Only `custom` builds its
+    /// own, from `[genesis]`; the rest share the built-in one.
+    pub fn genesis_block(&self) -> Result<Block> {
+        match self.chain {
+            Network::Custom => Ok(create_genesis_block_with(&self.genesis.params()?)),
+            _ => Ok(create_genesis_block()),
+        }
+    }
+
+    pub fn rpc_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.network.rpc_bind, self.network.rpc_port)
+    }
+
+    /// Settings that are likely mistakes. Looks at nothing but the config,
+    /// so a datadir is judged by its name alone.
+    pub fn sanity_report(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+
+        // The RPC server has no authentication to fall back on, only the
+        // allowlist
+        if !self.network.rpc_bind.is_loopback() && self.network.rpc_allow.is_empty() {
+            warnings.push(ConfigWarning::PublicRpcWithoutAuth(self.rpc_addr()));
+        }
+
+        let named = self.storage.datadir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<Network>().ok());
+        if let Some(datadir) = named
+            && datadir != self.chain {
+            warnings.push(ConfigWarning::NetworkMismatch { chain: self.chain, datadir });
+        }
+
+        if self.chain != Network::Custom && self.genesis != GenesisConfig::default() {
+            warnings.push(ConfigWarning::GenesisUnused(self.chain));
+        }
+
+        if let Some(file) = &self.logging.file {
+            warnings.push(ConfigWarning::DeprecatedLogFile(file.clone()));
+        }
+
+        warnings
+    }
+}
+
+/// A setting, or combination of settings, that is likely a mistake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// RPC reachable from other hosts, with nothing to stop them calling it
+    PublicRpcWithoutAuth(SocketAddr),
+    /// The datadir is named after a different network than `chain`
+    NetworkMismatch { chain: Network, datadir: Network },
+    /// `[genesis]` was changed for a network other than `custom`
+    GenesisUnused(Network),
+    /// The log file was set with the old `logging.file` key
+    DeprecatedLogFile(PathBuf),
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigWarning::PublicRpcWithoutAuth(addr) => write!(
+                f,
+                "RPC is bound to {}, reachable from other hosts, and has no authentication; bind it to a loopback address or set rpc_allow",
+                addr
+            ),
+            ConfigWarning::NetworkMismatch { chain, datadir } => write!(
+                f,
+                "Following {} with a datadir named for {}; check chain and storage.datadir",
+                chain.as_str(),
+                datadir.as_str()
+            ),
+            ConfigWarning::GenesisUnused(chain) => write!(
+                f,
+                "Only the custom network uses [genesis], ignoring it for {}",
+                chain.as_str()
+            ),
+            ConfigWarning::DeprecatedLogFile(file) => write!(
+                f,
+                "logging.file is deprecated, set logging.dir and logging.file_name instead; still logging to {}",
+                file.display()
+            ),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn summary_interval(&self) -> Duration {
+        Duration::from_secs(self.summary_interval_secs)
+    }
+
+    /// Split the deprecated `file` into `dir` and `file_name`. The directory
+    /// is made absolute since `file` was relative to the working directory,
+    /// not the datadir.
+    fn apply_legacy_file(&mut self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+
+        let name = file.file_name()
+            .with_context(|| format!("logging.file {} has no file name", file.display()))?;
+        self.file_name = name.to_string_lossy().into_owned();
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        self.dir = std::path::absolute(dir)
+            .with_context(|| format!("Failed to resolve logging.file {}", file.display()))?;
+        Ok(())
+    }
+}
+
+impl NetworkConfig {
+    pub fn rpc_bad_block_window(&self) -> Duration {
+        Duration::from_secs(self.rpc_bad_block_window_secs)
+    }
+
+    pub fn ban_duration(&self) -> Duration {
+        Duration::from_secs(self.ban_duration_secs)
+    }
+}
+
+impl GenesisConfig {
+    /// The genesis block parameters, checked so the block can be built and mined
+    pub fn params(&self) -> Result<GenesisParams> {
+        if self.message.is_empty() {
+            anyhow::bail!("genesis.message must not be empty");
+        }
+        if compact_to_target(self.bits) > compact_to_target(POW_LIMIT_BITS) {
+            anyhow::bail!("genesis.bits {:#010x} is easier than the proof of work limit {:#010x}", self.bits, POW_LIMIT_BITS);
+        }
+
+        let outputs = if self.premine.is_empty() {
+            GenesisParams::default().outputs
+        } else {
+            self.premine
+                .iter()
+                .map(|output| parse_hex(output).map_err(|e| anyhow::anyhow!("genesis.premine output '{}': {}", output, e)))
+                .collect::<Result<_>>()?
+        };
+        if outputs.iter().any(|output: &Vec<u8>| output.is_empty()) {
+            anyhow::bail!("genesis.premine outputs must not be empty");
+        }
+
+        Ok(GenesisParams {
+            time: self.time,
+            difficulty_compact: self.bits,
+            message: self.message.as_bytes().to_vec(),
+            outputs,
+        })
+    }
+}
+
+impl MempoolConfig {
+    pub fn expiry(&self) -> Duration {
+        Duration::from_secs(self.expiry_hours * 60 * 60)
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            rpc_bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            rpc_port: 6001,
+            p2p_enabled: true,
+            p2p_listen: vec![SocketAddr::from(([127, 0, 0, 1], 6000))],
+            peers: Vec::new(),
+            connect: Vec::new(),
+            rpc_max_bad_blocks: 10,
+            rpc_bad_block_window_secs: 600,
+            rpc_allow: Vec::new(),
+            rpc_deny: Vec::new(),
+            rpc_trusted: Vec::new(),
+            ban_score: 100,
+            flooding_penalty: 1,
+            invalid_block_penalty: 100,
+            ban_duration_secs: 24 * 60 * 60,
+            ban_whitelist: Vec::new(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            datadir: PathBuf::from("."),
+            backend: StorageBackend::File,
+            txindex: false,
+            prune: None,
+            auto_migrate: true,
+            sync_mode: SyncMode::Async,
+            write_queue_len: 64,
+        }
+    }
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        let params = GenesisParams::default();
+        Self {
+            time: params.time,
+            bits: params.difficulty_compact,
+            message: String::from_utf8(params.message).expect("Built-in genesis message is UTF-8"),
+            premine: Vec::new(),
+        }
+    }
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            max_txs: 50_000,
+            expiry_hours: 336,
+            template_max_txs: 100,
+            recent_txid_blocks: 100,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            targets: BTreeMap::new(),
+            console: ConsoleFormat::Compact,
+            file_enabled: true,
+            dir: PathBuf::from("logs"),
+            file_name: "hyperion-node.log".to_string(),
+            json: true,
+            max_file_size_mb: 10,
+            max_files: 9,
+            summary: true,
+            summary_interval_secs: 60,
+            file: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::crypto::Hashable;
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = NodeConfig::parse(r#"
+            chain = "testnet"
+            block_notify = "echo %s >> blocks.txt"
+
+            [network]
+            rpc_bind = "0.0.0.0"
+            rpc_port = 7001
+            p2p_enabled = true
+            p2p_listen = ["[::]:7000"]
+            peers = ["10.0.0.1:7000"]
+            connect = []
+            ban_duration_secs = 3600
+            ban_whitelist = ["192.168.1.20"]
+
+            [storage]
+            datadir = "/var/lib/hyperion"
+            backend = "file"
+            txindex = true
+            prune = 550
+
+            [mempool]
+            max_txs = 1000
+            expiry_hours = 24
+            template_max_txs = 5000
+
+            [logging]
+            level = "debug"
+            console = "pretty"
+            dir = "/var/log/hyperion"
+            json = false
+            max_files = 3
+
+            [logging.targets]
+            "hyperion_node::network" = "trace"
+        "#).expect("Failed to parse config");
+
+        assert_eq!(config.chain, Network::Testnet);
+        assert_eq!(config.block_notify.as_deref(), Some("echo %s >> blocks.txt"));
+        assert_eq!(config.tx_notify, None);
+        assert_eq!(config.rpc_addr(), "0.0.0.0:7001".parse().unwrap());
+        assert_eq!(config.network.p2p_listen, vec!["[::]:7000".parse().unwrap()]);
+        assert_eq!(config.network.ban_duration(), Duration::from_secs(3600));
+        assert_eq!(config.network.ban_whitelist, vec!["192.168.1.20".parse::<IpAddr>().unwrap()]);
+        assert_eq!(config.chain_dir(), PathBuf::from("/var/lib/hyperion/testnet"));
+        assert_eq!(config.storage.prune, Some(550));
+        assert_eq!(config.mempool.expiry(), Duration::from_secs(24 * 60 * 60));
+        assert_eq!(config.mempool.template_max_txs, 5000);
+        assert_eq!(config.logging.level, "debug");
+        assert!(!config.logging.json);
+        assert_eq!(config.logging.console, ConsoleFormat::Pretty);
+        assert_eq!(config.logging.max_files, 3);
+        assert_eq!(config.logging.targets["hyperion_node::network"], "trace");
+    }
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config = NodeConfig::parse(r#"
+            [network]
+            rpc_port = 7001
+        "#).expect("Failed to parse config");
+
+        let default = NodeConfig::default();
+        assert_eq!(config.network.rpc_port, 7001);
+        assert_eq!(config.network.p2p_listen, default.network.p2p_listen);
+        assert_eq!(config.chain, Network::Mainnet);
+        assert_eq!(config.mempool.max_txs, default.mempool.max_txs);
+        assert_eq!(config.logging.dir, default.logging.dir);
+    }
+
+    #[test]
+    fn test_invalid_config_names_field() {
+        let err = NodeConfig::parse("[network]\nrpc_port = \"abc\"\n").unwrap_err();
+        assert!(err.to_string().contains("rpc_port"), "{}", err);
+
+        let err = NodeConfig::parse("[mempool]\nmax_tx = 10\n").unwrap_err();
+        assert!(err.to_string().contains("max_tx"), "{}", err);
+    }
+
+    #[test]
+    fn test_sanity_report_flags_mistakes() {
+        assert!(NodeConfig::default().sanity_report().is_empty());
+
+        let config = NodeConfig::parse(r#"
+            chain = "regtest"
+
+            [network]
+            rpc_bind = "0.0.0.0"
+
+            [storage]
+            datadir = "/var/lib/hyperion/regtest"
+        "#).unwrap();
+        assert_eq!(config.sanity_report(), [ConfigWarning::PublicRpcWithoutAuth("0.0.0.0:6001".parse().unwrap())]);
+
+        // Mainnet data lives in the datadir itself, so this one is a test network's
+        let config = NodeConfig::parse(r#"
+            [network]
+            rpc_bind = "::1"
+
+            [storage]
+            datadir = "/var/lib/hyperion/testnet"
+        "#).unwrap();
+        assert_eq!(config.sanity_report(), [
+            ConfigWarning::NetworkMismatch { chain: Network::Mainnet, datadir: Network::Testnet },
+        ]);
+    }
+
+    #[test]
+    fn test_legacy_log_file_still_read() {
+        let config = NodeConfig::parse("[logging]\nfile = \"/var/log/hyperion.log\"\n").unwrap();
+        assert_eq!(config.logging.dir, PathBuf::from("/var/log"));
+        assert_eq!(config.logging.file_name, "hyperion.log");
+        assert_eq!(config.sanity_report(), [ConfigWarning::DeprecatedLogFile("/var/log/hyperion.log".into())]);
+
+        // Relative to where the node was started, as it always was
+        let config = NodeConfig::parse("[logging]\nfile = \"hyperion.log\"\n").unwrap();
+        assert_eq!(config.logging.dir, std::env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn test_only_regtest_skips_retarget() {
+        assert!(Network::Regtest.consensus_params().no_retarget);
+        assert!(!Network::Mainnet.consensus_params().no_retarget && !Network::Testnet.consensus_params().no_retarget);
+    }
+
+    #[test]
+    fn test_custom_genesis_follows_config() {
+        let custom = |message: &str| NodeConfig::parse(&format!(r#"
+            chain = "custom"
+
+            [genesis]
+            time = 1700000000
+            message = "{}"
+            premine = ["00aa"]
+        "#, message)).unwrap();
+
+        let lab = custom("lab one").genesis_block().unwrap();
+        let other = custom("lab two").genesis_block().unwrap();
+        assert_ne!(lab.double_sha256(), other.double_sha256());
+        assert_eq!(lab.header.time, 1_700_000_000);
+        assert_eq!(lab.transactions[0].outputs, [vec![0x00, 0xaa]]);
+
+        // Other networks keep the built-in genesis, whatever [genesis] says
+        let mut testnet = custom("lab one");
+        testnet.chain = Network::Testnet;
+        assert_eq!(testnet.genesis_block().unwrap(), create_genesis_block());
+        assert_eq!(testnet.sanity_report(), [ConfigWarning::GenesisUnused(Network::Testnet)]);
+
+        // As does custom left at the defaults
+        let config = NodeConfig { chain: Network::Custom, ..Default::default() };
+        assert_eq!(config.genesis_block().unwrap(), create_genesis_block());
+
+        let mut config = custom("lab one");
+        config.genesis.bits = 0x2100ffff;
+        assert!(config.genesis_block().is_err());
+        config.genesis.bits = POW_LIMIT_BITS;
+        config.genesis.premine = vec!["not hex".to_string()];
+        let err = config.genesis_block().unwrap_err();
+        assert_eq!(err.to_string(), "genesis.premine output 'not hex': odd number of hex digits (7)");
+    }
+
+    #[test]
+    fn test_default_config_roundtrips() {
+        let content = toml::to_string_pretty(&NodeConfig::default()).unwrap();
+        let config = NodeConfig::parse(&content).expect("Default config should parse");
+        assert_eq!(config.network.rpc_port, NodeConfig::default().network.rpc_port);
+    }
+}
@@ -0,0 +1,29 @@
+use hyperion_core::crypto::HASH_SIZE;
+
+use tokio::sync::broadcast;
+
+
+/// Events buffered per subscriber before the slowest one starts missing them
+const EVENT_CAPACITY: usize = 1024;
+
+/// Changes to the node's chain or mempool, published for hooks that react to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    /// A block was added to the tip of the chain
+    BlockConnected { hash: [u8; HASH_SIZE], height: u64 },
+    /// A block failed validation and was not added
+    BlockRejected { hash: [u8; HASH_SIZE] },
+    /// A transaction was accepted into the mempool
+    TxAccepted { txid: [u8; HASH_SIZE] },
+}
+
+pub type EventSender = broadcast::Sender<NodeEvent>;
+
+pub fn channel() -> EventSender {
+    broadcast::channel(EVENT_CAPACITY).0
+}
+
+/// Publish `event`; having no subscribers is not an error
+pub fn publish(events: &EventSender, event: NodeEvent) {
+    let _ = events.send(event);
+}
@@ -0,0 +1,474 @@
+//! The Hyperion full node. The binary parses the command line and sets up
+//! logging; everything from loading the chain to saving it again on
+//! shutdown lives here, so tests can run a node in-process.
+
+mod chain_export;
+mod chain_snapshot;
+mod chain_manager;
+#[cfg(test)]
+mod chain_ops;
+mod chain_writer;
+pub mod cli;
+pub mod config;
+mod events;
+mod utils;
+mod network;
+mod notify;
+mod storage;
+pub mod logging;
+mod mempool;
+mod metrics;
+mod reindex;
+mod rpc;
+mod status;
+mod testutil;
+mod txindex;
+
+use chain_manager::ChainManager;
+use chain_writer::{ChainWriter, FileStore};
+pub use chain_manager::TipSnapshot;
+use config::NodeConfig;
+use config::node_config::Network;
+use mempool::Mempool;
+use metrics::NodeMetrics;
+use network::{PeerManager, PeerManagerConfig};
+use network::banlist::BanList;
+use reindex::ReindexProgress;
+use rpc::NodeState;
+use rpc::access::RpcAccess;
+use rpc::health::{Lifecycle, Phase};
+use rpc::throttle::SubmitThrottle;
+use status::StatusReporter;
+use storage::DiskUsage;
+use txindex::TxIndex;
+
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::crypto::{hex32, Hashable};
+
+use anyhow::{Context, Result, bail};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use tokio::task::JoinHandle;
+use tracing::{info, warn, error};
+
+/// How long shutdown waits on each of peers and RPC before moving on
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Startup options given on the command line rather than in the config
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Rebuild the chain and indexes by replaying stored blocks
+    pub reindex: bool,
+    /// Migrate a legacy chain file even when the config says not to
+    pub migrate_storage: bool,
+    /// Fill the mempool with this many generated transactions (regtest only)
+    pub fill_mempool: Option<usize>,
+}
+
+/// A node ready to start
+pub struct Node {
+    config: NodeConfig,
+    options: RunOptions,
+}
+
+/// A running node, serving RPC and peers until shut down
+pub struct NodeHandle {
+    config: Arc<NodeConfig>,
+    chain_manager: ChainManager,
+    chain_writer: ChainWriter,
+    peers: PeerManager,
+    txindex: Option<Arc<RwLock<TxIndex>>>,
+    progress: Arc<ReindexProgress>,
+    shutdown: broadcast::Sender<()>,
+    /// Subscribed at startup, so a shutdown triggered at any point is seen
+    shutdown_rx: broadcast::Receiver<()>,
+    lifecycle: Arc<Lifecycle>,
+    rpc_task: JoinHandle<()>,
+    rpc_addr: SocketAddr,
+    p2p_addrs: Vec<SocketAddr>,
+}
+
+/// Starts the shutdown of a running node from anywhere
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sender: broadcast::Sender<()>,
+}
+
+impl ShutdownHandle {
+    pub fn trigger(&self) {
+        let _ = self.sender.send(());
+    }
+}
+
+/// Run a node with `config` until Ctrl+C or SIGTERM
+pub async fn run_with_config(config: NodeConfig, options: RunOptions) -> Result<()> {
+    let node = Node::new(config).with_options(options).start().await?;
+    let shutdown = node.shutdown_handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown.trigger();
+    });
+
+    info!("Press Ctrl+C to stop");
+    node.wait().await;
+    Ok(())
+}
+
+impl Node {
+    pub fn new(config: NodeConfig) -> Self {
+        Self { config, options: RunOptions::default() }
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Load the chain, bind RPC and the P2P listeners and start serving.
+    /// Port 0 in either address binds an ephemeral port, which the
+    /// handle's `rpc_addr` and `p2p_addrs` then report.
+    pub async fn start(self) -> Result<NodeHandle> {
+        let Node { config, options } = self;
+        info!("Staring Hyperion Node...");
+        info!("Network: {}, data directory: {}", config.chain.as_str(), config.chain_dir().display());
+
+        // Starting anyway would leave a datadir other than the one asked for
+        if let Some(prune) = config.storage.prune {
+            if config.storage.txindex {
+                bail!("--txindex is incompatible with --prune, the index needs every block");
+            }
+            bail!("Pruning is not supported yet, remove --prune {} (storage.prune)", prune);
+        }
+
+        let genesis = config.genesis_block().context("Invalid genesis configuration")?;
+        let genesis_hash = genesis.double_sha256();
+        if config.chain == Network::Custom {
+            // A changed [genesis] would start a different chain on top of this one's data
+            match storage::load_genesis_hash(&config.chain_dir()) {
+                Ok(stored) if stored != genesis_hash => bail!(
+                    "Genesis {} doesn't match {} this data directory was started with; check [genesis]",
+                    hex32(&genesis_hash),
+                    hex32(&stored)
+                ),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    storage::save_genesis_hash(&genesis_hash, &config.chain_dir())
+                        .context("Failed to save genesis hash")?;
+                }
+                Err(e) => bail!("Failed to read genesis hash: {}", e),
+            }
+        }
+
+        // Chain files from before the versioned format are rewritten before loading
+        if storage::chain_is_legacy(&config.chain_dir()).context("Failed to read chain file")? {
+            if !config.storage.auto_migrate && !options.migrate_storage {
+                bail!("Chain file is in the legacy format, restart with --migrate-storage to migrate it");
+            }
+            let summary = storage::migrate_chain(&config.chain_dir(), config.chain.consensus_params())
+                .context("Failed to migrate legacy chain file")?;
+            info!(
+                "Migrated {} blocks to chain format version {}, tip {}, original kept at {}",
+                summary.blocks,
+                storage::CHAIN_FORMAT_VERSION,
+                hex32(&summary.tip_hash),
+                summary.backup.display()
+            );
+        }
+
+        // Load blockchain and mempool. Only a missing chain file starts a
+        // new one; anything else would be overwritten on the next write.
+        let stored_chain = match storage::load_chain(&config.chain_dir()) {
+            Ok(chain) => chain,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No chain on disk, starting from genesis");
+                Blockchain::new(genesis)
+            }
+            Err(e) => bail!("Failed to load chain from disk: {}", e),
+        };
+        let stored_genesis = stored_chain.get_block_by_height(0).unwrap().double_sha256();
+        if stored_genesis != genesis_hash {
+            bail!(
+                "Stored chain starts at genesis {}, not this network's {}",
+                hex32(&stored_genesis),
+                hex32(&genesis_hash)
+            );
+        }
+
+        // A reindex replays the stored blocks in the background, so start from
+        // their genesis and let RPC report progress meanwhile
+        let progress = Arc::new(ReindexProgress::default());
+        let (mut chain, reindex_blocks) = if options.reindex {
+            storage::remove_txindex(&config.chain_dir()).context("Failed to remove transaction index")?;
+
+            let blocks: Vec<_> = stored_chain.iter().cloned().collect();
+            progress.start(blocks.len() as u64);
+            (Blockchain::new(blocks[0].clone()), Some(blocks))
+        } else {
+            (stored_chain, None)
+        };
+
+        let txindex = if config.storage.txindex {
+            let index = if reindex_blocks.is_some() {
+                TxIndex::new()
+            } else {
+                match storage::load_txindex(&config.chain_dir()) {
+                    Ok(index) => index,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => TxIndex::new(),
+                    Err(e) => bail!("Failed to load transaction index: {}, restart with --reindex to rebuild it", e),
+                }
+            };
+            Some(Arc::new(RwLock::new(index)))
+        } else {
+            None
+        };
+
+        let mut mempool = Mempool::load(&config.mempool, &config.chain_dir());
+
+        info!("Genesis Block: {}", hex32(&genesis_hash));
+
+        if let Some(count) = options.fill_mempool {
+            if config.chain != Network::Regtest {
+                bail!("--regtest-fill-mempool is only allowed on regtest");
+            }
+
+            let added = testutil::fill_mempool(&mut mempool, count);
+            info!("Added {} test transactions to mempool", added);
+        }
+
+        chain.set_params(config.chain.consensus_params());
+        let chain_manager = ChainManager::with_recent_blocks(chain, mempool, config.mempool.recent_txid_blocks);
+
+        let events = events::channel();
+        if let Some(command) = &config.block_notify {
+            notify::spawn_hook("block_notify", command.clone(), events.subscribe(), notify::block_hash);
+        }
+        if let Some(command) = &config.tx_notify {
+            notify::spawn_hook("tx_notify", command.clone(), events.subscribe(), notify::txid);
+        }
+
+        if let Some(txindex) = &txindex {
+            if reindex_blocks.is_none() {
+                let indexed = txindex.write().await.catch_up(&*chain_manager.chain().await);
+                info!("Transaction index caught up with {} blocks", indexed);
+            }
+            txindex::spawn_updater(txindex.clone(), chain_manager.clone(), events.subscribe());
+        }
+
+        let rpc_access = RpcAccess::new(&config.network)?;
+
+        let metrics = Arc::new(NodeMetrics::default());
+        metrics::spawn_event_counter(metrics.clone(), events.subscribe());
+
+        // Bans from earlier runs apply before the listener accepts anyone
+        let bans = match storage::load_banlist(&config.chain_dir()) {
+            Ok(bans) => bans,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BanList::new(),
+            Err(e) => {
+                warn!("Failed to load ban list: {}, starting with no bans", e);
+                BanList::new()
+            }
+        };
+        let chain_writer = ChainWriter::spawn(
+            chain_manager.clone(),
+            FileStore(config.chain_dir()),
+            metrics.clone(),
+            config.storage.sync_mode,
+            config.storage.write_queue_len,
+        );
+        let peers = PeerManager::new(PeerManagerConfig::from_network(&config.network), chain_manager.clone(), events.clone())
+            .with_ban_list(bans, config.chain_dir())
+            .with_chain_writer(chain_writer.clone());
+        let (shutdown, shutdown_rx) = broadcast::channel(1);
+
+        // Bind before anything else is spawned so a bad address or port in use stops startup
+        let p2p_addrs = network::start_listeners(&config, &peers, &shutdown)
+            .context("Failed to start P2P listener")?;
+        let rpc_listener = tokio::net::TcpListener::bind(config.rpc_addr()).await
+            .with_context(|| format!("Failed to bind RPC server to {}", config.rpc_addr()))?;
+        let rpc_addr = rpc_listener.local_addr()?;
+
+        if config.network.p2p_enabled {
+            if config.network.connect.is_empty() {
+                peers.add_addresses(config.network.peers.iter().copied()).await;
+
+                // Top up outbound connections from the address book and seeds
+                tokio::spawn(peers.clone().maintain_outbound(network::SystemResolver));
+            } else {
+                for addr in config.network.connect.clone() {
+                    let peers = peers.clone();
+                    tokio::spawn(async move {
+                        if peers.connect(addr).await.is_none() {
+                            warn!("Failed to connect to {}", addr);
+                        }
+                    });
+                }
+            }
+        }
+
+        // Start RPC server. `/health` reports the node as starting until
+        // the rest of startup is done.
+        let config = Arc::new(config);
+        let lifecycle = Arc::new(Lifecycle::default());
+        let rpc_state = NodeState {
+            chain_manager: chain_manager.clone(),
+            chain_writer: chain_writer.clone(),
+            peers: peers.clone(),
+            config: config.clone(),
+            p2p_addrs: p2p_addrs.clone(),
+            shutdown: shutdown.clone(),
+            txindex: txindex.clone(),
+            reindex: progress.clone(),
+            events,
+            submit_throttle: Arc::new(SubmitThrottle::new(
+                config.network.rpc_max_bad_blocks,
+                config.network.rpc_bad_block_window(),
+            )),
+            rpc_access: Arc::new(rpc_access),
+            lifecycle: lifecycle.clone(),
+            disk_usage: Arc::new(DiskUsage::new(config.chain_dir())),
+        };
+
+        let rpc_server = rpc::server::serve(rpc_listener, rpc_state);
+        let rpc_task = tokio::spawn(async move {
+            if let Err(e) = rpc_server.await {
+                error!("RPC server error: {}", e);
+            }
+        });
+
+        if let Some(blocks) = reindex_blocks {
+            tokio::spawn(reindex::reindex(chain_manager.clone(), blocks, txindex.clone(), progress.clone()));
+        }
+
+        // Drop transactions that have sat in the pool past their expiry
+        {
+            let chain_manager = chain_manager.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    let expired = chain_manager.expire_mempool().await;
+                    if expired > 0 {
+                        info!("Expired {} transactions from mempool", expired);
+                    }
+                }
+            });
+        }
+
+        if config.logging.summary && config.logging.summary_interval_secs > 0 {
+            StatusReporter::new(chain_manager.clone(), peers.clone(), metrics.clone())
+                .spawn(config.logging.summary_interval());
+        }
+
+        info!("RPC server listening on {}", rpc_addr);
+        lifecycle.set(Phase::Running);
+
+        Ok(NodeHandle {
+            config,
+            chain_manager,
+            chain_writer,
+            peers,
+            txindex,
+            progress,
+            shutdown,
+            shutdown_rx,
+            lifecycle,
+            rpc_task,
+            rpc_addr,
+            p2p_addrs,
+        })
+    }
+}
+
+impl NodeHandle {
+    /// Address the RPC server is bound to
+    pub fn rpc_addr(&self) -> SocketAddr {
+        self.rpc_addr
+    }
+
+    /// Addresses the P2P listener is bound to, empty when P2P is disabled
+    pub fn p2p_addrs(&self) -> &[SocketAddr] {
+        &self.p2p_addrs
+    }
+
+    /// The active chain's tip
+    pub fn tip(&self) -> Arc<TipSnapshot> {
+        self.chain_manager.tip()
+    }
+
+    /// Transactions in the mempool
+    pub async fn mempool_size(&self) -> usize {
+        self.chain_manager.mempool().await.len()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle { sender: self.shutdown.clone() }
+    }
+
+    /// Shut down now, as `wait` once triggered
+    pub async fn stop(self) {
+        self.shutdown_handle().trigger();
+        self.wait().await;
+    }
+
+    /// Serve until a shutdown is triggered, then stop serving and save the
+    /// chain, transaction index and mempool
+    pub async fn wait(mut self) {
+        let _ = self.shutdown_rx.recv().await;
+        self.lifecycle.set(Phase::ShuttingDown);
+        info!("Shutting down Hyperion Node...");
+        let config = &self.config;
+
+        // RPC and the P2P listeners stop taking new work as the shutdown
+        // fires, so let requests and peers wind down
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.peers.close_all()).await.is_err() {
+            warn!("Timed out waiting for peers to disconnect");
+        }
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.rpc_task).await.is_err() {
+            warn!("Timed out waiting for RPC requests to finish");
+        }
+
+        // An unfinished reindex leaves a partial chain; the stored one is still complete
+        if self.progress.is_active() {
+            warn!("Reindex interrupted, run with --reindex again to finish it");
+        } else {
+            // Writes still queued go first, then one for blocks connected
+            // without queueing one, such as a reindex's; the writer logs any failure
+            let _ = self.chain_writer.write().await;
+            let _ = self.chain_writer.flush().await;
+            if let Some(txindex) = &self.txindex
+                && let Err(e) = storage::save_txindex(&*txindex.read().await, &config.chain_dir()) {
+                error!("Failed to save transaction index to disk: {}", e);
+            }
+        }
+        if let Err(e) = self.chain_manager.mempool().await.save(&config.chain_dir()) {
+            error!("Failed to save mempool to disk: {}", e);
+        }
+
+        info!("Node stopped.");
+    }
+}
+
+/// Resolve on Ctrl+C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
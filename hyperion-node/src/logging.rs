@@ -0,0 +1,209 @@
+use crate::config::NodeConfig;
+use crate::config::node_config::{ConsoleFormat, LoggingConfig};
+use crate::storage;
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{Subscriber, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_rolling_file::RollingFileAppender;
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
+use tracing_subscriber::layer::Layered;
+
+
+type BoxedLayer = Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>;
+
+/// Install the global subscriber described by `config`, with the log
+/// directory resolved against `datadir`. Keep the returned guard alive until
+/// exit so buffered file output is flushed.
+pub fn init(config: &LoggingConfig, datadir: &Path) -> Result<Option<WorkerGuard>> {
+    let (subscriber, guard) = build_subscriber(config, datadir)?;
+    tracing::subscriber::set_global_default(subscriber)
+        .context("Failed to install log subscriber")?;
+    Ok(guard)
+}
+
+/// Echo the settings that matter most, then anything that looks like a mistake
+pub fn log_banner(config: &NodeConfig) {
+    let datadir = config.chain_dir();
+    let free_disk_mb = storage::free_space(&datadir).ok().map(|bytes| bytes / (1024 * 1024));
+    let p2p = if config.network.p2p_enabled {
+        format!("{:?}", config.network.p2p_listen)
+    } else {
+        "disabled".to_string()
+    };
+
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        network = config.chain.as_str(),
+        datadir = %datadir.display(),
+        free_disk_mb,
+        rpc = %config.rpc_addr(),
+        rpc_auth = false,
+        p2p = %p2p,
+        txindex = config.storage.txindex,
+        prune = config.storage.prune,
+        "Hyperion node configuration"
+    );
+
+    for warning in config.sanity_report() {
+        warn!("{}", warning);
+    }
+}
+
+/// Subscriber for `config` without installing it
+pub fn build_subscriber(
+    config: &LoggingConfig,
+    datadir: &Path,
+) -> Result<(Box<dyn Subscriber + Send + Sync>, Option<WorkerGuard>)> {
+    // RUST_LOG replaces the configured levels entirely
+    let env_filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(filter_directives(config))
+            .context("Invalid log level in [logging]")?,
+    };
+
+    let console_layer: BoxedLayer = match config.console {
+        ConsoleFormat::Compact => tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_thread_ids(false)
+            .with_level(false)
+            .compact()
+            .boxed(),
+        ConsoleFormat::Pretty => tracing_subscriber::fmt::layer().pretty().boxed(),
+        ConsoleFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    };
+
+    let mut layers = vec![console_layer];
+
+    let guard = if config.file_enabled {
+        let dir = log_dir(config, datadir);
+        check_writable(&dir)?;
+
+        let file_appender = RollingFileAppender::builder()
+            .filename(dir.join(&config.file_name).to_string_lossy().into_owned())
+            .max_filecount(config.max_files)
+            .condition_max_file_size(config.max_file_size_mb * 1024 * 1024)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to create log file in {}: {}", dir.display(), e))?;
+        let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+
+        let layer: BoxedLayer = if config.json {
+            tracing_subscriber::fmt::layer()
+                .with_writer(file_writer)
+                .json()
+                .with_target(true)
+                .with_thread_ids(true)
+                .with_current_span(false)
+                .boxed()
+        } else {
+            tracing_subscriber::fmt::layer()
+                .with_writer(file_writer)
+                .with_ansi(false)
+                .with_target(true)
+                .with_thread_ids(true)
+                .boxed()
+        };
+        layers.push(layer);
+        Some(guard)
+    } else {
+        None
+    };
+
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(layers);
+
+    Ok((Box::new(subscriber), guard))
+}
+
+/// Filter directives for `config`: the default level for the hyperion crates,
+/// then any per-target overrides
+pub fn filter_directives(config: &LoggingConfig) -> String {
+    let mut directives = vec![
+        format!("hyperion_node={}", config.level),
+        format!("hyperion_core={}", config.level),
+    ];
+    directives.extend(config.targets.iter().map(|(target, level)| format!("{}={}", target, level)));
+    directives.join(",")
+}
+
+/// Where log files go; relative directories live under `datadir`
+pub fn log_dir(config: &LoggingConfig, datadir: &Path) -> PathBuf {
+    datadir.join(&config.dir)
+}
+
+/// Create `dir` if needed and make sure a file can be written in it
+fn check_writable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Log directory {} cannot be created", dir.display()))?;
+
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"")
+        .with_context(|| format!("Log directory {} is not writable", dir.display()))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_datadir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hyperion-logging-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_filter_directives_include_targets() {
+        let mut config = LoggingConfig { level: "warn".to_string(), ..Default::default() };
+        config.targets.insert("hyperion_node::network".to_string(), "debug".to_string());
+
+        assert_eq!(
+            filter_directives(&config),
+            "hyperion_node=warn,hyperion_core=warn,hyperion_node::network=debug",
+        );
+    }
+
+    #[test]
+    fn test_file_written_under_datadir() {
+        let datadir = temp_datadir("file");
+        let config = LoggingConfig { json: false, ..Default::default() };
+
+        let (subscriber, guard) = build_subscriber(&config, &datadir).expect("Failed to build subscriber");
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "hyperion_node", "hello from the test");
+        });
+        drop(guard);
+
+        let log = fs::read_to_string(log_dir(&config, &datadir).join(&config.file_name)).unwrap();
+        assert!(log.contains("hello from the test"), "{}", log);
+        assert!(!log.starts_with('{'), "plain text expected: {}", log);
+        fs::remove_dir_all(datadir).unwrap();
+    }
+
+    #[test]
+    fn test_file_disabled_has_no_writer() {
+        let datadir = temp_datadir("disabled");
+        let config = LoggingConfig { file_enabled: false, console: ConsoleFormat::Json, ..Default::default() };
+
+        let (_subscriber, guard) = build_subscriber(&config, &datadir).expect("Failed to build subscriber");
+        assert!(guard.is_none());
+        assert!(!log_dir(&config, &datadir).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unwritable_dir_rejected() {
+        let config = LoggingConfig { dir: PathBuf::from("/proc/hyperion-logs"), ..Default::default() };
+
+        let err = build_subscriber(&config, Path::new(".")).err().expect("Expected an error");
+        assert!(err.to_string().contains("/proc/hyperion-logs"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_level_rejected() {
+        let config = LoggingConfig { level: "loud".to_string(), file_enabled: false, ..Default::default() };
+        assert!(build_subscriber(&config, Path::new(".")).is_err());
+    }
+}
@@ -1,144 +1,35 @@
-mod utils;
-mod network;
-mod storage;
-mod mempool;
-mod rpc;
-
-use mempool::Mempool;
-use rpc::{NodeState, start_server};
-
-use hyperion_core::chain::blockchain::Blockchain;
-use hyperion_core::block::Transaction;
-use hyperion_core::crypto::Hashable;
-
-use std::sync::Arc;
-use hex;
-use tokio::sync::RwLock;
-use tracing::{info, warn, error, debug};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
-use tracing_appender::non_blocking;
-use tracing_rolling_file::RollingFileAppender;
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
+use hyperion_node::{cli, logging, RunOptions};
 
 #[tokio::main]
 async fn main() {
-    let _log_guard = init_logging().unwrap_or_else(|e| {
-        eprintln!("Failed to initialize logging: {}", e);
+    let matches = cli::command().get_matches();
+    let config = cli::load_config(&matches).unwrap_or_else(|e| {
+        eprintln!("Failed to load configuration: {:#}", e);
         std::process::exit(1);
     });
 
-    info!("Staring Hyperion Node...");
-    
-    // Load blockchain and mempool
-    let chain = Arc::new(RwLock::new(
-        storage::load_chain().unwrap_or_else(|e| {
-            warn!("Failed to load chain from disk: {}, creating new genesis", e);
-            Blockchain::new_with_genesis()
-        })
-    ));
-
-    let mempool = Arc::new(RwLock::new(Mempool::load()));
-
-    info!("Genesis Block: {}", hex::encode(
-        chain.read().await.get_block_by_height(0).unwrap().double_sha256()
-    ));
-
-    // Add test transactions
-    {
-        let tx_count = 215;
-        let mut mempool_guard = mempool.write().await;
-        for i in 0..tx_count {
-            let tx = generate_random_tx(i);
-            mempool_guard.add_tx(tx);
-        }
-        info!("Added {} test transactions to mempool", tx_count);
-    }
-
-    // Start RPC server
-    let rpc_state = NodeState {
-        chain: chain.clone(),
-        mempool: mempool.clone(),
-    };
-    
-    tokio::spawn(async move {
-        if let Err(e) = start_server(rpc_state, 6001).await {
-            error!("RPC server error: {}", e);
-        }
-    });
-
-    // Start network listener asynchronously
-    tokio::spawn(async move {
-        network::start_network_listener("127.0.0.1:6000").await; // Changed port to 6000
+    let log_guard = logging::init(&config.logging, &config.storage.datadir).unwrap_or_else(|e| {
+        eprintln!("Failed to initialize logging: {:#}", e);
+        std::process::exit(1);
     });
 
-    info!("RPC server listening on 127.0.0.1:6001");
-    info!("P2P listener on 127.0.0.1:6000");
-    info!("Press Ctrl+C to stop");
-    
-    // Wait for Ctrl+C
-    tokio::signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
-    info!("Shutting down Hyperion Node...");
-
-    if let Err(e) = storage::save_chain(&*chain.read().await) {
-        error!("Failed to save blockchain to disk: {}", e);
-    }
-
-    info!("Node stopped.");
-}
-
-fn generate_random_tx(seed: i32) -> Transaction {
-    let mut rng = StdRng::seed_from_u64(seed as u64);
-    
-    let num_inputs = rng.random_range(1..=3);
-    let num_outputs = rng.random_range(1..=3);
+    logging::log_banner(&config);
 
-    let mut inputs = Vec::new();
-    for i in 0..num_inputs {
-        inputs.push(format!("in{}_{}", i, rng.random::<u32>()).into_bytes());
+    let options = RunOptions {
+        reindex: matches.get_flag("reindex"),
+        migrate_storage: matches.get_flag("migrate-storage"),
+        fill_mempool: matches.get_one::<usize>("regtest-fill-mempool").copied(),
+    };
+    let result = hyperion_node::run_with_config(config, options).await;
+    if let Err(e) = &result {
+        tracing::error!("{:#}", e);
     }
 
-    let mut outputs = Vec::new();
-    for i in 0..num_outputs {
-        outputs.push(format!("out{}_{}", i, rng.random::<u32>()).into_bytes());
+    // Last, so everything logged above is flushed to the file
+    drop(log_guard);
+    if result.is_err() {
+        std::process::exit(1);
     }
-
-    Transaction::new(inputs, outputs).unwrap()
-}
-
-fn init_logging() -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
-    let file_appender = RollingFileAppender::builder()
-        .filename("logs/hyperion-node.log".to_string())
-        .max_filecount(9)
-        .condition_max_file_size(10 * 1024 * 1024)
-        .build()
-        .map_err(|e| anyhow::anyhow!("Failed to create file appender: {}", e))?;
-
-    let (file_writer, guard) = non_blocking(file_appender);
-
-    let console_layer = tracing_subscriber::fmt::layer()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_level(false)
-        .compact();
-
-    let file_layer = tracing_subscriber::fmt::layer()
-        .with_writer(file_writer)
-        .json()
-        .with_target(true)
-        .with_thread_ids(true)
-        .with_current_span(false);
-
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("hyperion_node=info,hyperion_core=info"));
-
-    Registry::default()
-        .with(env_filter)
-        .with(console_layer)
-        .with(file_layer)
-        .init();
-
-    Ok(guard)
 }
 
 // fn print_block_details(block: &Block) {
@@ -1,51 +1,525 @@
-use hyperion_core::{block::Transaction, crypto::Hashable};
+use crate::config::node_config::MempoolConfig;
+use crate::storage;
 
+use hyperion_core::{block::{Serializable, Transaction}, crypto::{HASH_SIZE, Hashable}};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+type Txid = [u8; HASH_SIZE];
+
+/// Txid of the transaction an input spends an output of. Inputs are opaque
+/// bytes with no outpoint type, so this relies on the convention that a
+/// spending input starts with the txid it spends; shorter inputs spend
+/// nothing that could be pooled. An input that starts with a pooled txid by
+/// chance only makes its transaction wait for that one in templates.
+fn spent_txid(input: &[u8]) -> Option<Txid> {
+    input.get(..HASH_SIZE)?.try_into().ok()
+}
+
+/// Pooled transactions, oldest first, with the dependencies between them:
+/// a transaction spending an output of another pooled transaction is its
+/// child, and is only ever templated after it.
 pub struct Mempool {
-    pub txs: Vec<Transaction>,
+    txs: Vec<Transaction>,
+    /// When each pooled transaction arrived, by txid
+    added: HashMap<Txid, Instant>,
+    /// Pooled transactions each pooled transaction spends from, by txid
+    parents: HashMap<Txid, Vec<Txid>>,
+    /// Pooled transactions by the txids their inputs name, pooled or not, so
+    /// a parent pooled after its child (as a disconnected block's are) still
+    /// finds it
+    spenders: HashMap<Txid, Vec<Txid>>,
+    /// Serialized size of every pooled transaction, kept up to date by
+    /// every call that adds or removes one
+    bytes: usize,
+    max_txs: usize,
+    expiry: Duration,
+    /// Bumped once by every call that changes the pooled transactions
+    generation: u64,
 }
 
 impl Mempool {
+    #[cfg(test)]
     pub fn new() -> Self {
-        Self { txs: vec![] }
+        Self::with_config(&MempoolConfig::default())
+    }
+
+    pub fn with_config(config: &MempoolConfig) -> Self {
+        Self {
+            txs: vec![],
+            added: HashMap::new(),
+            parents: HashMap::new(),
+            spenders: HashMap::new(),
+            bytes: 0,
+            max_txs: config.max_txs,
+            expiry: config.expiry(),
+            generation: 0,
+        }
     }
 
-    pub fn add_tx(&mut self, tx: Transaction) {
+    /// Add `tx` to the pool, returning false if the pool is full or
+    /// already holds it
+    pub fn add_tx(&mut self, tx: Transaction) -> bool {
+        let txid = tx.double_sha256();
+        if self.txs.len() >= self.max_txs || self.added.contains_key(&txid) {
+            return false;
+        }
+
+        self.link(txid, &tx);
+        self.added.insert(txid, Instant::now());
+        self.bytes += tx.byte_size();
         self.txs.push(tx);
+        self.generation += 1;
+        true
+    }
+
+    pub fn contains(&self, tx: &Transaction) -> bool {
+        self.added.contains_key(&tx.double_sha256())
     }
 
+    /// Remove `tx`, as when a block confirms it. Its children stay pooled,
+    /// no longer waiting on it.
     pub fn remove_tx(&mut self, tx_to_remove: &Transaction) {
         let target_hash = tx_to_remove.double_sha256();
+        if self.added.remove(&target_hash).is_none() {
+            return;
+        }
+
         self.txs.retain(|existing_tx| {
             existing_tx.double_sha256() != target_hash
         });
+        self.unlink(target_hash, tx_to_remove);
+        self.bytes -= tx_to_remove.byte_size();
+        self.generation += 1;
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.txs.is_empty()
+    /// Remove every pooled transaction
+    pub fn clear(&mut self) {
+        if self.txs.is_empty() {
+            return;
+        }
+
+        self.txs.clear();
+        self.added.clear();
+        self.parents.clear();
+        self.spenders.clear();
+        self.bytes = 0;
+        self.generation += 1;
     }
 
-    pub fn get_next_transaction(&mut self, n: usize) -> Option<Vec<Transaction>> {
-        if self.txs.is_empty() {
-            return None;
+    /// Drop transactions that have been pooled longer than the expiry,
+    /// along with their descendants, which can't be mined without them.
+    /// Returns how many were removed.
+    pub fn expire(&mut self) -> usize {
+        let now = Instant::now();
+        let expiry = self.expiry;
+
+        let mut evicted: HashSet<_> = self.added
+            .iter()
+            .filter(|(_, at)| now.duration_since(**at) >= expiry)
+            .map(|(txid, _)| *txid)
+            .collect();
+        let descendants: Vec<_> = evicted.iter().flat_map(|txid| self.descendants(txid)).collect();
+        evicted.extend(descendants);
+        if evicted.is_empty() {
+            return 0;
+        }
+
+        let (gone, kept) = std::mem::take(&mut self.txs)
+            .into_iter()
+            .partition::<Vec<_>, _>(|tx| evicted.contains(&tx.double_sha256()));
+        self.txs = kept;
+        for tx in &gone {
+            let txid = tx.double_sha256();
+            self.added.remove(&txid);
+            self.unlink(txid, tx);
+            self.bytes -= tx.byte_size();
+        }
+
+        self.generation += 1;
+        gone.len()
+    }
+
+    /// Record the edges between `tx` and the pooled transactions it spends
+    /// from or that spend from it
+    fn link(&mut self, txid: Txid, tx: &Transaction) {
+        let spent: HashSet<_> = tx.inputs.iter().filter_map(|input| spent_txid(input)).collect();
+
+        let parents: Vec<_> = spent.iter().filter(|id| self.added.contains_key(*id)).copied().collect();
+        if !parents.is_empty() {
+            self.parents.insert(txid, parents);
+        }
+        for child in self.spenders.get(&txid).into_iter().flatten() {
+            self.parents.entry(*child).or_default().push(txid);
         }
+        for id in spent {
+            self.spenders.entry(id).or_default().push(txid);
+        }
+    }
+
+    fn unlink(&mut self, txid: Txid, tx: &Transaction) {
+        self.parents.remove(&txid);
+        for child in self.spenders.get(&txid).into_iter().flatten() {
+            if let Some(parents) = self.parents.get_mut(child) {
+                parents.retain(|parent| *parent != txid);
+                if parents.is_empty() {
+                    self.parents.remove(child);
+                }
+            }
+        }
+
+        for input in &tx.inputs {
+            if let Some(id) = spent_txid(input)
+                && let Some(spenders) = self.spenders.get_mut(&id) {
+                spenders.retain(|spender| *spender != txid);
+                if spenders.is_empty() {
+                    self.spenders.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Pooled transactions `txid` spends from directly
+    pub fn parents(&self, txid: &Txid) -> &[Txid] {
+        self.parents.get(txid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Pooled transactions spending from `txid` directly
+    pub fn children(&self, txid: &Txid) -> &[Txid] {
+        self.spenders.get(txid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every pooled transaction `txid` depends on, however indirectly
+    #[cfg(test)]
+    pub fn ancestors(&self, txid: &Txid) -> HashSet<Txid> {
+        Self::reachable(txid, |id| self.parents(id))
+    }
+
+    /// Every pooled transaction depending on `txid`, however indirectly
+    pub fn descendants(&self, txid: &Txid) -> HashSet<Txid> {
+        Self::reachable(txid, |id| self.children(id))
+    }
+
+    fn reachable<'a>(from: &Txid, next: impl Fn(&Txid) -> &'a [Txid]) -> HashSet<Txid> {
+        let mut found = HashSet::new();
+        let mut pending = next(from).to_vec();
+        while let Some(txid) = pending.pop() {
+            if found.insert(txid) {
+                pending.extend_from_slice(next(&txid));
+            }
+        }
+        found
+    }
+
+    /// Ancestor and descendant counts of every pooled transaction, by txid.
+    /// One pass over the dependency order, each transaction's ancestors
+    /// kept as a bitset over the positions before it, rather than a walk of
+    /// the graph per transaction.
+    pub fn dependency_counts(&self) -> HashMap<Txid, (usize, usize)> {
+        let order: Vec<Txid> = self.txs_by_dependency().iter().map(|tx| tx.double_sha256()).collect();
+        let position: HashMap<Txid, usize> = order.iter().enumerate().map(|(i, txid)| (*txid, i)).collect();
+        let mut ancestors: Vec<Vec<u64>> = Vec::with_capacity(order.len());
+        let mut descendants = vec![0; order.len()];
+
+        for txid in &order {
+            let mut bits: Vec<u64> = Vec::new();
+            for parent in self.parents(txid) {
+                let Some(&parent) = position.get(parent) else {
+                    continue;
+                };
+                let parent_bits = &ancestors[parent];
+                bits.resize(bits.len().max(parent_bits.len()).max(parent / 64 + 1), 0);
+                for (word, parent_word) in bits.iter_mut().zip(parent_bits) {
+                    *word |= parent_word;
+                }
+                bits[parent / 64] |= 1 << (parent % 64);
+            }
+            for (i, &word) in bits.iter().enumerate() {
+                let mut word = word;
+                while word != 0 {
+                    descendants[i * 64 + word.trailing_zeros() as usize] += 1;
+                    word &= word - 1;
+                }
+            }
+            ancestors.push(bits);
+        }
+
+        order
+            .into_iter()
+            .zip(ancestors.iter().zip(descendants))
+            .map(|(txid, (bits, descendants))| {
+                (txid, (bits.iter().map(|word| word.count_ones() as usize).sum(), descendants))
+            })
+            .collect()
+    }
+
+    /// Pooled transactions with every parent ahead of its children, and
+    /// otherwise oldest first. Any prefix of this order can be mined on its
+    /// own.
+    pub fn txs_by_dependency(&self) -> Vec<&Transaction> {
+        let by_txid: HashMap<_, _> = self.txs.iter().map(|tx| (tx.double_sha256(), tx)).collect();
+        let mut ordered = Vec::with_capacity(self.txs.len());
+        let mut placed = HashSet::new();
+
+        for tx in &self.txs {
+            // Place unplaced ancestors depth first, each after its own parents
+            let mut stack = vec![(tx.double_sha256(), false)];
+            while let Some((txid, parents_placed)) = stack.pop() {
+                if placed.contains(&txid) {
+                    continue;
+                }
+                if parents_placed {
+                    placed.insert(txid);
+                    ordered.push(by_txid[&txid]);
+                    continue;
+                }
+
+                stack.push((txid, true));
+                for parent in self.parents(&txid) {
+                    if !placed.contains(parent) {
+                        stack.push((*parent, false));
+                    }
+                }
+            }
+        }
+        ordered
+    }
 
-        let count = n.min(self.txs.len());
-        let txs: Vec<_> = self.txs.drain(..count).collect();
-        Some(txs)
+    /// Pooled transactions, oldest first
+    pub fn txs(&self) -> &[Transaction] {
+        &self.txs
+    }
+
+    /// Counter that moves on whenever the pooled transactions change, so
+    /// anything built from them can tell whether it is out of date
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
     }
 
     pub fn len(&self) -> usize {
         self.txs.len()
     }
 
+    /// Serialized size of every pooled transaction
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn max_txs(&self) -> usize {
+        self.max_txs
+    }
+
     /// Persist/load mempool
-    // TODO: Implement
-    pub fn save(&self) -> Result<(), std::io::Error> {
-        Ok(())
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        storage::save_mempool(&self.txs, dir)
+    }
+
+    /// Pool restored from `dir`, or an empty one if nothing was saved there.
+    /// Restored transactions count as freshly added for expiry.
+    pub fn load(config: &MempoolConfig, dir: &Path) -> Self {
+        let mut mempool = Self::with_config(config);
+
+        match storage::load_mempool(dir) {
+            Ok(txs) => {
+                for tx in txs {
+                    mempool.add_tx(tx);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to load mempool from disk: {}", e),
+        }
+
+        mempool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tx(i: u32) -> Transaction {
+        Transaction::new(vec![i.to_le_bytes().to_vec()], vec![b"out".to_vec()]).unwrap()
+    }
+
+    #[test]
+    fn test_full_pool_rejects_transactions() {
+        let mut mempool = Mempool::with_config(&MempoolConfig { max_txs: 2, ..Default::default() });
+
+        assert!(mempool.add_tx(make_tx(0)));
+        assert!(mempool.add_tx(make_tx(1)));
+        assert!(!mempool.add_tx(make_tx(2)));
+        assert_eq!(mempool.len(), 2);
+    }
+
+    #[test]
+    fn test_pooled_transaction_not_added_twice() {
+        let mut mempool = Mempool::new();
+        assert!(mempool.add_tx(make_tx(0)));
+        let generation = mempool.generation();
+
+        assert!(!mempool.add_tx(make_tx(0)));
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.generation(), generation);
+    }
+
+    #[test]
+    fn test_bytes_follow_pooled_transactions() {
+        let sizes = |txs: &[Transaction]| txs.iter().map(|tx| tx.serialize().unwrap().len()).sum::<usize>();
+        let mut mempool = Mempool::new();
+        let wide = Transaction::new(vec![vec![7; 300]], vec![b"out".to_vec()]).unwrap();
+        for tx in [make_tx(0), make_tx(1), wide.clone()] {
+            mempool.add_tx(tx);
+        }
+        assert!(!mempool.add_tx(make_tx(1)));
+        assert_eq!(mempool.bytes(), sizes(mempool.txs()));
+
+        mempool.remove_tx(&wide);
+        mempool.remove_tx(&wide);
+        assert_eq!(mempool.bytes(), sizes(&[make_tx(0), make_tx(1)]));
+        mempool.clear();
+        assert_eq!(mempool.bytes(), 0);
+
+        let mut mempool = Mempool::with_config(&MempoolConfig { expiry_hours: 0, ..Default::default() });
+        mempool.add_tx(wide);
+        mempool.expire();
+        assert_eq!(mempool.bytes(), 0);
+    }
+
+    #[test]
+    fn test_expired_transactions_dropped() {
+        let mut mempool = Mempool::with_config(&MempoolConfig { expiry_hours: 0, ..Default::default() });
+        mempool.add_tx(make_tx(0));
+        assert_eq!(mempool.expire(), 1);
+        assert!(mempool.is_empty());
+
+        let mut mempool = Mempool::new();
+        mempool.add_tx(make_tx(0));
+        assert_eq!(mempool.expire(), 0);
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_every_change_bumps_generation_once() {
+        let mut mempool = Mempool::with_config(&MempoolConfig { max_txs: 2, expiry_hours: 0, ..Default::default() });
+        assert_eq!(mempool.generation(), 0);
+
+        mempool.add_tx(make_tx(0));
+        assert_eq!(mempool.generation(), 1);
+        mempool.add_tx(make_tx(1));
+        assert_eq!(mempool.generation(), 2);
+        mempool.remove_tx(&make_tx(0));
+        assert_eq!(mempool.generation(), 3);
+        mempool.add_tx(make_tx(2));
+        assert_eq!(mempool.generation(), 4);
+        // Every expired transaction goes in one change
+        assert_eq!(mempool.expire(), 2);
+        assert_eq!(mempool.generation(), 5);
+
+        // Calls that change nothing leave it alone
+        mempool.remove_tx(&make_tx(0));
+        assert_eq!(mempool.expire(), 0);
+        mempool.add_tx(make_tx(3));
+        mempool.add_tx(make_tx(4));
+        assert!(!mempool.add_tx(make_tx(5)));
+        assert_eq!(mempool.generation(), 7);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("hyperion-mempool-{}", std::process::id()));
+        let mut mempool = Mempool::new();
+        mempool.add_tx(make_tx(0));
+        mempool.add_tx(make_tx(1));
+        mempool.save(&dir).unwrap();
+
+        let loaded = Mempool::load(&MempoolConfig::default(), &dir);
+        assert_eq!(loaded.txs, mempool.txs);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn spending(parent: &Transaction) -> Transaction {
+        Transaction::new(vec![parent.double_sha256().to_vec()], vec![b"out".to_vec()]).unwrap()
     }
 
-    pub fn load() -> Self {
-        // load from disk or default
-        Self::new()
+    #[test]
+    fn test_dependencies_tracked_in_either_order() {
+        let parent = make_tx(0);
+        let child = spending(&parent);
+        let grandchild = spending(&child);
+        let (parent_id, child_id, grandchild_id) =
+            (parent.double_sha256(), child.double_sha256(), grandchild.double_sha256());
+
+        let mut mempool = Mempool::new();
+        mempool.add_tx(grandchild.clone());
+        mempool.add_tx(make_tx(1));
+        assert!(mempool.parents(&grandchild_id).is_empty());
+        mempool.add_tx(parent.clone());
+        mempool.add_tx(child.clone());
+
+        assert_eq!(mempool.parents(&child_id), [parent_id]);
+        assert_eq!(mempool.children(&child_id), [grandchild_id]);
+        assert_eq!(mempool.ancestors(&grandchild_id), HashSet::from([parent_id, child_id]));
+        assert_eq!(mempool.descendants(&parent_id), HashSet::from([child_id, grandchild_id]));
+        assert_eq!(mempool.dependency_counts()[&parent_id], (0, 2));
+        assert_eq!(mempool.dependency_counts()[&child_id], (1, 1));
+        assert_eq!(mempool.dependency_counts()[&grandchild_id], (2, 0));
+        assert_eq!(
+            mempool.txs_by_dependency(),
+            vec![&parent, &child, &grandchild, &make_tx(1)]
+        );
+
+        // A confirmed parent frees its child
+        mempool.remove_tx(&parent);
+        assert!(mempool.parents(&child_id).is_empty());
+        assert_eq!(mempool.ancestors(&grandchild_id), HashSet::from([child_id]));
+    }
+
+    #[test]
+    fn test_dependency_counts_match_the_graph() {
+        // A diamond: two children of one root, both spent by a fourth
+        let root = make_tx(0);
+        let (left, right) = (spending(&root), Transaction::new(vec![root.double_sha256().to_vec()], vec![b"right".to_vec()]).unwrap());
+        let inputs = [&left, &right].map(|parent| parent.double_sha256().to_vec()).to_vec();
+        let join = Transaction::new(inputs, vec![b"out".to_vec()]).unwrap();
+
+        let mut mempool = Mempool::new();
+        for tx in [&join, &make_tx(1), &right, &root, &left] {
+            mempool.add_tx(tx.clone());
+        }
+        let counts = mempool.dependency_counts();
+        assert_eq!(counts.len(), 5);
+        for tx in mempool.txs() {
+            let txid = tx.double_sha256();
+            assert_eq!(counts[&txid], (mempool.ancestors(&txid).len(), mempool.descendants(&txid).len()));
+        }
+        assert_eq!(counts[&join.double_sha256()], (3, 0));
+        assert_eq!(counts[&root.double_sha256()], (0, 3));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_expiry_evicts_descendants() {
+        let parent = make_tx(0);
+        let child = spending(&parent);
+        let grandchild = spending(&child);
+
+        let mut mempool = Mempool::new();
+        for tx in [&parent, &child, &grandchild, &make_tx(1)] {
+            mempool.add_tx(tx.clone());
+        }
+        mempool.expiry = Duration::from_secs(1);
+        let stale = Instant::now().checked_sub(mempool.expiry).unwrap();
+        mempool.added.insert(parent.double_sha256(), stale);
+
+        assert_eq!(mempool.expire(), 3);
+        assert_eq!(mempool.txs(), [make_tx(1)]);
+        assert!(mempool.parents.is_empty() && mempool.spenders.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::events::NodeEvent;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+
+/// Node-wide counters, read by the status summary and RPC
+#[derive(Debug, Default)]
+pub struct NodeMetrics {
+    blocks_accepted: AtomicU64,
+    blocks_rejected: AtomicU64,
+    flushes: AtomicU64,
+    flush_failures: AtomicU64,
+    flush_micros: AtomicU64,
+}
+
+/// Point-in-time copy of `NodeMetrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub blocks_accepted: u64,
+    pub blocks_rejected: u64,
+    pub flushes: u64,
+    pub flush_failures: u64,
+    /// Total time spent writing the chain to disk
+    pub flush_time: Duration,
+}
+
+impl NodeMetrics {
+    pub fn record_flush(&self, ok: bool, elapsed: Duration) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.flush_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        self.flush_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            blocks_accepted: self.blocks_accepted.load(Ordering::Relaxed),
+            blocks_rejected: self.blocks_rejected.load(Ordering::Relaxed),
+            flushes: self.flushes.load(Ordering::Relaxed),
+            flush_failures: self.flush_failures.load(Ordering::Relaxed),
+            flush_time: Duration::from_micros(self.flush_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn record_event(&self, event: &NodeEvent) {
+        match event {
+            NodeEvent::BlockConnected { .. } => { self.blocks_accepted.fetch_add(1, Ordering::Relaxed); }
+            NodeEvent::BlockRejected { .. } => { self.blocks_rejected.fetch_add(1, Ordering::Relaxed); }
+            NodeEvent::TxAccepted { .. } => {}
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Counts accumulated between `earlier` and `self`
+    pub fn since(&self, earlier: &MetricsSnapshot) -> MetricsSnapshot {
+        MetricsSnapshot {
+            blocks_accepted: self.blocks_accepted - earlier.blocks_accepted,
+            blocks_rejected: self.blocks_rejected - earlier.blocks_rejected,
+            flushes: self.flushes - earlier.flushes,
+            flush_failures: self.flush_failures - earlier.flush_failures,
+            flush_time: self.flush_time - earlier.flush_time,
+        }
+    }
+}
+
+/// Count block events into `metrics` for as long as the node runs
+pub fn spawn_event_counter(metrics: Arc<NodeMetrics>, mut events: broadcast::Receiver<NodeEvent>) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => metrics.record_event(&event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+
+
+/// Byte and message totals in each direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub bytes_recv: u64,
+    pub bytes_sent: u64,
+    pub msgs_recv: u64,
+    pub msgs_sent: u64,
+}
+
+/// Lock-free traffic counters shared between a peer's reader and writer tasks
+#[derive(Debug, Default)]
+pub struct TrafficCounters {
+    bytes_recv: AtomicU64,
+    bytes_sent: AtomicU64,
+    msgs_recv: AtomicU64,
+    msgs_sent: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn record_recv(&self, bytes: usize) {
+        self.bytes_recv.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.msgs_recv.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.msgs_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TrafficStats {
+        TrafficStats {
+            bytes_recv: self.bytes_recv.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            msgs_recv: self.msgs_recv.load(Ordering::Relaxed),
+            msgs_sent: self.msgs_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Token bucket refilled at `rate` tokens per second up to `capacity`.
+///
+/// Taking a token from an empty bucket is allowed and puts it into debt;
+/// the caller is told how long to wait for the debt to be repaid.
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take one token, returning how long the caller should wait before using it
+    pub fn take(&mut self) -> Duration {
+        self.refill();
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_allows_burst_then_throttles() {
+        let mut bucket = TokenBucket::new(10.0, 5.0);
+
+        for _ in 0..5 {
+            assert_eq!(bucket.take(), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(), Duration::from_millis(100));
+        assert_eq!(bucket.take(), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refills_up_to_capacity() {
+        let mut bucket = TokenBucket::new(10.0, 5.0);
+        for _ in 0..5 {
+            bucket.take();
+        }
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        for _ in 0..5 {
+            assert_eq!(bucket.take(), Duration::ZERO);
+        }
+        assert!(bucket.take() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_traffic_counters() {
+        let counters = TrafficCounters::default();
+        counters.record_recv(100);
+        counters.record_recv(20);
+        counters.record_sent(7);
+
+        assert_eq!(counters.snapshot(), TrafficStats {
+            bytes_recv: 120,
+            bytes_sent: 7,
+            msgs_recv: 2,
+            msgs_sent: 1,
+        });
+    }
+}
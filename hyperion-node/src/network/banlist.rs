@@ -0,0 +1,52 @@
+use bincode::{Decode, Encode};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+
+/// Why and until when an address is banned
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct BanEntry {
+    pub reason: String,
+    /// Unix time the ban lifts
+    pub until: u64,
+}
+
+/// Addresses refused as peers, in either direction. Bans cover a whole IP
+/// address rather than a single port, and lapse on their own once expired.
+#[derive(Debug, Clone, Default, Encode, Decode)]
+pub struct BanList {
+    entries: BTreeMap<IpAddr, BanEntry>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ban `ip` until `until`, replacing any ban already in place
+    pub fn ban(&mut self, ip: IpAddr, reason: String, until: u64) {
+        self.entries.insert(ip, BanEntry { reason, until });
+    }
+
+    /// Lift the ban on `ip`, returning false if it wasn't banned
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.entries.remove(&ip).is_some()
+    }
+
+    pub fn is_banned(&self, ip: IpAddr, now: u64) -> bool {
+        self.entries.get(&ip).is_some_and(|entry| entry.until > now)
+    }
+
+    /// Bans still in force at `now`, by address
+    pub fn active(&self, now: u64) -> impl Iterator<Item = (IpAddr, &BanEntry)> {
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.until > now)
+            .map(|(ip, entry)| (*ip, entry))
+    }
+
+    /// Forget bans that have lapsed by `now`
+    pub fn sweep(&mut self, now: u64) {
+        self.entries.retain(|_, entry| entry.until > now);
+    }
+}
@@ -0,0 +1,150 @@
+use hyperion_core::block::{Block, Header, Transaction};
+use hyperion_core::crypto::{HASH_SIZE, Hashable};
+
+use bincode::{Decode, Encode};
+use std::collections::HashMap;
+
+
+/// Abbreviated txid used in compact block announcements
+pub type ShortId = u64;
+
+pub fn short_id(txid: &[u8; HASH_SIZE]) -> ShortId {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&txid[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Block announced as its header plus short txids, for peers that likely
+/// already hold most of its transactions in their mempool
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct CompactBlock {
+    /// Hash of the full block, checked once it has been reconstructed
+    pub block_hash: [u8; HASH_SIZE],
+    pub header: Header,
+    pub short_ids: Vec<ShortId>,
+}
+
+impl CompactBlock {
+    pub fn from_block(block: &Block) -> Self {
+        Self {
+            block_hash: block.double_sha256(),
+            header: block.header.clone(),
+            short_ids: block.transactions
+                .iter()
+                .map(|tx| short_id(&tx.double_sha256()))
+                .collect(),
+        }
+    }
+}
+
+/// Block being reassembled from a `CompactBlock`
+#[derive(Debug)]
+pub struct PartialBlock {
+    block_hash: [u8; HASH_SIZE],
+    header: Header,
+    txs: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    /// Fill in every transaction found in `pool`
+    pub fn new(compact: CompactBlock, pool: &[Transaction]) -> Self {
+        let by_short_id: HashMap<ShortId, &Transaction> = pool
+            .iter()
+            .map(|tx| (short_id(&tx.double_sha256()), tx))
+            .collect();
+
+        let txs = compact.short_ids
+            .iter()
+            .map(|id| by_short_id.get(id).map(|tx| (*tx).clone()))
+            .collect();
+
+        Self {
+            block_hash: compact.block_hash,
+            header: compact.header,
+            txs,
+        }
+    }
+
+    pub fn block_hash(&self) -> [u8; HASH_SIZE] {
+        self.block_hash
+    }
+
+    /// Indexes of the transactions still missing
+    pub fn missing(&self) -> Vec<u32> {
+        self.txs
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+
+    /// Fill the missing slots in order, returning false if `txs` doesn't match
+    /// the number of missing transactions
+    pub fn fill(&mut self, txs: Vec<Transaction>) -> bool {
+        let mut slots: Vec<_> = self.txs.iter_mut().filter(|tx| tx.is_none()).collect();
+        if slots.len() != txs.len() {
+            return false;
+        }
+
+        for (slot, tx) in slots.iter_mut().zip(txs) {
+            **slot = Some(tx);
+        }
+        true
+    }
+
+    /// The reconstructed block, if complete and matching the announced hash
+    pub fn into_block(self) -> Option<Block> {
+        let txs = self.txs.into_iter().collect::<Option<Vec<_>>>()?;
+        let block = Block::new(self.header, txs);
+
+        (block.double_sha256() == self.block_hash).then_some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::block::block::compute_merkle_root;
+
+    fn make_block(tx_count: u32) -> Block {
+        let txs: Vec<_> = (0..tx_count)
+            .map(|i| Transaction::new(vec![i.to_le_bytes().to_vec()], vec![b"out".to_vec()]).unwrap())
+            .collect();
+        let header = Header::new(1, 0, 0x207fffff, 0, [0u8; HASH_SIZE], compute_merkle_root(&txs));
+        Block::new(header, txs)
+    }
+
+    #[test]
+    fn test_reconstruct_from_full_pool() {
+        let block = make_block(5);
+        let partial = PartialBlock::new(CompactBlock::from_block(&block), &block.transactions);
+
+        assert!(partial.missing().is_empty());
+        assert_eq!(partial.into_block(), Some(block));
+    }
+
+    #[test]
+    fn test_reconstruct_with_missing_transactions() {
+        let block = make_block(5);
+        let pool = vec![block.transactions[0].clone(), block.transactions[3].clone()];
+        let mut partial = PartialBlock::new(CompactBlock::from_block(&block), &pool);
+
+        assert_eq!(partial.missing(), vec![1, 2, 4]);
+        assert!(!partial.fill(vec![block.transactions[1].clone()]));
+
+        let missing = [1, 2, 4].iter().map(|&i| block.transactions[i].clone()).collect();
+        assert!(partial.fill(missing));
+        assert_eq!(partial.into_block(), Some(block));
+    }
+
+    #[test]
+    fn test_wrong_transactions_fail_reconstruction() {
+        let block = make_block(3);
+        let mut partial = PartialBlock::new(CompactBlock::from_block(&block), &[]);
+
+        let other = make_block(4);
+        assert!(partial.fill(other.transactions[1..].to_vec()));
+        assert_eq!(partial.into_block(), None);
+    }
+}
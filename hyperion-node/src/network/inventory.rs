@@ -0,0 +1,124 @@
+use hyperion_core::crypto::HASH_SIZE;
+
+use bincode::{Decode, Encode};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+
+/// Reference to a relayable item by hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum InvItem {
+    Block([u8; HASH_SIZE]),
+    Tx([u8; HASH_SIZE]),
+}
+
+/// Bounded set of recently seen inventory.
+///
+/// Entries expire after `ttl`, and once `capacity` is reached the oldest
+/// entry is evicted to make room, so the set never grows unboundedly.
+pub struct SeenCache {
+    capacity: usize,
+    ttl: Duration,
+    order: VecDeque<(InvItem, Instant)>,
+    entries: HashMap<InvItem, Instant>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            order: VecDeque::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Record `item`, returning false if it was already present
+    pub fn insert(&mut self, item: InvItem) -> bool {
+        self.expire();
+        if self.entries.contains_key(&item) {
+            return false;
+        }
+
+        while self.entries.len() >= self.capacity {
+            match self.order.pop_front() {
+                Some((oldest, _)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        let now = Instant::now();
+        self.order.push_back((item, now));
+        self.entries.insert(item, now);
+        true
+    }
+
+    pub fn contains(&self, item: &InvItem) -> bool {
+        self.entries
+            .get(item)
+            .is_some_and(|inserted| inserted.elapsed() < self.ttl)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn expire(&mut self) {
+        while let Some((item, inserted)) = self.order.front() {
+            if inserted.elapsed() < self.ttl {
+                break;
+            }
+            self.entries.remove(item);
+            self.order.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_contains() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+
+        assert!(cache.insert(InvItem::Block([1u8; HASH_SIZE])));
+        assert!(!cache.insert(InvItem::Block([1u8; HASH_SIZE])));
+        assert!(cache.contains(&InvItem::Block([1u8; HASH_SIZE])));
+        assert!(!cache.contains(&InvItem::Tx([1u8; HASH_SIZE])));
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let mut cache = SeenCache::new(3, Duration::from_secs(60));
+        for i in 0..5u8 {
+            cache.insert(InvItem::Tx([i; HASH_SIZE]));
+        }
+
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains(&InvItem::Tx([0u8; HASH_SIZE])));
+        assert!(!cache.contains(&InvItem::Tx([1u8; HASH_SIZE])));
+        assert!(cache.contains(&InvItem::Tx([4u8; HASH_SIZE])));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_entries_expire() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+        cache.insert(InvItem::Block([1u8; HASH_SIZE]));
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        cache.insert(InvItem::Block([2u8; HASH_SIZE]));
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(!cache.contains(&InvItem::Block([1u8; HASH_SIZE])));
+        assert!(cache.contains(&InvItem::Block([2u8; HASH_SIZE])));
+
+        // Expired entries are dropped on the next insert and can be re-added
+        assert!(cache.insert(InvItem::Block([1u8; HASH_SIZE])));
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,146 @@
+use super::compact::CompactBlock;
+use super::inventory::InvItem;
+
+use hyperion_core::block::{Block, Transaction};
+use hyperion_core::crypto::HASH_SIZE;
+
+use bincode::{Decode, Encode, config::standard, decode_from_slice, encode_to_vec};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+
+/// Version of the peer-to-peer wire protocol; 2 added the genesis hash to `Version`
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Feature bit: peer accepts `CompactBlock` announcements
+pub const FEATURE_COMPACT_BLOCKS: u64 = 1 << 0;
+
+/// Every feature bit this version understands; other bits a peer
+/// advertises are ignored
+pub const KNOWN_FEATURES: u64 = FEATURE_COMPACT_BLOCKS;
+
+/// Largest frame payload we are willing to read from a peer
+pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Messages exchanged between peers.
+///
+/// Each message travels as a frame: a little-endian `u32` payload length
+/// followed by the bincode encoding of the message.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Message {
+    /// First message on every connection; `features` is a bitmask of
+    /// `FEATURE_*`. Peers whose `genesis_hash` differs follow another chain.
+    Version { protocol_version: u32, features: u64, nonce: u64, genesis_hash: [u8; HASH_SIZE] },
+    /// Acknowledges the remote `Version`
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    /// Announce items the sender has
+    Inv(Vec<InvItem>),
+    /// Request announced items
+    GetData(Vec<InvItem>),
+    Block(Block),
+    Tx(Transaction),
+    /// New block announced as header and short txids
+    CompactBlock(CompactBlock),
+    /// Request transactions of a compact block by index
+    GetBlockTxn { block_hash: [u8; HASH_SIZE], indexes: Vec<u32> },
+    /// Transactions answering a `GetBlockTxn`, in the requested order
+    BlockTxn { block_hash: [u8; HASH_SIZE], txs: Vec<Transaction> },
+    /// Sent just before closing a connection, with a human-readable reason
+    Disconnect(String),
+}
+
+impl Message {
+    /// Feature bits a peer must have negotiated to be sent this message;
+    /// zero for baseline messages every peer understands
+    pub fn required_features(&self) -> u64 {
+        match self {
+            Message::CompactBlock(_) | Message::GetBlockTxn { .. } | Message::BlockTxn { .. } => FEATURE_COMPACT_BLOCKS,
+            _ => 0,
+        }
+    }
+}
+
+/// Write a single framed message, returning the number of bytes written
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<usize> {
+    let payload = encode_to_vec(message, standard())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    writer.write_all(&(payload.len() as u32).to_le_bytes()).await?;
+    writer.write_all(&payload).await?;
+    writer.flush().await?;
+
+    Ok(4 + payload.len())
+}
+
+/// Read a single framed message
+#[cfg(test)]
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Message> {
+    read_frame(reader).await.map(|(message, _len)| message)
+}
+
+/// Read a single framed message along with the number of bytes it occupied
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(Message, usize)> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes).await?;
+
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds limit of {}", len, MAX_FRAME_SIZE),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    // The limit also bounds lengths claimed inside the payload, which bincode
+    // would otherwise allocate for up front
+    decode_from_slice(&payload, standard().with_limit::<MAX_FRAME_SIZE>())
+        .map(|(message, _len)| (message, 4 + len))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_message_roundtrip() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        let sent = Message::Version { protocol_version: PROTOCOL_VERSION, features: 0, nonce: 7, genesis_hash: [3; HASH_SIZE] };
+        write_message(&mut a, &sent).await.expect("Failed to write message");
+        write_message(&mut a, &Message::Ping(42)).await.expect("Failed to write ping");
+
+        assert_eq!(read_message(&mut b).await.expect("Failed to read message"), sent);
+        assert_eq!(read_message(&mut b).await.expect("Failed to read ping"), Message::Ping(42));
+    }
+
+    #[test]
+    fn test_only_compact_messages_need_features() {
+        let compact = Message::GetBlockTxn { block_hash: [0; HASH_SIZE], indexes: vec![0] };
+        assert_eq!(compact.required_features(), FEATURE_COMPACT_BLOCKS);
+        assert_eq!(Message::Inv(Vec::new()).required_features(), 0);
+        assert_eq!(Message::Ping(1).required_features(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_rejected() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        a.write_all(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes()).await.unwrap();
+        let err = read_message(&mut b).await.expect_err("Oversized frame should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Inv whose varint length claims 2^40 items in a 10-byte payload
+        let mut frame = vec![10, 0, 0, 0, 4, 0xfd];
+        frame.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        a.write_all(&frame).await.unwrap();
+
+        let err = read_message(&mut b).await.expect_err("Huge claimed length should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,217 @@
+pub mod bandwidth;
+pub mod banlist;
+pub mod compact;
+pub mod inventory;
+pub mod message;
+pub mod peer;
+pub mod peer_manager;
+pub mod seeds;
+
+pub use peer::Direction;
+pub use peer_manager::{PeerManager, PeerManagerConfig};
+pub use seeds::SystemResolver;
+
+use crate::config::NodeConfig;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+
+/// Bind a listener on every address in `addrs`. Failure here is fatal and
+/// is left to the caller.
+///
+/// An IPv6 address is bound dual-stack unless an IPv4 address with the same
+/// port is also listed, in which case it is restricted to IPv6 so the two
+/// don't collide.
+pub fn bind_listeners(addrs: &[SocketAddr]) -> io::Result<Vec<TcpListener>> {
+    addrs
+        .iter()
+        .map(|addr| {
+            let only_v6 = addr.is_ipv6()
+                && addrs.iter().any(|other| other.is_ipv4() && other.port() == addr.port());
+
+            let listener = bind_socket(*addr, only_v6)?;
+            info!("P2P listener on {}", listener.local_addr()?);
+            Ok(listener)
+        })
+        .collect()
+}
+
+fn bind_socket(addr: SocketAddr, only_v6: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(only_v6)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Bind and start the P2P listeners configured in `config`, returning the
+/// addresses actually bound (empty when P2P is disabled). The listeners stop
+/// accepting once `shutdown` fires.
+pub fn start_listeners(
+    config: &NodeConfig,
+    peers: &PeerManager,
+    shutdown: &broadcast::Sender<()>,
+) -> io::Result<Vec<SocketAddr>> {
+    if !config.network.p2p_enabled {
+        info!("P2P disabled, not listening for peers");
+        return Ok(Vec::new());
+    }
+
+    let listeners = bind_listeners(&config.network.p2p_listen)?;
+    let mut bound = Vec::with_capacity(listeners.len());
+
+    for listener in listeners {
+        bound.push(listener.local_addr()?);
+        tokio::spawn(accept_loop(listener, peers.clone(), shutdown.subscribe()));
+    }
+
+    Ok(bound)
+}
+
+/// Hand accepted connections to `peers` until `shutdown` fires, then close
+/// the listener.
+///
+/// A failed accept (e.g. out of file descriptors) is logged and retried
+/// after a short pause rather than ending the loop.
+pub async fn accept_loop(listener: TcpListener, peers: PeerManager, mut shutdown: broadcast::Receiver<()>) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.recv() => break,
+        };
+
+        match accepted {
+            Ok((socket, peer_addr)) => {
+                peers.add_peer(socket, peer_addr, Direction::Inbound).await;
+            }
+            Err(e) => {
+                warn!("Failed to accept P2P connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+
+    info!("Stopped accepting P2P connections on {:?}", listener.local_addr());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_manager::ChainManager;
+    use crate::mempool::Mempool;
+    use message::{Message, PROTOCOL_VERSION, read_message, write_message};
+
+    use hyperion_core::chain::blockchain::Blockchain;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    fn test_peers() -> PeerManager {
+        PeerManager::new(
+            PeerManagerConfig::default(),
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            crate::events::channel(),
+        )
+    }
+
+    async fn assert_handshake(addr: SocketAddr) {
+        let mut stream = TcpStream::connect(addr).await.expect("Failed to connect");
+        let Message::Version { genesis_hash, .. } = read_message(&mut stream).await.unwrap() else {
+            panic!("Expected a version");
+        };
+
+        // Claim the node's own chain
+        let version = Message::Version { protocol_version: PROTOCOL_VERSION, features: 0, nonce: 1, genesis_hash };
+        write_message(&mut stream, &version).await.unwrap();
+        write_message(&mut stream, &Message::Verack).await.unwrap();
+        assert_eq!(read_message(&mut stream).await.unwrap(), Message::Verack);
+    }
+
+    #[tokio::test]
+    async fn test_listener_survives_garbage() {
+        let mut listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).expect("Failed to bind");
+        let listener = listeners.remove(0);
+        let addr = listener.local_addr().unwrap();
+        let (shutdown, _) = broadcast::channel(1);
+        tokio::spawn(accept_loop(listener, test_peers(), shutdown.subscribe()));
+
+        // Oversized length prefix, a frame that doesn't decode, then an Inv
+        // claiming 2^40 items
+        let mut bad = TcpStream::connect(addr).await.expect("Failed to connect");
+        bad.write_all(&[0xff; 64]).await.unwrap();
+        let mut bad = TcpStream::connect(addr).await.expect("Failed to connect");
+        bad.write_all(&[4, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]).await.unwrap();
+        let mut bad = TcpStream::connect(addr).await.expect("Failed to connect");
+        bad.write_all(&[10, 0, 0, 0, 4, 0xfd, 0, 0, 0, 0, 0, 1, 0, 0]).await.unwrap();
+
+        assert_handshake(addr).await;
+    }
+
+    #[tokio::test]
+    async fn test_bind_failure_is_reported() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        assert!(bind_listeners(&[addr]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_listen_on_ipv6_loopback() {
+        let mut config = NodeConfig::default();
+        config.network.p2p_listen = vec!["[::1]:0".parse().unwrap()];
+
+        let (shutdown, _) = broadcast::channel(1);
+        let bound = start_listeners(&config, &test_peers(), &shutdown).expect("Failed to bind IPv6 loopback");
+        assert_eq!(bound.len(), 1);
+        assert!(bound[0].is_ipv6());
+        assert_ne!(bound[0].port(), 0);
+
+        assert_handshake(bound[0]).await;
+    }
+
+    #[tokio::test]
+    async fn test_p2p_disabled_does_not_listen() {
+        // Find a free port, then release it
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+        let mut config = NodeConfig::default();
+        config.network.p2p_enabled = false;
+        config.network.p2p_listen = vec![addr];
+
+        let bound = start_listeners(&config, &test_peers(), &broadcast::channel(1).0).expect("Disabled P2P should not fail");
+        assert!(bound.is_empty());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_listener_and_closes_peers() {
+        let peers = test_peers();
+        let (shutdown, _) = broadcast::channel(1);
+        let mut config = NodeConfig::default();
+        config.network.p2p_listen = vec!["127.0.0.1:0".parse().unwrap()];
+
+        let bound = start_listeners(&config, &peers, &shutdown).expect("Failed to bind");
+        let mut stream = TcpStream::connect(bound[0]).await.unwrap();
+        assert!(matches!(read_message(&mut stream).await.unwrap(), Message::Version { .. }));
+
+        shutdown.send(()).unwrap();
+        peers.close_all().await;
+
+        // The peer is told why before the connection closes
+        assert!(matches!(read_message(&mut stream).await.unwrap(), Message::Disconnect(_)));
+        assert!(read_message(&mut stream).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(TcpStream::connect(bound[0]).await.is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use super::bandwidth::{TokenBucket, TrafficCounters, TrafficStats};
+use super::message::{Message, PROTOCOL_VERSION, read_frame, write_message};
+use super::peer_manager::PeerManager;
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::{Instant, interval_at, sleep, sleep_until};
+use tracing::{debug, info, warn};
+
+
+pub type PeerId = u64;
+
+/// How long a closing connection may spend flushing queued messages
+const WRITER_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Point-in-time view of a connected peer
+#[derive(Debug, Clone)]
+pub struct PeerStats {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub direction: Direction,
+    pub connected_at: u32,
+    /// Protocol version announced by the peer, set once the handshake completes
+    pub protocol_version: Option<u32>,
+    /// Feature bits both sides advertised, set once the handshake completes
+    pub features: u64,
+    /// Round-trip time of the last answered ping
+    pub ping_latency: Option<Duration>,
+    pub traffic: TrafficStats,
+    /// Accumulated penalty points; the peer is dropped at `max_misbehavior`
+    pub misbehavior: u32,
+}
+
+/// Why a peer connection ended
+#[derive(Debug)]
+pub enum DisconnectReason {
+    Closed,
+    HandshakeTimeout,
+    SelfConnection,
+    PingTimeout,
+    /// Misbehavior score reached `max_misbehavior`
+    Misbehaving,
+    /// The peer's address was banned while it was connected
+    Banned,
+    /// The peer's `Version` names another genesis block
+    WrongGenesis,
+    /// The remote closed the connection with a `Disconnect`
+    Remote(String),
+    /// The local node is shutting down
+    Shutdown,
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Closed => write!(f, "connection closed"),
+            DisconnectReason::HandshakeTimeout => write!(f, "handshake timed out"),
+            DisconnectReason::SelfConnection => write!(f, "connected to self"),
+            DisconnectReason::WrongGenesis => write!(f, "different genesis block"),
+            DisconnectReason::PingTimeout => write!(f, "ping timeout"),
+            DisconnectReason::Misbehaving => write!(f, "misbehaving"),
+            DisconnectReason::Banned => write!(f, "banned"),
+            DisconnectReason::Remote(reason) => write!(f, "remote closed: {}", reason),
+            DisconnectReason::Shutdown => write!(f, "node shutting down"),
+        }
+    }
+}
+
+/// Misbehavior that adds to a peer's score, each class by its own
+/// configured penalty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offense {
+    /// A second spent throttled for exceeding `max_msgs_per_sec`
+    Flooding,
+    /// A block that fails proof of work or its merkle root
+    InvalidBlock,
+}
+
+/// Drive a single peer connection until it closes or is dropped.
+///
+/// The socket is split into a reader task (frames -> `inbound`), a writer task
+/// (`outbound` -> frames) and this loop, which owns the protocol state so the
+/// `select!` below never cancels a half-read frame. A reason sent on `kick`
+/// ends the connection from the manager's side.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn run_peer<S>(
+    manager: PeerManager,
+    id: PeerId,
+    addr: SocketAddr,
+    direction: Direction,
+    stream: S,
+    sender: mpsc::Sender<Message>,
+    mut outbound: mpsc::Receiver<Message>,
+    traffic: Arc<TrafficCounters>,
+    mut kick: oneshot::Receiver<DisconnectReason>,
+)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let (inbound_tx, mut inbound) = mpsc::channel(64);
+    let mut closing = manager.closing();
+
+    let total_traffic = manager.traffic();
+    let read_traffic = traffic.clone();
+    let read_total = total_traffic.clone();
+
+    let read_task = tokio::spawn(async move {
+        loop {
+            match read_frame(&mut reader).await {
+                Ok((message, len)) => {
+                    read_traffic.record_recv(len);
+                    read_total.record_recv(len);
+
+                    if inbound_tx.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    warn!("Read from peer {} failed: {}", addr, e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let write_task = tokio::spawn(async move {
+        while let Some(message) = outbound.recv().await {
+            match write_message(&mut writer, &message).await {
+                Ok(len) => {
+                    traffic.record_sent(len);
+                    total_traffic.record_sent(len);
+                }
+                Err(e) => {
+                    warn!("Write to peer {} failed: {}", addr, e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let reason = match handshake(&manager, id, &sender, &mut inbound, &mut closing, &mut kick).await {
+        Ok(()) => {
+            info!("Peer {} connected ({:?})", addr, direction);
+            manager.mark_established(id).await;
+            message_loop(&manager, id, addr, &sender, &mut inbound, &mut closing, &mut kick).await
+        }
+        Err(reason) => reason,
+    };
+
+    info!("Peer {} disconnected: {}", addr, reason);
+
+    if matches!(reason, DisconnectReason::Shutdown | DisconnectReason::Banned | DisconnectReason::WrongGenesis) {
+        let _ = sender.send(Message::Disconnect(reason.to_string())).await;
+    }
+
+    read_task.abort();
+    manager.remove_peer(id).await;
+
+    // With every sender gone the writer drains what is queued and exits; give
+    // it a moment so a final `Disconnect` reaches the peer
+    drop(sender);
+    let writer_handle = write_task.abort_handle();
+    if tokio::time::timeout(WRITER_FLUSH_TIMEOUT, write_task).await.is_err() {
+        writer_handle.abort();
+    }
+}
+
+async fn handshake(
+    manager: &PeerManager,
+    id: PeerId,
+    sender: &mpsc::Sender<Message>,
+    inbound: &mut mpsc::Receiver<Message>,
+    closing: &mut broadcast::Receiver<()>,
+    kick: &mut oneshot::Receiver<DisconnectReason>,
+) -> Result<(), DisconnectReason> {
+    let version = Message::Version {
+        protocol_version: PROTOCOL_VERSION,
+        features: manager.local_features(),
+        nonce: manager.local_nonce(),
+        genesis_hash: manager.genesis_hash(),
+    };
+    sender.send(version).await.map_err(|_| DisconnectReason::Closed)?;
+
+    let deadline = sleep(manager.config().handshake_timeout);
+    tokio::pin!(deadline);
+
+    let mut got_version = false;
+    let mut got_verack = false;
+
+    while !(got_version && got_verack) {
+        tokio::select! {
+            message = inbound.recv() => match message {
+                Some(Message::Version { protocol_version, features, nonce, genesis_hash }) => {
+                    if nonce == manager.local_nonce() {
+                        return Err(DisconnectReason::SelfConnection);
+                    }
+                    if genesis_hash != manager.genesis_hash() {
+                        return Err(DisconnectReason::WrongGenesis);
+                    }
+                    manager.set_peer_version(id, protocol_version, features).await;
+                    sender.send(Message::Verack).await.map_err(|_| DisconnectReason::Closed)?;
+                    got_version = true;
+                }
+                Some(Message::Verack) => got_verack = true,
+                Some(Message::Disconnect(reason)) => return Err(DisconnectReason::Remote(reason)),
+                Some(Message::Ping(nonce)) => {
+                    sender.send(Message::Pong(nonce)).await.map_err(|_| DisconnectReason::Closed)?;
+                }
+                Some(other) => debug!("Ignoring {:?} before handshake", other),
+                None => return Err(DisconnectReason::Closed),
+            },
+            _ = &mut deadline => return Err(DisconnectReason::HandshakeTimeout),
+            _ = closing.recv() => return Err(DisconnectReason::Shutdown),
+            Ok(reason) = &mut *kick => return Err(reason),
+        }
+    }
+
+    Ok(())
+}
+
+/// Dispatch peer messages to the manager while keeping the connection alive:
+/// answer pings, send our own every `ping_interval` and drop the peer once
+/// `max_missed_pings` consecutive pings go unanswered for `ping_timeout`.
+///
+/// Processing is rate limited by a per-peer token bucket; every full second
+/// spent throttled counts as an `Offense::Flooding`. A throttled message is
+/// held until its turn rather than slept on, so pings, kicks and shutdown
+/// aren't held up with it.
+#[allow(clippy::too_many_arguments)]
+async fn message_loop(
+    manager: &PeerManager,
+    id: PeerId,
+    addr: SocketAddr,
+    sender: &mpsc::Sender<Message>,
+    inbound: &mut mpsc::Receiver<Message>,
+    closing: &mut broadcast::Receiver<()>,
+    kick: &mut oneshot::Receiver<DisconnectReason>,
+) -> DisconnectReason {
+    let config = manager.config();
+    let mut ping_timer = interval_at(Instant::now() + config.ping_interval, config.ping_interval);
+
+    let mut pings = PingState { pending: None, missed: 0 };
+
+    let pong_deadline = sleep_until(Instant::now());
+    tokio::pin!(pong_deadline);
+
+    let mut bucket = TokenBucket::new(config.max_msgs_per_sec, config.msg_burst);
+    let mut throttled = Duration::ZERO;
+    // Message received while throttled, processed once `resume` passes
+    let mut held: Option<Option<Message>> = None;
+    let resume = sleep_until(Instant::now());
+    tokio::pin!(resume);
+
+    loop {
+        tokio::select! {
+            message = inbound.recv(), if held.is_none() => {
+                let wait = bucket.take();
+                if wait.is_zero() {
+                    if let Err(reason) = dispatch(manager, id, addr, sender, message, &mut pings).await {
+                        return reason;
+                    }
+                    continue;
+                }
+
+                held = Some(message);
+                resume.as_mut().reset(Instant::now() + wait);
+                throttled += wait;
+
+                let seconds = throttled.as_secs() as u32;
+                if seconds > 0 {
+                    throttled -= Duration::from_secs(seconds as u64);
+                    if manager.add_misbehavior(id, Offense::Flooding, seconds).await {
+                        return DisconnectReason::Misbehaving;
+                    }
+                }
+            }
+            _ = &mut resume, if held.is_some() => {
+                let message = held.take().flatten();
+                if let Err(reason) = dispatch(manager, id, addr, sender, message, &mut pings).await {
+                    return reason;
+                }
+            }
+            _ = ping_timer.tick() => {
+                let nonce = rand::random::<u64>();
+                if sender.send(Message::Ping(nonce)).await.is_err() {
+                    return DisconnectReason::Closed;
+                }
+                pings.pending = Some((nonce, Instant::now()));
+                pong_deadline.as_mut().reset(Instant::now() + config.ping_timeout);
+            }
+            _ = &mut pong_deadline, if pings.pending.is_some() => {
+                pings.pending = None;
+                pings.missed += 1;
+                warn!("Peer {} missed ping ({}/{})", addr, pings.missed, config.max_missed_pings);
+
+                if pings.missed >= config.max_missed_pings {
+                    return DisconnectReason::PingTimeout;
+                }
+            }
+            _ = closing.recv() => return DisconnectReason::Shutdown,
+            Ok(reason) = &mut *kick => return reason,
+        }
+    }
+}
+
+/// Keepalive state of a connection
+struct PingState {
+    /// (nonce, sent_at) of the ping we are waiting on
+    pending: Option<(u64, Instant)>,
+    missed: u32,
+}
+
+/// Handle one message from the peer; `None` means its reader has finished
+async fn dispatch(
+    manager: &PeerManager,
+    id: PeerId,
+    addr: SocketAddr,
+    sender: &mpsc::Sender<Message>,
+    message: Option<Message>,
+    pings: &mut PingState,
+) -> Result<(), DisconnectReason> {
+    match message {
+        Some(Message::Ping(nonce)) => {
+            sender.send(Message::Pong(nonce)).await.map_err(|_| DisconnectReason::Closed)?;
+        }
+        Some(Message::Pong(nonce)) => {
+            if let Some((expected, sent_at)) = pings.pending
+                && expected == nonce {
+                manager.set_ping_latency(id, sent_at.elapsed()).await;
+                pings.pending = None;
+                pings.missed = 0;
+            }
+        }
+        Some(Message::Version { .. } | Message::Verack) => {
+            debug!("Ignoring repeated handshake message from {}", addr);
+        }
+        Some(Message::Disconnect(reason)) => return Err(DisconnectReason::Remote(reason)),
+        Some(other) => manager.handle_message(id, other).await,
+        None => return Err(DisconnectReason::Closed),
+    }
+    Ok(())
+}
@@ -0,0 +1,1626 @@
+use super::bandwidth::{TrafficCounters, TrafficStats};
+use super::banlist::{BanEntry, BanList};
+use super::compact::{CompactBlock, PartialBlock};
+use super::inventory::{InvItem, SeenCache};
+use super::message::{FEATURE_COMPACT_BLOCKS, KNOWN_FEATURES, Message, write_message};
+use super::peer::{Direction, DisconnectReason, Offense, PeerId, PeerStats, run_peer};
+use super::seeds::{Resolver, SeedConfig, resolve_seeds};
+
+use crate::chain_manager::{BlockSource, ChainManager, ConnectError};
+use crate::chain_writer::ChainWriter;
+use crate::config::node_config::NetworkConfig;
+use crate::events::{self, EventSender, NodeEvent};
+use crate::{storage, utils};
+
+use hyperion_core::block::{Block, Transaction};
+use hyperion_core::crypto::{hex32, Hashable, HASH_SIZE};
+use hyperion_core::error::blockchain::BlockchainError;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::{RwLock, broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+
+/// Tunables for peer connections
+#[derive(Debug, Clone)]
+pub struct PeerManagerConfig {
+    /// How often each peer is pinged
+    pub ping_interval: Duration,
+    /// How long to wait for the matching pong
+    pub ping_timeout: Duration,
+    /// Consecutive unanswered pings before the peer is dropped
+    pub max_missed_pings: u32,
+    /// Time allowed for the version/verack exchange
+    pub handshake_timeout: Duration,
+    /// Upper bound on connections in either direction
+    pub max_connections: usize,
+    /// Upper bound on connections accepted from the listener
+    pub max_inbound: usize,
+    /// Upper bound on connections we open; these slots are held back from
+    /// inbound peers so the node can't be surrounded by inbound connections
+    pub max_outbound: usize,
+    /// Bootstrap addresses used when the address book can't fill outbound slots
+    pub seeds: SeedConfig,
+    /// How often outbound slots are topped up
+    pub outbound_interval: Duration,
+    /// Time allowed for an outbound TCP connect
+    pub connect_timeout: Duration,
+    /// Sustained rate of messages processed per peer
+    pub max_msgs_per_sec: f64,
+    /// Messages a peer may send in a burst above `max_msgs_per_sec`
+    pub msg_burst: f64,
+    /// Misbehavior score at which a peer is disconnected and banned
+    pub max_misbehavior: u32,
+    /// Points added per second a peer spends throttled
+    pub flooding_penalty: u32,
+    /// Points added for each invalid block a peer sends
+    pub invalid_block_penalty: u32,
+    /// How long a misbehaving peer stays banned
+    pub ban_duration: Duration,
+    /// Addresses never banned; misbehaving peers from them are only disconnected
+    pub whitelist: Vec<IpAddr>,
+    /// Announce blocks as `CompactBlock` to peers that support it
+    pub compact_blocks: bool,
+    /// Number of block hashes and txids remembered as already seen
+    pub seen_capacity: usize,
+    /// Number of items remembered per peer as already known to it
+    pub known_capacity: usize,
+    /// How long seen/known entries are remembered
+    pub seen_ttl: Duration,
+    /// How long a requested item may take to arrive before another peer
+    /// announcing it is asked instead
+    pub request_timeout: Duration,
+}
+
+impl Default for PeerManagerConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(120),
+            ping_timeout: Duration::from_secs(20),
+            max_missed_pings: 2,
+            handshake_timeout: Duration::from_secs(30),
+            max_connections: 125,
+            max_inbound: 117,
+            max_outbound: 8,
+            seeds: SeedConfig {
+                default_port: 6000,
+                ..Default::default()
+            },
+            outbound_interval: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            max_msgs_per_sec: 500.0,
+            msg_burst: 2_000.0,
+            max_misbehavior: 100,
+            flooding_penalty: 1,
+            invalid_block_penalty: 100,
+            ban_duration: Duration::from_secs(24 * 60 * 60),
+            whitelist: Vec::new(),
+            compact_blocks: true,
+            seen_capacity: 50_000,
+            known_capacity: 5_000,
+            seen_ttl: Duration::from_secs(20 * 60),
+            request_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PeerManagerConfig {
+    /// Defaults with the ban policy from the node's `[network]` settings
+    pub fn from_network(network: &NetworkConfig) -> Self {
+        Self {
+            max_misbehavior: network.ban_score,
+            flooding_penalty: network.flooding_penalty,
+            invalid_block_penalty: network.invalid_block_penalty,
+            ban_duration: network.ban_duration(),
+            whitelist: network.ban_whitelist.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn penalty(&self, offense: Offense) -> u32 {
+        match offense {
+            Offense::Flooding => self.flooding_penalty,
+            Offense::InvalidBlock => self.invalid_block_penalty,
+        }
+    }
+}
+
+struct PeerEntry {
+    stats: PeerStats,
+    traffic: Arc<TrafficCounters>,
+    sender: PeerSender,
+    /// Set once the version/verack exchange has completed
+    established: bool,
+    /// Items this peer announced to us or we announced to it
+    known: SeenCache,
+    /// Ends the connection; taken by the first `disconnect`
+    kick: Option<oneshot::Sender<DisconnectReason>>,
+}
+
+/// Outgoing queue of a peer that only accepts messages the peer can parse
+#[derive(Clone)]
+struct PeerSender {
+    queue: mpsc::Sender<Message>,
+    /// Feature bits negotiated in the handshake; none until it completes
+    features: u64,
+}
+
+impl PeerSender {
+    fn supports(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
+    /// Queue `message`, dropping it if the peer didn't negotiate a feature it needs
+    async fn send(&self, message: Message) {
+        if !self.supports(message.required_features()) {
+            debug!("Not sending {:?} to a peer without its feature bits", message);
+            return;
+        }
+        let _ = self.queue.send(message).await;
+    }
+}
+
+/// Item requested with a `GetData` that hasn't arrived yet
+struct InFlight {
+    from: PeerId,
+    requested_at: Instant,
+    /// Other peers that announced the item, asked if `from` goes away
+    alternates: Vec<PeerId>,
+}
+
+/// Compact block waiting on a `BlockTxn` from the peer that announced it
+struct PendingCompact {
+    from: PeerId,
+    partial: PartialBlock,
+    requested_at: Instant,
+}
+
+/// Number of live connections by direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionCounts {
+    pub inbound: usize,
+    pub outbound: usize,
+}
+
+impl ConnectionCounts {
+    pub fn total(&self) -> usize {
+        self.inbound + self.outbound
+    }
+}
+
+/// Tracks live peer connections and relays blocks and transactions between
+/// them; cheap to clone and share between tasks.
+#[derive(Clone)]
+pub struct PeerManager {
+    config: Arc<PeerManagerConfig>,
+    chain_manager: ChainManager,
+    /// Queues a save of the chain for each block accepted from a peer, if set
+    chain_writer: Option<ChainWriter>,
+    peers: Arc<RwLock<HashMap<PeerId, PeerEntry>>>,
+    /// Addresses of peers we may connect out to
+    address_book: Arc<RwLock<HashSet<SocketAddr>>>,
+    bans: Arc<Mutex<BanList>>,
+    /// Directory the ban list is saved to on every change, if any
+    ban_dir: Option<PathBuf>,
+    /// Held across a save so an older list never lands after a newer one
+    ban_save: Arc<tokio::sync::Mutex<()>>,
+    /// Items already received, consulted before `GetData` and `Inv`
+    seen: Arc<Mutex<SeenCache>>,
+    /// Items requested from a peer and not yet received
+    in_flight: Arc<Mutex<HashMap<InvItem, InFlight>>>,
+    pending_compact: Arc<Mutex<HashMap<[u8; 32], PendingCompact>>>,
+    next_id: Arc<AtomicU64>,
+    /// Totals across all peers, including disconnected ones
+    traffic: Arc<TrafficCounters>,
+    /// Sent in our `Version` so we can detect connecting to ourselves
+    local_nonce: u64,
+    /// Signalled by `close_all` to end every peer connection
+    closing: broadcast::Sender<()>,
+    events: EventSender,
+}
+
+impl PeerManager {
+    pub fn new(
+        config: PeerManagerConfig,
+        chain_manager: ChainManager,
+        events: EventSender,
+    ) -> Self {
+        let seen = SeenCache::new(config.seen_capacity, config.seen_ttl);
+
+        Self {
+            config: Arc::new(config),
+            chain_manager,
+            chain_writer: None,
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            address_book: Arc::new(RwLock::new(HashSet::new())),
+            bans: Arc::new(Mutex::new(BanList::new())),
+            ban_dir: None,
+            ban_save: Arc::new(tokio::sync::Mutex::new(())),
+            seen: Arc::new(Mutex::new(seen)),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            pending_compact: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            traffic: Arc::new(TrafficCounters::default()),
+            local_nonce: rand::random(),
+            closing: broadcast::channel(1).0,
+            events,
+        }
+    }
+
+    /// Start from `bans` and save the list to `dir` whenever it changes
+    pub fn with_ban_list(mut self, bans: BanList, dir: PathBuf) -> Self {
+        self.bans = Arc::new(Mutex::new(bans));
+        self.ban_dir = Some(dir);
+        self
+    }
+
+    /// Save the chain through `writer` whenever a peer's block connects
+    pub fn with_chain_writer(mut self, writer: ChainWriter) -> Self {
+        self.chain_writer = Some(writer);
+        self
+    }
+
+    pub fn config(&self) -> &PeerManagerConfig {
+        &self.config
+    }
+
+    pub fn local_nonce(&self) -> u64 {
+        self.local_nonce
+    }
+
+    /// Genesis hash of our chain, which peers must share
+    pub fn genesis_hash(&self) -> [u8; HASH_SIZE] {
+        self.chain_manager.tip().genesis_hash
+    }
+
+    /// Feature bits advertised in our `Version`
+    pub fn local_features(&self) -> u64 {
+        if self.config.compact_blocks { FEATURE_COMPACT_BLOCKS } else { 0 }
+    }
+
+    /// Register a connected stream and spawn the task driving it.
+    ///
+    /// Returns `None` if `addr` is banned or the connection limit for
+    /// `direction` has been reached, in which case the peer is sent a
+    /// `Disconnect` and dropped.
+    pub async fn add_peer<S>(&self, stream: S, addr: SocketAddr, direction: Direction) -> Option<PeerId>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        if self.is_banned(addr.ip()) {
+            info!("Refusing {:?} peer {}: banned", direction, addr);
+            Self::refuse(stream, "banned");
+            return None;
+        }
+
+        let mut peers = self.peers.write().await;
+
+        let counts = Self::count(&peers);
+        if !self.has_slot(counts, direction) {
+            drop(peers);
+            warn!("Refusing {:?} peer {}: connection limit reached ({:?})", direction, addr, counts);
+            Self::refuse(stream, "too many connections");
+            return None;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, outbound) = mpsc::channel(64);
+        let traffic = Arc::new(TrafficCounters::default());
+        let (kick, kicked) = oneshot::channel();
+
+        let entry = PeerEntry {
+            stats: PeerStats {
+                id,
+                addr,
+                direction,
+                connected_at: utils::current_timestamp(),
+                protocol_version: None,
+                features: 0,
+                ping_latency: None,
+                traffic: TrafficStats::default(),
+                misbehavior: 0,
+            },
+            traffic: traffic.clone(),
+            sender: PeerSender { queue: sender.clone(), features: 0 },
+            established: false,
+            known: SeenCache::new(self.config.known_capacity, self.config.seen_ttl),
+            kick: Some(kick),
+        };
+
+        peers.insert(id, entry);
+        drop(peers);
+        tokio::spawn(run_peer(self.clone(), id, addr, direction, stream, sender, outbound, traffic, kicked));
+
+        Some(id)
+    }
+
+    /// Tell a peer we won't talk to it and drop the connection
+    fn refuse<S>(stream: S, reason: &str)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let reason = reason.to_string();
+        tokio::spawn(async move {
+            let mut stream = Box::pin(stream);
+            let _ = write_message(&mut stream, &Message::Disconnect(reason)).await;
+        });
+    }
+
+    /// Open an outbound TCP connection to `addr` and register it
+    pub async fn connect(&self, addr: SocketAddr) -> Option<PeerId> {
+        if self.is_banned(addr.ip()) {
+            debug!("Not connecting to banned {}", addr);
+            return None;
+        }
+
+        match tokio::time::timeout(self.config.connect_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                // Remember reachable addresses so later top-ups don't need the seeds
+                self.add_addresses([addr]).await;
+                self.add_peer(stream, addr, Direction::Outbound).await
+            }
+            Ok(Err(e)) => {
+                debug!("Failed to connect to {}: {}", addr, e);
+                None
+            }
+            Err(_) => {
+                debug!("Timed out connecting to {}", addr);
+                None
+            }
+        }
+    }
+
+    pub async fn add_addresses(&self, addrs: impl IntoIterator<Item = SocketAddr>) {
+        self.address_book.write().await.extend(addrs);
+    }
+
+    /// Addresses to try for new outbound connections.
+    ///
+    /// Known addresses we aren't connected to come first; the seeds are only
+    /// consulted when those can't fill the free outbound slots.
+    pub async fn outbound_candidates<R: Resolver>(&self, resolver: &R) -> Vec<SocketAddr> {
+        let (connected, counts) = {
+            let peers = self.peers.read().await;
+            let connected: HashSet<_> = peers.values().map(|entry| entry.stats.addr).collect();
+            (connected, Self::count(&peers))
+        };
+
+        let wanted = self.config.max_outbound.saturating_sub(counts.outbound);
+        let mut candidates: Vec<_> = self.address_book.read().await
+            .iter()
+            .filter(|addr| !connected.contains(addr) && !self.is_banned(addr.ip()))
+            .copied()
+            .collect();
+
+        if candidates.len() < wanted {
+            for addr in resolve_seeds(resolver, &self.config.seeds).await {
+                if !connected.contains(&addr) && !candidates.contains(&addr) && !self.is_banned(addr.ip()) {
+                    candidates.push(addr);
+                }
+            }
+        }
+
+        candidates
+    }
+
+    /// Keep outbound slots filled, running until the task is dropped
+    pub async fn maintain_outbound<R: Resolver>(self, resolver: R) {
+        let mut timer = tokio::time::interval(self.config.outbound_interval);
+
+        loop {
+            timer.tick().await;
+
+            let counts = self.connection_counts().await;
+            let wanted = self.config.max_outbound.saturating_sub(counts.outbound);
+            if wanted == 0 {
+                continue;
+            }
+
+            let candidates = self.outbound_candidates(&resolver).await;
+            let mut opened = 0;
+            for addr in candidates {
+                if opened == wanted {
+                    break;
+                }
+                if self.connect(addr).await.is_some() {
+                    opened += 1;
+                }
+            }
+        }
+    }
+
+    /// Traffic totals across all peers since startup
+    pub fn total_traffic(&self) -> TrafficStats {
+        self.traffic.snapshot()
+    }
+
+    pub async fn connection_counts(&self) -> ConnectionCounts {
+        Self::count(&*self.peers.read().await)
+    }
+
+    /// Send every peer a `Disconnect` and wait for the connections to close
+    pub async fn close_all(&self) {
+        let _ = self.closing.send(());
+
+        while !self.peers.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.bans.lock().unwrap().is_banned(ip, utils::current_timestamp() as u64)
+    }
+
+    /// Bans in force, by address
+    pub fn banned(&self) -> Vec<(IpAddr, BanEntry)> {
+        let now = utils::current_timestamp() as u64;
+        self.bans.lock().unwrap()
+            .active(now)
+            .map(|(ip, entry)| (ip, entry.clone()))
+            .collect()
+    }
+
+    /// Ban `ip` for `duration`, disconnecting any peers connected from it,
+    /// returning false if it is whitelisted and so never banned. Fails only
+    /// if the ban list couldn't be saved; the ban holds regardless.
+    pub async fn ban(&self, ip: IpAddr, reason: &str, duration: Duration) -> io::Result<bool> {
+        if self.config.whitelist.contains(&ip) {
+            info!("Not banning whitelisted address {}", ip);
+            return Ok(false);
+        }
+        let until = (utils::current_timestamp() as u64).saturating_add(duration.as_secs());
+        self.bans.lock().unwrap().ban(ip, reason.to_string(), until);
+        info!("Banned {} for {}s: {}", ip, duration.as_secs(), reason);
+
+        let connected: Vec<_> = self.peers.read().await
+            .values()
+            .filter(|entry| entry.stats.addr.ip() == ip)
+            .map(|entry| entry.stats.id)
+            .collect();
+        for id in connected {
+            self.disconnect(id, DisconnectReason::Banned).await;
+        }
+
+        self.save_bans().await.map(|()| true)
+    }
+
+    /// Lift the ban on `ip`, returning false if it wasn't banned
+    pub async fn unban(&self, ip: IpAddr) -> io::Result<bool> {
+        if !self.bans.lock().unwrap().unban(ip) {
+            return Ok(false);
+        }
+        info!("Unbanned {}", ip);
+
+        self.save_bans().await.map(|()| true)
+    }
+
+    /// Save the ban list, off the async runtime since it writes to disk
+    async fn save_bans(&self) -> io::Result<()> {
+        let Some(dir) = &self.ban_dir else {
+            return Ok(());
+        };
+
+        let _ordered = self.ban_save.lock().await;
+        let bans = {
+            let mut bans = self.bans.lock().unwrap();
+            bans.sweep(utils::current_timestamp() as u64);
+            bans.clone()
+        };
+        let dir = dir.clone();
+        tokio::task::spawn_blocking(move || storage::save_banlist(&bans, &dir))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// End a peer's connection for `reason`
+    async fn disconnect(&self, id: PeerId, reason: DisconnectReason) {
+        let kick = self.peers.write().await.get_mut(&id).and_then(|entry| entry.kick.take());
+        if let Some(kick) = kick {
+            let _ = kick.send(reason);
+        }
+    }
+
+    pub async fn remove_peer(&self, id: PeerId) {
+        self.peers.write().await.remove(&id);
+        self.pending_compact.lock().unwrap().retain(|_, pending| pending.from != id);
+
+        // Ask another peer for whatever this one was still to deliver
+        let mut retries: HashMap<PeerId, Vec<InvItem>> = HashMap::new();
+        self.in_flight.lock().unwrap().retain(|item, request| {
+            request.alternates.retain(|&peer| peer != id);
+            if request.from != id {
+                return true;
+            }
+            match request.alternates.pop() {
+                Some(peer) => {
+                    request.from = peer;
+                    request.requested_at = Instant::now();
+                    retries.entry(peer).or_default().push(*item);
+                    true
+                }
+                None => false,
+            }
+        });
+        for (peer, items) in retries {
+            debug!("Re-requesting {} items from peer {} after peer {} left", items.len(), peer, id);
+            self.send_to(peer, Message::GetData(items)).await;
+        }
+    }
+
+    pub async fn peers(&self) -> Vec<PeerStats> {
+        let mut peers: Vec<_> = self.peers.read().await
+            .values()
+            .map(|entry| PeerStats {
+                traffic: entry.traffic.snapshot(),
+                features: entry.sender.features,
+                ..entry.stats.clone()
+            })
+            .collect();
+
+        peers.sort_by_key(|p| p.id);
+        peers
+    }
+
+    /// Announce a block this node connected itself, e.g. one submitted over RPC
+    pub async fn relay_block(&self, hash: [u8; 32], height: u64) {
+        events::publish(&self.events, NodeEvent::BlockConnected { hash, height });
+        self.mark_seen(InvItem::Block(hash));
+        self.announce(InvItem::Block(hash)).await;
+    }
+
+    /// Record the peer's `Version`; only features both sides advertise are
+    /// used, and bits this version doesn't know are dropped
+    pub(super) async fn set_peer_version(&self, id: PeerId, version: u32, features: u64) {
+        let features = self.local_features() & features & KNOWN_FEATURES;
+
+        if let Some(entry) = self.peers.write().await.get_mut(&id) {
+            entry.stats.protocol_version = Some(version);
+            entry.sender.features = features;
+        }
+    }
+
+    pub(super) async fn set_ping_latency(&self, id: PeerId, latency: Duration) {
+        if let Some(entry) = self.peers.write().await.get_mut(&id) {
+            entry.stats.ping_latency = Some(latency);
+        }
+    }
+
+    pub(super) fn closing(&self) -> broadcast::Receiver<()> {
+        self.closing.subscribe()
+    }
+
+    pub(super) fn traffic(&self) -> Arc<TrafficCounters> {
+        self.traffic.clone()
+    }
+
+    /// Add the penalty for `count` of `offense` to a peer's misbehavior
+    /// score. Returns true once the score reaches `max_misbehavior`, at
+    /// which point the caller drops the peer; the first time, its address
+    /// is also banned unless whitelisted.
+    pub(super) async fn add_misbehavior(&self, id: PeerId, offense: Offense, count: u32) -> bool {
+        let points = self.config.penalty(offense).saturating_mul(count);
+        let (addr, before, after) = {
+            let mut peers = self.peers.write().await;
+            let Some(entry) = peers.get_mut(&id) else {
+                return false;
+            };
+
+            let before = entry.stats.misbehavior;
+            entry.stats.misbehavior = before.saturating_add(points);
+            (entry.stats.addr, before, entry.stats.misbehavior)
+        };
+        warn!("Peer {} misbehaving ({:?}), score now {}", addr, offense, after);
+
+        let max = self.config.max_misbehavior;
+        if after < max {
+            return false;
+        }
+        if before < max
+            && let Err(e) = self.ban(addr.ip(), &format!("misbehaving ({:?})", offense), self.config.ban_duration).await {
+            warn!("Failed to save ban list: {}", e);
+        }
+        true
+    }
+
+    pub(super) async fn mark_established(&self, id: PeerId) {
+        if let Some(entry) = self.peers.write().await.get_mut(&id) {
+            entry.established = true;
+        }
+    }
+
+    /// Handle a post-handshake message other than ping/pong
+    pub(super) async fn handle_message(&self, from: PeerId, message: Message) {
+        let required = message.required_features();
+        if required != 0 && !self.peers.read().await.get(&from).is_some_and(|entry| entry.sender.supports(required)) {
+            debug!("Ignoring {:?} from peer {} that didn't negotiate it", message, from);
+            return;
+        }
+
+        match message {
+            Message::Inv(items) => self.handle_inv(from, items).await,
+            Message::GetData(items) => self.handle_get_data(from, items).await,
+            Message::Block(block) => self.handle_block(from, block).await,
+            Message::Tx(tx) => self.handle_tx(from, tx).await,
+            Message::CompactBlock(compact) => self.handle_compact_block(from, compact).await,
+            Message::GetBlockTxn { block_hash, indexes } => {
+                self.handle_get_block_txn(from, block_hash, indexes).await
+            }
+            Message::BlockTxn { block_hash, txs } => self.handle_block_txn(from, block_hash, txs).await,
+            other => debug!("Ignoring unexpected {:?} from peer {}", other, from),
+        }
+    }
+
+    async fn handle_inv(&self, from: PeerId, items: Vec<InvItem>) {
+        self.mark_known(from, &items).await;
+
+        // Request only what we haven't received, or asked another peer for
+        // recently enough that it may still arrive
+        let wanted: Vec<_> = {
+            let seen = self.seen.lock().unwrap();
+            let mut in_flight = self.in_flight.lock().unwrap();
+            items
+                .into_iter()
+                .filter(|item| !seen.contains(item))
+                .filter(|item| match in_flight.get_mut(item) {
+                    Some(request) if request.requested_at.elapsed() < self.config.request_timeout => {
+                        if request.from != from && !request.alternates.contains(&from) {
+                            request.alternates.push(from);
+                        }
+                        false
+                    }
+                    _ => {
+                        in_flight.insert(*item, InFlight { from, requested_at: Instant::now(), alternates: Vec::new() });
+                        true
+                    }
+                })
+                .collect()
+        };
+
+        if !wanted.is_empty() {
+            self.send_to(from, Message::GetData(wanted)).await;
+        }
+    }
+
+    async fn handle_get_data(&self, from: PeerId, items: Vec<InvItem>) {
+        for item in items {
+            let reply = match item {
+                InvItem::Block(hash) => self.chain_manager.chain().await
+                    .find_block(hash)
+                    .cloned()
+                    .map(Message::Block),
+                InvItem::Tx(hash) => self.chain_manager.mempool().await
+                    .txs()
+                    .iter()
+                    .find(|tx| tx.double_sha256() == hash)
+                    .cloned()
+                    .map(Message::Tx),
+            };
+
+            match reply {
+                Some(message) => {
+                    self.mark_known(from, &[item]).await;
+                    self.send_to(from, message).await;
+                }
+                None => debug!("Peer {} requested unknown {:?}", from, item),
+            }
+        }
+    }
+
+    async fn handle_block(&self, from: PeerId, block: Block) {
+        let item = InvItem::Block(block.double_sha256());
+        self.mark_known(from, &[item]).await;
+        self.mark_seen(item);
+
+        self.accept_block(from, block).await;
+    }
+
+    async fn handle_compact_block(&self, from: PeerId, compact: CompactBlock) {
+        let hash = compact.block_hash;
+        let item = InvItem::Block(hash);
+        self.mark_known(from, &[item]).await;
+
+        // Peers that never send the missing transactions don't hold the block up for good
+        let timeout = self.config.request_timeout;
+        self.pending_compact.lock().unwrap().retain(|pending_hash, pending| {
+            let expired = pending.requested_at.elapsed() >= timeout;
+            if expired {
+                debug!("Peer {} never sent the missing txs of block {}", pending.from, hex32(pending_hash));
+            }
+            !expired
+        });
+
+        // Already have it, or are already fetching its missing transactions
+        if self.chain_manager.chain().await.find_block(hash).is_some() {
+            self.mark_seen(item);
+            return;
+        }
+        if self.pending_compact.lock().unwrap().contains_key(&hash) {
+            if let Some(request) = self.in_flight.lock().unwrap().get_mut(&item)
+                && request.from != from && !request.alternates.contains(&from) {
+                request.alternates.push(from);
+            }
+            return;
+        }
+        // Seen only once it arrives in full, so it can still be fetched elsewhere
+        self.in_flight.lock().unwrap().insert(item, InFlight { from, requested_at: Instant::now(), alternates: Vec::new() });
+
+        let partial = PartialBlock::new(compact, self.chain_manager.mempool().await.txs());
+        let missing = partial.missing();
+        if missing.is_empty() {
+            self.complete_compact(from, partial).await;
+            return;
+        }
+
+        debug!("Requesting {} missing txs of block {} from peer {}", missing.len(), hex32(&hash), from);
+        self.pending_compact.lock().unwrap().insert(hash, PendingCompact { from, partial, requested_at: Instant::now() });
+        self.send_to(from, Message::GetBlockTxn { block_hash: hash, indexes: missing }).await;
+    }
+
+    async fn handle_get_block_txn(&self, from: PeerId, block_hash: [u8; 32], indexes: Vec<u32>) {
+        let txs = {
+            let chain = self.chain_manager.chain().await;
+            let Some(block) = chain.find_block(block_hash) else {
+                debug!("Peer {} requested txs of unknown block {}", from, hex32(&block_hash));
+                return;
+            };
+
+            indexes
+                .iter()
+                .map(|&i| block.transactions.get(i as usize).cloned())
+                .collect::<Option<Vec<_>>>()
+        };
+
+        match txs {
+            Some(txs) => self.send_to(from, Message::BlockTxn { block_hash, txs }).await,
+            None => debug!("Peer {} requested out of range txs of block {}", from, hex32(&block_hash)),
+        }
+    }
+
+    async fn handle_block_txn(&self, from: PeerId, block_hash: [u8; 32], txs: Vec<Transaction>) {
+        let pending = {
+            let mut pending = self.pending_compact.lock().unwrap();
+            match pending.get(&block_hash) {
+                Some(entry) if entry.from == from => pending.remove(&block_hash),
+                _ => None,
+            }
+        };
+
+        let Some(PendingCompact { mut partial, .. }) = pending else {
+            debug!("Unsolicited block txs for {} from peer {}", hex32(&block_hash), from);
+            return;
+        };
+
+        if partial.fill(txs) {
+            self.complete_compact(from, partial).await;
+        } else {
+            self.request_full_block(from, block_hash).await;
+        }
+    }
+
+    /// Accept a reconstructed compact block, or fall back to fetching it in full
+    async fn complete_compact(&self, from: PeerId, partial: PartialBlock) {
+        let hash = partial.block_hash();
+
+        match partial.into_block() {
+            Some(block) => {
+                self.mark_seen(InvItem::Block(hash));
+                self.accept_block(from, block).await;
+            }
+            None => self.request_full_block(from, hash).await,
+        }
+    }
+
+    async fn request_full_block(&self, from: PeerId, hash: [u8; 32]) {
+        debug!("Compact reconstruction of block {} failed, requesting it in full", hex32(&hash));
+        self.send_to(from, Message::GetData(vec![InvItem::Block(hash)])).await;
+    }
+
+    /// Connect a block received from `from` and relay it, returning true if it was new and valid
+    async fn accept_block(&self, from: PeerId, block: Block) -> bool {
+        let hash = block.double_sha256();
+        let submitter = self.peers.read().await.get(&from).map(|entry| entry.stats.addr.to_string());
+
+        match self.chain_manager.connect_block_from(block, BlockSource::P2p, submitter).await {
+            Ok(height) => {
+                info!(height = %height, "Accepted block {} from peer {}", hex32(&hash), from);
+                // The writer logs failures
+                if let Some(writer) = &self.chain_writer {
+                    let _ = writer.write().await;
+                }
+                events::publish(&self.events, NodeEvent::BlockConnected { hash, height });
+            }
+            Err(ConnectError::Known) => return false,
+            Err(e) => {
+                warn!("Rejected block {} from peer {}: {}", hex32(&hash), from, e);
+                events::publish(&self.events, NodeEvent::BlockRejected { hash });
+
+                // A block that doesn't extend our tip may just be stale;
+                // one that fails its own checks can't be honest
+                if matches!(
+                    e,
+                    ConnectError::Invalid(
+                        BlockchainError::InvalidPoW
+                            | BlockchainError::InvalidMerkleRoot
+                            | BlockchainError::IncorrectDifficulty { .. }
+                            | BlockchainError::EmptyBlock
+                    )
+                )
+                    && self.add_misbehavior(from, Offense::InvalidBlock, 1).await {
+                    self.disconnect(from, DisconnectReason::Misbehaving).await;
+                }
+                return false;
+            }
+        }
+
+        self.announce(InvItem::Block(hash)).await;
+        true
+    }
+
+    async fn handle_tx(&self, from: PeerId, tx: Transaction) {
+        let item = InvItem::Tx(tx.double_sha256());
+        self.mark_known(from, &[item]).await;
+        self.mark_seen(item);
+
+        if self.chain_manager.add_mempool_tx(tx).await.is_err() {
+            return;
+        }
+        if let InvItem::Tx(txid) = item {
+            events::publish(&self.events, NodeEvent::TxAccepted { txid });
+        }
+
+        self.announce(item).await;
+    }
+
+    fn count(peers: &HashMap<PeerId, PeerEntry>) -> ConnectionCounts {
+        let mut counts = ConnectionCounts::default();
+        for entry in peers.values() {
+            match entry.stats.direction {
+                Direction::Inbound => counts.inbound += 1,
+                Direction::Outbound => counts.outbound += 1,
+            }
+        }
+        counts
+    }
+
+    /// Whether another `direction` connection fits within the configured limits
+    fn has_slot(&self, counts: ConnectionCounts, direction: Direction) -> bool {
+        let config = &self.config;
+        match direction {
+            Direction::Inbound => {
+                let inbound_limit = config.max_inbound
+                    .min(config.max_connections.saturating_sub(config.max_outbound));
+                counts.inbound < inbound_limit
+            }
+            Direction::Outbound => {
+                counts.outbound < config.max_outbound && counts.total() < config.max_connections
+            }
+        }
+    }
+
+    /// Announce `item` to every established peer not already known to have it:
+    /// blocks go out as `CompactBlock` to peers that negotiated it, everything
+    /// else as an `Inv`
+    async fn announce(&self, item: InvItem) {
+        let compact = match item {
+            InvItem::Block(hash) => self.chain_manager.chain().await.find_block(hash).map(CompactBlock::from_block),
+            InvItem::Tx(_) => None,
+        };
+
+        let targets: Vec<_> = {
+            let mut peers = self.peers.write().await;
+            peers
+                .values_mut()
+                .filter(|entry| entry.established)
+                .filter_map(|entry| entry.known.insert(item).then(|| entry.sender.clone()))
+                .collect()
+        };
+
+        for sender in targets {
+            let message = match &compact {
+                Some(compact) if sender.supports(FEATURE_COMPACT_BLOCKS) => Message::CompactBlock(compact.clone()),
+                _ => Message::Inv(vec![item]),
+            };
+            sender.send(message).await;
+        }
+    }
+
+    /// Record `item` as received, returning true if it is new
+    fn mark_seen(&self, item: InvItem) -> bool {
+        self.in_flight.lock().unwrap().remove(&item);
+        self.seen.lock().unwrap().insert(item)
+    }
+
+    async fn mark_known(&self, id: PeerId, items: &[InvItem]) {
+        if let Some(entry) = self.peers.write().await.get_mut(&id) {
+            for item in items {
+                entry.known.insert(*item);
+            }
+        }
+    }
+
+    async fn send_to(&self, id: PeerId, message: Message) {
+        let sender = self.peers.read().await.get(&id).map(|entry| entry.sender.clone());
+        if let Some(sender) = sender {
+            sender.send(message).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_writer::FileStore;
+    use crate::config::NodeConfig;
+    use crate::config::node_config::{Network, SyncMode};
+    use crate::mempool::Mempool;
+    use crate::metrics::NodeMetrics;
+    use crate::network::message::{Message, PROTOCOL_VERSION, read_message, write_message};
+    use hyperion_core::chain::blockchain::Blockchain;
+
+    use std::io;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::io::DuplexStream;
+    use tokio::time::{Instant, sleep, sleep_until, timeout};
+
+    fn test_addr() -> SocketAddr {
+        "127.0.0.1:16000".parse().unwrap()
+    }
+
+    fn test_manager() -> PeerManager {
+        PeerManager::new(
+            PeerManagerConfig::default(),
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        )
+    }
+
+    /// Forward frames from `from` to `to`, counting `Block` messages on the way
+    fn spawn_forwarder<R, W>(mut from: R, mut to: W, blocks: Arc<AtomicUsize>)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        tokio::spawn(async move {
+            while let Ok(message) = read_message(&mut from).await {
+                if matches!(message, Message::Block(_)) {
+                    blocks.fetch_add(1, Ordering::SeqCst);
+                }
+                if write_message(&mut to, &message).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Connect two managers through a proxy that counts relayed blocks
+    async fn link(a: &PeerManager, b: &PeerManager, blocks: &Arc<AtomicUsize>) {
+        let (a_local, a_proxy) = tokio::io::duplex(64 * 1024);
+        let (b_local, b_proxy) = tokio::io::duplex(64 * 1024);
+
+        let (a_read, a_write) = tokio::io::split(a_proxy);
+        let (b_read, b_write) = tokio::io::split(b_proxy);
+        spawn_forwarder(a_read, b_write, blocks.clone());
+        spawn_forwarder(b_read, a_write, blocks.clone());
+
+        a.add_peer(a_local, test_addr(), Direction::Outbound).await;
+        b.add_peer(b_local, test_addr(), Direction::Inbound).await;
+    }
+
+    async fn established_count(manager: &PeerManager) -> usize {
+        manager.peers.read().await.values().filter(|entry| entry.established).count()
+    }
+
+    /// Connect a mock peer to `manager` and complete the handshake from its side
+    async fn connect_mock_peer(manager: &PeerManager) -> DuplexStream {
+        connect_mock_peer_with(manager, 0).await
+    }
+
+    /// Like `connect_mock_peer`, advertising `features` in its `Version`
+    async fn connect_mock_peer_with(manager: &PeerManager, features: u64) -> DuplexStream {
+        let (local, mut remote) = tokio::io::duplex(64 * 1024);
+        manager.add_peer(local, test_addr(), Direction::Inbound).await;
+
+        let msg = read_message(&mut remote).await.expect("Failed to read version");
+        assert!(matches!(msg, Message::Version { .. }));
+
+        let version = Message::Version {
+            protocol_version: PROTOCOL_VERSION,
+            features,
+            nonce: 1,
+            genesis_hash: manager.genesis_hash(),
+        };
+        write_message(&mut remote, &version).await.expect("Failed to send version");
+        write_message(&mut remote, &Message::Verack).await.expect("Failed to send verack");
+
+        let msg = read_message(&mut remote).await.expect("Failed to read verack");
+        assert_eq!(msg, Message::Verack);
+
+        remote
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unresponsive_peer_disconnected_after_two_missed_pings() {
+        let manager = test_manager();
+        let _remote = connect_mock_peer(&manager).await;
+        let connected_at = Instant::now();
+
+        // Pings at 120s and 240s, each timing out 20s later
+        sleep_until(connected_at + Duration::from_secs(259)).await;
+        assert_eq!(manager.peers().await.len(), 1);
+
+        sleep_until(connected_at + Duration::from_secs(261)).await;
+        assert_eq!(manager.peers().await.len(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_responsive_peer_stays_connected_and_records_latency() {
+        let manager = test_manager();
+        let mut remote = connect_mock_peer(&manager).await;
+
+        for _ in 0..3 {
+            let Message::Ping(nonce) = read_message(&mut remote).await.expect("Failed to read ping") else {
+                panic!("Expected a ping");
+            };
+            sleep(Duration::from_secs(3)).await;
+            write_message(&mut remote, &Message::Pong(nonce)).await.expect("Failed to send pong");
+        }
+
+        sleep(Duration::from_secs(30)).await;
+        let peers = manager.peers().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].protocol_version, Some(PROTOCOL_VERSION));
+        assert_eq!(peers[0].ping_latency, Some(Duration::from_secs(3)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_inbound_ping_answered() {
+        let manager = test_manager();
+        let mut remote = connect_mock_peer(&manager).await;
+
+        write_message(&mut remote, &Message::Ping(42)).await.expect("Failed to send ping");
+        let reply = read_message(&mut remote).await.expect("Failed to read pong");
+        assert_eq!(reply, Message::Pong(42));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wrong_pong_nonce_counts_as_missed() {
+        let manager = test_manager();
+        let mut remote = connect_mock_peer(&manager).await;
+        let connected_at = Instant::now();
+
+        for _ in 0..2 {
+            let Message::Ping(nonce) = read_message(&mut remote).await.expect("Failed to read ping") else {
+                panic!("Expected a ping");
+            };
+            write_message(&mut remote, &Message::Pong(nonce.wrapping_add(1))).await.expect("Failed to send pong");
+        }
+
+        sleep_until(connected_at + Duration::from_secs(261)).await;
+        assert_eq!(manager.peers().await.len(), 0);
+    }
+
+    /// Result of relaying one block around a triangle of nodes
+    struct RelayOutcome {
+        /// Full `Block` messages exchanged
+        full_blocks: usize,
+        /// Bytes sent by all nodes after the handshakes
+        bytes: u64,
+    }
+
+    /// Link three nodes in a triangle, mine a block holding `txs` on the first
+    /// and wait for it to reach the other two; node `i` starts with the first
+    /// `pooled[i]` of `txs` in its mempool
+    async fn relay_in_triangle(compact_blocks: bool, txs: Vec<Transaction>, pooled: [usize; 3]) -> RelayOutcome {
+        let config = PeerManagerConfig { compact_blocks, ..Default::default() };
+        let nodes: Vec<_> = pooled
+            .iter()
+            .map(|&count| {
+                let mut mempool = Mempool::new();
+                for tx in &txs[..count] {
+                    mempool.add_tx(tx.clone());
+                }
+
+                PeerManager::new(
+                    config.clone(),
+                    ChainManager::new(Blockchain::new_with_genesis(), mempool),
+                    events::channel(),
+                )
+            })
+            .collect();
+        let blocks = Arc::new(AtomicUsize::new(0));
+
+        link(&nodes[0], &nodes[1], &blocks).await;
+        link(&nodes[1], &nodes[2], &blocks).await;
+        link(&nodes[2], &nodes[0], &blocks).await;
+
+        timeout(Duration::from_secs(5), async {
+            for node in &nodes {
+                while established_count(node).await < 2 {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            }
+        }).await.expect("Peers did not finish handshaking");
+
+        let bytes_sent = || nodes.iter().map(|node| node.total_traffic().bytes_sent).sum::<u64>();
+        let handshake_bytes = bytes_sent();
+
+        let block = Blockchain::mine_new_block(&*nodes[0].chain_manager.chain().await, txs, 1);
+        let hash = block.double_sha256();
+        nodes[0].chain_manager.connect_block(block).await.expect("Failed to add mined block");
+        nodes[0].relay_block(hash, 1).await;
+
+        timeout(Duration::from_secs(5), async {
+            for node in &nodes[1..] {
+                while node.chain_manager.chain().await.find_block(hash).is_none() {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            }
+        }).await.expect("Block did not propagate");
+
+        // Give any redundant relays time to show up before counting
+        sleep(Duration::from_millis(200)).await;
+
+        RelayOutcome {
+            full_blocks: blocks.load(Ordering::SeqCst),
+            bytes: bytes_sent() - handshake_bytes,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_relayed_once_per_peer_in_triangle() {
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+
+        let outcome = relay_in_triangle(false, vec![tx], [0, 0, 0]).await;
+        assert_eq!(outcome.full_blocks, 2);
+    }
+
+    #[tokio::test]
+    async fn test_compact_relay_reduces_bytes() {
+        let txs: Vec<_> = (0..50u8)
+            .map(|i| Transaction::new(vec![vec![i; 64]], vec![vec![i; 64]]).unwrap())
+            .collect();
+
+        // The last node is missing half the block's transactions and must fetch them
+        let full = relay_in_triangle(false, txs.clone(), [0, 50, 25]).await;
+        let compact = relay_in_triangle(true, txs, [0, 50, 25]).await;
+
+        assert_eq!(compact.full_blocks, 0);
+        assert!(
+            compact.bytes < full.bytes / 2,
+            "compact relay sent {} bytes, full relay {}", compact.bytes, full.bytes,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_baseline_peer_only_sent_baseline_messages() {
+        let manager = test_manager();
+        assert_eq!(manager.local_features(), FEATURE_COMPACT_BLOCKS);
+
+        // Every bit but compact blocks, none of which this version knows
+        let mut remote = connect_mock_peer_with(&manager, !FEATURE_COMPACT_BLOCKS).await;
+        let peer = manager.peers().await[0].id;
+        assert_eq!(manager.peers().await[0].features, 0);
+
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let block = Blockchain::mine_new_block(&*manager.chain_manager.chain().await, vec![tx], 1);
+        let hash = block.double_sha256();
+
+        // A gated message can't be queued for the peer at all
+        manager.send_to(peer, Message::CompactBlock(CompactBlock::from_block(&block))).await;
+
+        // New blocks are announced with a plain `Inv`
+        manager.chain_manager.connect_block(block.clone()).await.expect("Failed to add mined block");
+        manager.relay_block(hash, 1).await;
+        let announcement = read_message(&mut remote).await.expect("Failed to read announcement");
+        assert_eq!(announcement, Message::Inv(vec![InvItem::Block(hash)]));
+
+        // Compact messages from the peer are ignored rather than answered
+        let compact = CompactBlock::from_block(&block);
+        write_message(&mut remote, &Message::CompactBlock(compact)).await.expect("Failed to send compact block");
+        let request = Message::GetBlockTxn { block_hash: hash, indexes: vec![0] };
+        write_message(&mut remote, &request).await.expect("Failed to send block txn request");
+        write_message(&mut remote, &Message::Ping(9)).await.expect("Failed to send ping");
+        let reply = read_message(&mut remote).await.expect("Failed to read pong");
+        assert_eq!(reply, Message::Pong(9));
+    }
+
+    /// Whether the next message `remote` gets, ahead of the pong for a ping
+    /// it sends now, is a `GetData` for `items`
+    async fn requested(remote: &mut DuplexStream, items: &[InvItem]) -> bool {
+        write_message(remote, &Message::Ping(5)).await.expect("Failed to send ping");
+        match read_message(remote).await.expect("Failed to read reply") {
+            Message::GetData(requested) => {
+                assert_eq!(requested, items);
+                assert_eq!(read_message(remote).await.expect("Failed to read pong"), Message::Pong(5));
+                true
+            }
+            other => {
+                assert_eq!(other, Message::Pong(5));
+                false
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_undelivered_item_requested_from_another_peer() {
+        let manager = test_manager();
+        let mut first = connect_mock_peer(&manager).await;
+        let mut second = connect_mock_peer(&manager).await;
+        let mut third = connect_mock_peer(&manager).await;
+        let item = InvItem::Tx([4; HASH_SIZE]);
+
+        write_message(&mut first, &Message::Inv(vec![item])).await.expect("Failed to announce");
+        assert!(requested(&mut first, &[item]).await);
+        write_message(&mut second, &Message::Inv(vec![item])).await.expect("Failed to announce");
+        assert!(!requested(&mut second, &[item]).await);
+
+        // The first peer leaves without delivering, so the second is asked
+        drop(first);
+        let request = timeout(Duration::from_secs(5), read_message(&mut second)).await
+            .expect("Item was not re-requested")
+            .expect("Failed to read request");
+        assert_eq!(request, Message::GetData(vec![item]));
+
+        // The second never answers either; once the request times out the
+        // next peer to announce the item is asked for it
+        write_message(&mut third, &Message::Inv(vec![item])).await.expect("Failed to announce");
+        assert!(!requested(&mut third, &[item]).await);
+        sleep(manager.config().request_timeout).await;
+        write_message(&mut third, &Message::Inv(vec![item])).await.expect("Failed to announce");
+        assert!(requested(&mut third, &[item]).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unanswered_compact_block_expires() {
+        let manager = test_manager();
+        let mut first = connect_mock_peer_with(&manager, FEATURE_COMPACT_BLOCKS).await;
+        let mut second = connect_mock_peer_with(&manager, FEATURE_COMPACT_BLOCKS).await;
+
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let block = Blockchain::mine_new_block(&*manager.chain_manager.chain().await, vec![tx.clone()], 1);
+        let hash = block.double_sha256();
+        let compact = Message::CompactBlock(CompactBlock::from_block(&block));
+        let missing = Message::GetBlockTxn { block_hash: hash, indexes: vec![0] };
+
+        // Only the first announcer is asked while its request is fresh
+        write_message(&mut first, &compact).await.expect("Failed to send compact block");
+        assert_eq!(read_message(&mut first).await.expect("Failed to read request"), missing);
+        write_message(&mut second, &compact).await.expect("Failed to send compact block");
+        write_message(&mut second, &Message::Ping(3)).await.expect("Failed to send ping");
+        assert_eq!(read_message(&mut second).await.expect("Failed to read pong"), Message::Pong(3));
+
+        // The first never answers, so the next announcement is followed up
+        sleep(manager.config().request_timeout).await;
+        write_message(&mut second, &compact).await.expect("Failed to send compact block");
+        assert_eq!(read_message(&mut second).await.expect("Failed to read request"), missing);
+        assert_eq!(manager.pending_compact.lock().unwrap().len(), 1);
+
+        write_message(&mut second, &Message::BlockTxn { block_hash: hash, txs: vec![tx] }).await.expect("Failed to send txs");
+        timeout(Duration::from_secs(5), async {
+            while manager.chain_manager.chain().await.find_block(hash).is_none() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("Block was not reconstructed");
+        assert!(manager.pending_compact.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_other_custom_genesis() {
+        let custom = |message: &str| {
+            let mut config = NodeConfig { chain: Network::Custom, ..Default::default() };
+            config.genesis.message = message.to_string();
+            let genesis = config.genesis_block().unwrap();
+            PeerManager::new(
+                PeerManagerConfig::default(),
+                ChainManager::new(Blockchain::new(genesis), Mempool::new()),
+                events::channel(),
+            )
+        };
+        let lab = custom("lab one");
+        let other = custom("lab two");
+        assert_ne!(lab.genesis_hash(), other.genesis_hash());
+
+        link(&lab, &other, &Arc::new(AtomicUsize::new(0))).await;
+        timeout(Duration::from_secs(5), async {
+            while !lab.peers().await.is_empty() || !other.peers().await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("Peers on different chains stayed connected");
+        assert_eq!(established_count(&lab).await + established_count(&other).await, 0);
+
+        // The same config on both sides connects
+        let twin = custom("lab one");
+        link(&lab, &twin, &Arc::new(AtomicUsize::new(0))).await;
+        timeout(Duration::from_secs(5), async {
+            while established_count(&lab).await < 1 || established_count(&twin).await < 1 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("Peers on the same chain did not finish handshaking");
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_refuses_extra_inbound() {
+        let config = PeerManagerConfig {
+            max_connections: 5,
+            max_inbound: 10,
+            max_outbound: 2,
+            ..Default::default()
+        };
+        let manager = PeerManager::new(
+            config,
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        );
+
+        // Two of the five slots are held back for outbound, leaving three inbound
+        let limit = 3;
+        let mut handshakes = 0;
+        let mut connections = Vec::new();
+
+        for _ in 0..limit + 5 {
+            let (local, mut remote) = tokio::io::duplex(64 * 1024);
+            manager.add_peer(local, test_addr(), Direction::Inbound).await;
+
+            match read_message(&mut remote).await.expect("Failed to read first message") {
+                Message::Version { .. } => {
+                    let version = Message::Version {
+                        protocol_version: PROTOCOL_VERSION,
+                        features: 0,
+                        nonce: 1,
+                        genesis_hash: manager.genesis_hash(),
+                    };
+                    write_message(&mut remote, &version).await.expect("Failed to send version");
+                    write_message(&mut remote, &Message::Verack).await.expect("Failed to send verack");
+
+                    if read_message(&mut remote).await.expect("Failed to read verack") == Message::Verack {
+                        handshakes += 1;
+                    }
+                }
+                Message::Disconnect(_) => {}
+                other => panic!("Unexpected {:?}", other),
+            }
+            connections.push(remote);
+        }
+
+        assert_eq!(handshakes, limit);
+        assert_eq!(manager.connection_counts().await, ConnectionCounts { inbound: limit, outbound: 0 });
+
+        // The reserved slots still accept outbound connections
+        let (local, _remote) = tokio::io::duplex(64 * 1024);
+        assert!(manager.add_peer(local, test_addr(), Direction::Outbound).await.is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flooding_peer_throttled_without_affecting_others() {
+        let config = PeerManagerConfig {
+            max_msgs_per_sec: 10.0,
+            msg_burst: 10.0,
+            ..Default::default()
+        };
+        let manager = PeerManager::new(
+            config,
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        );
+
+        let flooder = connect_mock_peer(&manager).await;
+        let mut honest = connect_mock_peer(&manager).await;
+        let start = Instant::now();
+
+        // Push pings as fast as possible and count how many get answered
+        let (mut flood_read, mut flood_write) = tokio::io::split(flooder);
+        tokio::spawn(async move {
+            for nonce in 0..1_000 {
+                if write_message(&mut flood_write, &Message::Ping(nonce)).await.is_err() {
+                    break;
+                }
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let answered = Arc::new(AtomicUsize::new(0));
+        let counter = answered.clone();
+        tokio::spawn(async move {
+            while let Ok(message) = read_message(&mut flood_read).await {
+                if matches!(message, Message::Pong(_)) {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        // The well-behaved peer is answered immediately
+        sleep(Duration::from_secs(1)).await;
+        write_message(&mut honest, &Message::Ping(7)).await.expect("Failed to send ping");
+        let reply = timeout(Duration::from_millis(10), read_message(&mut honest)).await
+            .expect("Honest peer was throttled")
+            .expect("Failed to read pong");
+        assert_eq!(reply, Message::Pong(7));
+
+        sleep_until(start + Duration::from_secs(5)).await;
+        let processed = answered.load(Ordering::SeqCst);
+        assert!(processed <= 10 + 5 * 10 + 1, "processed {} messages in 5s", processed);
+
+        let score_at = |peers: &[PeerStats], i: usize| peers[i].misbehavior;
+        let early = manager.peers().await;
+        assert!(score_at(&early, 0) > 0);
+        assert_eq!(score_at(&early, 1), 0);
+
+        sleep_until(start + Duration::from_secs(10)).await;
+        let later = manager.peers().await;
+        assert!(score_at(&later, 0) > score_at(&early, 0));
+        assert_eq!(score_at(&later, 1), 0);
+        assert!(later[0].traffic.msgs_recv > later[1].traffic.msgs_recv);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_peer_does_not_delay_shutdown() {
+        let config = PeerManagerConfig {
+            max_msgs_per_sec: 0.01,
+            msg_burst: 1.0,
+            flooding_penalty: 0,
+            ..Default::default()
+        };
+        let manager = PeerManager::new(
+            config,
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        );
+        let mut remote = connect_mock_peer(&manager).await;
+
+        // The second ping waits 100s for its turn
+        write_message(&mut remote, &Message::Ping(1)).await.expect("Failed to send ping");
+        write_message(&mut remote, &Message::Ping(2)).await.expect("Failed to send ping");
+        assert_eq!(read_message(&mut remote).await.expect("Failed to read pong"), Message::Pong(1));
+
+        let start = Instant::now();
+        manager.close_all().await;
+        assert!(start.elapsed() < Duration::from_secs(1), "shutdown took {:?}", start.elapsed());
+    }
+
+    /// Have a mock peer of a manager with `whitelist` send it a block with
+    /// a bad merkle root, returning the manager once the peer is dropped
+    async fn send_invalid_block(whitelist: Vec<IpAddr>) -> PeerManager {
+        let manager = PeerManager::new(
+            PeerManagerConfig { whitelist, ..Default::default() },
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        );
+        let mut remote = connect_mock_peer(&manager).await;
+
+        let mut block = Blockchain::mine_new_block(&*manager.chain_manager.chain().await, Vec::new(), 1);
+        block.transactions.push(Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap());
+        write_message(&mut remote, &Message::Block(block)).await.expect("Failed to send block");
+
+        timeout(Duration::from_secs(5), async {
+            while !manager.peers().await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("Misbehaving peer was not dropped");
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_block_from_peer_is_saved() {
+        let dir = std::env::temp_dir().join(format!("hyperion-peer-save-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let writer = ChainWriter::spawn(
+            chain_manager.clone(),
+            FileStore(dir.clone()),
+            Arc::new(NodeMetrics::default()),
+            SyncMode::Async,
+            1,
+        );
+        let events = events::channel();
+        let mut connected = events.subscribe();
+        let manager = PeerManager::new(PeerManagerConfig::default(), chain_manager, events).with_chain_writer(writer.clone());
+        let mut remote = connect_mock_peer(&manager).await;
+
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let block = Blockchain::mine_new_block(&*manager.chain_manager.chain().await, vec![tx], 1);
+        let hash = block.double_sha256();
+        write_message(&mut remote, &Message::Block(block)).await.expect("Failed to send block");
+        let event = timeout(Duration::from_secs(5), connected.recv()).await.expect("Block was not connected").unwrap();
+        assert_eq!(event, NodeEvent::BlockConnected { hash, height: 1 });
+
+        // The write was queued before the block was announced
+        writer.flush().await.expect("Failed to save chain");
+        let saved = storage::load_chain(&dir).expect("Chain was not saved");
+        assert_eq!(saved.latest_block().double_sha256(), hash);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_block_bans_sender() {
+        let manager = send_invalid_block(Vec::new()).await;
+
+        let banned = manager.banned();
+        assert_eq!(banned.len(), 1);
+        assert_eq!(banned[0].0, test_addr().ip());
+        assert_eq!(banned[0].1.reason, "misbehaving (InvalidBlock)");
+
+        // It can't come back, and we won't connect out to it either
+        let (local, _remote) = tokio::io::duplex(1024);
+        assert!(manager.add_peer(local, test_addr(), Direction::Inbound).await.is_none());
+        assert!(manager.connect(test_addr()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_whitelist_overrides_ban() {
+        let manager = send_invalid_block(vec![test_addr().ip()]).await;
+
+        assert!(manager.banned().is_empty());
+        let _remote = connect_mock_peer(&manager).await;
+        assert_eq!(manager.peers().await.len(), 1);
+    }
+
+    struct StubResolver;
+
+    impl Resolver for StubResolver {
+        async fn resolve(&self, _host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(vec![SocketAddr::new("10.0.0.9".parse().unwrap(), port)])
+        }
+    }
+
+    fn seeded_manager() -> PeerManager {
+        let config = PeerManagerConfig {
+            max_outbound: 2,
+            seeds: SeedConfig {
+                static_seeds: vec!["10.0.0.8:6000".parse().unwrap()],
+                dns_seeds: vec!["seed.example".to_string()],
+                default_port: 6000,
+            },
+            ..Default::default()
+        };
+
+        PeerManager::new(
+            config,
+            ChainManager::new(Blockchain::new_with_genesis(), Mempool::new()),
+            events::channel(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_seeds_used_when_address_book_empty() {
+        let manager = seeded_manager();
+
+        let candidates = manager.outbound_candidates(&StubResolver).await;
+        assert_eq!(candidates, vec![
+            "10.0.0.8:6000".parse::<SocketAddr>().unwrap(),
+            "10.0.0.9:6000".parse::<SocketAddr>().unwrap(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_seeds_skipped_when_address_book_suffices() {
+        let manager = seeded_manager();
+        let known: Vec<SocketAddr> = vec![
+            "192.168.1.1:6000".parse().unwrap(),
+            "192.168.1.2:6000".parse().unwrap(),
+        ];
+        manager.add_addresses(known.clone()).await;
+
+        let mut candidates = manager.outbound_candidates(&StubResolver).await;
+        candidates.sort();
+        assert_eq!(candidates, known);
+    }
+
+    #[tokio::test]
+    async fn test_seeds_top_up_sparse_address_book() {
+        let manager = seeded_manager();
+        manager.add_addresses(["192.168.1.1:6000".parse().unwrap()]).await;
+
+        let candidates = manager.outbound_candidates(&StubResolver).await;
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0], "192.168.1.1:6000".parse::<SocketAddr>().unwrap());
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+
+/// How long a single DNS seed lookup may take before it is abandoned
+const DNS_SEED_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hostname resolution used for DNS seeds, abstracted so tests can stub it
+pub trait Resolver: Send + Sync {
+    /// Resolve every A/AAAA record for `host`, paired with `port`
+    fn resolve(&self, host: &str, port: u16) -> impl Future<Output = io::Result<Vec<SocketAddr>>> + Send;
+}
+
+/// Resolver backed by the operating system
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+/// Bootstrap addresses for a network
+#[derive(Debug, Clone, Default)]
+pub struct SeedConfig {
+    /// Addresses connected to directly
+    pub static_seeds: Vec<SocketAddr>,
+    /// Hostnames whose A/AAAA records are candidate peers
+    pub dns_seeds: Vec<String>,
+    /// Port paired with addresses resolved from `dns_seeds`
+    pub default_port: u16,
+}
+
+/// Collect seed addresses: static seeds first, then every address the DNS
+/// seeds resolve to. Lookup failures are logged and skipped.
+pub async fn resolve_seeds<R: Resolver>(resolver: &R, seeds: &SeedConfig) -> Vec<SocketAddr> {
+    let mut addrs = seeds.static_seeds.clone();
+
+    for host in &seeds.dns_seeds {
+        match timeout(DNS_SEED_TIMEOUT, resolver.resolve(host, seeds.default_port)).await {
+            Ok(Ok(resolved)) => {
+                debug!("DNS seed {} returned {} addresses", host, resolved.len());
+                addrs.extend(resolved);
+            }
+            Ok(Err(e)) => warn!("Failed to resolve DNS seed {}: {}", host, e),
+            Err(_) => warn!("Timed out resolving DNS seed {}", host),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    addrs.retain(|addr| seen.insert(*addr));
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubResolver;
+
+    impl Resolver for StubResolver {
+        async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            match host {
+                "seed.example" => Ok(vec![
+                    SocketAddr::new("10.0.0.1".parse().unwrap(), port),
+                    SocketAddr::new("::1".parse().unwrap(), port),
+                ]),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, "no such host")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_seeds_merges_static_and_dns() {
+        let seeds = SeedConfig {
+            static_seeds: vec!["10.0.0.1:6000".parse().unwrap()],
+            dns_seeds: vec!["seed.example".to_string(), "broken.example".to_string()],
+            default_port: 6000,
+        };
+
+        let addrs = resolve_seeds(&StubResolver, &seeds).await;
+        assert_eq!(addrs, vec![
+            "10.0.0.1:6000".parse::<SocketAddr>().unwrap(),
+            "[::1]:6000".parse::<SocketAddr>().unwrap(),
+        ]);
+    }
+}
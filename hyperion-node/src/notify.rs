@@ -0,0 +1,164 @@
+use crate::events::NodeEvent;
+use hyperion_core::crypto::hex32;
+
+use std::process::Stdio;
+use tokio::process::Command;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+
+/// Run `command` through the shell for every event `select` maps to a hash,
+/// with `%s` replaced by that hash.
+///
+/// Invocations run one at a time in event order. At most one more waits
+/// behind the one running; events arriving while it waits are dropped, so a
+/// burst costs at most two runs. Failures are only logged.
+pub fn spawn_hook(
+    name: &'static str,
+    command: String,
+    mut events: broadcast::Receiver<NodeEvent>,
+    select: fn(&NodeEvent) -> Option<String>,
+) {
+    let (pending_tx, mut pending) = mpsc::channel::<String>(1);
+
+    tokio::spawn(async move {
+        while let Some(hash) = pending.recv().await {
+            run(name, &command.replace("%s", &hash)).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("{} hook fell behind, skipped {} events", name, skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            if let Some(hash) = select(&event) {
+                match pending_tx.try_send(hash) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(hash)) => {
+                        debug!("{} hook busy with one run pending, skipping {}", name, hash);
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+        }
+    });
+}
+
+pub fn block_hash(event: &NodeEvent) -> Option<String> {
+    match event {
+        NodeEvent::BlockConnected { hash, .. } => Some(hex32(hash)),
+        _ => None,
+    }
+}
+
+pub fn txid(event: &NodeEvent) -> Option<String> {
+    match event {
+        NodeEvent::TxAccepted { txid } => Some(hex32(txid)),
+        _ => None,
+    }
+}
+
+async fn run(name: &str, command: &str) {
+    debug!("Running {} hook: {}", name, command);
+
+    let status = shell(command)
+        .stdin(Stdio::null())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("{} hook `{}` exited with {}", name, command, status),
+        Err(e) => warn!("Failed to run {} hook `{}`: {}", name, command, e),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::chain_manager::ChainManager;
+    use crate::events;
+    use crate::mempool::Mempool;
+    use crate::network::{PeerManager, PeerManagerConfig};
+
+    use hyperion_core::chain::blockchain::Blockchain;
+    use hyperion_core::crypto::Hashable;
+    use std::fs;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_block_notify_runs_in_order() {
+        let out = std::env::temp_dir().join(format!("hyperion-blocknotify-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&out);
+
+        let events = events::channel();
+        spawn_hook("block_notify", format!("echo %s >> {}", out.display()), events.subscribe(), block_hash);
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let peers = PeerManager::new(PeerManagerConfig::default(), chain_manager.clone(), events);
+
+        // Generate a few regtest-style blocks the way `submit_block` connects
+        // them, each once the hook has seen the last
+        let mut expected = String::new();
+        for _ in 0..3 {
+            let block = Blockchain::mine_new_block(&*chain_manager.chain().await, Vec::new(), 1);
+            let hash = block.double_sha256();
+            let height = chain_manager.connect_block(block).await.expect("Failed to add mined block");
+            peers.relay_block(hash, height).await;
+            expected += &format!("{}\n", hex::encode(hash));
+
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while fs::read_to_string(&out).unwrap_or_default() != expected {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            }).await.expect("Hook did not record every block in order");
+        }
+
+        fs::remove_file(out).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_burst_runs_hook_at_most_twice() {
+        let out = std::env::temp_dir().join(format!("hyperion-blocknotify-burst-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&out);
+
+        let events = events::channel();
+        spawn_hook("block_notify", format!("sleep 0.3; echo %s >> {}", out.display()), events.subscribe(), block_hash);
+
+        // Let the first start running, then burst while it sleeps
+        events::publish(&events, NodeEvent::BlockConnected { hash: [0; 32], height: 0 });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for i in 1..5u8 {
+            events::publish(&events, NodeEvent::BlockConnected { hash: [i; 32], height: i as u64 });
+        }
+
+        // The second waits behind the running one; the rest are dropped
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let expected = format!("{}\n{}\n", hex::encode([0u8; 32]), hex::encode([1u8; 32]));
+        assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+
+        fs::remove_file(out).unwrap();
+    }
+}
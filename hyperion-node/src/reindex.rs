@@ -0,0 +1,152 @@
+use crate::chain_manager::{BlockSource, ChainManager};
+use crate::txindex::TxIndex;
+
+use hyperion_core::block::Block;
+use hyperion_core::crypto::{hex32, Hashable};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+
+/// Log progress every this many replayed blocks
+const PROGRESS_INTERVAL: u64 = 1_000;
+
+/// How far a running reindex has got, shared with RPC
+#[derive(Debug, Default)]
+pub struct ReindexProgress {
+    active: AtomicBool,
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+impl ReindexProgress {
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Fraction of stored blocks replayed, 1.0 when no reindex is running
+    pub fn verification_progress(&self) -> f64 {
+        if !self.is_active() {
+            return 1.0;
+        }
+
+        let total = self.total.load(Ordering::SeqCst);
+        if total == 0 {
+            return 0.0;
+        }
+        self.done.load(Ordering::SeqCst) as f64 / total as f64
+    }
+
+    /// Mark a reindex of `total` blocks as running
+    pub fn start(&self, total: u64) {
+        self.done.store(0, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+        self.active.store(true, Ordering::SeqCst);
+    }
+
+    fn advance(&self) -> u64 {
+        self.done.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn finish(&self) {
+        self.active.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Rebuild `chain` and `txindex` by replaying `blocks` (genesis first) with
+/// full validation. Replay stops at the first invalid block, leaving the
+/// chain at the last good one.
+///
+/// `progress` must already be started so RPC sees the reindex from the moment
+/// the node comes up.
+pub async fn reindex(
+    chain_manager: ChainManager,
+    blocks: Vec<Block>,
+    txindex: Option<Arc<RwLock<TxIndex>>>,
+    progress: Arc<ReindexProgress>,
+) {
+    let total = blocks.len() as u64;
+    info!("Reindexing {} blocks", total);
+
+    let mut blocks = blocks.into_iter();
+    if let Some(genesis) = blocks.next() {
+        chain_manager.reset_chain(genesis).await;
+        if let Some(txindex) = &txindex {
+            let mut index = txindex.write().await;
+            *index = TxIndex::new();
+            index.catch_up(&*chain_manager.chain().await);
+        }
+        progress.advance();
+    }
+
+    for (height, block) in (1u64..).zip(blocks) {
+        let hash = block.double_sha256();
+
+        if let Err(e) = chain_manager.connect_block_from(block, BlockSource::Import, None).await {
+            error!("Stored block {} at height {} is invalid, stopping reindex: {}", hex32(&hash), height, e);
+            break;
+        }
+
+        if let Some(txindex) = &txindex {
+            txindex.write().await.catch_up(&*chain_manager.chain().await);
+        }
+
+        let done = progress.advance();
+        if done.is_multiple_of(PROGRESS_INTERVAL) {
+            info!("Reindex progress: {}/{} blocks ({:.1}%)", done, total, progress.verification_progress() * 100.0);
+        }
+    }
+
+    progress.finish();
+    info!("Reindex complete at height {}", chain_manager.chain().await.len() - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::Mempool;
+    use hyperion_core::chain::blockchain::Blockchain;
+    use hyperion_core::testutil::ChainBuilder;
+
+    fn stored_chain(blocks: usize) -> Blockchain {
+        ChainBuilder::new(blocks as u64).mine_blocks(blocks, 1).build()
+    }
+
+    #[tokio::test]
+    async fn test_reindex_replays_every_block() {
+        let stored = stored_chain(3);
+        let blocks: Vec<_> = stored.iter().cloned().collect();
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let txindex = Arc::new(RwLock::new(TxIndex::new()));
+        let progress = Arc::new(ReindexProgress::default());
+        progress.start(blocks.len() as u64);
+        assert_eq!(progress.verification_progress(), 0.0);
+
+        reindex(chain_manager.clone(), blocks, Some(txindex.clone()), progress.clone()).await;
+
+        assert!(!progress.is_active());
+        assert_eq!(progress.verification_progress(), 1.0);
+        assert_eq!(chain_manager.chain().await.len(), 4);
+
+        let txid = stored.get_block_by_height(3).unwrap().transactions[0].double_sha256();
+        assert_eq!(txindex.read().await.height_of(&txid), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_reindex_stops_at_invalid_block() {
+        let mut blocks: Vec<_> = stored_chain(3).iter().cloned().collect();
+        blocks[2].header.prev_hash = [0xff; 32];
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let progress = Arc::new(ReindexProgress::default());
+        progress.start(blocks.len() as u64);
+
+        reindex(chain_manager.clone(), blocks, None, progress.clone()).await;
+
+        assert!(!progress.is_active());
+        assert_eq!(chain_manager.chain().await.len(), 2);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::node_config::NetworkConfig;
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+/// Groups of RPC methods `rpc_allow` and `rpc_deny` may name instead of the
+/// methods themselves. Every method the node serves is in one of them.
+pub const METHOD_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "read",
+        &[
+            "get_block",
+            "get_block_count",
+            "get_blockchain_info",
+            "get_chain_timing",
+            "get_headers",
+            "get_recent_blocks",
+            "get_raw_transaction",
+            "decode_raw_transaction",
+            "get_mempool_info",
+            "get_raw_mempool",
+            "get_mining_info",
+            "get_network_info",
+            "get_version_info",
+        ],
+    ),
+    ("mining", &["get_block_template", "submit_block"]),
+    (
+        "admin",
+        &[
+            "get_peer_info",
+            "set_ban",
+            "list_banned",
+            "export_chain",
+            "import_chain",
+            "snapshot_chain",
+            "restore_chain",
+        ],
+    ),
+];
+
+/// Which methods each caller may use. A method in `rpc_deny` is refused to
+/// everyone; otherwise callers from `rpc_trusted` may use any method, and
+/// the rest those in `rpc_allow`, or any when it's empty.
+#[derive(Debug, Default)]
+pub struct RpcAccess {
+    /// None allows every method
+    allow: Option<HashSet<&'static str>>,
+    deny: HashSet<&'static str>,
+    trusted: HashSet<IpAddr>,
+}
+
+impl RpcAccess {
+    /// Fails on a name that is neither a method nor a group, so a typo
+    /// doesn't quietly leave a method open
+    pub fn new(network: &NetworkConfig) -> Result<Self> {
+        let allow = match network.rpc_allow.is_empty() {
+            true => None,
+            false => Some(expand("network.rpc_allow", &network.rpc_allow)?),
+        };
+        Ok(Self {
+            allow,
+            deny: expand("network.rpc_deny", &network.rpc_deny)?,
+            trusted: network.rpc_trusted.iter().copied().collect(),
+        })
+    }
+
+    pub fn allows(&self, method: &str, source: IpAddr) -> bool {
+        if self.deny.contains(method) {
+            return false;
+        }
+        self.trusted.contains(&source) || self.allow.as_ref().is_none_or(|allow| allow.contains(method))
+    }
+}
+
+/// The methods `names` stand for, each a method or a group of them
+fn expand(field: &str, names: &[String]) -> Result<HashSet<&'static str>> {
+    let mut methods = HashSet::new();
+    for name in names {
+        if let Some((_, group)) = METHOD_GROUPS.iter().find(|(group, _)| group == name) {
+            methods.extend(group.iter().copied());
+        } else if let Some(&method) = METHOD_GROUPS.iter().flat_map(|(_, group)| group.iter()).find(|&&method| method == name) {
+            methods.insert(method);
+        } else {
+            let groups: Vec<&str> = METHOD_GROUPS.iter().map(|(group, _)| *group).collect();
+            bail!("Unknown RPC method or group '{}' in {}; groups are {}", name, field, groups.join(", "));
+        }
+    }
+    Ok(methods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(allow: &[&str], deny: &[&str], trusted: &[&str]) -> Result<RpcAccess> {
+        let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        RpcAccess::new(&NetworkConfig {
+            rpc_allow: strings(allow),
+            rpc_deny: strings(deny),
+            rpc_trusted: trusted.iter().map(|ip| ip.parse().unwrap()).collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_groups_and_methods_combine() {
+        let public: IpAddr = "203.0.113.5".parse().unwrap();
+        let miner: IpAddr = "10.0.0.2".parse().unwrap();
+        let access = access(&["read", "submit_block"], &["import_chain"], &["10.0.0.2"]).unwrap();
+
+        assert!(access.allows("get_block", public));
+        assert!(access.allows("submit_block", public));
+        assert!(!access.allows("get_block_template", public));
+        assert!(!access.allows("set_ban", public));
+        // Trusted callers get everything but what's denied
+        assert!(access.allows("get_block_template", miner));
+        assert!(access.allows("set_ban", miner));
+        assert!(!access.allows("import_chain", miner));
+
+        assert!(RpcAccess::default().allows("import_chain", public));
+    }
+
+    #[test]
+    fn test_unknown_names_are_refused() {
+        let error = access(&["read", "raed"], &[], &[]).unwrap_err().to_string();
+        assert!(error.contains("'raed'") && error.contains("rpc_allow"), "{}", error);
+        assert!(access(&[], &["generate"], &[]).is_err());
+    }
+}
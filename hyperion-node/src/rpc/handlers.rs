@@ -1,48 +1,100 @@
-use super::types::*;
+use super::access::RpcAccess;
+use super::health::Lifecycle;
+use super::throttle::SubmitThrottle;
 
-use crate::mempool::Mempool;
+use crate::chain_export::ChainExport;
+use crate::chain_snapshot::ChainSnapshot;
+use crate::chain_manager::{BlockArrival, BlockSource, ChainManager, ConnectError};
+use crate::chain_writer::ChainWriter;
+use crate::config::NodeConfig;
+use crate::config::node_config::Network;
+use crate::events::{self, EventSender, NodeEvent};
+use crate::network::{Direction, PeerManager};
+use crate::network::message::PROTOCOL_VERSION;
+use crate::reindex::ReindexProgress;
+use crate::storage::DiskUsage;
+use crate::txindex::TxIndex;
 use crate::utils;
 
-use hyperion_core::block::{Block, Serializable};
-use hyperion_core::chain::blockchain::Blockchain;
-use hyperion_core::consensus::adjust_difficulty;
-use hyperion_core::crypto::Hashable;
+use hyperion_core::block::{Block, Serializable, Transaction};
+use hyperion_core::consensus::{self, adjust_difficulty, MAX_BLOCK_OVERHEAD, MAX_BLOCK_SIZE, MAX_FUTURE_BLOCK_TIME};
+use hyperion_core::crypto::{hex32, parse_hex, parse_hex32, to_hex, Hashable};
+use hyperion_rpc::*;
 
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use axum::extract::State;
-use tokio::sync::RwLock;
-use tracing::{debug, info, warn, error, instrument};
+use tokio::sync::{RwLock, broadcast};
+use tracing::{debug, info, warn, instrument};
+
+/// Most headers returned by one `get_headers` call
+const MAX_HEADERS: usize = 2000;
 
 #[derive(Clone)]
 pub struct NodeState {
-    pub chain: Arc<RwLock<Blockchain>>,
-    pub mempool: Arc<RwLock<Mempool>>,
+    pub chain_manager: ChainManager,
+    /// Saves the chain after blocks connect, off the request path
+    pub chain_writer: ChainWriter,
+    pub peers: PeerManager,
+    pub config: Arc<NodeConfig>,
+    /// Addresses the P2P listener is bound to, empty when P2P is disabled
+    pub p2p_addrs: Vec<SocketAddr>,
+    /// Fired once when the node begins shutting down
+    pub shutdown: broadcast::Sender<()>,
+    /// Present when `storage.txindex` is enabled
+    pub txindex: Option<Arc<RwLock<TxIndex>>>,
+    pub reindex: Arc<ReindexProgress>,
+    pub events: EventSender,
+    pub submit_throttle: Arc<SubmitThrottle>,
+    /// Which methods each caller may use
+    pub rpc_access: Arc<RpcAccess>,
+    pub lifecycle: Arc<Lifecycle>,
+    /// Size of the chain's files, for `get_blockchain_info`
+    pub disk_usage: Arc<DiskUsage>,
 }
 
 #[instrument(skip(state), fields(height))]
 pub async fn get_block_template(
     State(state): State<NodeState>,
-    _params: Option<serde_json::Value>,
+    params: Option<GetBlockTemplateParams>,
 ) -> Result<BlockTemplate, RpcError> {
-    let chain = state.chain.read().await;
-    let mut mempool = state.mempool.write().await;
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
 
-    let transactions = mempool.get_next_transaction(100).unwrap_or_default();
-    let latest_block = chain.latest_block();
-    let difficulty = adjust_difficulty(&chain);
-    let merkle_root = hyperion_core::block::block::compute_merkle_root(&transactions);
+    let cap = state.config.mempool.template_max_txs;
+    let max_txs = match params.and_then(|params| params.max_txs) {
+        Some(max_txs) => usize::try_from(max_txs).map_or(cap, |max_txs| max_txs.min(cap)),
+        None => cap,
+    };
+    let selected = state.chain_manager.select_template_txs(max_txs, MAX_BLOCK_SIZE - MAX_BLOCK_OVERHEAD).await;
+    let tx_count = selected.transactions.len() as u64;
+    // Without a coinbase to fill it, a template can only be empty
+    if tx_count == 0 && !state.chain_manager.tip().params.allow_empty_blocks {
+        return Err(RpcError::custom(-10, "No transactions to mine, and this chain doesn't allow empty blocks"));
+    }
 
-    let height = chain.len() as u64;
-    tracing::Span::current().record("height", &height);
+    tracing::Span::current().record("height", selected.height);
 
+    // A tip stamped ahead of this node's clock moves the time forward
+    let now = utils::current_timestamp();
     let template = BlockTemplate {
         version: 1,
-        previous_block_hash: hex::encode(latest_block.double_sha256()),
-        transactions,
-        difficulty_compact: difficulty,
-        timestamp: utils::current_timestamp(),
-        height,
-        merkle_root: hex::encode(merkle_root),
+        previous_block_hash: hex32(&selected.prev_hash),
+        transactions: selected.transactions,
+        difficulty_compact: selected.difficulty,
+        timestamp: now.max(selected.min_time),
+        cur_time: Some(now),
+        min_time: Some(selected.min_time),
+        max_time: Some(now.saturating_add(MAX_FUTURE_BLOCK_TIME)),
+        height: selected.height,
+        merkle_root: hex32(&selected.merkle_root),
+        size_estimate: Some((MAX_BLOCK_OVERHEAD + selected.size) as u64),
+        tx_count: Some(tx_count),
+        longpollid: None,
     };
 
     debug!(
@@ -61,53 +113,66 @@ pub async fn get_block_template(
 pub async fn submit_block(
     State(state): State<NodeState>,
     params: Option<SubmitBlockParams>,
+    source: IpAddr,
 ) -> Result<SubmitBlockResult, RpcError> {
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
+
     let params = params.ok_or_else(|| RpcError::invalid_params("Missing block data"))?;
 
-    let block_bytes = hex::decode(&params.block_hex)
-        .map_err(|e| RpcError::invalid_params(&format!("Invalid hex: {}", e)))?;
+    let block_bytes = decode_hex("block_hex", &params.block_hex)?;
 
     let block = Block::from_bytes(&block_bytes)
         .map_err(|e| RpcError::invalid_params(&format!("Invalid block: {}", e)))?;
 
-    let block_hash = hex::encode(block.double_sha256());
+    let block_hash = hex32(&block.double_sha256());
     tracing::Span::current().record("block_hash", &block_hash);
 
-    // Add block to chain
-    let mut chain = state.chain.write().await;
-    match chain.add_block(block.clone(), false) {
-        Ok(()) => {
+    let hash = block.double_sha256();
+    let tx_count = block.transactions.len();
+
+    match state.chain_manager.connect_block_from(block, BlockSource::Rpc, Some(source.to_string())).await {
+        Ok(height) => {
             info!(
                 //block_hash = %block_hash,
-                height = %chain.len(),
-                tx_count = %block.transactions.len(),
+                height = %height,
+                tx_count = %tx_count,
                 "Block accepted"
             );
 
-            let mut mempool = state.mempool.write().await;
-            for tx in &block.transactions {
-                mempool.remove_tx(tx); 
-            }
+            // The writer logs failures; per_block answers only once the write is done
+            let _ = state.chain_writer.write().await;
 
-            if let Err(e) = crate::storage::save_chain(&*chain) {
-                error!("Failed to save blockchain to disk: {}", e);
-            }
+            state.peers.relay_block(hash, height).await;
 
             Ok(SubmitBlockResult {
                 accepted: true,
                 message: None,
+                reject_reason: None,
+                block_hash: Some(block_hash),
+                height: Some(height),
             })
         },
         Err(e) => {
             warn!(
                 //block_hash = %block_hash,
-                error = ?e,
+                error = %e,
                 "Block rejected"
             );
+            events::publish(&state.events, NodeEvent::BlockRejected { hash });
 
+            let reject_reason = e.reject_reason().to_string();
+            let message = match e {
+                ConnectError::Invalid(e) => format!("{:?}", e),
+                ConnectError::Known => e.to_string(),
+            };
             Ok(SubmitBlockResult {
                 accepted: false,
-                message: Some(format!("{:?}", e)),
+                message: Some(message),
+                reject_reason: Some(reject_reason),
+                block_hash: Some(block_hash),
+                height: None,
             })
         }
     }
@@ -117,20 +182,22 @@ pub async fn get_mining_info(
     State(state): State<NodeState>,
     _params: Option<serde_json::Value>,
 ) -> Result<MiningInfo, RpcError> {
-    let chain = state.chain.read().await;
-    let mempool = state.mempool.read().await;
+    let tip = state.chain_manager.tip();
+    let mempool = state.chain_manager.mempool().await;
 
-    let difficulty = adjust_difficulty(&chain);
-    let difficulty_f64 = difficulty as f64;  // Convert compact to readable
+    let difficulty_f64 = tip.next_difficulty as f64;  // Convert compact to readable
 
     Ok(MiningInfo {
-        blocks: chain.len() as u64,
+        blocks: tip.height + 1,
         current_block_size: 0,  // TODO: Calculate
         current_block_tx: 0,  // TODO: Calculate
         difficulty: difficulty_f64,
         network_hashps: 0.0,  // TODO: Estimate
         pooled_tx: mempool.len() as u64,
-        chain: "hyperion".to_string(),
+        chain: state.config.chain.as_str().to_string(),
+        next_retarget_height: Some(tip.retarget.next_retarget_height),
+        blocks_until_retarget: Some(tip.retarget.blocks_until_retarget),
+        estimated_next_difficulty: Some(tip.retarget.estimated_next_difficulty as f64),
     })
 }
 
@@ -138,17 +205,64 @@ pub async fn get_blockchain_info(
     State(state): State<NodeState>,
     _params: Option<serde_json::Value>,
 ) -> Result<ChainInfo, RpcError> {
-    let chain = state.chain.read().await;
+    // Measured before taking the chain lock, which the file check needn't hold up
+    let size_on_disk = state.disk_usage.bytes().await;
+    let chain = state.chain_manager.chain().await;
     let latest_block = chain.latest_block();
     let difficulty = adjust_difficulty(&chain);
 
     Ok(ChainInfo {
-        chain: "hyperion".to_string(),
+        chain: state.config.chain.as_str().to_string(),
         blocks: chain.len() as u64,
         headers: chain.len() as u64,
-        best_blockhash: hex::encode(latest_block.double_sha256()),
+        best_blockhash: hex32(&latest_block.double_sha256()),
         difficulty: difficulty as f64,
         median_time: latest_block.header.time,
+        verification_progress: state.reindex.verification_progress(),
+        size_on_disk: Some(size_on_disk),
+        chain_work: Some(state.chain_manager.tip().chain_work.to_string()),
+        last_block: state.chain_manager.recent_blocks(1).into_iter().next().map(recent_block),
+    })
+}
+
+pub async fn get_recent_blocks(
+    State(state): State<NodeState>,
+    params: Option<GetRecentBlocksParams>,
+) -> Result<Vec<RecentBlock>, RpcError> {
+    let count = params
+        .unwrap_or_default()
+        .count
+        .map_or(usize::MAX, |count| usize::try_from(count).unwrap_or(usize::MAX));
+
+    Ok(state.chain_manager.recent_blocks(count).into_iter().map(recent_block).collect())
+}
+
+fn recent_block(arrival: BlockArrival) -> RecentBlock {
+    RecentBlock {
+        hash: hex32(&arrival.hash),
+        height: arrival.height,
+        received_time: arrival.received_time,
+        propagation_secs: arrival.propagation_secs,
+        source: arrival.source.as_str().to_string(),
+        submitter: arrival.submitter,
+    }
+}
+
+pub async fn get_version_info(
+    State(state): State<NodeState>,
+    _params: Option<serde_json::Value>,
+) -> Result<VersionInfo, RpcError> {
+    let chain = state.chain_manager.chain().await;
+    let genesis = chain
+        .get_block_by_height(0)
+        .ok_or_else(|| RpcError::internal_error("Chain has no genesis block"))?;
+
+    Ok(VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_major: PROTOCOL_MAJOR,
+        protocol_minor: PROTOCOL_MINOR,
+        network: state.config.chain.as_str().to_string(),
+        genesis_hash: hex32(&genesis.double_sha256()),
     })
 }
 
@@ -156,6 +270,446 @@ pub async fn get_block_count(
     State(state): State<NodeState>,
     _params: Option<serde_json::Value>,
 ) -> Result<u64, RpcError> {
-    let chain = state.chain.read().await;
-    Ok(chain.len() as u64 - 1)  // Bitcoin returns height, not count
-}
\ No newline at end of file
+    Ok(state.chain_manager.tip().height)  // Bitcoin returns height, not count
+}
+
+pub async fn get_raw_transaction(
+    State(state): State<NodeState>,
+    params: Option<GetRawTransactionParams>,
+) -> Result<RawTransaction, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing txid"))?;
+    let txindex = state.txindex.as_ref()
+        .ok_or_else(|| RpcError::custom(-8, "Transaction index is disabled, enable storage.txindex"))?;
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
+
+    let txid = decode_hash("txid", &params.txid)?;
+
+    let height = txindex.read().await.height_of(&txid)
+        .ok_or_else(|| RpcError::custom(-5, "No such transaction"))?;
+
+    let chain = state.chain_manager.chain().await;
+    let block = chain.get_block_by_height(height as usize)
+        .ok_or_else(|| RpcError::internal_error("Transaction index points past the chain tip"))?;
+    let tx: &Transaction = block.transactions
+        .iter()
+        .find(|tx| tx.double_sha256() == txid)
+        .ok_or_else(|| RpcError::internal_error("Transaction index is inconsistent with the chain"))?;
+
+    Ok(RawTransaction {
+        txid: params.txid,
+        hex: to_hex(tx.serialize().map_err(|e| RpcError::internal_error(&e.to_string()))?),
+        block_hash: hex32(&block.double_sha256()),
+        height,
+    })
+}
+
+pub async fn decode_raw_transaction(
+    State(_state): State<NodeState>,
+    params: Option<DecodeRawTransactionParams>,
+) -> Result<DecodedTransaction, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing transaction data"))?;
+    decode_transaction(&params.tx_hex)
+}
+
+/// Take apart a serialized transaction, without regard to the chain or pool
+fn decode_transaction(tx_hex: &str) -> Result<DecodedTransaction, RpcError> {
+    let bytes = parse_hex(tx_hex).map_err(|e| RpcError::decode_failed(&format!("tx_hex: {}", e)))?;
+    let tx = Transaction::from_bytes(&bytes)
+        .map_err(|e| RpcError::decode_failed(&format!("Invalid transaction: {}", e)))?;
+
+    Ok(DecodedTransaction {
+        txid: hex32(&tx.double_sha256()),
+        size: bytes.len() as u64,
+        inputs: tx.inputs.iter().map(to_hex).collect(),
+        outputs: tx.outputs.iter().map(to_hex).collect(),
+    })
+}
+
+pub async fn get_block(
+    State(state): State<NodeState>,
+    params: Option<GetBlockParams>,
+) -> Result<RawBlock, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing hash"))?;
+    let hash = decode_hash("hash", &params.hash)?;
+
+    let chain = state.chain_manager.chain().await;
+    let height = chain.height_of(hash).ok_or_else(|| RpcError::custom(-5, "No such block"))?;
+    let block = chain.get_block_by_height(height).unwrap();
+
+    Ok(RawBlock {
+        hash: params.hash,
+        hex: to_hex(block.serialize().map_err(|e| RpcError::internal_error(&e.to_string()))?),
+        height: height as u64,
+    })
+}
+
+pub async fn get_headers(
+    State(state): State<NodeState>,
+    params: Option<GetHeadersParams>,
+) -> Result<HeaderBatch, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing start and count"))?;
+
+    let chain = state.chain_manager.chain().await;
+    let start = match &params.start {
+        BlockRef::Height(height) => usize::try_from(*height).ok().filter(|&height| height < chain.len()),
+        BlockRef::Hash(hash) => chain.height_of(decode_hash("start", hash)?),
+    }
+    .ok_or_else(|| RpcError::custom(-5, "No such block"))?;
+
+    let count = params.count.min(MAX_HEADERS as u64) as usize;
+    let headers = chain.get_headers_range(start, count)
+        .map(|header| header.serialize().map(to_hex))
+        .collect::<Result<_, _>>()
+        .map_err(|e| RpcError::internal_error(&e.to_string()))?;
+
+    Ok(HeaderBatch { start_height: start as u64, headers })
+}
+
+pub async fn get_chain_timing(
+    State(state): State<NodeState>,
+    params: Option<GetChainTimingParams>,
+) -> Result<ChainTiming, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing window"))?;
+    let window = usize::try_from(params.window).unwrap_or(usize::MAX);
+
+    let chain = state.chain_manager.chain().await;
+    let timing = consensus::chain_timing(chain.iter().map(|block| &block.header), window);
+    drop(chain);
+
+    Ok(ChainTiming {
+        blocks: timing.blocks,
+        mean_interval: timing.mean,
+        median_interval: timing.median,
+        stddev_interval: timing.stddev,
+        min_interval: timing.min,
+        max_interval: timing.max,
+        epochs: timing.epochs
+            .into_iter()
+            .map(|epoch| EpochTiming {
+                start_height: epoch.start_height,
+                end_height: epoch.end_height,
+                retarget_height: epoch.retarget_height,
+                actual_time: epoch.actual_time,
+                expected_time: epoch.expected_time,
+            })
+            .collect(),
+    })
+}
+
+pub async fn export_chain(
+    State(state): State<NodeState>,
+    params: Option<ExportChainParams>,
+) -> Result<ChainExportResult, RpcError> {
+    let params = params.unwrap_or_default();
+    let file = params.path.as_deref().map(|path| datadir_file(&state, path)).transpose()?;
+
+    let chain = state.chain_manager.chain().await;
+    let tip = chain.len() as u64 - 1;
+    let from = params.from_height.unwrap_or(0);
+    let to = params.to_height.unwrap_or(tip);
+    if from > to || to > tip {
+        return Err(RpcError::custom(-8, &format!("Heights must satisfy from_height <= to_height <= {}", tip)));
+    }
+    let export = ChainExport::of(&chain, from as usize, to as usize);
+    drop(chain);
+
+    let bytes = export.serialize().map_err(|e| RpcError::internal_error(&e.to_string()))?;
+    let (path, hex) = match (params.path, file) {
+        (Some(path), Some(file)) => {
+            write_new_file(&file, &bytes).await.map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => RpcError::custom(-8, &format!("{} already exists", path)),
+                _ => RpcError::internal_error(&format!("Failed to write {}: {}", path, e)),
+            })?;
+            info!(%path, from, to, "Exported chain");
+            (Some(path), None)
+        }
+        _ => (None, Some(to_hex(&bytes))),
+    };
+
+    Ok(ChainExportResult { from_height: from, to_height: to, blocks: export.block_count() as u64, path, hex })
+}
+
+pub async fn import_chain(
+    State(state): State<NodeState>,
+    params: Option<ImportChainParams>,
+) -> Result<ChainImportResult, RpcError> {
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
+
+    let bytes = match params.unwrap_or_default() {
+        ImportChainParams { path: Some(path), hex: None } => tokio::fs::read(datadir_file(&state, &path)?).await
+            .map_err(|e| RpcError::custom(-8, &format!("Failed to read {}: {}", path, e)))?,
+        ImportChainParams { path: None, hex: Some(hex) } => parse_hex(&hex)
+            .map_err(|e| RpcError::decode_failed(&format!("hex: {}", e)))?,
+        _ => return Err(RpcError::invalid_params("Expected exactly one of path and hex")),
+    };
+
+    let export = ChainExport::decode(&bytes).map_err(|e| RpcError::decode_failed(&e.to_string()))?;
+    let report = export.import(&state.chain_manager).await.map_err(|e| RpcError::custom(-8, &e.to_string()))?;
+    if let Some(stopped) = &report.stopped {
+        warn!("Chain import stopped: {}", stopped);
+    }
+
+    let tip = state.chain_manager.tip();
+    if report.accepted > 0 {
+        info!(accepted = report.accepted, skipped = report.skipped, height = tip.height, "Imported chain");
+        let _ = state.chain_writer.write().await;
+        // Peers fetch whatever they're missing below the new tip
+        state.peers.relay_block(tip.hash, tip.height).await;
+    }
+
+    Ok(ChainImportResult {
+        accepted: report.accepted,
+        skipped: report.skipped,
+        rejected: report.rejected,
+        height: tip.height,
+        error: report.stopped.map(|stopped| stopped.to_string()),
+    })
+}
+
+/// `path` as a file in the chain's data directory. RPC callers name files
+/// relative to it and can't reach outside: absolute paths and `..` are
+/// refused.
+fn datadir_file(state: &NodeState, path: &str) -> Result<PathBuf, RpcError> {
+    let relative = Path::new(path);
+    if path.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(RpcError::invalid_params("path must be relative to the data directory and not use .."));
+    }
+    Ok(state.config.chain_dir().join(relative))
+}
+
+/// Write `bytes` to a file that mustn't exist yet, synced before returning
+async fn write_new_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::OpenOptions::new().write(true).create_new(true).open(path).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await
+}
+
+/// Snapshots skip block validation on restore, so only regtest serves them
+fn require_regtest(state: &NodeState, method: &str) -> Result<(), RpcError> {
+    if state.config.chain != Network::Regtest {
+        return Err(RpcError::method_disabled(method));
+    }
+    Ok(())
+}
+
+pub async fn snapshot_chain(
+    State(state): State<NodeState>,
+    params: Option<ChainSnapshotParams>,
+) -> Result<ChainSnapshotResult, RpcError> {
+    require_regtest(&state, "snapshot_chain")?;
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
+    let path = params.ok_or_else(|| RpcError::invalid_params("Missing path"))?.path;
+    let file = datadir_file(&state, &path)?;
+    let already_exists = || RpcError::custom(-8, &format!("{} already exists", path));
+    if tokio::fs::try_exists(&file).await.unwrap_or(false) {
+        return Err(already_exists());
+    }
+
+    let snapshot = ChainSnapshot::take(&state.chain_manager, state.config.chain, state.txindex.as_deref()).await;
+    let bytes = snapshot.serialize().map_err(|e| RpcError::internal_error(&e.to_string()))?;
+
+    // Written beside the target and linked into place, so a reader never sees
+    // half a snapshot and one taken meanwhile isn't replaced
+    let mut partial = file.clone().into_os_string();
+    partial.push(".partial");
+    let written = match tokio::fs::write(&partial, &bytes).await {
+        Ok(()) => tokio::fs::hard_link(&partial, &file).await,
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&partial).await;
+    written.map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => already_exists(),
+        _ => RpcError::internal_error(&format!("Failed to write {}: {}", path, e)),
+    })?;
+    info!(%path, height = snapshot.height, "Saved chain snapshot");
+
+    Ok(ChainSnapshotResult {
+        path,
+        height: snapshot.height,
+        hash: hex32(&snapshot.tip_hash),
+        mempool_size: snapshot.mempool.len() as u64,
+    })
+}
+
+pub async fn restore_chain(
+    State(state): State<NodeState>,
+    params: Option<ChainSnapshotParams>,
+) -> Result<ChainSnapshotResult, RpcError> {
+    require_regtest(&state, "restore_chain")?;
+    if state.reindex.is_active() {
+        return Err(RpcError::reindexing());
+    }
+    let path = params.ok_or_else(|| RpcError::invalid_params("Missing path"))?.path;
+
+    let bytes = tokio::fs::read(datadir_file(&state, &path)?).await
+        .map_err(|e| RpcError::custom(-8, &format!("Failed to read {}: {}", path, e)))?;
+    let snapshot = ChainSnapshot::decode(&bytes).map_err(|e| RpcError::decode_failed(&e.to_string()))?;
+    snapshot.restore(&state.chain_manager, state.config.chain, state.txindex.as_deref()).await
+        .map_err(|e| RpcError::custom(-8, &e.to_string()))?;
+
+    let tip = state.chain_manager.tip();
+    info!(%path, height = tip.height, "Restored chain snapshot");
+    let _ = state.chain_writer.write().await;
+    // Peers fetch whatever they're missing below the new tip
+    state.peers.relay_block(tip.hash, tip.height).await;
+
+    Ok(ChainSnapshotResult {
+        path,
+        height: tip.height,
+        hash: hex32(&tip.hash),
+        mempool_size: state.chain_manager.mempool().await.len() as u64,
+    })
+}
+
+/// `value` as a 32-byte hash, for the param named `field`
+fn decode_hash(field: &str, value: &str) -> Result<[u8; 32], RpcError> {
+    parse_hex32(value).map_err(|e| RpcError::invalid_params(&format!("{}: {}", field, e)))
+}
+
+/// `value` as bytes, for the param named `field`
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, RpcError> {
+    parse_hex(value).map_err(|e| RpcError::invalid_params(&format!("{}: {}", field, e)))
+}
+
+pub async fn get_mempool_info(
+    State(state): State<NodeState>,
+    _params: Option<serde_json::Value>,
+) -> Result<MempoolInfo, RpcError> {
+    let mempool = state.chain_manager.mempool().await;
+
+    Ok(MempoolInfo {
+        size: mempool.len() as u64,
+        max_size: mempool.max_txs() as u64,
+        generation: Some(mempool.generation()),
+    })
+}
+
+pub async fn get_raw_mempool(
+    State(state): State<NodeState>,
+    params: Option<GetRawMempoolParams>,
+) -> Result<RawMempool, RpcError> {
+    let params = params.unwrap_or_default();
+    let mempool = state.chain_manager.mempool().await;
+
+    if !params.verbose {
+        return Ok(RawMempool::Txids(
+            mempool.txs().iter().map(|tx| hex32(&tx.double_sha256())).collect()
+        ));
+    }
+
+    let counts = mempool.dependency_counts();
+    Ok(RawMempool::Verbose(mempool
+        .txs()
+        .iter()
+        .map(|tx| {
+            let txid = tx.double_sha256();
+            let (ancestors, descendants) = counts[&txid];
+            let entry = MempoolEntry {
+                size: tx.byte_size() as u64,
+                ancestor_count: ancestors as u64,
+                descendant_count: descendants as u64,
+                depends: mempool.parents(&txid).iter().map(hex32).collect(),
+            };
+            (hex32(&txid), entry)
+        })
+        .collect()))
+}
+
+pub async fn get_peer_info(
+    State(state): State<NodeState>,
+    _params: Option<serde_json::Value>,
+) -> Result<Vec<PeerInfo>, RpcError> {
+    let peers = state.peers.peers().await;
+
+    Ok(peers
+        .into_iter()
+        .map(|peer| PeerInfo {
+            id: peer.id,
+            addr: peer.addr.to_string(),
+            inbound: peer.direction == Direction::Inbound,
+            conn_time: peer.connected_at,
+            version: peer.protocol_version,
+            features: peer.features,
+            ping_time: peer.ping_latency.map(|d| d.as_secs_f64()),
+            bytes_sent: peer.traffic.bytes_sent,
+            bytes_recv: peer.traffic.bytes_recv,
+            msgs_sent: peer.traffic.msgs_sent,
+            msgs_recv: peer.traffic.msgs_recv,
+            misbehavior: peer.misbehavior,
+        })
+        .collect())
+}
+
+pub async fn get_network_info(
+    State(state): State<NodeState>,
+    _params: Option<serde_json::Value>,
+) -> Result<NetworkInfo, RpcError> {
+    let counts = state.peers.connection_counts().await;
+    let traffic = state.peers.total_traffic();
+    let config = state.peers.config();
+
+    Ok(NetworkInfo {
+        protocol_version: PROTOCOL_VERSION,
+        connections: counts.total() as u64,
+        connections_in: counts.inbound as u64,
+        connections_out: counts.outbound as u64,
+        max_connections: config.max_connections as u64,
+        max_inbound: config.max_inbound as u64,
+        max_outbound: config.max_outbound as u64,
+        total_bytes_recv: traffic.bytes_recv,
+        total_bytes_sent: traffic.bytes_sent,
+        local_addresses: state.p2p_addrs.iter().map(|addr| addr.to_string()).collect(),
+    })
+}
+
+pub async fn set_ban(
+    State(state): State<NodeState>,
+    params: Option<SetBanParams>,
+) -> Result<Vec<BannedPeer>, RpcError> {
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing address and command"))?;
+    let ip: IpAddr = params.address.parse()
+        .map_err(|_| RpcError::invalid_params("address must be an IP address"))?;
+
+    let saved = match params.command {
+        BanCommand::Add => {
+            let duration = params.duration_secs
+                .map(Duration::from_secs)
+                .unwrap_or(state.peers.config().ban_duration);
+            let reason = params.reason.as_deref().unwrap_or("manually banned");
+            match state.peers.ban(ip, reason, duration).await {
+                Ok(false) => return Err(RpcError::custom(-30, "Address is whitelisted")),
+                saved => saved.map(|_| ()),
+            }
+        }
+        BanCommand::Remove => match state.peers.unban(ip).await {
+            Ok(false) => return Err(RpcError::custom(-30, "Address is not banned")),
+            saved => saved.map(|_| ()),
+        },
+    };
+    saved.map_err(|e| RpcError::internal_error(&format!("Failed to save ban list: {}", e)))?;
+
+    list_banned(State(state), None).await
+}
+
+pub async fn list_banned(
+    State(state): State<NodeState>,
+    _params: Option<serde_json::Value>,
+) -> Result<Vec<BannedPeer>, RpcError> {
+    Ok(state.peers
+        .banned()
+        .into_iter()
+        .map(|(ip, entry)| BannedPeer {
+            address: ip.to_string(),
+            reason: entry.reason,
+            banned_until: entry.until,
+        })
+        .collect())
+}
@@ -0,0 +1,92 @@
+use super::handlers::NodeState;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Where the node is between startup and exit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Loading and checking the chain and indexes
+    Starting,
+    Running,
+    ShuttingDown,
+}
+
+/// The node's phase, set by the startup sequence and the shutdown signal
+/// and read by `/health`
+#[derive(Debug)]
+pub struct Lifecycle {
+    phase: AtomicU8,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self { phase: AtomicU8::new(Phase::Starting as u8) }
+    }
+}
+
+impl Lifecycle {
+    pub fn phase(&self) -> Phase {
+        match self.phase.load(Ordering::SeqCst) {
+            0 => Phase::Starting,
+            1 => Phase::Running,
+            _ => Phase::ShuttingDown,
+        }
+    }
+
+    pub fn set(&self, phase: Phase) {
+        self.phase.store(phase as u8, Ordering::SeqCst);
+    }
+}
+
+/// Body of `/health` and `/live`
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// `ok`, or `unavailable` along with a `reason`
+    pub status: &'static str,
+    pub phase: Phase,
+    /// A reindex is replaying stored blocks
+    pub reindexing: bool,
+    pub height: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Readiness: 200 once the node is up and serving its whole chain, 503
+/// while it starts, reindexes or shuts down
+pub async fn health(State(state): State<NodeState>) -> (StatusCode, Json<HealthReport>) {
+    let phase = state.lifecycle.phase();
+    let reindexing = state.reindex.is_active();
+    let reason = match phase {
+        Phase::Starting => Some("starting".to_string()),
+        Phase::ShuttingDown => Some("shutting down".to_string()),
+        Phase::Running if reindexing => Some(format!(
+            "reindexing, {:.0}% of stored blocks replayed",
+            state.reindex.verification_progress() * 100.0
+        )),
+        Phase::Running => None,
+    };
+
+    let status = if reason.is_none() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report(&state, phase, reindexing, reason)))
+}
+
+/// Liveness: 200 whenever the node answers at all
+pub async fn live(State(state): State<NodeState>) -> (StatusCode, Json<HealthReport>) {
+    let report = report(&state, state.lifecycle.phase(), state.reindex.is_active(), None);
+    (StatusCode::OK, Json(report))
+}
+
+fn report(state: &NodeState, phase: Phase, reindexing: bool, reason: Option<String>) -> HealthReport {
+    HealthReport {
+        status: if reason.is_none() { "ok" } else { "unavailable" },
+        phase,
+        reindexing,
+        height: state.chain_manager.tip().height,
+        reason,
+    }
+}
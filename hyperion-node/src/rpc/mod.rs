@@ -1,6 +1,8 @@
 pub mod server;
+pub mod access;
 pub mod handlers;
-pub mod types;
+pub mod health;
+pub mod params;
+pub mod throttle;
 
-pub use handlers::NodeState;
-pub use server::start_server;
\ No newline at end of file
+pub use handlers::NodeState;
\ No newline at end of file
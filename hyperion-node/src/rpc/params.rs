@@ -0,0 +1,232 @@
+//! Reading JSON-RPC params into their types. Params go through
+//! `ParamsDeserializer`, whose error records what serde objected to as it
+//! happens, so an invalid params error can name the field and what it
+//! expected without picking them out of an error message.
+
+use hyperion_rpc::{InvalidParam, RpcError};
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, Expected, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Read `params` as `P`, with no params at all, or null, as `None`
+pub fn parse_params<P: DeserializeOwned>(params: Option<Value>) -> Result<Option<P>, RpcError> {
+    let Some(params) = params.filter(|params| !params.is_null()) else {
+        return Ok(None);
+    };
+
+    serde_path_to_error::deserialize(ParamsDeserializer(params)).map(Some).map_err(|e| {
+        let path = e.path().to_string();
+        let error = e.into_inner();
+        let message = error.to_string();
+        let (field, expected) = match error {
+            // A missing field is reported at its parent
+            ParamError::Missing(missing) if path == "." => (missing.to_string(), None),
+            ParamError::Missing(missing) => (format!("{}.{}", path, missing), None),
+            ParamError::Invalid { expected, .. } => (path, Some(expected)),
+            ParamError::Other(_) => (path, None),
+        };
+        RpcError::invalid_param(InvalidParam { field, expected, message })
+    })
+}
+
+/// Why params didn't fit their type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamError {
+    /// A required field was left out
+    Missing(&'static str),
+    /// A value was there but of the wrong type or out of range
+    Invalid { expected: String, message: String },
+    Other(String),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::Missing(field) => write!(f, "missing field `{}`", field),
+            ParamError::Invalid { message, .. } | ParamError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl de::Error for ParamError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ParamError::Other(msg.to_string())
+    }
+
+    fn invalid_type(unexpected: Unexpected, expected: &dyn Expected) -> Self {
+        Self::invalid(expected.to_string(), format!("invalid type: {}, expected {}", unexpected, expected))
+    }
+
+    fn invalid_value(unexpected: Unexpected, expected: &dyn Expected) -> Self {
+        Self::invalid(expected.to_string(), format!("invalid value: {}, expected {}", unexpected, expected))
+    }
+
+    fn invalid_length(len: usize, expected: &dyn Expected) -> Self {
+        Self::invalid(expected.to_string(), format!("invalid length {}, expected {}", len, expected))
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        let expected = one_of(expected);
+        Self::invalid(expected.clone(), format!("unknown variant `{}`, expected {}", variant, expected))
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        let expected = one_of(expected);
+        Self::invalid(expected.clone(), format!("unknown field `{}`, expected {}", field, expected))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ParamError::Missing(field)
+    }
+}
+
+impl ParamError {
+    fn invalid(expected: String, message: String) -> Self {
+        ParamError::Invalid { expected, message }
+    }
+}
+
+/// `names` as serde lists them in its own messages
+fn one_of(names: &[&str]) -> String {
+    match names {
+        [] => "nothing".to_string(),
+        [name] => format!("`{}`", name),
+        [first, second] => format!("`{}` or `{}`", first, second),
+        names => format!("one of {}", names.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// A JSON value read with `ParamError` for its errors
+struct ParamsDeserializer(Value);
+
+impl ParamsDeserializer {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match &self.0 {
+            Value::Null => Unexpected::Unit,
+            Value::Bool(b) => Unexpected::Bool(*b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(u), _) => Unexpected::Unsigned(u),
+                (_, Some(i)) => Unexpected::Signed(i),
+                _ => Unexpected::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => Unexpected::Str(s),
+            Value::Array(_) => Unexpected::Seq,
+            Value::Object(_) => Unexpected::Map,
+        }
+    }
+}
+
+fn visit_object<'de, V: Visitor<'de>>(object: Map<String, Value>, visitor: V) -> Result<V::Value, ParamError> {
+    let mut map = MapDeserializer::new(object.into_iter().map(|(key, value)| (key, ParamsDeserializer(value))));
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+impl<'de> de::Deserializer<'de> for ParamsDeserializer {
+    type Error = ParamError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(u), _) => visitor.visit_u64(u),
+                (_, Some(i)) => visitor.visit_i64(i),
+                _ => visitor.visit_f64(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => visitor.visit_string(s),
+            Value::Array(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter().map(ParamsDeserializer));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Object(object) => visit_object(object, visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ParamError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Unit variants as their name, others as an object with the variant's
+    /// name as its only key
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ParamError> {
+        match self.0 {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Object(object) if object.len() == 1 => {
+                let map = MapDeserializer::new(object.into_iter().map(|(key, value)| (key, ParamsDeserializer(value))));
+                visitor.visit_enum(MapAccessDeserializer::new(map))
+            }
+            _ => Err(de::Error::invalid_type(self.unexpected(), &"a variant name or an object with one key")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ParamError> for ParamsDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_rpc::{BanCommand, GetHeadersParams, SetBanParams};
+    use serde_json::json;
+
+    fn invalid<P: DeserializeOwned + fmt::Debug>(params: Value) -> InvalidParam {
+        let error = parse_params::<P>(Some(params)).unwrap_err();
+        serde_json::from_value(error.data.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_params_read_like_serde_json() {
+        let params: SetBanParams = parse_params(Some(json!({ "address": "10.0.0.1", "command": "add", "duration_secs": 60 })))
+            .unwrap()
+            .unwrap();
+        assert_eq!((params.command, params.duration_secs, params.reason), (BanCommand::Add, Some(60), None));
+        let params: GetHeadersParams = parse_params(Some(json!({ "start": "00", "count": 2 }))).unwrap().unwrap();
+        assert_eq!(params.count, 2);
+        assert!(parse_params::<SetBanParams>(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_errors_are_structured() {
+        let param = invalid::<SetBanParams>(json!({ "address": "10.0.0.1", "command": "nuke" }));
+        assert_eq!((param.field.as_str(), param.expected.as_deref()), ("command", Some("`add` or `remove`")));
+        assert_eq!(param.message, "unknown variant `nuke`, expected `add` or `remove`");
+
+        let param = invalid::<SetBanParams>(json!({ "address": "10.0.0.1", "command": "add", "duration_secs": -1 }));
+        assert_eq!((param.field.as_str(), param.expected.as_deref()), ("duration_secs", Some("u64")));
+
+        let param = invalid::<SetBanParams>(json!("10.0.0.1"));
+        assert_eq!((param.field.as_str(), param.expected.as_deref()), (".", Some("struct SetBanParams")));
+        assert_eq!(param.message, "invalid type: string \"10.0.0.1\", expected struct SetBanParams");
+    }
+}
@@ -1,26 +1,33 @@
 use super::handlers::*;
-use super::types::*;
+use super::params::parse_params;
+use super::health::{health, live};
+use super::throttle::SubmitThrottle;
+use hyperion_rpc::*;
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
     http::StatusCode,
     response::Json,
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
 use tower_http::cors::CorsLayer;
-use tracing::debug;
+use tracing::{debug, warn};
 
 pub fn create_router(state: NodeState) -> Router {
     Router::new()
         .route("/", post(handle_rpc))
         .route("/rpc", post(handle_rpc))
+        .route("/health", get(health))
+        .route("/live", get(live))
         .layer(CorsLayer::permissive())
         .with_state(state)
 }
 
 pub async fn handle_rpc(
     state: State<NodeState>,
+    ConnectInfo(source): ConnectInfo<SocketAddr>,
     Json(request): Json<Value>,
 ) -> Result<Json<RpcResponse<Value>>, StatusCode> {
     debug!("RPC request: {}", request);
@@ -38,9 +45,23 @@ pub async fn handle_rpc(
         }
     };
 
+    if !state.rpc_access.allows(&rpc_req.method, source.ip()) {
+        debug!(source = %source.ip(), method = %rpc_req.method, "Refusing disabled RPC method");
+        return Ok(Json(RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: rpc_req.id,
+            result: None,
+            error: Some(RpcError::method_disabled(&rpc_req.method)),
+        }));
+    }
+
     let response = match rpc_req.method.as_str() {
         "get_block_template" => {
-            match get_block_template(state, rpc_req.params).await {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_block_template(state, params).await,
+                Err(error) => Err(error),
+            };
+            match result {
                 Ok(result) => RpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: rpc_req.id,
@@ -56,13 +77,230 @@ pub async fn handle_rpc(
             }
         }
         "submit_block" => {
-            let params: Option<SubmitBlockParams> = rpc_req.params
-                .map(|p| serde_json::from_value(p))
-                .transpose()
-                .map_err(|e| RpcError::invalid_params(&e.to_string()))
-                .unwrap_or(None);
+            let source = source.ip();
+            let throttle = state.submit_throttle.clone();
+            if throttle.is_throttled(source) {
+                warn!(%source, "Refusing block from source with too many invalid submissions");
+                return Err(StatusCode::TOO_MANY_REQUESTS);
+            }
+
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => submit_block(state, params, source).await,
+                Err(error) => Err(error),
+            };
+            record_submission(&throttle, source, &result);
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_raw_transaction" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_raw_transaction(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "decode_raw_transaction" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => decode_raw_transaction(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_block" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_block(state, params).await,
+                Err(error) => Err(error),
+            };
 
-            match submit_block(state, params).await {
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "export_chain" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => export_chain(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "import_chain" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => import_chain(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "snapshot_chain" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => snapshot_chain(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "restore_chain" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => restore_chain(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_recent_blocks" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_recent_blocks(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_chain_timing" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_chain_timing(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_headers" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_headers(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
                 Ok(result) => RpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: rpc_req.id,
@@ -109,6 +347,22 @@ pub async fn handle_rpc(
                 },
             }
         }
+        "get_version_info" => {
+            match get_version_info(state, rpc_req.params).await {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
         "get_block_count" => {
             match get_block_count(state, rpc_req.params).await {
                 Ok(result) => RpcResponse {
@@ -125,6 +379,112 @@ pub async fn handle_rpc(
                 },
             }
         }
+        "get_peer_info" => {
+            match get_peer_info(state, rpc_req.params).await {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_network_info" => {
+            match get_network_info(state, rpc_req.params).await {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "set_ban" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => set_ban(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "list_banned" => {
+            match list_banned(state, rpc_req.params).await {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_mempool_info" => {
+            match get_mempool_info(state, rpc_req.params).await {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+        "get_raw_mempool" => {
+            let result = match parse_params(rpc_req.params) {
+                Ok(params) => get_raw_mempool(state, params).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(result) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: Some(serde_json::to_value(result).unwrap()),
+                    error: None,
+                },
+                Err(error) => RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: rpc_req.id,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
         _ => RpcResponse {
             jsonrpc: "2.0".to_string(),
             id: rpc_req.id,
@@ -136,11 +496,920 @@ pub async fn handle_rpc(
     Ok(Json(response))
 }
 
-pub async fn start_server(state: NodeState, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+/// Count rejected and undecodable blocks against `source`; an accepted block
+/// clears its record. Other errors, such as reindexing, say nothing about the
+/// submitter and are ignored.
+fn record_submission(throttle: &SubmitThrottle, source: IpAddr, result: &Result<SubmitBlockResult, RpcError>) {
+    match result {
+        Ok(SubmitBlockResult { accepted: true, .. }) => throttle.record_success(source),
+        Ok(_) => throttle.record_failure(source),
+        Err(RpcError { code: -32602, .. }) => throttle.record_failure(source),
+        Err(_) => {}
+    }
+}
+
+/// Serve RPC on `listener` until `state.shutdown` fires, then stop accepting
+/// and let in-flight requests finish. Subscribes to the shutdown before the
+/// future is first polled, so a shutdown right after spawning it isn't missed.
+pub fn serve(listener: tokio::net::TcpListener, state: NodeState) -> impl Future<Output = std::io::Result<()>> {
+    let mut shutdown = state.shutdown.subscribe();
     let app = create_router(state);
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
-    //println!("RPC server listening on http://127.0.0.1:{}", port);
 
-    axum::serve(listener, app).await?;
-    Ok(())
-}
\ No newline at end of file
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.recv().await;
+        })
+        .into_future()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_manager::ChainManager;
+    use crate::chain_writer::{ChainWriter, FileStore, SlowStore};
+    use crate::config::NodeConfig;
+    use crate::config::node_config::{Network, SyncMode};
+    use crate::events;
+    use crate::mempool::Mempool;
+    use crate::metrics::NodeMetrics;
+    use crate::reindex::{self, ReindexProgress};
+    use crate::rpc::access::RpcAccess;
+    use crate::rpc::health::{Lifecycle, Phase};
+    use crate::storage::{self, DiskUsage};
+    use crate::txindex::TxIndex;
+    use crate::network::{Direction, PeerManager, PeerManagerConfig};
+    use crate::network::banlist::BanList;
+    use crate::network::message::{Message, read_message};
+
+    use hyperion_core::block::{Block, Header, Serializable, Transaction};
+    use hyperion_core::chain::blockchain::Blockchain;
+    use hyperion_core::consensus::{
+        ConsensusParams, ADJUSTMENT_INTERVAL, MAX_BLOCK_OVERHEAD, MAX_BLOCK_SIZE, MAX_FUTURE_BLOCK_TIME, TARGET_BLOCK_TIME,
+    };
+    use hyperion_core::crypto::Hashable;
+    use hyperion_core::testutil::ChainBuilder;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{RwLock, broadcast};
+
+    /// State as a node started with the default configuration builds it,
+    /// with its data directory under a temp dir unique to `name`
+    fn default_state(name: &str) -> NodeState {
+        let mut config = NodeConfig::default();
+        config.storage.datadir = std::env::temp_dir().join(format!("hyperion-rpc-{}-{}", name, std::process::id()));
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::load(&config.mempool, &config.chain_dir()));
+        let config_network = config.network.clone();
+        let metrics = Arc::new(NodeMetrics::default());
+        let chain_writer = ChainWriter::spawn(
+            chain_manager.clone(),
+            FileStore(config.chain_dir()),
+            metrics.clone(),
+            config.storage.sync_mode,
+            config.storage.write_queue_len,
+        );
+
+        NodeState {
+            peers: PeerManager::new(PeerManagerConfig::default(), chain_manager.clone(), events::channel()),
+            chain_manager,
+            chain_writer,
+            p2p_addrs: Vec::new(),
+            shutdown: broadcast::channel(1).0,
+            txindex: None,
+            reindex: Arc::new(ReindexProgress::default()),
+            events: events::channel(),
+            submit_throttle: Arc::new(SubmitThrottle::new(
+                config_network.rpc_max_bad_blocks,
+                config_network.rpc_bad_block_window(),
+            )),
+            rpc_access: Arc::new(RpcAccess::default()),
+            lifecycle: Arc::new(Lifecycle::default()),
+            disk_usage: Arc::new(DiskUsage::new(config.chain_dir())),
+            config: Arc::new(config),
+        }
+    }
+
+    async fn call_from(state: NodeState, source: &str, method: &str, params: Value) -> Result<RpcResponse<Value>, StatusCode> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let source = ConnectInfo(source.parse().unwrap());
+        handle_rpc(State(state), source, Json(request)).await.map(|Json(response)| response)
+    }
+
+    async fn call_with(state: NodeState, method: &str, params: Value) -> RpcResponse<Value> {
+        call_from(state, "127.0.0.1:50000", method, params).await.unwrap()
+    }
+
+    async fn call(state: NodeState, method: &str) -> Value {
+        let response = call_with(state, method, Value::Null).await;
+        assert!(response.error.is_none(), "{} failed: {:?}", method, response.error);
+        response.result.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_default_node_has_empty_mempool() {
+        let info = call(default_state("mempool"), "get_mempool_info").await;
+
+        assert_eq!(info["size"], 0);
+        assert_eq!(info["max_size"], NodeConfig::default().mempool.max_txs as u64);
+        assert_eq!(info["generation"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_raw_mempool_counts_relatives() {
+        let state = default_state("raw-mempool");
+        let spending = |parent: &Transaction| {
+            Transaction::new(vec![parent.double_sha256().to_vec()], vec![b"out".to_vec()]).unwrap()
+        };
+        let parent = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let child = spending(&parent);
+        let grandchild = spending(&child);
+        for tx in [&parent, &child, &grandchild] {
+            state.chain_manager.add_mempool_tx(tx.clone()).await.unwrap();
+        }
+        let [parent_id, child_id, grandchild_id] = [&parent, &child, &grandchild].map(|tx| hex::encode(tx.double_sha256()));
+
+        let txids = call(state.clone(), "get_raw_mempool").await;
+        assert_eq!(txids, json!([parent_id, child_id, grandchild_id]));
+
+        let verbose = call_with(state, "get_raw_mempool", json!({ "verbose": true })).await.result.unwrap();
+        assert_eq!(verbose[&parent_id]["ancestor_count"], 0);
+        assert_eq!(verbose[&parent_id]["descendant_count"], 2);
+        assert_eq!(verbose[&child_id]["depends"], json!([parent_id]));
+        assert_eq!(verbose[&grandchild_id]["ancestor_count"], 2);
+        assert_eq!(verbose[&grandchild_id]["descendant_count"], 0);
+        assert_eq!(verbose[&grandchild_id]["size"], grandchild.byte_size() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_version_info_identifies_chain() {
+        let info = call(default_state("version"), "get_version_info").await;
+        let genesis = hex::encode(hyperion_core::consensus::create_genesis_block().double_sha256());
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["protocol_major"], PROTOCOL_MAJOR);
+        assert_eq!(info["protocol_minor"], PROTOCOL_MINOR);
+        assert_eq!(info["network"], "mainnet");
+        assert_eq!(info["genesis_hash"], genesis);
+    }
+
+    #[tokio::test]
+    async fn test_get_block_by_hash() {
+        let state = default_state("get-block");
+        let genesis = hyperion_core::consensus::create_genesis_block();
+        let hash = hex::encode(genesis.double_sha256());
+
+        let response = call_with(state.clone(), "get_block", json!({ "hash": hash })).await;
+        let block: RawBlock = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(block.height, 0);
+        assert_eq!(Block::from_bytes(&hex::decode(block.hex).unwrap()).unwrap().double_sha256(), genesis.double_sha256());
+
+        let response = call_with(state.clone(), "get_block", json!({ "hash": "11".repeat(32) })).await;
+        assert_eq!(response.error.unwrap().code, -5);
+        let response = call_with(state, "get_block", json!({ "hash": "11" })).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_decode_raw_transaction_roundtrips() {
+        let state = default_state("decode-tx");
+        let tx = Transaction::new(vec![vec![0xaa; 32], b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let bytes = tx.serialize().unwrap();
+
+        let response = call_with(state.clone(), "decode_raw_transaction", json!({ "tx_hex": hex::encode(&bytes) })).await;
+        let decoded: DecodedTransaction = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(decoded.txid, hex::encode(tx.double_sha256()));
+        assert_eq!(decoded.size, bytes.len() as u64);
+        assert_eq!(decoded.inputs, ["aa".repeat(32), hex::encode(b"in")]);
+        assert_eq!(decoded.outputs, [hex::encode(b"out")]);
+
+        // Bad hex, truncated, trailing bytes, and inputs claiming 2^64 - 1 entries
+        let malformed = ["zz".to_string(), hex::encode(&bytes[..bytes.len() - 1]), hex::encode(&bytes) + "00", format!("fd{}", "ff".repeat(8))];
+        for tx_hex in malformed {
+            let response = call_with(state.clone(), "decode_raw_transaction", json!({ "tx_hex": tx_hex })).await;
+            assert_eq!(response.error.unwrap().code, -22, "{}", tx_hex);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exported_chain_imports_into_fresh_node() {
+        let mut source = default_state("export-source");
+        let mut chain = Blockchain::new_with_genesis();
+        for i in 1..=4u32 {
+            let block = Blockchain::mine_new_block(&chain, Vec::new(), i * TARGET_BLOCK_TIME);
+            chain.add_block(block, false).unwrap();
+        }
+        source.chain_manager = ChainManager::new(chain, Mempool::new());
+
+        let response = call_with(source.clone(), "export_chain", Value::Null).await;
+        let export: ChainExportResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((export.from_height, export.to_height, export.blocks), (0, 4, 5));
+
+        // Files are named within the data directory, and never overwritten
+        std::fs::create_dir_all(source.config.chain_dir()).unwrap();
+        let params = json!({ "path": "prefix.export", "to_height": 2 });
+        call_with(source.clone(), "export_chain", params.clone()).await.result.unwrap();
+        assert_eq!(call_with(source.clone(), "export_chain", params).await.error.unwrap().message, "prefix.export already exists");
+        let outside = source.config.chain_dir().join("outside.export");
+        for path in [outside.to_str().unwrap(), "../outside.export", ""] {
+            let response = call_with(source.clone(), "export_chain", json!({ "path": path })).await;
+            assert_eq!(response.error.unwrap().code, -32602, "{}", path);
+            let response = call_with(source.clone(), "import_chain", json!({ "path": path })).await;
+            assert_eq!(response.error.unwrap().code, -32602, "{}", path);
+        }
+        assert!(!outside.exists());
+
+        // A node with a prefix of the chain skips what it has
+        let target = default_state("export-target");
+        std::fs::create_dir_all(target.config.chain_dir()).unwrap();
+        std::fs::copy(source.config.chain_dir().join("prefix.export"), target.config.chain_dir().join("prefix.export")).unwrap();
+        let response = call_with(target.clone(), "import_chain", json!({ "path": "prefix.export" })).await;
+        let imported: ChainImportResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((imported.accepted, imported.skipped, imported.height), (2, 1, 2));
+
+        let response = call_with(target.clone(), "import_chain", json!({ "hex": export.hex })).await;
+        let imported: ChainImportResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((imported.accepted, imported.skipped, imported.rejected, imported.error), (2, 3, 0, None));
+        assert_eq!(target.chain_manager.tip().hash, source.chain_manager.tip().hash);
+
+        let response = call_with(target.clone(), "export_chain", json!({ "from_height": 3, "to_height": 9 })).await;
+        assert_eq!(response.error.unwrap().code, -8);
+        let response = call_with(target.clone(), "import_chain", json!({ "hex": "00ff" })).await;
+        assert_eq!(response.error.unwrap().code, -22);
+        let response = call_with(target.clone(), "import_chain", Value::Null).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+        for state in [source, target] {
+            std::fs::remove_dir_all(&state.config.storage.datadir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restores_into_fresh_regtest_node() {
+        let regtest = |name: &str| {
+            let mut state = default_state(name);
+            state.config = Arc::new(NodeConfig { chain: Network::Regtest, ..(*state.config).clone() });
+            state
+        };
+        let source = regtest("snapshot-source");
+        // Spaced at the target time so the difficulty stays put
+        for block in ChainBuilder::new(0).mine_blocks(50, 0).build().iter().skip(1) {
+            let params = json!({ "block_hex": hex::encode(block.serialize().unwrap()) });
+            assert_eq!(call_with(source.clone(), "submit_block", params).await.result.unwrap()["accepted"], true);
+        }
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        source.chain_manager.add_mempool_tx(tx.clone()).await.unwrap();
+
+        let path = "regtest.snapshot";
+        std::fs::create_dir_all(source.config.chain_dir()).unwrap();
+        let response = call_with(source.clone(), "snapshot_chain", json!({ "path": path })).await;
+        let taken: ChainSnapshotResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((taken.height, taken.mempool_size), (50, 1));
+        assert!(!source.config.chain_dir().join("regtest.snapshot.partial").exists());
+
+        // Files are named within the data directory, and never overwritten
+        let response = call_with(source.clone(), "snapshot_chain", json!({ "path": path })).await;
+        assert_eq!(response.error.unwrap().message, "regtest.snapshot already exists");
+        let outside = source.config.chain_dir().join("outside.snapshot");
+        for path in [outside.to_str().unwrap(), "../outside.snapshot", ""] {
+            for method in ["snapshot_chain", "restore_chain"] {
+                let response = call_with(source.clone(), method, json!({ "path": path })).await;
+                assert_eq!(response.error.unwrap().code, -32602, "{} {}", method, path);
+            }
+        }
+        assert!(!outside.exists());
+
+        let target = regtest("snapshot-target");
+        std::fs::create_dir_all(target.config.chain_dir()).unwrap();
+        std::fs::copy(source.config.chain_dir().join(path), target.config.chain_dir().join(path)).unwrap();
+        let response = call_with(target.clone(), "restore_chain", json!({ "path": path })).await;
+        let restored: ChainSnapshotResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(restored, taken);
+        assert_eq!(target.chain_manager.tip().hash, source.chain_manager.tip().hash);
+        assert_eq!(target.chain_manager.mempool().await.txs(), [tx]);
+
+        // The restored node carries on mining as usual
+        submit_blocks(&target, 2).await;
+        assert_eq!(target.chain_manager.tip().height, 52);
+
+        // Other networks don't serve snapshots at all
+        let mainnet = default_state("snapshot-mainnet");
+        for method in ["snapshot_chain", "restore_chain"] {
+            let response = call_with(mainnet.clone(), method, json!({ "path": path })).await;
+            assert_eq!(response.error.unwrap().code, RpcError::METHOD_NOT_FOUND, "{}", method);
+        }
+        assert_eq!(mainnet.chain_manager.tip().height, 0);
+
+        let response = call_with(target.clone(), "restore_chain", json!({ "path": "regtest.missing" })).await;
+        assert_eq!(response.error.unwrap().code, -8);
+        for state in [source, target] {
+            let _ = std::fs::remove_dir_all(&state.config.storage.datadir);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_params_name_the_field() {
+        let state = default_state("bad-params");
+        let invalid_param = |response: RpcResponse<Value>| {
+            let error = response.error.unwrap();
+            assert_eq!(error.code, -32602);
+            serde_json::from_value::<InvalidParam>(error.data.unwrap()).unwrap()
+        };
+
+        // Once read as no params at all, and refused as missing block data
+        let response = call_with(state.clone(), "submit_block", json!({ "block_hex": 5 })).await;
+        let param = invalid_param(response);
+        assert_eq!((param.field.as_str(), param.expected.as_deref()), ("block_hex", Some("a string")));
+
+        let response = call_with(state.clone(), "get_headers", json!({ "start": 0, "count": "all" })).await;
+        let param = invalid_param(response);
+        assert_eq!((param.field.as_str(), param.expected.as_deref()), ("count", Some("u64")));
+
+        let response = call_with(state.clone(), "set_ban", json!({ "address": "10.0.0.1" })).await;
+        let param = invalid_param(response);
+        assert_eq!((param.field.as_str(), param.expected), ("command", None));
+        assert_eq!(param.message, "missing field `command`");
+
+        // Null params are still no params
+        let response = call_with(state, "submit_block", Value::Null).await;
+        assert_eq!(response.error.unwrap().message, "Invalid params: Missing block data");
+    }
+
+    #[tokio::test]
+    async fn test_chain_timing_measures_generated_chain() {
+        let mut state = default_state("chain-timing");
+        let mut chain = Blockchain::new_with_genesis();
+        for time in [600, 1200, 1500, 1800, 2100, 2400] {
+            let block = Blockchain::mine_new_block(&chain, Vec::new(), time);
+            chain.add_block(block, false).unwrap();
+        }
+        state.chain_manager = ChainManager::new(chain, Mempool::new());
+
+        let response = call_with(state.clone(), "get_chain_timing", json!({ "window": 4 })).await;
+        let timing: ChainTiming = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(timing.blocks, 4);
+        assert_eq!((timing.mean_interval, timing.median_interval, timing.stddev_interval), (300.0, 300.0, 0.0));
+        assert_eq!((timing.min_interval, timing.max_interval), (300, 300));
+        // The window for height 6 took a third of its expected time
+        let epoch = EpochTiming { start_height: 3, end_height: 5, retarget_height: 6, actual_time: 600, expected_time: 1800 };
+        assert_eq!(timing.epochs, [epoch]);
+
+        let response = call_with(state.clone(), "get_chain_timing", json!({ "window": 100 })).await;
+        let timing: ChainTiming = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((timing.blocks, timing.max_interval, timing.epochs.len()), (7, 600, 2));
+
+        let response = call_with(state, "get_chain_timing", Value::Null).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_get_headers_stops_at_tip() {
+        let mut state = default_state("get-headers");
+        let mut chain = Blockchain::new_with_genesis();
+        for i in 1..=4u32 {
+            let block = Blockchain::mine_new_block(&chain, Vec::new(), i * TARGET_BLOCK_TIME);
+            chain.add_block(block, false).unwrap();
+        }
+        let expected: Vec<_> = chain.iter().map(|block| hex::encode(block.header.serialize().unwrap())).collect();
+        let hash_at_3 = hex::encode(chain.get_block_by_height(3).unwrap().double_sha256());
+        state.chain_manager = ChainManager::new(chain, Mempool::new());
+
+        // Asking past the tip returns what there is
+        let response = call_with(state.clone(), "get_headers", json!({ "start": 2, "count": 10 })).await;
+        let batch: HeaderBatch = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((batch.start_height, &batch.headers[..]), (2, &expected[2..]));
+        let header = Header::from_bytes(&hex::decode(&batch.headers[0]).unwrap()).unwrap();
+        assert_eq!(header.time, 2 * TARGET_BLOCK_TIME);
+
+        let response = call_with(state.clone(), "get_headers", json!({ "start": hash_at_3, "count": 1 })).await;
+        let batch: HeaderBatch = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!((batch.start_height, &batch.headers[..]), (3, &expected[3..4]));
+
+        // Counts are capped
+        let response = call_with(state.clone(), "get_headers", json!({ "start": 0, "count": u64::MAX })).await;
+        assert_eq!(serde_json::from_value::<HeaderBatch>(response.result.unwrap()).unwrap().headers, expected);
+
+        for start in [json!("11".repeat(32)), json!(5)] {
+            let response = call_with(state.clone(), "get_headers", json!({ "start": start, "count": 1 })).await;
+            assert_eq!(response.error.unwrap().code, -5, "{}", start);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_template_states_its_time_window() {
+        let state = default_state("time-window");
+        let before = crate::utils::current_timestamp();
+        let template: BlockTemplate = serde_json::from_value(call(state.clone(), "get_block_template").await).unwrap();
+        let genesis_time = state.chain_manager.tip().time;
+
+        let cur_time = template.cur_time.unwrap();
+        assert!(cur_time >= before && cur_time <= before + 5);
+        assert_eq!(template.min_time, Some(genesis_time + 1));
+        assert_eq!(template.max_time, Some(cur_time + MAX_FUTURE_BLOCK_TIME));
+        assert_eq!(template.timestamp, cur_time.max(genesis_time + 1));
+    }
+
+    #[tokio::test]
+    async fn test_template_fills_block_to_size_limit() {
+        let state = default_state("block-size");
+        let tx = |n: u32, len: usize| Transaction::new(vec![n.to_le_bytes().to_vec(), vec![0; len]], vec![b"out".to_vec()]).unwrap();
+
+        // Nine large transactions, then one sized to fill the block exactly
+        let budget = MAX_BLOCK_SIZE - MAX_BLOCK_OVERHEAD;
+        let mut pooled: Vec<_> = (0..9).map(|n| tx(n, 100_000)).collect();
+        let remaining = budget - pooled.iter().map(|tx| tx.byte_size()).sum::<usize>();
+        let mut len = remaining;
+        while tx(9, len).byte_size() > remaining {
+            len -= 1;
+        }
+        pooled.push(tx(9, len));
+        assert_eq!(pooled.iter().map(|tx| tx.byte_size()).sum::<usize>(), budget);
+        // A single byte more doesn't fit
+        pooled.push(tx(10, 0));
+        for tx in pooled {
+            state.chain_manager.add_mempool_tx(tx).await.unwrap();
+        }
+
+        let template: BlockTemplate = serde_json::from_value(call(state.clone(), "get_block_template").await).unwrap();
+        assert_eq!(template.tx_count, Some(10));
+        assert_eq!(template.size_estimate, Some(MAX_BLOCK_SIZE as u64));
+
+        // The block, even at its widest nonce, fits within the estimate and the rule
+        let header = Header::new(
+            template.version,
+            template.timestamp,
+            template.difficulty_compact,
+            u64::MAX,
+            hex::decode(&template.previous_block_hash).unwrap().try_into().unwrap(),
+            hex::decode(&template.merkle_root).unwrap().try_into().unwrap(),
+        );
+        let block = Block::new(header, template.transactions.to_vec());
+        assert!(block.byte_size() as u64 <= template.size_estimate.unwrap());
+        assert!(block.validate_size().is_ok());
+        assert!(block.validate_merkle_root().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_template_takes_at_most_requested_txs() {
+        let mut config = NodeConfig::default();
+        config.mempool.template_max_txs = 10;
+        let state = NodeState { config: Arc::new(config), ..default_state("template-cap") };
+        for n in 0..20u32 {
+            let tx = Transaction::new(vec![n.to_le_bytes().to_vec()], vec![b"out".to_vec()]).unwrap();
+            state.chain_manager.add_mempool_tx(tx).await.unwrap();
+        }
+
+        // Requests above the node's cap get the cap, as do those without one
+        for (max_txs, expected) in [(json!(0), 0), (json!(5), 5), (json!(50), 10), (Value::Null, 10)] {
+            let response = call_with(state.clone(), "get_block_template", json!({ "max_txs": max_txs })).await;
+            let template: BlockTemplate = serde_json::from_value(response.result.unwrap()).unwrap();
+            assert_eq!(template.tx_count, Some(expected), "max_txs {}", max_txs);
+            assert_eq!(template.transactions.len(), expected as usize);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_empty_template_where_empty_blocks_are_refused() {
+        let mut state = default_state("no-empty-blocks");
+        let mut chain = Blockchain::new_with_genesis();
+        chain.set_params(ConsensusParams { allow_empty_blocks: false, ..Default::default() });
+        state.chain_manager = ChainManager::new(chain, Mempool::new());
+
+        let response = call_with(state.clone(), "get_block_template", Value::Null).await;
+        assert_eq!(response.error.unwrap().code, -10);
+
+        // Nor is an empty block taken
+        let empty = ChainBuilder::new(0).mine_blocks(1, 0).tip().clone();
+        let params = json!({ "block_hex": hex::encode(empty.serialize().unwrap()) });
+        let submitted = call_with(state.clone(), "submit_block", params).await.result.unwrap();
+        assert_eq!(submitted["reject_reason"], "bad-blk-length");
+
+        state.chain_manager.add_mempool_tx(Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap()).await.unwrap();
+        let template: BlockTemplate = serde_json::from_value(call(state, "get_block_template").await).unwrap();
+        assert_eq!(template.tx_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_mining_info_projects_next_retarget() {
+        let mut state = default_state("retarget");
+        let mut chain = Blockchain::new_with_genesis();
+        // Four blocks in: one of the window for height 6 still to come
+        for i in 1..=4u32 {
+            let block = Blockchain::mine_new_block(&chain, Vec::new(), i * TARGET_BLOCK_TIME / 2);
+            chain.add_block(block, false).unwrap();
+        }
+        let next = Blockchain::mine_new_block(&chain, Vec::new(), 5 * TARGET_BLOCK_TIME / 2);
+        state.chain_manager = ChainManager::new(chain, Mempool::new());
+
+        let info: MiningInfo = serde_json::from_value(call(state.clone(), "get_mining_info").await).unwrap();
+        assert_eq!((info.next_retarget_height, info.blocks_until_retarget), (Some(6), Some(1)));
+        let estimate = info.estimated_next_difficulty.unwrap();
+
+        // Once the window fills at the same pace, the estimate is what the
+        // node requires
+        state.chain_manager.connect_block(next).await.unwrap();
+        let info: MiningInfo = serde_json::from_value(call(state.clone(), "get_mining_info").await).unwrap();
+        assert_eq!((info.next_retarget_height, info.blocks_until_retarget), (Some(6), Some(0)));
+        assert_eq!(info.difficulty, estimate);
+        assert_eq!(info.estimated_next_difficulty, Some(estimate));
+    }
+
+    /// Status and JSON body of `GET path`
+    async fn get(addr: SocketAddr, path: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_health_follows_lifecycle() {
+        let state = default_state("health");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, state.clone()));
+
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!((status, &body["phase"], &body["reason"]), (503, &json!("starting"), &json!("starting")));
+        // Live throughout
+        assert_eq!(get(addr, "/live").await.0, 200);
+
+        state.lifecycle.set(Phase::Running);
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, json!({ "status": "ok", "phase": "running", "reindexing": false, "height": 0 }));
+
+        // Not ready again until a reindex finishes
+        state.reindex.start(1);
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!((status, &body["reindexing"]), (503, &json!(true)));
+        assert!(body["reason"].as_str().unwrap().starts_with("reindexing"), "{}", body);
+        let genesis = state.chain_manager.chain().await.get_block_by_height(0).unwrap().clone();
+        reindex::reindex(state.chain_manager.clone(), vec![genesis], None, state.reindex.clone()).await;
+        assert_eq!(get(addr, "/health").await.0, 200);
+
+        state.lifecycle.set(Phase::ShuttingDown);
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!((status, &body["status"], &body["phase"]), (503, &json!("unavailable"), &json!("shutting_down")));
+        assert_eq!(get(addr, "/live").await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_completes_pending_request() {
+        let state = default_state("shutdown");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, state.clone()));
+
+        // Hold the chain so the request stays pending across the shutdown
+        let chain = state.chain_manager.lock_chain().await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"get_blockchain_info","params":null}"#;
+        let request = format!(
+            "POST /rpc HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            addr, body.len(), body,
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut first = [0u8; 1];
+        let early = tokio::time::timeout(Duration::from_millis(100), stream.read(&mut first)).await;
+        assert!(early.is_err(), "request was answered before the shutdown");
+
+        state.shutdown.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!server.is_finished(), "server exited with a request in flight");
+        let late = TcpStream::connect(addr).await;
+        assert!(late.is_err(), "server still accepts connections after the shutdown");
+        drop(chain);
+
+        // The pending request is answered, then the connection closes
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await
+            .expect("connection stayed open after the shutdown")
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""blocks":1"#), "{}", response);
+
+        tokio::time::timeout(Duration::from_secs(5), server).await
+            .expect("server did not stop")
+            .unwrap()
+            .unwrap();
+    }
+
+    /// Mine `count` empty blocks on the tip and submit them
+    async fn submit_blocks(state: &NodeState, count: u32) {
+        for _ in 0..count {
+            let chain = state.chain_manager.chain().await;
+            let block = Blockchain::mine_new_block(&chain, Vec::new(), chain.latest_block().header.time + 1);
+            drop(chain);
+            let params = json!({ "block_hex": hex::encode(block.serialize().unwrap()) });
+            assert_eq!(call_with(state.clone(), "submit_block", params).await.result.unwrap()["accepted"], true);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_disk_does_not_delay_async_submissions() {
+        let mut state = default_state("slow-disk");
+        let store = SlowStore { delay: Duration::from_millis(500), ..Default::default() };
+        state.chain_writer = ChainWriter::spawn(
+            state.chain_manager.clone(),
+            store.clone(),
+            Arc::new(NodeMetrics::default()),
+            SyncMode::Async,
+            state.config.storage.write_queue_len,
+        );
+
+        let started = tokio::time::Instant::now();
+        submit_blocks(&state, 3).await;
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        state.chain_writer.flush().await.unwrap();
+        assert_eq!(store.saved.lock().unwrap().last(), Some(&3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blockchain_info_reports_size_and_work() {
+        let state = default_state("chain-info");
+        let info = || async { serde_json::from_value::<ChainInfo>(call(state.clone(), "get_blockchain_info").await).unwrap() };
+        let submit = |count| {
+            let state = state.clone();
+            async move {
+                submit_blocks(&state, count).await;
+                state.chain_writer.flush().await.unwrap();
+            }
+        };
+
+        // Nothing saved yet
+        let fresh = info().await;
+        assert_eq!(fresh.size_on_disk, Some(0));
+        let genesis_work = fresh.chain_work.unwrap();
+        assert_eq!(genesis_work.len(), 64);
+
+        // Accepted blocks are saved once the writer catches up, and the size is measured
+        // again once the last measurement is a few seconds old
+        submit(1).await;
+        assert_eq!(info().await.size_on_disk, Some(0));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let one = info().await;
+        submit(3).await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let four = info().await;
+        assert!(four.size_on_disk > one.size_on_disk && one.size_on_disk > Some(0));
+        // Equal-length hex compares like the numbers
+        assert!(four.chain_work > one.chain_work && one.chain_work.unwrap() > genesis_work);
+
+        state.reindex.start(10);
+        assert!(info().await.verification_progress < 1.0);
+        std::fs::remove_dir_all(state.config.chain_dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recent_blocks_newest_first_with_sources() {
+        let state = default_state("recent-blocks");
+        assert_eq!(call(state.clone(), "get_recent_blocks").await, json!([]));
+        assert_eq!(call(state.clone(), "get_blockchain_info").await["last_block"], Value::Null);
+
+        let block = ChainBuilder::new(0).mine_blocks(1, 0).tip().clone();
+        state.chain_manager.connect_block(block).await.unwrap();
+        submit_blocks(&state, 2).await;
+
+        let recent: Vec<RecentBlock> = serde_json::from_value(call(state.clone(), "get_recent_blocks").await).unwrap();
+        let labels: Vec<_> = recent.iter().map(|block| (block.height, block.source.as_str(), block.submitter.as_deref())).collect();
+        assert_eq!(labels, [(3, "rpc", Some("127.0.0.1")), (2, "rpc", Some("127.0.0.1")), (1, "generate", None)]);
+        let tip = state.chain_manager.tip();
+        assert_eq!(recent[0].hash, hex::encode(tip.hash));
+        assert_eq!(recent[0].propagation_secs, recent[0].received_time as i64 - tip.time as i64);
+
+        let latest = call_with(state.clone(), "get_recent_blocks", json!({ "count": 1 })).await.result.unwrap();
+        let info: ChainInfo = serde_json::from_value(call(state.clone(), "get_blockchain_info").await).unwrap();
+        assert_eq!(serde_json::from_value::<Vec<RecentBlock>>(latest).unwrap(), vec![info.last_block.unwrap()]);
+        state.chain_writer.flush().await.unwrap();
+        std::fs::remove_dir_all(state.config.chain_dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_txindex_lookups_wait_for_reindex() {
+        let mut state = default_state("reindex");
+        state.txindex = Some(Arc::new(RwLock::new(TxIndex::new())));
+        state.reindex.start(3);
+
+        let params = json!({ "txid": hex::encode([0u8; 32]) });
+        let response = call_with(state.clone(), "get_raw_transaction", params).await;
+        assert_eq!(response.error.map(|e| e.code), Some(-28));
+        let info = call(state, "get_blockchain_info").await;
+        assert_eq!(info["verification_progress"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_block_burst_throttles_source() {
+        let state = default_state("throttle");
+        let limit = state.config.network.rpc_max_bad_blocks;
+        let garbage = json!({ "block_hex": "deadbeef" });
+
+        for _ in 0..limit {
+            let response = call_from(state.clone(), "10.0.0.1:4000", "submit_block", garbage.clone()).await.unwrap();
+            assert_eq!(response.error.map(|e| e.code), Some(-32602));
+        }
+
+        // Refused without taking the chain lock
+        let chain = state.chain_manager.lock_chain().await;
+        let refused = call_from(state.clone(), "10.0.0.1:4001", "submit_block", garbage.clone()).await;
+        assert_eq!(refused.err(), Some(StatusCode::TOO_MANY_REQUESTS));
+        drop(chain);
+
+        // Another source still gets a normal answer
+        let response = call_from(state.clone(), "10.0.0.2:4000", "submit_block", garbage).await.unwrap();
+        assert_eq!(response.error.map(|e| e.code), Some(-32602));
+
+        // Other methods stay available to the throttled source
+        assert!(call_from(state, "10.0.0.1:4000", "get_block_count", Value::Null).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bad_hex_errors_name_the_param() {
+        let state = default_state("bad-hex");
+        let cases = [
+            ("submit_block", json!({ "block_hex": "zz00" }), "Invalid params: block_hex: invalid hex character 'z' at position 0"),
+            ("submit_block", json!({ "block_hex": "abc" }), "Invalid params: block_hex: odd number of hex digits (3)"),
+            ("get_block", json!({ "hash": "00ff" }), "Invalid params: hash: expected 32 bytes (64 hex digits), got 2"),
+            ("get_headers", json!({ "start": "ab".repeat(33), "count": 1 }), "Invalid params: start: expected 32 bytes (64 hex digits), got 33"),
+            ("decode_raw_transaction", json!({ "tx_hex": "0x00" }), "Decode failed: tx_hex: invalid hex character 'x' at position 1"),
+        ];
+        for (method, params, message) in cases {
+            let error = call_with(state.clone(), method, params).await.error.unwrap();
+            assert_eq!(error.message, message, "{}", method);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_profile_refuses_other_methods() {
+        let mut config = NodeConfig::default();
+        config.network.rpc_allow = vec!["read".to_string()];
+        config.network.rpc_trusted = vec!["10.0.0.2".parse().unwrap()];
+        let state = NodeState { rpc_access: Arc::new(RpcAccess::new(&config.network).unwrap()), ..default_state("read-only") };
+        let tip = state.chain_manager.tip().hash;
+        let public = |method: &'static str, params: Value| call_from(state.clone(), "203.0.113.5:4000", method, params);
+
+        let block = public("get_block", json!({ "hash": hex::encode(tip) })).await.unwrap();
+        assert!(block.error.is_none(), "{:?}", block.error);
+
+        // Refused before the handler runs: garbage would otherwise fail to decode
+        let block = json!({ "block_hex": "deadbeef" });
+        for (method, params) in [("submit_block", block.clone()), ("get_block_template", Value::Null), ("set_ban", Value::Null)] {
+            let error = public(method, params).await.unwrap().error.unwrap();
+            assert_eq!(error.code, RpcError::METHOD_NOT_FOUND);
+            assert_eq!(error.message, format!("Method disabled: {}", method));
+        }
+        let error = public("generate", json!({ "blocks": 1 })).await.unwrap().error.unwrap();
+        assert_eq!(error.code, RpcError::METHOD_NOT_FOUND);
+        assert!(!state.submit_throttle.is_throttled("203.0.113.5".parse().unwrap()));
+
+        // A trusted miner may still mine
+        let response = call_from(state.clone(), "10.0.0.2:4000", "submit_block", block).await.unwrap();
+        assert_eq!(response.error.map(|e| e.code), Some(-32602));
+    }
+
+    /// State on a chain of `blocks` blocks, one transaction each, and a block
+    /// extending it that hasn't been submitted
+    fn snapshot_state(name: &str, blocks: u32) -> (NodeState, Block) {
+        let mut state = default_state(name);
+        let mut chain = Blockchain::new_with_genesis();
+        // Timestamps on schedule keep the difficulty from ratcheting up
+        let spacing = TARGET_BLOCK_TIME * ADJUSTMENT_INTERVAL as u32 / (ADJUSTMENT_INTERVAL as u32 - 1);
+        let start = chain.latest_block().header.time;
+        for i in 1..=blocks {
+            let tx = Transaction::new(vec![i.to_le_bytes().to_vec()], vec![b"out".to_vec()]).unwrap();
+            let block = Blockchain::mine_new_block(&chain, vec![tx], start + i * spacing);
+            chain.add_block(block, false).unwrap();
+        }
+        let block = Blockchain::mine_new_block(&chain, Vec::new(), start + (blocks + 1) * spacing);
+        state.chain_manager = ChainManager::new(chain, Mempool::new());
+        (state, block)
+    }
+
+    /// Submitting used to hold the chain write lock across the disk write, so
+    /// template requests queued behind every block submission. Templates now
+    /// come from the tip snapshot and never touch the chain lock.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_template_does_not_wait_for_submit() {
+        let (state, block) = snapshot_state("snapshot", 100);
+        let old_tip = hex::encode(state.chain_manager.tip().hash);
+        let new_tip = hex::encode(block.double_sha256());
+
+        // A template is served even while the chain is locked
+        let locked = state.chain_manager.lock_chain().await;
+        tokio::time::timeout(Duration::from_secs(1), call(state.clone(), "get_block_template"))
+            .await
+            .expect("template waited for the chain lock");
+        drop(locked);
+
+        // Templates requested alongside the submit build on one tip or the other
+        let params = json!({ "block_hex": hex::encode(block.serialize().unwrap()) });
+        let mut templates = tokio::task::JoinSet::new();
+        for _ in 0..20 {
+            templates.spawn(call(state.clone(), "get_block_template"));
+        }
+        let (submitted, templates) = tokio::join!(
+            call_with(state.clone(), "submit_block", params.clone()),
+            templates.join_all(),
+        );
+
+        assert_eq!(submitted.result.unwrap()["accepted"], true);
+        let resubmitted = call_with(state.clone(), "submit_block", params).await.result.unwrap();
+        assert_eq!(resubmitted["reject_reason"], "duplicate");
+        for template in templates {
+            assert!(template["max_time"].as_u64() > template["timestamp"].as_u64());
+            let (prev, height) = (template["previous_block_hash"].as_str().unwrap(), template["height"].as_u64().unwrap());
+            assert!(
+                (prev == old_tip && height == 101) || (prev == new_tip && height == 102),
+                "inconsistent template on {} at {}", prev, height,
+            );
+        }
+        assert_eq!(call(state.clone(), "get_block_count").await, 101);
+        std::fs::remove_dir_all(state.config.chain_dir()).unwrap();
+    }
+
+    /// Template latency while a block is submitted on a 2,000-block chain.
+    /// Before the snapshot a template could wait out the whole submit and its
+    /// save; now the worst case is a scheduler tick or so. Run with
+    /// `cargo test -- --ignored --nocapture` to see the numbers.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore = "slow: builds a 2,000-block chain"]
+    async fn test_template_latency_during_submit() {
+        let (state, block) = snapshot_state("latency", 2_000);
+        let params = json!({ "block_hex": hex::encode(block.serialize().unwrap()) });
+        let submitting = Arc::new(AtomicBool::new(true));
+
+        let poller = {
+            let (state, submitting) = (state.clone(), submitting.clone());
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                while submitting.load(Ordering::SeqCst) {
+                    let started = std::time::Instant::now();
+                    call(state.clone(), "get_block_template").await;
+                    latencies.push(started.elapsed());
+                }
+                latencies
+            })
+        };
+
+        let started = std::time::Instant::now();
+        let submitted = call_with(state.clone(), "submit_block", params).await;
+        let submit = started.elapsed();
+        submitting.store(false, Ordering::SeqCst);
+        assert_eq!(submitted.result.unwrap()["accepted"], true);
+
+        let mut latencies = poller.await.unwrap();
+        latencies.sort();
+        let worst = *latencies.last().unwrap();
+        println!(
+            "submit took {:?}; {} templates meanwhile, median {:?}, worst {:?}",
+            submit, latencies.len(), latencies[latencies.len() / 2], worst,
+        );
+        assert!(worst < Duration::from_millis(50), "a template took {:?}", worst);
+        std::fs::remove_dir_all(state.config.chain_dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ban_survives_restart() {
+        let mut state = default_state("ban");
+        let dir = state.config.chain_dir();
+        let peers = |bans| PeerManager::new(PeerManagerConfig::default(), state.chain_manager.clone(), events::channel())
+            .with_ban_list(bans, dir.clone());
+        state.peers = peers(BanList::new());
+
+        let params = json!({ "address": "10.0.0.7", "command": "add", "reason": "spam" });
+        let banned: Vec<BannedPeer> = serde_json::from_value(call_with(state.clone(), "set_ban", params).await.result.unwrap()).unwrap();
+        assert_eq!((banned.len(), banned[0].reason.as_str()), (1, "spam"));
+
+        // A restarted peer manager still refuses the address, on any port
+        let restarted = peers(storage::load_banlist(&dir).expect("Ban list wasn't saved"));
+        let (local, mut remote) = tokio::io::duplex(1024);
+        assert!(restarted.add_peer(local, "10.0.0.7:16000".parse().unwrap(), Direction::Inbound).await.is_none());
+        assert_eq!(read_message(&mut remote).await.unwrap(), Message::Disconnect("banned".to_string()));
+        let (local, _remote) = tokio::io::duplex(1024);
+        assert!(restarted.add_peer(local, "10.0.0.8:16000".parse().unwrap(), Direction::Inbound).await.is_some());
+
+        let params = json!({ "address": "10.0.0.7", "command": "remove" });
+        assert_eq!(call_with(state.clone(), "set_ban", params.clone()).await.result.unwrap(), json!([]));
+        assert_eq!(call_with(state.clone(), "set_ban", params).await.error.unwrap().code, -30);
+        assert!(storage::load_banlist(&dir).unwrap().active(0).next().is_none());
+
+        // Whitelisted addresses can't be banned, and long bans don't overflow
+        let config = PeerManagerConfig { whitelist: vec!["10.0.0.9".parse().unwrap()], ..Default::default() };
+        state.peers = PeerManager::new(config, state.chain_manager.clone(), events::channel());
+        let params = json!({ "address": "10.0.0.9", "command": "add" });
+        assert_eq!(call_with(state.clone(), "set_ban", params).await.error.unwrap().message, "Address is whitelisted");
+        let params = json!({ "address": "10.0.0.7", "command": "add", "duration_secs": u64::MAX });
+        let banned: Vec<BannedPeer> = serde_json::from_value(call_with(state.clone(), "set_ban", params).await.result.unwrap()).unwrap();
+        assert_eq!(banned[0].banned_until, u64::MAX);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
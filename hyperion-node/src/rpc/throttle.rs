@@ -0,0 +1,101 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Tracks failed `submit_block` calls per source address so a client
+/// spamming invalid blocks is turned away before it reaches the chain lock.
+/// Failures older than `window` no longer count, and an accepted block
+/// clears the source's record.
+#[derive(Debug)]
+pub struct SubmitThrottle {
+    failures: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+    max_failures: usize,
+    window: Duration,
+}
+
+impl SubmitThrottle {
+    /// `max_failures` of 0 disables throttling
+    pub fn new(max_failures: usize, window: Duration) -> Self {
+        Self {
+            failures: Mutex::new(HashMap::new()),
+            max_failures,
+            window,
+        }
+    }
+
+    /// Whether `ip` has used up its failures for the current window
+    pub fn is_throttled(&self, ip: IpAddr) -> bool {
+        if self.max_failures == 0 {
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+        match failures.get_mut(&ip) {
+            Some(times) => {
+                Self::expire(times, now, self.window);
+                times.len() >= self.max_failures
+            }
+            None => false,
+        }
+    }
+
+    pub fn record_failure(&self, ip: IpAddr) {
+        if self.max_failures == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+
+        // Drop sources whose failures have all aged out so the map stays small
+        failures.retain(|_, times| {
+            Self::expire(times, now, self.window);
+            !times.is_empty()
+        });
+
+        let times = failures.entry(ip).or_default();
+        times.push_back(now);
+        if times.len() > self.max_failures {
+            times.pop_front();
+        }
+    }
+
+    pub fn record_success(&self, ip: IpAddr) {
+        self.failures.lock().unwrap().remove(&ip);
+    }
+
+    fn expire(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+        while times.front().is_some_and(|t| now.duration_since(*t) >= window) {
+            times.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failures_decay_and_success_resets() {
+        let throttle = SubmitThrottle::new(3, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        for _ in 0..3 {
+            throttle.record_failure(ip);
+        }
+        assert!(throttle.is_throttled(ip));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(!throttle.is_throttled(ip));
+
+        for _ in 0..2 {
+            throttle.record_failure(ip);
+        }
+        throttle.record_success(ip);
+        throttle.record_failure(ip);
+        assert!(!throttle.is_throttled(ip));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::chain_manager::ChainManager;
+use crate::metrics::{MetricsSnapshot, NodeMetrics};
+use crate::network::PeerManager;
+
+use hyperion_core::crypto::{hex32, Hashable};
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+
+/// Periodic one-line summary of what the node is doing
+pub struct StatusReporter {
+    chain_manager: ChainManager,
+    peers: PeerManager,
+    metrics: Arc<NodeMetrics>,
+    /// Counters at the previous summary, so each one reports a delta
+    last: MetricsSnapshot,
+}
+
+impl StatusReporter {
+    pub fn new(
+        chain_manager: ChainManager,
+        peers: PeerManager,
+        metrics: Arc<NodeMetrics>,
+    ) -> Self {
+        let last = metrics.snapshot();
+        Self { chain_manager, peers, metrics, last }
+    }
+
+    pub async fn log_summary(&mut self) {
+        let (height, best_hash) = {
+            let chain = self.chain_manager.chain().await;
+            (chain.len() as u64 - 1, hex32(&chain.latest_block().double_sha256()))
+        };
+        let (mempool_txs, mempool_bytes) = {
+            let mempool = self.chain_manager.mempool().await;
+            (mempool.len(), mempool.bytes())
+        };
+        let peers = self.peers.connection_counts().await;
+
+        let now = self.metrics.snapshot();
+        let delta = now.since(&self.last);
+        self.last = now;
+
+        info!(
+            height,
+            best_hash = %&best_hash[..16],
+            mempool_txs,
+            mempool_bytes,
+            peers_in = peers.inbound,
+            peers_out = peers.outbound,
+            blocks_accepted = delta.blocks_accepted,
+            blocks_rejected = delta.blocks_rejected,
+            flushes = delta.flushes,
+            flush_failures = delta.flush_failures,
+            flush_ms = delta.flush_time.as_millis() as u64,
+            "Status: height {} ({}…), {} txs in mempool, {} peers",
+            height, &best_hash[..16], mempool_txs, peers.total(),
+        );
+    }
+
+    /// Log a summary every `interval` for as long as the node runs
+    pub fn spawn(mut self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                self.log_summary().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{self, NodeEvent};
+    use crate::mempool::Mempool;
+    use crate::metrics;
+    use crate::network::PeerManagerConfig;
+    use hyperion_core::chain::blockchain::Blockchain;
+
+    use std::io::Write;
+    use std::sync::Mutex;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Log sink shared with the test
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_reports_height_after_new_blocks() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(captured.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let events = events::channel();
+        let metrics = Arc::new(NodeMetrics::default());
+        metrics::spawn_event_counter(metrics.clone(), events.subscribe());
+
+        let chain_manager = ChainManager::new(Blockchain::new_with_genesis(), Mempool::new());
+        let peers = PeerManager::new(PeerManagerConfig::default(), chain_manager.clone(), events.clone());
+        let mut reporter = StatusReporter::new(chain_manager.clone(), peers.clone(), metrics.clone());
+
+        // Generate two blocks
+        for _ in 0..2 {
+            let block = Blockchain::mine_new_block(&*chain_manager.chain().await, Vec::new(), 1);
+            let hash = block.double_sha256();
+            let height = chain_manager.connect_block(block).await.unwrap();
+            peers.relay_block(hash, height).await;
+        }
+        events::publish(&events, NodeEvent::BlockRejected { hash: [0; 32] });
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while metrics.snapshot().blocks_rejected == 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.expect("Events were not counted");
+
+        reporter.log_summary().await;
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("Status: height 2"), "{}", output);
+        assert!(output.contains("blocks_accepted=2"), "{}", output);
+        assert!(output.contains("blocks_rejected=1"), "{}", output);
+    }
+}
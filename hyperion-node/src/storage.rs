@@ -1,13 +1,415 @@
 use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
 use hyperion_core::chain::blockchain::Blockchain;
-use hyperion_core::block::Serializable;
+use hyperion_core::block::{Serializable, Transaction};
+use hyperion_core::consensus::ConsensusParams;
+use hyperion_core::crypto::{Hashable, HASH_SIZE};
+use crate::network::banlist::BanList;
+use crate::txindex::TxIndex;
 
-pub fn save_chain(chain: &Blockchain) -> std::io::Result<()> {
-    let bytes = chain.serialize().unwrap();
-    fs::write("blockchain.dat", bytes)
+const CHAIN_FILE: &str = "blockchain.dat";
+const MEMPOOL_FILE: &str = "mempool.dat";
+const TXINDEX_FILE: &str = "txindex.dat";
+const BANLIST_FILE: &str = "banlist.dat";
+const GENESIS_FILE: &str = "genesis.dat";
+
+/// How long a measured `DiskUsage` is reused before the files are checked again
+const DISK_USAGE_TTL: Duration = Duration::from_secs(5);
+
+/// First bytes of a chain file in the current format. Legacy files open with
+/// the bincode length of their block list, and no bincode varint starts with 0xff.
+const CHAIN_MAGIC: [u8; 4] = *b"\xffHYC";
+/// Bumped whenever the chain file changes shape
+pub const CHAIN_FORMAT_VERSION: u32 = 1;
+const CHAIN_HEADER_LEN: usize = CHAIN_MAGIC.len() + 4;
+
+/// Write an already serialized chain, so the chain lock needn't be held for
+/// the disk write. The chain is written and synced under a temporary name,
+/// then renamed over the old file, so a crash mid-write leaves the previous
+/// chain in place. Returns once the rename is synced.
+pub fn write_chain(bytes: &[u8], dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let partial = dir.join(format!("{}.tmp", CHAIN_FILE));
+    let mut file = fs::File::create(&partial)?;
+    file.write_all(&with_chain_header(bytes))?;
+    file.sync_all()?;
+    fs::rename(&partial, dir.join(CHAIN_FILE))?;
+    sync_dir(dir)
+}
+
+/// Make renames within `dir` survive a crash
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn load_chain(dir: &Path) -> std::io::Result<Blockchain> {
+    let bytes = fs::read(dir.join(CHAIN_FILE))?;
+    if !has_chain_header(&bytes) {
+        return Err(invalid_data("Chain file is in the legacy format and needs migrating"));
+    }
+
+    let version = u32::from_le_bytes(bytes[CHAIN_MAGIC.len()..CHAIN_HEADER_LEN].try_into().unwrap());
+    if version > CHAIN_FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "Chain file version {} is newer than this node's {}", version, CHAIN_FORMAT_VERSION
+        )));
+    }
+    Blockchain::from_bytes(&bytes[CHAIN_HEADER_LEN..]).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// Whether `dir` holds a chain file written before the versioned format.
+/// No chain file at all isn't legacy.
+pub fn chain_is_legacy(dir: &Path) -> std::io::Result<bool> {
+    let mut magic = [0u8; CHAIN_MAGIC.len()];
+    let read = match fs::File::open(dir.join(CHAIN_FILE)) {
+        Ok(file) => file.take(magic.len() as u64).read(&mut magic)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    Ok(read > 0 && magic[..read] != CHAIN_MAGIC[..read])
+}
+
+/// What a migration rewrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationSummary {
+    /// Blocks in the migrated chain, genesis included
+    pub blocks: usize,
+    pub tip_hash: [u8; HASH_SIZE],
+    /// Where the original file was kept
+    pub backup: PathBuf,
+}
+
+/// Rewrite a legacy chain file in `dir` in the current format. Every block
+/// after genesis is validated again under `params`, and nothing is written
+/// unless all of them pass. Nodes that wrote legacy files never checked a
+/// block's bits against the retarget, so neither does this. The original is
+/// kept beside it as `.bak`.
+pub fn migrate_chain(dir: &Path, params: ConsensusParams) -> std::io::Result<MigrationSummary> {
+    let path = dir.join(CHAIN_FILE);
+    let bytes = fs::read(&path)?;
+    if has_chain_header(&bytes) {
+        return Err(invalid_data("Chain file is already in the current format"));
+    }
+
+    let mut blocks = legacy::decode(&bytes)?.into_iter();
+    let genesis = blocks.next().ok_or_else(|| invalid_data("Legacy chain file holds no blocks"))?;
+    let mut chain = Blockchain::new(genesis);
+    chain.set_params(ConsensusParams { no_retarget: true, ..params });
+    for block in blocks {
+        let height = chain.len();
+        chain.add_block(block, false)
+            .map_err(|e| invalid_data(format!("Legacy block {} is invalid: {}", height, e)))?;
+    }
+
+    // The new file is complete before the original moves aside
+    let migrated = dir.join(format!("{}.new", CHAIN_FILE));
+    let backup = dir.join(format!("{}.bak", CHAIN_FILE));
+    let encoded = chain.serialize().map_err(|e| std::io::Error::other(e.to_string()))?;
+    fs::write(&migrated, with_chain_header(&encoded))?;
+    fs::rename(&path, &backup)?;
+    fs::rename(&migrated, &path)?;
+
+    Ok(MigrationSummary {
+        blocks: chain.len(),
+        tip_hash: chain.latest_block().double_sha256(),
+        backup,
+    })
+}
+
+fn with_chain_header(bytes: &[u8]) -> Vec<u8> {
+    let mut file = Vec::with_capacity(CHAIN_HEADER_LEN + bytes.len());
+    file.extend_from_slice(&CHAIN_MAGIC);
+    file.extend_from_slice(&CHAIN_FORMAT_VERSION.to_le_bytes());
+    file.extend_from_slice(bytes);
+    file
+}
+
+/// Whether `bytes` open with the chain magic. A prefix of it too short to
+/// tell counts as not.
+fn has_chain_header(bytes: &[u8]) -> bool {
+    bytes.len() >= CHAIN_HEADER_LEN && bytes[..CHAIN_MAGIC.len()] == CHAIN_MAGIC
+}
+
+fn invalid_data(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// The chain file layout from before the versioned format: the chain's
+/// blocks, bincode encoded with nothing around them. These types are frozen;
+/// they must keep decoding old files however the live ones change.
+mod legacy {
+    use hyperion_core::block::{Block, Header, Transaction};
+    use hyperion_core::crypto::HASH_SIZE;
+
+    use bincode::Decode;
+
+    #[derive(Decode)]
+    struct LegacyHeader {
+        version: u32,
+        time: u32,
+        difficulty_compact: u32,
+        nonce: u64,
+        prev_hash: [u8; HASH_SIZE],
+        merkle_root: [u8; HASH_SIZE],
+    }
+
+    #[derive(Decode)]
+    struct LegacyTransaction {
+        inputs: Vec<Vec<u8>>,
+        outputs: Vec<Vec<u8>>,
+    }
+
+    #[derive(Decode)]
+    struct LegacyBlock {
+        header: LegacyHeader,
+        transactions: Vec<LegacyTransaction>,
+    }
+
+    impl From<LegacyBlock> for Block {
+        fn from(block: LegacyBlock) -> Self {
+            let LegacyHeader { version, time, difficulty_compact, nonce, prev_hash, merkle_root } = block.header;
+            let transactions = block.transactions
+                .into_iter()
+                .map(|tx| Transaction { inputs: tx.inputs, outputs: tx.outputs })
+                .collect();
+            Block {
+                header: Header::new(version, time, difficulty_compact, nonce, prev_hash, merkle_root),
+                transactions,
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> std::io::Result<Vec<Block>> {
+        let (blocks, len): (Vec<LegacyBlock>, _) = bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map_err(|e| super::invalid_data(format!("Not a legacy chain file: {}", e)))?;
+        if len != bytes.len() {
+            return Err(super::invalid_data(format!("{} trailing bytes after the legacy chain", bytes.len() - len)));
+        }
+        Ok(blocks.into_iter().map(Block::from).collect())
+    }
+}
+
+pub fn save_mempool(txs: &[Transaction], dir: &Path) -> std::io::Result<()> {
+    let bytes = bincode::encode_to_vec(txs, bincode::config::standard())
+        .map_err(std::io::Error::other)?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(MEMPOOL_FILE), bytes)
+}
+
+pub fn load_mempool(dir: &Path) -> std::io::Result<Vec<Transaction>> {
+    let bytes = fs::read(dir.join(MEMPOOL_FILE))?;
+    bincode::decode_from_slice(&bytes, bincode::config::standard())
+        .map(|(txs, _)| txs)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+pub fn save_txindex(index: &TxIndex, dir: &Path) -> std::io::Result<()> {
+    let bytes = bincode::encode_to_vec(index, bincode::config::standard())
+        .map_err(std::io::Error::other)?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(TXINDEX_FILE), bytes)
 }
 
-pub fn load_chain() -> std::io::Result<Blockchain> {
-    let bytes = fs::read("blockchain.dat")?;
-    Ok(Blockchain::from_bytes(&bytes).unwrap())
-}
\ No newline at end of file
+pub fn load_txindex(dir: &Path) -> std::io::Result<TxIndex> {
+    let bytes = fs::read(dir.join(TXINDEX_FILE))?;
+    bincode::decode_from_slice(&bytes, bincode::config::standard())
+        .map(|(index, _)| index)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+pub fn save_banlist(bans: &BanList, dir: &Path) -> std::io::Result<()> {
+    let bytes = bincode::encode_to_vec(bans, bincode::config::standard())
+        .map_err(std::io::Error::other)?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(BANLIST_FILE), bytes)
+}
+
+pub fn load_banlist(dir: &Path) -> std::io::Result<BanList> {
+    let bytes = fs::read(dir.join(BANLIST_FILE))?;
+    bincode::decode_from_slice(&bytes, bincode::config::standard())
+        .map(|(bans, _)| bans)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Record the genesis hash a data directory was started with
+pub fn save_genesis_hash(hash: &[u8; HASH_SIZE], dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(GENESIS_FILE), hash)
+}
+
+pub fn load_genesis_hash(dir: &Path) -> std::io::Result<[u8; HASH_SIZE]> {
+    let bytes = fs::read(dir.join(GENESIS_FILE))?;
+    bytes.try_into().map_err(|_| invalid_data("Genesis file doesn't hold a hash"))
+}
+
+/// Discard the transaction index so it is rebuilt from scratch
+pub fn remove_txindex(dir: &Path) -> std::io::Result<()> {
+    match fs::remove_file(dir.join(TXINDEX_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Bytes free to unprivileged users on the filesystem holding `dir`. A `dir`
+/// not created yet is measured at its nearest existing ancestor.
+#[cfg(unix)]
+pub fn free_space(dir: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = dir.ancestors().find(|dir| dir.exists()).unwrap_or(Path::new("."));
+    let path = std::ffi::CString::new(existing.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is NUL-terminated and stats is only read once statvfs has
+    // filled it in
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stats = unsafe { stats.assume_init() };
+    // The field types vary by platform
+    #[allow(clippy::unnecessary_cast)]
+    let free = stats.f_bavail as u64 * stats.f_frsize as u64;
+    Ok(free)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_dir: &Path) -> std::io::Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Bytes the chain and transaction index files in a directory take, measured
+/// at most once per `DISK_USAGE_TTL`
+#[derive(Debug)]
+pub struct DiskUsage {
+    dir: PathBuf,
+    measured: Mutex<Option<(Instant, u64)>>,
+}
+
+impl DiskUsage {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, measured: Mutex::new(None) }
+    }
+
+    /// The measured size, checking the files again once it is stale. Only
+    /// the cache is locked; the files are checked without blocking the runtime.
+    pub async fn bytes(&self) -> u64 {
+        if let Some((at, bytes)) = *self.measured.lock().unwrap()
+            && at.elapsed() < DISK_USAGE_TTL {
+            return bytes;
+        }
+
+        // Files not written yet take no space
+        let mut bytes = 0;
+        for file in [CHAIN_FILE, TXINDEX_FILE] {
+            if let Ok(metadata) = tokio::fs::metadata(self.dir.join(file)).await {
+                bytes += metadata.len();
+            }
+        }
+        *self.measured.lock().unwrap() = Some((Instant::now(), bytes));
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::consensus::{difficulty_at, POW_LIMIT_BITS};
+    use hyperion_core::testutil::ChainBuilder;
+
+    /// Twelve blocks saved by a node from before the versioned format, mined
+    /// by its miner at wall-clock time. The first two hold transactions; the
+    /// first retarget, at height 3, gave 0x2100ffff.
+    const LEGACY_CHAIN: &[u8] = include_bytes!("../testdata/legacy-blockchain.dat");
+    const LEGACY_TIP: &str = "468b433d8f24314b3db4e9d5ffa79c06e0c12b5ac92293d6b41562613a7ad277";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hyperion-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_chain_replaces_file_whole() {
+        let dir = test_dir("write");
+        let short = ChainBuilder::new(0).mine_blocks(1, 1).build();
+        let long = ChainBuilder::new(0).mine_blocks(3, 1).build();
+        write_chain(&short.serialize().unwrap(), &dir).unwrap();
+
+        // A write cut short leaves only its temporary file behind
+        fs::write(dir.join("blockchain.dat.tmp"), b"\xffHYC\x01").unwrap();
+        assert_eq!(load_chain(&dir).unwrap().len(), 2);
+
+        write_chain(&long.serialize().unwrap(), &dir).unwrap();
+        assert_eq!(load_chain(&dir).unwrap().len(), 4);
+        assert!(!dir.join("blockchain.dat.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_fixture_migrates_to_same_tip() {
+        let dir = test_dir("migrate");
+        fs::write(dir.join(CHAIN_FILE), LEGACY_CHAIN).unwrap();
+        assert!(chain_is_legacy(&dir).unwrap());
+        assert!(load_chain(&dir).is_err());
+
+        let summary = migrate_chain(&dir, ConsensusParams::default()).unwrap();
+        assert_eq!(summary.blocks, 12);
+        assert_eq!(hex::encode(summary.tip_hash), LEGACY_TIP);
+        assert_eq!(fs::read(&summary.backup).unwrap(), LEGACY_CHAIN);
+
+        // The rewritten file loads as is, and isn't migrated again
+        assert!(!chain_is_legacy(&dir).unwrap());
+        let chain = load_chain(&dir).unwrap();
+        assert_eq!(chain.len(), 12);
+        assert_eq!(hex::encode(chain.latest_block().double_sha256()), LEGACY_TIP);
+        assert_eq!(chain.get_block_by_height(3).unwrap().header.difficulty_compact, 0x2100ffff);
+        // Across all three retargets its bits are the ones the rules give
+        assert!(chain.validate());
+        assert!(migrate_chain(&dir, ConsensusParams::default()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_bits_off_the_retarget_still_migrate() {
+        let dir = test_dir("migrate-bits");
+        // Legacy nodes took blocks at any bits their proof of work met, here
+        // blocks a second apart that never retarget
+        let chain = ChainBuilder::new(0).with_difficulty(POW_LIMIT_BITS).with_interval(1).mine_blocks(7, 1).build();
+        assert_ne!(difficulty_at(&chain, 3), POW_LIMIT_BITS);
+        fs::write(dir.join(CHAIN_FILE), chain.serialize().unwrap()).unwrap();
+
+        let summary = migrate_chain(&dir, ConsensusParams::default()).unwrap();
+        assert_eq!((summary.blocks, summary.tip_hash), (8, chain.latest_block().double_sha256()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_legacy_chain_is_left_alone() {
+        let dir = test_dir("migrate-invalid");
+        // The fixture mines empty blocks, which these params refuse
+        let params = ConsensusParams { allow_empty_blocks: false, ..Default::default() };
+        fs::write(dir.join(CHAIN_FILE), LEGACY_CHAIN).unwrap();
+
+        let err = migrate_chain(&dir, params).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(dir.join(CHAIN_FILE)).unwrap(), LEGACY_CHAIN);
+        assert!(!dir.join("blockchain.dat.bak").exists());
+
+        // No chain file yet is nothing to migrate
+        fs::remove_file(dir.join(CHAIN_FILE)).unwrap();
+        assert!(!chain_is_legacy(&dir).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use crate::mempool::Mempool;
+
+use hyperion_core::block::Transaction;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+
+/// Deterministic transaction for `seed`, with 1-3 inputs and outputs so it
+/// passes `Transaction::new`'s structural checks
+pub fn generate_random_tx(seed: u64) -> Transaction {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let num_inputs = rng.random_range(1..=3);
+    let num_outputs = rng.random_range(1..=3);
+
+    let mut inputs = Vec::new();
+    for i in 0..num_inputs {
+        inputs.push(format!("in{}_{}", i, rng.random::<u32>()).into_bytes());
+    }
+
+    let mut outputs = Vec::new();
+    for i in 0..num_outputs {
+        outputs.push(format!("out{}_{}", i, rng.random::<u32>()).into_bytes());
+    }
+
+    Transaction::new(inputs, outputs).expect("generated transaction has inputs and outputs")
+}
+
+/// Add `count` generated transactions to `mempool`, returning how many fit
+pub fn fill_mempool(mempool: &mut Mempool, count: usize) -> usize {
+    (0..count as u64)
+        .filter(|&seed| mempool.add_tx(generate_random_tx(seed)))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::crypto::Hashable;
+
+    #[test]
+    fn test_generated_transactions_are_deterministic() {
+        assert_eq!(generate_random_tx(7).double_sha256(), generate_random_tx(7).double_sha256());
+        assert_ne!(generate_random_tx(7).double_sha256(), generate_random_tx(8).double_sha256());
+    }
+
+    #[test]
+    fn test_fill_mempool() {
+        let mut mempool = Mempool::new();
+        assert_eq!(fill_mempool(&mut mempool, 215), 215);
+        assert_eq!(mempool.len(), 215);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::chain_manager::ChainManager;
+use crate::events::NodeEvent;
+
+use hyperion_core::block::Block;
+use hyperion_core::chain::blockchain::Blockchain;
+use hyperion_core::crypto::HASH_SIZE;
+
+use bincode::{Decode, Encode};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{RwLock, broadcast::{self, error::RecvError}};
+use tracing::warn;
+
+
+/// Height of the block holding each confirmed transaction, by txid
+#[derive(Debug, Default, Encode, Decode)]
+pub struct TxIndex {
+    heights: HashMap<[u8; HASH_SIZE], u64>,
+    /// Height of the highest block indexed so far
+    tip_height: Option<u64>,
+}
+
+impl TxIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_block(&mut self, block: &Block, height: u64) {
+        for txid in block.iter_txids() {
+            self.heights.insert(txid, height);
+        }
+        self.tip_height = Some(height);
+    }
+
+    pub fn height_of(&self, txid: &[u8; HASH_SIZE]) -> Option<u64> {
+        self.heights.get(txid).copied()
+    }
+
+    /// Index the blocks of `chain` above the current tip, returning how many
+    /// were added
+    pub fn catch_up(&mut self, chain: &Blockchain) -> usize {
+        let start = self.tip_height.map_or(0, |height| height as usize + 1);
+
+        for (height, block) in chain.iter_with_heights().skip(start) {
+            self.add_block(block, height as u64);
+        }
+        chain.len().saturating_sub(start)
+    }
+}
+
+/// Keep `index` in step with blocks connected after startup
+pub fn spawn_updater(
+    index: Arc<RwLock<TxIndex>>,
+    chain_manager: ChainManager,
+    mut events: broadcast::Receiver<NodeEvent>,
+) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(NodeEvent::BlockConnected { .. }) => {
+                    let chain = chain_manager.chain().await;
+                    index.write().await.catch_up(&chain);
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {
+                    // Missed connects are picked up from the chain on the next one
+                    warn!("Transaction index updater fell behind");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperion_core::block::Transaction;
+    use hyperion_core::crypto::Hashable;
+
+    #[test]
+    fn test_catch_up_indexes_new_blocks() {
+        let mut chain = Blockchain::new_with_genesis();
+        let mut index = TxIndex::new();
+        assert_eq!(index.catch_up(&chain), 1);
+
+        let tx = Transaction::new(vec![b"in".to_vec()], vec![b"out".to_vec()]).unwrap();
+        let block = Blockchain::mine_new_block(&chain, vec![tx.clone()], 1);
+        chain.add_block(block, false).unwrap();
+
+        assert_eq!(index.catch_up(&chain), 1);
+        assert_eq!(index.height_of(&tx.double_sha256()), Some(1));
+        assert_eq!(index.catch_up(&chain), 0);
+    }
+}
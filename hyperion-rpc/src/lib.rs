@@ -0,0 +1,773 @@
+//! JSON-RPC types shared by the node, which serves them, and the miner,
+//! which calls them. Fields added for newer nodes carry `#[serde(default)]`
+//! so either side can talk to an older other side.
+
+use hyperion_core::block::Transaction;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Major version of the protocol in this crate, bumped for changes a side
+/// built against an older major version can't handle
+pub const PROTOCOL_MAJOR: u32 = 1;
+/// Minor version, bumped for additions older sides can ignore
+pub const PROTOCOL_MINOR: u32 = 0;
+
+// JSON-RPC 2.0 standard types
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcRequest<T> {
+    pub jsonrpc: String,
+    /// Echoed back in the response; clients may use any JSON value
+    pub id: Value,
+    pub method: String,
+    /// Missing params read as `None`
+    pub params: Option<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse<T> {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub result: Option<T>,
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+/// What was wrong with a malformed param, carried in the `data` of an
+/// invalid params error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidParam {
+    /// Path to the offending param, such as `start` or `transactions[0]`,
+    /// or `.` for the params as a whole
+    pub field: String,
+    /// What the param should have been, when it was there at all
+    #[serde(default)]
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+// Mining specific types
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct GetBlockTemplateParams {
+    /// Most transactions wanted, up to the node's own cap; the cap if missing
+    #[serde(default)]
+    pub max_txs: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockTemplate {
+    pub version: u32,
+    pub previous_block_hash: String,
+    /// Shared by everything built from the template rather than copied
+    pub transactions: Arc<Vec<Transaction>>,
+    pub difficulty_compact: u32,
+    /// Time to mine with, at least `min_time`
+    pub timestamp: u32,
+    /// The node's clock when it built the template, so clients can tell
+    /// how far theirs is off; missing from nodes that predate it
+    #[serde(default)]
+    pub cur_time: Option<u32>,
+    /// Earliest time a block built from this template may carry; missing
+    /// from nodes that predate it
+    #[serde(default)]
+    pub min_time: Option<u32>,
+    /// Latest time a block built from this template may carry; missing
+    /// from nodes that predate it
+    #[serde(default)]
+    pub max_time: Option<u32>,
+    pub height: u64,
+    pub merkle_root: String,
+    /// Most bytes a block built from the template serializes to; missing
+    /// from nodes that predate it
+    #[serde(default)]
+    pub size_estimate: Option<u64>,
+    /// Transactions in the template; missing from nodes that predate it
+    #[serde(default)]
+    pub tx_count: Option<u64>,
+    /// Present when the node supports longpolling for new templates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longpollid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitBlockParams {
+    pub block_hex: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitBlockResult {
+    pub accepted: bool,
+    pub message: Option<String>,
+    /// Why the block was rejected, e.g. `stale-prevblk` or `high-hash`;
+    /// missing from nodes that predate it
+    #[serde(default)]
+    pub reject_reason: Option<String>,
+    /// Hash of the block as the node decoded it, which should match the
+    /// submitter's own; missing from nodes that predate it
+    #[serde(default)]
+    pub block_hash: Option<String>,
+    /// Height the block was connected at, if accepted
+    #[serde(default)]
+    pub height: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MiningInfo {
+    pub blocks: u64,
+    pub current_block_size: u64,
+    pub current_block_tx: u64,
+    pub difficulty: f64,
+    pub network_hashps: f64,
+    pub pooled_tx: u64,
+    pub chain: String,
+    /// Height of the next block mined at a retargeted difficulty; missing
+    /// from nodes that predate it
+    #[serde(default)]
+    pub next_retarget_height: Option<u64>,
+    /// Blocks still to be mined before `next_retarget_height`; missing from
+    /// nodes that predate it
+    #[serde(default)]
+    pub blocks_until_retarget: Option<u64>,
+    /// Difficulty the retarget would set at the pace of its window so far,
+    /// in the same units as `difficulty`; missing from nodes that predate it
+    #[serde(default)]
+    pub estimated_next_difficulty: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainInfo {
+    pub chain: String,
+    pub blocks: u64,
+    pub headers: u64,
+    pub best_blockhash: String,
+    pub difficulty: f64,
+    pub median_time: u32,
+    /// Fraction of stored blocks replayed by a running reindex, otherwise 1.0
+    pub verification_progress: f64,
+    /// Bytes the chain and its index take in the data directory; missing
+    /// from nodes that predate it, like `chain_work`
+    #[serde(default)]
+    pub size_on_disk: Option<u64>,
+    /// Total work of the active chain, as 64 hex digits
+    #[serde(default)]
+    pub chain_work: Option<String>,
+    /// The latest block the node connected, unless none has since it started
+    #[serde(default)]
+    pub last_block: Option<RecentBlock>,
+}
+
+/// Who a node is, for clients to check before relying on it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    /// Node software version
+    pub version: String,
+    pub protocol_major: u32,
+    pub protocol_minor: u32,
+    /// Network the node follows, e.g. `mainnet`
+    pub network: String,
+    pub genesis_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetRawTransactionParams {
+    pub txid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawTransaction {
+    pub txid: String,
+    /// Serialized transaction
+    pub hex: String,
+    pub block_hash: String,
+    pub height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodeRawTransactionParams {
+    /// Serialized transaction, as `get_raw_transaction` returns it
+    pub tx_hex: String,
+}
+
+/// A serialized transaction taken apart. Transactions carry no version or
+/// lock time yet, so neither appears here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedTransaction {
+    pub txid: String,
+    /// Serialized size in bytes
+    pub size: u64,
+    /// Each input's data as hex
+    pub inputs: Vec<String>,
+    /// Each output's data as hex
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetBlockParams {
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawBlock {
+    pub hash: String,
+    /// Serialized block, as `submit_block` takes it
+    pub hex: String,
+    pub height: u64,
+}
+
+/// A block on the active chain, by height or by hash as hex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockRef {
+    Height(u64),
+    Hash(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetHeadersParams {
+    pub start: BlockRef,
+    /// Headers wanted; nodes cap it
+    pub count: u64,
+}
+
+/// Consecutive headers of the active chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderBatch {
+    /// Height of the first header
+    pub start_height: u64,
+    /// Serialized headers as hex, one per block in height order. Fewer
+    /// than asked for means the batch reached the tip.
+    pub headers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ExportChainParams {
+    /// File on the node's machine to write the export to; returned inline
+    /// as hex if missing
+    #[serde(default)]
+    pub path: Option<String>,
+    /// First height to export, genesis if missing
+    #[serde(default)]
+    pub from_height: Option<u64>,
+    /// Last height to export, the tip if missing
+    #[serde(default)]
+    pub to_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainExportResult {
+    pub from_height: u64,
+    pub to_height: u64,
+    pub blocks: u64,
+    /// Where the export was written, when it was asked for as a file
+    #[serde(default)]
+    pub path: Option<String>,
+    /// The export itself, when it wasn't written to a file
+    #[serde(default)]
+    pub hex: Option<String>,
+}
+
+/// An export to import, either `path` or `hex`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ImportChainParams {
+    /// File on the node's machine to read the export from
+    #[serde(default)]
+    pub path: Option<String>,
+    /// The export itself
+    #[serde(default)]
+    pub hex: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainImportResult {
+    /// Blocks added to the chain
+    pub accepted: u64,
+    /// Blocks the chain already had
+    pub skipped: u64,
+    /// 1 if the import stopped at a block that failed, 0 otherwise
+    pub rejected: u64,
+    /// Tip height once the import finished
+    pub height: u64,
+    /// Why the import stopped early, naming the block
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Where `snapshot_chain` writes a snapshot or `restore_chain` reads one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSnapshotParams {
+    /// File in the node's data directory for the chain, relative to it
+    pub path: String,
+}
+
+/// The chain state a snapshot was taken of or restored to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSnapshotResult {
+    pub path: String,
+    pub height: u64,
+    /// Hash of the tip block
+    pub hash: String,
+    /// Transactions in the mempool
+    pub mempool_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetChainTimingParams {
+    /// Most recent blocks to measure
+    pub window: u64,
+}
+
+/// Observed spacing of the most recent blocks, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainTiming {
+    /// Blocks measured, fewer than the window on a short chain
+    pub blocks: u64,
+    /// Statistics over the time between each measured block and its parent
+    pub mean_interval: f64,
+    pub median_interval: f64,
+    pub stddev_interval: f64,
+    pub min_interval: i64,
+    pub max_interval: i64,
+    /// Retarget windows ending among the measured blocks, oldest first
+    pub epochs: Vec<EpochTiming>,
+}
+
+/// A retarget window's measured time against the time it expected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochTiming {
+    pub start_height: u64,
+    pub end_height: u64,
+    /// Height of the first block at the difficulty the window set
+    pub retarget_height: u64,
+    pub actual_time: u32,
+    pub expected_time: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetRecentBlocksParams {
+    /// Most blocks to return, all the node remembers if unset
+    #[serde(default)]
+    pub count: Option<u64>,
+}
+
+/// A block the node connected since it started, and how it arrived
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentBlock {
+    pub hash: String,
+    pub height: u64,
+    /// Unix time the node connected the block
+    pub received_time: u64,
+    /// Seconds from the block's header time to `received_time`, negative
+    /// for a block dated ahead of the node's clock
+    pub propagation_secs: i64,
+    /// `rpc`, `p2p` or `import`
+    pub source: String,
+    /// RPC client or peer address that handed the block over, where known
+    pub submitter: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MempoolInfo {
+    /// Transactions currently pooled
+    pub size: u64,
+    /// Pool capacity in transactions
+    pub max_size: u64,
+    /// Moves on whenever the pooled transactions change; missing from
+    /// nodes that predate it
+    #[serde(default)]
+    pub generation: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct GetRawMempoolParams {
+    /// Describe each transaction rather than list txids
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+/// A pooled transaction and where it sits among the others
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MempoolEntry {
+    /// Serialized size in bytes
+    pub size: u64,
+    /// Pooled transactions this one depends on, however indirectly
+    pub ancestor_count: u64,
+    /// Pooled transactions depending on this one, however indirectly
+    pub descendant_count: u64,
+    /// Txids of the pooled transactions this one spends from directly
+    pub depends: Vec<String>,
+}
+
+/// Pooled transactions, as bare txids unless asked for verbosely
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RawMempool {
+    Txids(Vec<String>),
+    Verbose(BTreeMap<String, MempoolEntry>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub id: u64,
+    pub addr: String,
+    pub inbound: bool,
+    pub conn_time: u32,
+    pub version: Option<u32>,
+    /// Feature bits negotiated with the peer
+    pub features: u64,
+    /// Round-trip time of the last answered ping, in seconds
+    pub ping_time: Option<f64>,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub msgs_sent: u64,
+    pub msgs_recv: u64,
+    pub misbehavior: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanCommand {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetBanParams {
+    /// IP address; a ban covers every port
+    pub address: String,
+    pub command: BanCommand,
+    /// How long an `add` lasts; the node's configured ban duration if missing
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BannedPeer {
+    pub address: String,
+    pub reason: String,
+    /// Unix time the ban lifts
+    pub banned_until: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub protocol_version: u32,
+    pub connections: u64,
+    pub connections_in: u64,
+    pub connections_out: u64,
+    pub max_connections: u64,
+    pub max_inbound: u64,
+    pub max_outbound: u64,
+    pub total_bytes_recv: u64,
+    pub total_bytes_sent: u64,
+    /// Addresses the P2P listener is bound to
+    pub local_addresses: Vec<String>,
+}
+
+// Error codes (Bitcoin-compatible)
+impl RpcError {
+    /// Code of `method_not_found`, which clients may check for to detect
+    /// older nodes
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+
+    pub fn method_not_found() -> Self {
+        Self {
+            code: Self::METHOD_NOT_FOUND,
+            message: "Method not found".to_string(),
+            data: None,
+        }
+    }
+
+    /// A method this node has but won't run for the caller. Shares
+    /// `method_not_found`'s code, so clients treat both alike.
+    pub fn method_disabled(method: &str) -> Self {
+        Self {
+            code: Self::METHOD_NOT_FOUND,
+            message: format!("Method disabled: {}", method),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(msg: &str) -> Self {
+        Self {
+            code: -32602,
+            message: format!("Invalid params: {}", msg),
+            data: None,
+        }
+    }
+
+    /// Invalid params error naming the param at fault
+    pub fn invalid_param(param: InvalidParam) -> Self {
+        Self {
+            code: -32602,
+            message: format!("Invalid params: {}: {}", param.field, param.message),
+            data: Some(serde_json::to_value(param).unwrap()),
+        }
+    }
+
+    pub fn internal_error(msg: &str) -> Self {
+        Self {
+            code: -32603,
+            message: format!("Internal error: {}", msg),
+            data: None,
+        }
+    }
+
+    /// Serialized data, such as a transaction, that doesn't decode
+    pub fn decode_failed(msg: &str) -> Self {
+        Self {
+            code: -22,
+            message: format!("Decode failed: {}", msg),
+            data: None,
+        }
+    }
+
+    pub fn reindexing() -> Self {
+        Self {
+            code: -28,
+            message: "Node is reindexing, try again once it has finished".to_string(),
+            data: None,
+        }
+    }
+
+    pub fn custom(code: i32, msg: &str) -> Self {
+        Self {
+            code,
+            message: msg.to_string(),
+            data: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    /// `value` serializes to exactly `expected` and reads back unchanged
+    fn assert_wire<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: &T, expected: Value) {
+        assert_eq!(serde_json::to_value(value).unwrap(), expected);
+        assert_eq!(&serde_json::from_value::<T>(expected).unwrap(), value);
+    }
+
+    fn template() -> BlockTemplate {
+        BlockTemplate {
+            version: 1,
+            previous_block_hash: "00".repeat(32),
+            transactions: Arc::new(vec![Transaction { inputs: vec![vec![1, 2]], outputs: vec![vec![3]] }]),
+            difficulty_compact: 0x1f00_ffff,
+            timestamp: 1_700_000_000,
+            cur_time: Some(1_700_000_000),
+            min_time: Some(1_699_999_001),
+            max_time: Some(1_700_007_200),
+            height: 42,
+            merkle_root: "ab".repeat(32),
+            size_estimate: Some(180),
+            tx_count: Some(1),
+            longpollid: None,
+        }
+    }
+
+    #[test]
+    fn test_envelope_wire_format() {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: json!(7),
+            method: "submit_block".to_string(),
+            params: Some(SubmitBlockParams { block_hex: "00ff".to_string() }),
+        };
+        assert_wire(&request, json!({
+            "jsonrpc": "2.0", "id": 7, "method": "submit_block", "params": { "block_hex": "00ff" },
+        }));
+
+        // Requests without params, and with string ids
+        let request: RpcRequest<Value> =
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": "a", "method": "get_block_count" })).unwrap();
+        assert_eq!((request.id, request.params), (json!("a"), None));
+
+        let response: RpcResponse<u64> = RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: json!(7),
+            result: None,
+            error: Some(RpcError::method_not_found()),
+        };
+        assert_wire(&response, json!({
+            "jsonrpc": "2.0", "id": 7, "result": null,
+            "error": { "code": -32601, "message": "Method not found", "data": null },
+        }));
+    }
+
+    #[test]
+    fn test_mining_wire_format() {
+        assert_wire(&template(), json!({
+            "version": 1,
+            "previous_block_hash": "00".repeat(32),
+            "transactions": [{ "inputs": [[1, 2]], "outputs": [[3]] }],
+            "difficulty_compact": 0x1f00_ffff,
+            "timestamp": 1_700_000_000,
+            "cur_time": 1_700_000_000,
+            "min_time": 1_699_999_001,
+            "max_time": 1_700_007_200,
+            "height": 42,
+            "merkle_root": "ab".repeat(32),
+            "size_estimate": 180,
+            "tx_count": 1,
+        }));
+
+        let longpolling = BlockTemplate { longpollid: Some("abc:3".to_string()), ..template() };
+        assert_eq!(serde_json::to_value(&longpolling).unwrap()["longpollid"], "abc:3");
+
+        let result = SubmitBlockResult {
+            accepted: false,
+            message: Some("stale".to_string()),
+            reject_reason: Some("stale-prevblk".to_string()),
+            block_hash: Some("ab".repeat(32)),
+            height: None,
+        };
+        assert_wire(&result, json!({
+            "accepted": false, "message": "stale", "reject_reason": "stale-prevblk",
+            "block_hash": "ab".repeat(32), "height": null,
+        }));
+        // Nodes predating the hash echo leave it out
+        let old: SubmitBlockResult = serde_json::from_value(json!({ "accepted": true, "message": null })).unwrap();
+        assert_eq!((old.block_hash, old.height), (None, None));
+
+        let info = MiningInfo {
+            blocks: 10,
+            current_block_size: 300,
+            current_block_tx: 2,
+            difficulty: 1.5,
+            network_hashps: 1000.0,
+            pooled_tx: 4,
+            chain: "regtest".to_string(),
+            next_retarget_height: Some(12),
+            blocks_until_retarget: Some(1),
+            estimated_next_difficulty: Some(2.5),
+        };
+        assert_wire(&info, json!({
+            "blocks": 10, "current_block_size": 300, "current_block_tx": 2, "difficulty": 1.5,
+            "network_hashps": 1000.0, "pooled_tx": 4, "chain": "regtest", "next_retarget_height": 12,
+            "blocks_until_retarget": 1, "estimated_next_difficulty": 2.5,
+        }));
+
+        let version = VersionInfo {
+            version: "0.1.0".to_string(),
+            protocol_major: 1,
+            protocol_minor: 0,
+            network: "regtest".to_string(),
+            genesis_hash: "cd".repeat(32),
+        };
+        assert_wire(&version, json!({
+            "version": "0.1.0", "protocol_major": 1, "protocol_minor": 0, "network": "regtest",
+            "genesis_hash": "cd".repeat(32),
+        }));
+    }
+
+    #[test]
+    fn test_headers_wire_format() {
+        // The start is a bare height or hash
+        let by_height = GetHeadersParams { start: BlockRef::Height(5), count: 2000 };
+        assert_wire(&by_height, json!({ "start": 5, "count": 2000 }));
+        let by_hash = GetHeadersParams { start: BlockRef::Hash("ab".repeat(32)), count: 1 };
+        assert_wire(&by_hash, json!({ "start": "ab".repeat(32), "count": 1 }));
+
+        let batch = HeaderBatch { start_height: 5, headers: vec!["01".to_string(), "02".to_string()] };
+        assert_wire(&batch, json!({ "start_height": 5, "headers": ["01", "02"] }));
+    }
+
+    #[test]
+    fn test_ban_wire_format() {
+        let params = SetBanParams {
+            address: "10.0.0.1".to_string(),
+            command: BanCommand::Add,
+            duration_secs: Some(3600),
+            reason: Some("spam".to_string()),
+        };
+        assert_wire(&params, json!({ "address": "10.0.0.1", "command": "add", "duration_secs": 3600, "reason": "spam" }));
+
+        // Duration and reason may be left out
+        let remove: SetBanParams = serde_json::from_value(json!({ "address": "::1", "command": "remove" })).unwrap();
+        assert_eq!((remove.command, remove.duration_secs, remove.reason), (BanCommand::Remove, None, None));
+
+        let banned = BannedPeer { address: "10.0.0.1".to_string(), reason: "spam".to_string(), banned_until: 1_700_000_000 };
+        assert_wire(&banned, json!({ "address": "10.0.0.1", "reason": "spam", "banned_until": 1_700_000_000 }));
+    }
+
+    #[test]
+    fn test_raw_mempool_wire_format() {
+        let params: GetRawMempoolParams = serde_json::from_value(json!({})).unwrap();
+        assert!(!params.verbose);
+
+        assert_wire(&RawMempool::Txids(vec!["ab".to_string()]), json!(["ab"]));
+        let entry = MempoolEntry { size: 40, ancestor_count: 1, descendant_count: 0, depends: vec!["cd".to_string()] };
+        assert_wire(&RawMempool::Verbose(BTreeMap::from([("ab".to_string(), entry)])), json!({
+            "ab": { "size": 40, "ancestor_count": 1, "descendant_count": 0, "depends": ["cd"] },
+        }));
+    }
+
+    #[test]
+    fn test_invalid_param_wire_format() {
+        let param = InvalidParam {
+            field: "block_hex".to_string(),
+            expected: Some("a string".to_string()),
+            message: "invalid type: integer `5`, expected a string".to_string(),
+        };
+        let error = RpcError::invalid_param(param.clone());
+        assert_eq!(error.message, "Invalid params: block_hex: invalid type: integer `5`, expected a string");
+        assert_wire(&error, json!({
+            "code": -32602,
+            "message": error.message,
+            "data": { "field": "block_hex", "expected": "a string", "message": param.message },
+        }));
+        assert_eq!(serde_json::from_value::<InvalidParam>(error.data.unwrap()).unwrap(), param);
+    }
+
+    /// Fields later nodes added may be missing from older ones
+    #[test]
+    fn test_older_nodes_are_understood() {
+        let mut old = serde_json::to_value(template()).unwrap();
+        for field in ["cur_time", "min_time", "max_time", "size_estimate", "tx_count"] {
+            old.as_object_mut().unwrap().remove(field);
+        }
+        let parsed: BlockTemplate = serde_json::from_value(old).unwrap();
+        let missing = BlockTemplate {
+            cur_time: None,
+            min_time: None,
+            max_time: None,
+            size_estimate: None,
+            tx_count: None,
+            ..template()
+        };
+        assert_eq!(parsed, missing);
+
+        let result: SubmitBlockResult = serde_json::from_value(json!({ "accepted": true, "message": null })).unwrap();
+        assert_eq!(result.reject_reason, None);
+
+        let info: MiningInfo = serde_json::from_value(json!({
+            "blocks": 10, "current_block_size": 0, "current_block_tx": 0, "difficulty": 1.5,
+            "network_hashps": 0.0, "pooled_tx": 0, "chain": "regtest",
+        }))
+        .unwrap();
+        assert_eq!((info.next_retarget_height, info.estimated_next_difficulty), (None, None));
+
+        let info: ChainInfo = serde_json::from_value(json!({
+            "chain": "regtest", "blocks": 1, "headers": 1, "best_blockhash": "00".repeat(32), "difficulty": 1.0,
+            "median_time": 0, "verification_progress": 1.0,
+        }))
+        .unwrap();
+        assert_eq!((info.size_on_disk, info.chain_work), (None, None));
+
+        let error: RpcError = serde_json::from_value(json!({ "code": -1, "message": "boom" })).unwrap();
+        assert_eq!(error, RpcError::custom(-1, "boom"));
+    }
+}